@@ -0,0 +1,325 @@
+/*
+ * my_rcc 的运行时库
+ * 由 my_rcc 自己编译，my_rcc link 在程序用到其中的函数时链接进去
+ *
+ * 语言中没有指针解引用，读写内存和系统调用用启动代码中的汇编函数:
+ * __syscall(n, a, b, c, d, e, f)  系统调用 n
+ * __write8(fd, c)                  把一个字节写到 fd
+ * __load8(p) __store8(p, v)        读写一个字节，读出时零扩展
+ * __load64(p) __store64(p, v)      读写 8 个字节
+ * int 是 64 位的，可以放下指针
+*/
+
+int __syscall(int n, int a, int b, int c, int d, int e, int f);
+int __write8(int fd, int c);
+int __load8(char *p);
+void __store8(char *p, int v);
+int __load64(char *p);
+void __store64(char *p, int v);
+
+static char *advance(char *p, int n) {
+    return (char *)((int)p + n);
+}
+
+int write(int fd, char *buf, int n) {
+    return __syscall(1, fd, (int)buf, n, 0, 0, 0);
+}
+
+void exit(int status) {
+    __syscall(231, status, 0, 0, 0, 0, 0);
+}
+
+/*
+ * 每次分配单独 mmap 匿名的私有页
+ * 开头 16 字节记录映射的大小，free 时 munmap
+*/
+char *malloc(int size) {
+    int total = (size + 16 + 4095) / 4096 * 4096;
+    int p = __syscall(9, 0, total, 3, 34, -1, 0);
+    // 失败时返回 -4095 到 -1
+    if (p < 0 && p > -4096) {
+        return 0;
+    }
+    __store64((char *)p, total);
+    return advance((char *)p, 16);
+}
+
+void free(char *p) {
+    char *base;
+    if (p == 0) {
+        return;
+    }
+    base = advance(p, -16);
+    __syscall(11, (int)base, __load64(base), 0, 0, 0, 0);
+}
+
+char *memcpy(char *dest, char *src, int n) {
+    int i;
+    for (i = 0; i < n; i = i + 1) {
+        __store8(advance(dest, i), __load8(advance(src, i)));
+    }
+    return dest;
+}
+
+char *memset(char *dest, int c, int n) {
+    int i;
+    for (i = 0; i < n; i = i + 1) {
+        __store8(advance(dest, i), c);
+    }
+    return dest;
+}
+
+int strlen(char *s) {
+    int n = 0;
+    while (__load8(advance(s, n)) != 0) {
+        n = n + 1;
+    }
+    return n;
+}
+
+int putchar(int c) {
+    __write8(1, c);
+    return c;
+}
+
+int puts(char *s) {
+    write(1, s, strlen(s));
+    write(1, "\n", 1);
+    return 0;
+}
+
+/*
+ * printf 的缓冲区，共 4096 字节
+ * 开头 8 字节是输出缓冲区中的字节数，之后 8 字节是一共输出的字节数
+ * 之后 32 字节暂存转换出来的数字 (写在末尾)，剩下的是输出缓冲区，满了就 write 出去
+*/
+static void flush(char *buf) {
+    write(1, advance(buf, 48), __load64(buf));
+    __store64(buf, 0);
+}
+
+static void emit(char *buf, int c) {
+    int n = __load64(buf);
+    if (n == 4096 - 48) {
+        flush(buf);
+        n = 0;
+    }
+    __store8(advance(buf, 48 + n), c);
+    __store64(buf, n + 1);
+    __store64(advance(buf, 8), __load64(advance(buf, 8)) + 1);
+}
+
+static void emit_repeated(char *buf, int c, int n) {
+    while (n > 0) {
+        emit(buf, c);
+        n = n - 1;
+    }
+}
+
+static void emit_text(char *buf, char *s, int n) {
+    int i;
+    for (i = 0; i < n; i = i + 1) {
+        emit(buf, __load8(advance(s, i)));
+    }
+}
+
+/*
+ * 把 v 作为 64 位无符号数按 base 进制写到暂存区的末尾，返回位数
+ * 最高位是 1 时先分成高低各 32 位做一步除法，v = high * 2^32 + (v 的低 32 位)，中间结果都不会溢出
+*/
+static int convert(char *buf, int v, int base) {
+    int n = 0;
+    int high;
+    int low;
+    int digit;
+    if (v < 0) {
+        high = (v >> 32) & ((1 << 32) - 1);
+        low = ((high % base) << 32) + (v & ((1 << 32) - 1));
+        digit = low % base;
+        __store8(advance(buf, 47), digit < 10 ? 48 + digit : 87 + digit);
+        v = ((high / base) << 32) + low / base;
+        n = 1;
+    }
+    while (v != 0 || n == 0) {
+        digit = v % base;
+        __store8(advance(buf, 47 - n), digit < 10 ? 48 + digit : 87 + digit);
+        v = v / base;
+        n = n + 1;
+    }
+    return n;
+}
+
+// 标志字符对应的位: - 1，0 2，+ 4，空格 8，# 16，不是标志时为 0
+static int flag(int c) {
+    if (c == 45) {
+        return 1;
+    } else if (c == 48) {
+        return 2;
+    } else if (c == 43) {
+        return 4;
+    } else if (c == 32) {
+        return 8;
+    } else if (c == 35) {
+        return 16;
+    }
+    return 0;
+}
+
+/*
+ * 输出一个字段: 前缀 (符号或者 0x)，zeros 个 0，text 的 n 个字符
+ * 不够 width 时用空格补在左边，有 - 标志时补在右边
+*/
+static void emit_field(char *buf, int flags, int width, char *prefix, int zeros, char *text, int n) {
+    int padding = width - strlen(prefix) - zeros - n;
+    if ((flags & 1) == 0) {
+        emit_repeated(buf, 32, padding);
+    }
+    emit_text(buf, prefix, strlen(prefix));
+    emit_repeated(buf, 48, zeros);
+    emit_text(buf, text, n);
+    if (flags & 1) {
+        emit_repeated(buf, 32, padding);
+    }
+}
+
+/*
+ * 输出无符号数 v 的 base 进制
+ * 精度是最少的位数，精度为 0 时 0 不输出数字
+ * 有 0 标志、没有 - 标志和精度时用 0 补足宽度
+*/
+static void emit_number(char *buf, int flags, int width, int precision, char *prefix, int v, int base) {
+    int n = precision == 0 && v == 0 ? 0 : convert(buf, v, base);
+    int zeros = precision > n ? precision - n : 0;
+    if (precision < 0 && (flags & 3) == 2 && width > strlen(prefix) + n) {
+        zeros = width - strlen(prefix) - n;
+    }
+    emit_field(buf, flags, width, prefix, zeros, advance(buf, 48 - n), n);
+}
+
+/*
+ * 支持 %d %i %u %x %c %s %%
+ * 可以带标志 - 0 + 空格 #，宽度和精度 (可以是 *，从参数中取)，l ll 长度修饰 (int 已经是 64 位)
+ * %u %x 没有长度修饰时是 32 位的 unsigned int，有时是 64 位的 unsigned long
+ * 返回输出的字节数
+*/
+int printf(char *fmt, ...) {
+    va_list ap;
+    char *buf = malloc(4096);
+    int total;
+    int i = 0;
+    int c;
+    int flags;
+    int width;
+    int precision;
+    int wide;
+    int v;
+    char *s;
+    int n;
+
+    va_start(ap, fmt);
+    __store64(buf, 0);
+    __store64(advance(buf, 8), 0);
+    while (__load8(advance(fmt, i)) != 0) {
+        c = __load8(advance(fmt, i));
+        i = i + 1;
+        if (c != 37) {
+            emit(buf, c);
+        } else {
+            c = __load8(advance(fmt, i));
+            flags = 0;
+            while (flag(c) != 0) {
+                flags = flags | flag(c);
+                i = i + 1;
+                c = __load8(advance(fmt, i));
+            }
+
+            // 宽度，* 是负数时相当于 - 标志
+            width = 0;
+            if (c == 42) {
+                width = va_arg(ap, int);
+                if (width < 0) {
+                    flags = flags | 1;
+                    width = -width;
+                }
+                i = i + 1;
+                c = __load8(advance(fmt, i));
+            }
+            while (c >= 48 && c <= 57) {
+                width = width * 10 + c - 48;
+                i = i + 1;
+                c = __load8(advance(fmt, i));
+            }
+
+            // 精度，没有或者 * 是负数时为 -1
+            precision = -1;
+            if (c == 46) {
+                precision = 0;
+                i = i + 1;
+                c = __load8(advance(fmt, i));
+                if (c == 42) {
+                    precision = va_arg(ap, int);
+                    if (precision < 0) {
+                        precision = -1;
+                    }
+                    i = i + 1;
+                    c = __load8(advance(fmt, i));
+                }
+                while (c >= 48 && c <= 57) {
+                    precision = precision * 10 + c - 48;
+                    i = i + 1;
+                    c = __load8(advance(fmt, i));
+                }
+            }
+
+            wide = 0;
+            while (c == 108) {
+                wide = 1;
+                i = i + 1;
+                c = __load8(advance(fmt, i));
+            }
+            if (c != 0) {
+                i = i + 1;
+            }
+
+            if (c == 100 || c == 105) {
+                v = va_arg(ap, int);
+                if (v < 0) {
+                    emit_number(buf, flags, width, precision, "-", -v, 10);
+                } else {
+                    emit_number(buf, flags, width, precision, flags & 4 ? "+" : (flags & 8 ? " " : ""), v, 10);
+                }
+            } else if (c == 117 || c == 120) {
+                v = va_arg(ap, int);
+                if (!wide) {
+                    v = v & ((1 << 32) - 1);
+                }
+                if (c == 117) {
+                    emit_number(buf, flags, width, precision, "", v, 10);
+                } else {
+                    emit_number(buf, flags, width, precision, (flags & 16) && v != 0 ? "0x" : "", v, 16);
+                }
+            } else if (c == 99) {
+                __store8(advance(buf, 47), va_arg(ap, int));
+                emit_field(buf, flags, width, "", 0, advance(buf, 47), 1);
+            } else if (c == 115) {
+                s = va_arg(ap, char *);
+                if (s == 0) {
+                    s = "(null)";
+                }
+                n = strlen(s);
+                if (precision >= 0 && precision < n) {
+                    n = precision;
+                }
+                emit_field(buf, flags, width, "", 0, s, n);
+            } else if (c == 37) {
+                emit(buf, 37);
+            }
+        }
+    }
+    va_end(ap);
+
+    total = __load64(advance(buf, 8));
+    flush(buf);
+    free(buf);
+    return total;
+}
@@ -0,0 +1,169 @@
+use super::super::ir::{Function, Ty};
+use super::super::stack::{align_to, allocate, outgoing_size};
+
+/*
+ * AArch64 的栈帧布局
+ * 所有的值都放在栈帧中，序言之后 sp 不再改变，栈槽和值都用相对 sp 的偏移访问
+ * 调用的栈参数写在栈帧的最底部
+ *
+ * x29+16 ...      栈上传来的参数
+ * x29             调用者的 x29 和返回地址 x30
+ * x29-192 ~ x29   可变参数的寄存器保存区 (只有可变参数函数有，先 x0-x7 再 q0-q7)
+ * ...             没有分到栈槽的值
+ * ...             栈槽 (局部变量)
+ * sp+N ~ sp+N+16  phi 赋值打破环时用的临时位置
+ * sp ~ sp+N       调用的栈参数
+*/
+
+// 整数参数寄存器的个数 x0 ~ x7
+pub const GP_ARG_REGS: usize = 8;
+// 浮点参数寄存器的个数 v0 ~ v7
+pub const FP_ARG_REGS: usize = 8;
+// 寄存器保存区的大小
+const VA_SAVE_SIZE: isize = GP_ARG_REGS as isize * 8 + FP_ARG_REGS as isize * 16;
+
+/*
+ * 参数的位置
+ * 整数寄存器
+ * 浮点寄存器
+ * 栈上 (相对于第一个栈参数的偏移)
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum ArgLocation {
+    Gp(usize),
+    Fp(usize),
+    Stack(isize),
+}
+
+/*
+ * AAPCS64 调用约定
+ * 整数和指针依次使用 x0 ~ x7
+ * float double long double 依次使用 v0 ~ v7 (long double 是128位的四精度，放在整个 q 寄存器中)
+ * 用完寄存器的参数放在栈上，每个占8字节，long double 占16字节并且16字节对齐
+ * 可变参数和普通参数的传法相同
+ * 返回每个参数的位置和栈参数占用的大小 (16字节对齐)
+*/
+pub fn classify_arguments(types: &[Ty]) -> (Vec<ArgLocation>, isize) {
+    let mut gp = 0;
+    let mut fp = 0;
+    let mut offset = 0;
+
+    let locations = types.iter().map(|t| match t {
+        t if t.is_float() && fp < FP_ARG_REGS => {
+            fp += 1;
+            ArgLocation::Fp(fp - 1)
+        }
+        t if !t.is_float() && gp < GP_ARG_REGS => {
+            gp += 1;
+            ArgLocation::Gp(gp - 1)
+        }
+        Ty::F80 => {
+            offset = align_to(offset, 16) + 16;
+            ArgLocation::Stack(offset - 16)
+        }
+        _ => {
+            offset += 8;
+            ArgLocation::Stack(offset - 8)
+        }
+    }).collect();
+
+    (locations, align_to(offset, 16))
+}
+
+// 尾调用的栈参数写到调用者收到的栈参数的位置，不能比调用者的多
+pub fn tail_call_fits(caller: &[Ty], callee: &[Ty]) -> bool {
+    classify_arguments(callee).1 <= classify_arguments(caller).1
+}
+
+/*
+ * 可变参数函数的寄存器保存区
+ * 保存区相对 sp 的位置
+ * va_list 中 __gr_offs 和 __vr_offs 的初值 (负数，表示保存区中还剩多少)
+ * 第一个未命名栈参数相对 x29 的位置
+*/
+#[derive(Debug, Clone, Default)]
+pub struct VaArea {
+    pub save_offset: isize,
+    pub gr_offs: isize,
+    pub vr_offs: isize,
+    pub stack_offset: isize,
+}
+
+/*
+ * 一个函数的栈帧
+ * 每个栈槽相对 sp 的偏移
+ * 每个值相对 sp 的偏移，已经被删除的值没有位置
+ * 每个参数传进来的位置
+ * 调用者自己的栈参数的大小，尾调用的栈参数不能比它多
+ * 序言中 sp 要减去的大小 (16字节对齐)
+ * 可变参数的保存区
+ * phi 赋值用的临时位置
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub slots: Vec<isize>,
+    pub values: Vec<Option<isize>>,
+    pub params: Vec<ArgLocation>,
+    pub incoming_size: isize,
+    pub size: isize,
+    pub va_area: Option<VaArea>,
+    pub scratch: isize,
+}
+
+/*
+ * 计算函数的栈帧
+ * 值在寄存器中都是64位的，每个值占8字节，long double 占16字节
+*/
+pub fn layout(function: &Function) -> Frame {
+    let types: Vec<Ty> = function.params.iter().map(|p| function.ty(*p)).collect();
+    let (params, incoming_size) = classify_arguments(&types);
+    let mut used = outgoing_size(function, |types, _| classify_arguments(types).1);
+
+    let scratch = allocate(&mut used, 16, 16);
+
+    let slots = function.slots.iter().map(|slot| {
+        allocate(&mut used, slot.size as isize, slot.align as isize)
+    }).collect();
+
+    let defined = function.def_blocks();
+    let values = function.values.iter().zip(defined.iter()).map(|(ty, def)| {
+        def.map(|_| {
+            let size = ty.size().max(8) as isize;
+            allocate(&mut used, size, size)
+        })
+    }).collect();
+
+    let mut size = align_to(used, 16);
+
+    // 保存区在栈帧的最上面，紧挨着 x29
+    let va_area = if function.variadic {
+        let gp = params.iter().filter(|l| matches!(l, ArgLocation::Gp(_))).count() as isize;
+        let fp = params.iter().filter(|l| matches!(l, ArgLocation::Fp(_))).count() as isize;
+        // 命名的栈参数之后就是未命名的栈参数
+        let named_stack = types.iter().zip(params.iter()).map(|(t, l)| match l {
+            ArgLocation::Stack(offset) => offset + t.size().max(8) as isize,
+            _ => 0,
+        }).max().unwrap_or(0);
+
+        let area = VaArea {
+            save_offset: size,
+            gr_offs: -(GP_ARG_REGS as isize - gp) * 8,
+            vr_offs: -(FP_ARG_REGS as isize - fp) * 16,
+            stack_offset: 16 + named_stack,
+        };
+        size += VA_SAVE_SIZE;
+        Some(area)
+    } else {
+        None
+    };
+
+    Frame {
+        slots,
+        values,
+        params,
+        incoming_size,
+        size,
+        va_area,
+        scratch,
+    }
+}
@@ -0,0 +1,815 @@
+use super::float::{float_bits, quad_bits};
+use super::generator::{add_suffix, unique_suffix};
+use super::ir::*;
+use super::ir::phi::{has_phi, phi_moves, sequence};
+
+pub mod frame;
+
+use self::frame::{classify_arguments, layout, ArgLocation, Frame};
+
+/*
+ * AArch64 的代码生成
+ * 和 x86-64 共用前端和中间表示，这里只把 IR 翻译成 GNU as 的 AArch64 汇编
+ * 每个值在栈帧中有固定的位置，指令把操作数读到临时寄存器，算完再写回去
+ * 整数: 第一个操作数和结果 x9，第二个操作数 x10
+ * 浮点数: 第一个操作数和结果 s0 d0 q0，第二个操作数 s1 d1 q1
+ * x16 用来计算放不进指令的地址
+ * long double 是 IEEE 四精度，运算和转换调用 libgcc 的软件浮点函数
+*/
+
+/*
+ * 生成一个函数时的上下文
+ * 正在生成的函数
+ * 栈帧布局
+ * 已经生成的指令 (每行一条)
+ * 函数用到的只读数据 (浮点常量)，放在函数的指令之后
+*/
+struct Context<'a> {
+    function: &'a Function,
+    frame: Frame,
+    code: Vec<String>,
+    data: Vec<String>,
+}
+
+impl<'a> Context<'a> {
+    fn emit(&mut self, instr: String) {
+        self.code.push(format!("  {}", instr));
+    }
+
+    fn emit_label(&mut self, label: String) {
+        self.code.push(format!("{}:", label));
+    }
+
+    fn ty(&self, v: Value) -> Ty {
+        self.function.ty(v)
+    }
+
+    // 值相对 sp 的位置
+    fn home(&self, v: Value) -> isize {
+        self.frame.values[v.0].unwrap_or_else(|| unreachable!("Value {} has no location", v))
+    }
+
+    // 基本块的标签
+    fn label(&self, block: BlockId) -> String {
+        format!(".L{}.bb{}", self.function.name, block.0)
+    }
+
+    /*
+     * [base, #offset] 形式的地址
+     * ldr str 的偏移是无符号的，要是访问大小的倍数，不超过 4095 倍
+     * 放不下时先把地址算到 x16 中
+    */
+    fn memory(&mut self, base: &str, offset: isize, size: usize) -> String {
+        let size = size as isize;
+        if offset >= 0 && offset % size == 0 && offset / size <= 4095 {
+            format!("[{}, #{}]", base, offset)
+        } else {
+            self.emit_immediate("x16", offset as i64);
+            self.emit(format!("add x16, {}, x16", base));
+            "[x16]".to_string()
+        }
+    }
+
+    // 把 base+offset 算到 reg 中
+    fn emit_address(&mut self, reg: &str, base: &str, offset: isize) {
+        if (0..=4095).contains(&offset) {
+            self.emit(format!("add {}, {}, #{}", reg, base, offset));
+        } else {
+            self.emit_immediate(reg, offset as i64);
+            self.emit(format!("add {}, {}, {}", reg, base, reg));
+        }
+    }
+
+    /*
+     * 把常量放到寄存器中
+     * 16位能表示的直接 mov，否则 movz 之后用 movk 逐段填入
+    */
+    fn emit_immediate(&mut self, reg: &str, n: i64) {
+        if (-65536..65536).contains(&n) {
+            self.emit(format!("mov {}, #{}", reg, n));
+            return;
+        }
+        let bits = n as u64;
+        self.emit(format!("movz {}, #{:#x}", reg, bits & 0xffff));
+        for i in 1..4 {
+            let chunk = (bits >> (16 * i)) & 0xffff;
+            if chunk != 0 {
+                self.emit(format!("movk {}, #{:#x}, lsl #{}", reg, chunk, 16 * i));
+            }
+        }
+    }
+
+    // 从 base+offset 读出 t 类型的数到寄存器
+    fn emit_load(&mut self, t: Ty, reg: &str, base: &str, offset: isize) {
+        let address = self.memory(base, offset, t.size());
+        match t {
+            Ty::I8 => self.emit(format!("ldrsb {}, {}", reg, address)),
+            _ => self.emit(format!("ldr {}, {}", reg, address)),
+        }
+    }
+
+    // 把寄存器中 t 类型的数写到 base+offset
+    fn emit_store(&mut self, t: Ty, reg: &str, base: &str, offset: isize) {
+        let address = self.memory(base, offset, t.size());
+        match t {
+            Ty::I8 => self.emit(format!("strb {}, {}", word(reg), address)),
+            _ => self.emit(format!("str {}, {}", reg, address)),
+        }
+    }
+
+    // 把值读到 n 号寄存器 (整数 xn，浮点数 sn dn qn)
+    fn load_value(&mut self, v: Value, n: u8) {
+        let t = register_type(self.ty(v));
+        let offset = self.home(v);
+        self.emit_load(t, &register(t, n), "sp", offset);
+    }
+
+    // n 号寄存器中的结果写回值的位置
+    fn store_value(&mut self, v: Value, n: u8) {
+        let t = register_type(self.ty(v));
+        let offset = self.home(v);
+        self.emit_store(t, &register(t, n), "sp", offset);
+    }
+
+    // 读取第一个操作数: x9 / s0 d0 q0
+    fn load_first(&mut self, v: Value) {
+        self.load_value(v, first(self.ty(v)));
+    }
+
+    // 读取第二个操作数: x10 / s1 d1 q1
+    fn load_second(&mut self, v: Value) {
+        self.load_value(v, first(self.ty(v)) + 1);
+    }
+
+    // 结果写回值的位置
+    fn store_result(&mut self, v: Value) {
+        self.store_value(v, first(self.ty(v)));
+    }
+}
+
+/*
+ * 值在寄存器中的类型
+ * char 在寄存器中按64位处理
+*/
+fn register_type(ty: Ty) -> Ty {
+    match ty {
+        Ty::I8 => Ty::I64,
+        t => t,
+    }
+}
+
+// 第一个操作数所在寄存器的编号，整数是 x9，浮点数是 v0
+fn first(ty: Ty) -> u8 {
+    if ty.is_float() { 0 } else { 9 }
+}
+
+// n 号寄存器按类型的名字
+fn register(ty: Ty, n: u8) -> String {
+    match ty {
+        Ty::F32 => format!("s{}", n),
+        Ty::F64 => format!("d{}", n),
+        Ty::F80 => format!("q{}", n),
+        _ => format!("x{}", n),
+    }
+}
+
+// 64位寄存器的低32位
+fn word(reg: &str) -> String {
+    reg.replacen('x', "w", 1)
+}
+
+/*
+ * 层级遍历
+ * Module->Function
+ * 返回整个汇编文件
+*/
+pub fn generate(module: &Module) -> Result<String, String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    // 字符串常量
+    if !module.strings.is_empty() {
+        lines.push("  .section .rodata".to_string());
+        for (i, s) in module.strings.iter().enumerate() {
+            let bytes: Vec<String> = s.iter().chain(std::iter::once(&0)).map(|b| b.to_string()).collect();
+            lines.push(format!(".LS{}:", i));
+            lines.push(format!("  .byte {}", bytes.join(",")));
+        }
+    }
+    lines.push("  .text".to_string());
+
+    for function in module.functions.iter() {
+        lines.extend(generate_function(function)?);
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+/*
+ * 层级遍历
+ * Function->Block
+ * 序言保存 x29 x30，x29 指向它们，再给栈帧留出空间
+*/
+fn generate_function(function: &Function) -> Result<Vec<String>, String> {
+    let mut context = Context {
+        function,
+        frame: layout(function),
+        code: Vec::new(),
+        data: Vec::new(),
+    };
+
+    // static 函数只在本文件中可见
+    if !function.specifiers.is_static {
+        context.code.push(format!(".global {}", function.name));
+    }
+    context.code.push("  .p2align 2".to_string());
+    context.emit_label(function.name.clone());
+    context.emit("stp x29, x30, [sp, #-16]!".to_string());
+    context.emit("mov x29, sp".to_string());
+
+    let size = context.frame.size;
+    if size > 4095 {
+        context.emit_immediate("x16", size as i64);
+        context.emit("sub sp, sp, x16".to_string());
+    } else if size > 0 {
+        context.emit(format!("sub sp, sp, #{}", size));
+    }
+
+    generate_parameters(&mut context);
+
+    for b in function.block_ids() {
+        let label = context.label(b);
+        context.emit_label(label);
+        let block = &function.blocks[b.0];
+        for instr in block.instrs.iter() {
+            generate_instr(instr, &mut context);
+        }
+        generate_terminator(b, &block.terminator, &mut context)?;
+    }
+
+    let mut code = context.code;
+    if !context.data.is_empty() {
+        code.push("  .section .rodata".to_string());
+        code.extend(context.data);
+        code.push("  .text".to_string());
+    }
+    Ok(code)
+}
+
+/*
+ * 参数保存到栈帧中
+ * 可变参数函数先把所有参数寄存器存到保存区，供 va_arg 使用
+*/
+fn generate_parameters(context: &mut Context) {
+    if let Some(va_area) = context.frame.va_area.clone() {
+        for i in 0..8 {
+            context.emit_store(Ty::I64, &format!("x{}", i), "sp", va_area.save_offset + i as isize * 8);
+        }
+        for i in 0..8 {
+            context.emit_store(Ty::F80, &format!("q{}", i), "sp", va_area.save_offset + 64 + i as isize * 16);
+        }
+    }
+
+    let function = context.function;
+    for (param, location) in function.params.iter().zip(context.frame.params.clone()) {
+        let ty = context.ty(*param);
+        match location {
+            // char 只有低8位是有效的
+            ArgLocation::Gp(r) => {
+                if ty == Ty::I8 {
+                    context.emit(format!("sxtb x{}, w{}", r, r));
+                }
+                context.store_value(*param, r as u8);
+            }
+            ArgLocation::Fp(r) => context.store_value(*param, r as u8),
+            // 保存的 x29 x30 之后就是栈参数
+            ArgLocation::Stack(offset) => {
+                context.emit_load(ty, &register(register_type(ty), first(ty)), "x29", 16 + offset);
+                context.store_result(*param);
+            }
+        }
+    }
+}
+
+fn generate_instr(instr: &Instr, context: &mut Context) {
+    match instr {
+        Instr::Const(v, n) => {
+            context.emit_immediate("x9", *n);
+            context.store_result(*v);
+        }
+
+        Instr::FloatConst(v, f) => {
+            // 浮点常量放在 .rodata 中
+            let t = context.ty(*v);
+            let label = add_suffix(".LC", &unique_suffix());
+            let (align, words) = match t {
+                Ty::F32 => (2, vec![format!(".word {:#x}", float_bits(f, t) as u32)]),
+                Ty::F64 => (3, vec![format!(".quad {:#x}", float_bits(f, t) as u64)]),
+                _ => {
+                    let bits = quad_bits(f);
+                    (4, vec![format!(".quad {:#x}", bits as u64), format!(".quad {:#x}", (bits >> 64) as u64)])
+                }
+            };
+            context.data.push(format!("  .p2align {}", align));
+            context.data.push(format!("{}:", label));
+            context.data.extend(words.into_iter().map(|w| format!("  {}", w)));
+
+            context.emit(format!("adrp x16, {}", label));
+            context.emit(format!("ldr {}, [x16, :lo12:{}]", register(t, 0), label));
+            context.store_result(*v);
+        }
+
+        Instr::StringAddr(v, index) => {
+            context.emit(format!("adrp x9, .LS{}", index));
+            context.emit(format!("add x9, x9, :lo12:.LS{}", index));
+            context.store_result(*v);
+        }
+
+        Instr::SlotAddr(v, slot) => {
+            let offset = context.frame.slots[slot.0];
+            context.emit_address("x9", "sp", offset);
+            context.store_result(*v);
+        }
+
+        Instr::Load(v, slot) => {
+            let t = context.ty(*v);
+            let offset = context.frame.slots[slot.0];
+            context.emit_load(t, &register(register_type(t), first(t)), "sp", offset);
+            context.store_result(*v);
+        }
+
+        Instr::Store(slot, v) => {
+            let t = context.ty(*v);
+            let offset = context.frame.slots[slot.0];
+            context.load_first(*v);
+            context.emit_store(t, &register(register_type(t), first(t)), "sp", offset);
+        }
+
+        Instr::Neg(v, a) => {
+            context.load_first(*a);
+            match context.ty(*a) {
+                Ty::F32 => context.emit("fneg s0, s0".to_string()),
+                Ty::F64 => context.emit("fneg d0, d0".to_string()),
+                // 翻转高64位中的符号位
+                Ty::F80 => {
+                    context.emit("fmov x9, v0.d[1]".to_string());
+                    context.emit("eor x9, x9, #0x8000000000000000".to_string());
+                    context.emit("fmov v0.d[1], x9".to_string());
+                }
+                _ => context.emit("neg x9, x9".to_string()),
+            }
+            context.store_result(*v);
+        }
+
+        Instr::Binary(v, op, a, b) => {
+            let t = context.ty(*a);
+            context.load_first(*a);
+            context.load_second(*b);
+            match t {
+                Ty::F80 => context.emit(format!("bl {}", quad_operator(*op))),
+                Ty::F32 | Ty::F64 => {
+                    let (r, a, b) = (register(t, 0), register(t, 0), register(t, 1));
+                    context.emit(format!("{} {}, {}, {}", float_operator(*op), r, a, b));
+                }
+                _ => generate_integer_operator(*op, context),
+            }
+            context.store_result(*v);
+        }
+
+        Instr::Compare(v, op, a, b) => {
+            let t = context.ty(*a);
+            context.load_first(*a);
+            context.load_second(*b);
+            let condition = match t {
+                // 比较函数返回的整数和0比较
+                Ty::F80 => {
+                    context.emit(format!("bl {}", quad_compare(*op)));
+                    context.emit("cmp w0, #0".to_string());
+                    integer_condition(*op)
+                }
+                Ty::F32 | Ty::F64 => {
+                    context.emit(format!("fcmp {}, {}", register(t, 0), register(t, 1)));
+                    float_condition(*op)
+                }
+                _ => {
+                    context.emit("cmp x9, x10".to_string());
+                    integer_condition(*op)
+                }
+            };
+            context.emit(format!("cset x9, {}", condition));
+            context.store_result(*v);
+        }
+
+        Instr::Convert(v, a) => {
+            context.load_first(*a);
+            generate_convert(context.ty(*a), context.ty(*v), context);
+            context.store_result(*v);
+        }
+
+        Instr::Call(v, name, args, _) => generate_call(*v, name, args, context),
+
+        /*
+         * va_list 的结构
+         * __stack(8) __gr_top(8) __vr_top(8) __gr_offs(4) __vr_offs(4)
+         * gr_top vr_top 是两个保存区的末尾，offs 是负的偏移，用完时变成0
+        */
+        Instr::VaStart(ap) => {
+            let va_area = context.frame.va_area.clone().expect("va_start outside variadic function");
+            context.load_first(*ap);
+            context.emit_address("x10", "x29", va_area.stack_offset);
+            context.emit("str x10, [x9]".to_string());
+            context.emit("sub x10, x29, #128".to_string());
+            context.emit("str x10, [x9, #8]".to_string());
+            context.emit("str x29, [x9, #16]".to_string());
+            context.emit(format!("mov w10, #{}", va_area.gr_offs));
+            context.emit("str w10, [x9, #24]".to_string());
+            context.emit(format!("mov w10, #{}", va_area.vr_offs));
+            context.emit("str w10, [x9, #28]".to_string());
+        }
+
+        Instr::VaArg(v, ap) => {
+            context.load_first(*ap);
+            generate_va_arg(context.ty(*v), context);
+            context.store_result(*v);
+        }
+
+        Instr::MemCopy(dest, src, size) => {
+            context.load_value(*dest, 9);
+            context.load_value(*src, 10);
+            for offset in (0..*size).step_by(8) {
+                context.emit(format!("ldr x11, [x10, #{}]", offset));
+                context.emit(format!("str x11, [x9, #{}]", offset));
+            }
+        }
+
+        // phi 在前驱跳转过来之前赋值
+        Instr::Phi(_, _) => {}
+    }
+}
+
+/*
+ * 整数运算，左边在 x9，右边在 x10，结果在 x9
+ * 取余用商乘回去再相减
+*/
+fn generate_integer_operator(op: BinOp, context: &mut Context) {
+    let name = match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "sub",
+        BinOp::Mul => "mul",
+        BinOp::Div => "sdiv",
+        BinOp::And => "and",
+        BinOp::Or => "orr",
+        BinOp::Xor => "eor",
+        BinOp::Shl => "lsl",
+        BinOp::Shr => "asr",
+        BinOp::Rem => {
+            context.emit("sdiv x11, x9, x10".to_string());
+            context.emit("msub x9, x11, x10, x9".to_string());
+            return;
+        }
+    };
+    context.emit(format!("{} x9, x9, x10", name));
+}
+
+fn float_operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "fadd",
+        BinOp::Sub => "fsub",
+        BinOp::Mul => "fmul",
+        BinOp::Div => "fdiv",
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
+
+// long double 的运算函数，参数在 q0 q1，结果在 q0
+fn quad_operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "__addtf3",
+        BinOp::Sub => "__subtf3",
+        BinOp::Mul => "__multf3",
+        BinOp::Div => "__divtf3",
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
+
+/*
+ * long double 的比较函数，返回值和0比较的结果就是比较的结果
+ * 有 NaN 时它们返回使比较不成立 (不等成立) 的值
+*/
+fn quad_compare(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "__eqtf2",
+        CmpOp::Ne => "__netf2",
+        CmpOp::Lt => "__lttf2",
+        CmpOp::Le => "__letf2",
+        CmpOp::Gt => "__gttf2",
+        CmpOp::Ge => "__getf2",
+    }
+}
+
+fn integer_condition(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "eq",
+        CmpOp::Ne => "ne",
+        CmpOp::Lt => "lt",
+        CmpOp::Le => "le",
+        CmpOp::Gt => "gt",
+        CmpOp::Ge => "ge",
+    }
+}
+
+/*
+ * fcmp 之后的条件
+ * 无序 (NaN) 时 NZCV 是 0011，< <= 用 mi ls 才能在无序时不成立
+*/
+fn float_condition(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "eq",
+        CmpOp::Ne => "ne",
+        CmpOp::Lt => "mi",
+        CmpOp::Le => "ls",
+        CmpOp::Gt => "gt",
+        CmpOp::Ge => "ge",
+    }
+}
+
+/*
+ * 类型转换
+ * 值在 x9 / s0 d0 q0 之间移动
+ * 整数和指针都在 x9 中，转换到 char 时截断
+ * 和 long double 之间的转换调用 libgcc
+*/
+fn generate_convert(from: Ty, to: Ty, context: &mut Context) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (Ty::F32, Ty::F64) => context.emit("fcvt d0, s0".to_string()),
+        (Ty::F64, Ty::F32) => context.emit("fcvt s0, d0".to_string()),
+        (Ty::F32, Ty::F80) => context.emit("bl __extendsftf2".to_string()),
+        (Ty::F64, Ty::F80) => context.emit("bl __extenddftf2".to_string()),
+        (Ty::F80, Ty::F32) => context.emit("bl __trunctfsf2".to_string()),
+        (Ty::F80, Ty::F64) => context.emit("bl __trunctfdf2".to_string()),
+
+        (_, Ty::F32) => context.emit("scvtf s0, x9".to_string()),
+        (_, Ty::F64) => context.emit("scvtf d0, x9".to_string()),
+        (_, Ty::F80) => {
+            context.emit("mov x0, x9".to_string());
+            context.emit("bl __floatditf".to_string());
+        }
+
+        _ => {
+            match from {
+                Ty::F32 => context.emit("fcvtzs x9, s0".to_string()),
+                Ty::F64 => context.emit("fcvtzs x9, d0".to_string()),
+                Ty::F80 => {
+                    context.emit("bl __fixtfdi".to_string());
+                    context.emit("mov x9, x0".to_string());
+                }
+                _ => {}
+            }
+            if to == Ty::I8 {
+                context.emit("sxtb x9, w9".to_string());
+            }
+        }
+    }
+}
+
+/*
+ * 从 va_list (地址在 x9) 中取出下一个参数
+ * 整数从 x0-x7 的保存区取，浮点数从 q0-q7 的保存区取
+ * offs 不小于0说明保存区用完了，从 __stack 取，long double 在栈上16字节对齐
+*/
+fn generate_va_arg(t: Ty, context: &mut Context) {
+    let suffix = unique_suffix();
+    let stack_label = add_suffix(".Lva_stack", &suffix);
+    let load_label = add_suffix(".Lva_load", &suffix);
+    let (top, offs, step) = if t.is_float() { (16, 28, 16) } else { (8, 24, 8) };
+
+    context.emit(format!("ldrsw x10, [x9, #{}]", offs));
+    context.emit("cmp x10, #0".to_string());
+    context.emit(format!("b.ge {}", stack_label));
+    context.emit(format!("add x11, x10, #{}", step));
+    context.emit(format!("str w11, [x9, #{}]", offs));
+    context.emit(format!("ldr x12, [x9, #{}]", top));
+    context.emit("add x12, x12, x10".to_string());
+    context.emit(format!("b {}", load_label));
+
+    context.emit_label(stack_label);
+    context.emit("ldr x12, [x9]".to_string());
+    if t == Ty::F80 {
+        context.emit("add x12, x12, #15".to_string());
+        context.emit("and x12, x12, #-16".to_string());
+    }
+    context.emit(format!("add x11, x12, #{}", t.size().max(8)));
+    context.emit("str x11, [x9]".to_string());
+
+    context.emit_label(load_label);
+    context.emit_load(t, &register(register_type(t), first(t)), "x12", 0);
+}
+
+/*
+ * 函数调用
+ * 栈参数写在栈帧底部预留的空间，sp 不需要调整
+ * 返回值在 x0 / s0 d0 q0
+*/
+fn generate_call(dest: Option<Value>, name: &str, args: &[Value], context: &mut Context) {
+    generate_arguments(args, "sp", 0, context);
+    context.emit(format!("bl {}", name));
+
+    if let Some(v) = dest {
+        // 返回的 char 只有低8位是有效的
+        if context.ty(v) == Ty::I8 {
+            context.emit("sxtb x0, w0".to_string());
+        }
+        context.store_value(v, 0);
+    }
+}
+
+/*
+ * 尾调用
+ * 栈参数写到调用者自己收到栈参数的位置 [x29+16+偏移]，然后恢复栈帧跳到被调用的函数
+ * 语义检查和尾调用优化已经按 AArch64 的调用约定检查过，放不下时报错，不能退回普通的调用
+*/
+fn generate_tail_call(name: &str, args: &[Value], context: &mut Context) -> Result<(), String> {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    if classify_arguments(&types).1 > context.frame.incoming_size {
+        return Err(format!("Arguments of tail call to {} do not fit in the stack arguments of {}", name, context.function.name));
+    }
+    generate_arguments(args, "x29", 16, context);
+    generate_epilogue(context);
+    context.emit(format!("b {}", name));
+    Ok(())
+}
+
+/*
+ * 准备调用的参数
+ * 栈上的参数写到 base+start+偏移，寄存器参数直接从栈帧读到寄存器中
+*/
+fn generate_arguments(args: &[Value], base: &str, start: isize, context: &mut Context) {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    let (locations, _) = classify_arguments(&types);
+
+    // 栈参数要经过 x9 / v0，先于寄存器参数写好
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        if let ArgLocation::Stack(offset) = location {
+            let t = register_type(context.ty(*arg));
+            context.load_first(*arg);
+            context.emit_store(t, &register(t, first(t)), base, start + offset);
+        }
+    }
+
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        match location {
+            ArgLocation::Gp(r) | ArgLocation::Fp(r) => context.load_value(*arg, *r as u8),
+            ArgLocation::Stack(_) => {}
+        }
+    }
+}
+
+/*
+ * 跳到 to 之前给 to 中的 phi 赋值
+ * 每个值都在栈帧中，赋值就是 (目的, 来源, 大小) 的内存复制，环用栈帧中的临时位置打破
+*/
+fn generate_phi_moves(from: BlockId, to: BlockId, context: &mut Context) {
+    let moves = phi_moves(context.function, from, to).into_iter()
+        .map(|(v, a)| (context.home(v), context.home(a), context.ty(v).size().max(8)))
+        .collect();
+    for (dest, src, size) in sequence(moves, context.frame.scratch) {
+        generate_copy(dest, src, size, context);
+    }
+}
+
+// 栈帧中的复制，16字节的经过 q0
+fn generate_copy(dest: isize, src: isize, size: usize, context: &mut Context) {
+    let t = if size == 16 { Ty::F80 } else { Ty::I64 };
+    let reg = register(t, first(t));
+    context.emit_load(t, &reg, "sp", src);
+    context.emit_store(t, &reg, "sp", dest);
+}
+
+fn generate_terminator(block: BlockId, terminator: &Terminator, context: &mut Context) -> Result<(), String> {
+    match terminator {
+        Terminator::Jump(target) => {
+            generate_phi_moves(block, *target, context);
+            context.emit(format!("b {}", context.label(*target)));
+        }
+
+        Terminator::Branch(c, if_true, if_false) => {
+            // 目标有 phi 时需要先在这条边上赋值
+            let false_edge = if has_phi(context.function, *if_false) {
+                format!("{}.to{}", context.label(block), if_false.0)
+            } else {
+                context.label(*if_false)
+            };
+            context.load_first(*c);
+            context.emit(format!("cbz x9, {}", false_edge));
+            generate_phi_moves(block, *if_true, context);
+            context.emit(format!("b {}", context.label(*if_true)));
+
+            if has_phi(context.function, *if_false) {
+                context.emit_label(false_edge);
+                generate_phi_moves(block, *if_false, context);
+                context.emit(format!("b {}", context.label(*if_false)));
+            }
+        }
+
+        Terminator::Return(v) => {
+            // 返回值放在 x0 / s0 d0 q0
+            if let Some(v) = v {
+                context.load_value(*v, 0);
+            }
+            generate_epilogue(context);
+            context.emit("ret".to_string());
+        }
+
+        Terminator::TailCall(name, args, _) => return generate_tail_call(name, args, context),
+    }
+    Ok(())
+}
+
+// 恢复调用者的栈帧
+fn generate_epilogue(context: &mut Context) {
+    context.emit("mov sp, x29".to_string());
+    context.emit("ldp x29, x30, [sp], #16".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::super::driver::analyze;
+    use super::super::options::{Arch, Options};
+    use super::super::target::target;
+
+    // 调用、变参函数、浮点数和循环
+    const PROGRAM: &str = "#include <stdarg.h>
+        int printf(char *format, ...);
+        double scale(double x, float y) { return x * y + 0.5; }
+        int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+        double average(int n, ...) {
+            va_list ap;
+            va_start(ap, n);
+            double s = 0;
+            for (int i = 0; i < n; i = i + 1) s = s + va_arg(ap, double);
+            va_end(ap);
+            return s / n;
+        }
+        int main() {
+            int total = 0;
+            for (int i = 0; i < 10; i = i + 1) {
+                if (i % 3 == 0) continue;
+                total = total + i * i;
+            }
+            printf(\"%d %d %.2f %.3f %s\\n\", total, fib(15), scale(2.5, 3.0f), average(3, 1.0, 2.5, 4.0), \"ok\");
+            return total % 100;
+        }";
+
+    fn found(tool: &str) -> bool {
+        Command::new(tool).arg("--version").output().is_ok()
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("my_rcc_aarch64_{}_{}", std::process::id(), name))
+    }
+
+    /*
+     * 按 -O0 和 -O2 生成汇编，有 llvm-mc 时汇编成目标文件
+     * 再有 aarch64-linux-gnu-gcc 和 qemu-aarch64 时链接并运行，比较输出和退出码
+    */
+    #[test]
+    fn compiles_calls_varargs_floats_and_loops() {
+        for opt_level in [0, 2] {
+            let options = Options { input: "test.c".to_string(), target: Arch::Aarch64, opt_level, ..Options::default() };
+            let assembly = target(Arch::Aarch64).assembly(&analyze(PROGRAM, &options), &options).unwrap();
+            assert!(assembly.contains("main:"));
+            if !found("llvm-mc") {
+                eprintln!("llvm-mc not found, assembly is not checked");
+                continue;
+            }
+
+            let (source, object, executable) = (temp(&format!("{}.s", opt_level)), temp(&format!("{}.o", opt_level)), temp(&opt_level.to_string()));
+            fs::write(&source, &assembly).unwrap();
+            let assembled = Command::new("llvm-mc").arg("-triple=aarch64-linux-gnu").arg("-filetype=obj").arg("-o").arg(&object).arg(&source).output().unwrap();
+            let linked = assembled.status.success() && found("qemu-aarch64") && found("aarch64-linux-gnu-gcc")
+                && Command::new("aarch64-linux-gnu-gcc").arg("-static").arg("-o").arg(&executable).arg(&object).status().unwrap().success();
+            let output = if linked { Some(Command::new("qemu-aarch64").arg(&executable).output().unwrap()) } else { None };
+            for path in [&source, &object, &executable] {
+                let _ = fs::remove_file(path);
+            }
+
+            assert!(assembled.status.success(), "-O{}: {}", opt_level, String::from_utf8_lossy(&assembled.stderr));
+            match output {
+                Some(output) => {
+                    assert_eq!(String::from_utf8_lossy(&output.stdout), "159 610 8.00 2.500 ok\n");
+                    assert_eq!(output.status.code(), Some(59));
+                }
+                None => eprintln!("qemu-aarch64 or aarch64-linux-gnu-gcc not found, the program is not run"),
+            }
+        }
+    }
+}
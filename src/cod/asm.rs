@@ -0,0 +1,256 @@
+/*
+ * 生成的 x86-64 指令
+ * 生成器先得到指令序列，经过窥孔优化之后再按选择的语法输出 (见 dialect)
+ * 只包含生成器用到的指令和操作数形式
+*/
+
+// 通用寄存器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+// 操作数的大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Dword,
+    Qword,
+    Tbyte,
+}
+
+/*
+ * 内存地址
+ * 寄存器加偏移 [rbp-8]
+ * 相对 rip 的标签 [rip+.LC0]
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Base(Reg, isize),
+    Rip(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Reg, Size),
+    Xmm(u8),
+    Imm(i64),
+    Mem(Size, Address),
+}
+
+pub const RAX: Operand = Operand::Reg(Reg::Rax, Size::Qword);
+pub const RCX: Operand = Operand::Reg(Reg::Rcx, Size::Qword);
+pub const RDX: Operand = Operand::Reg(Reg::Rdx, Size::Qword);
+pub const RDI: Operand = Operand::Reg(Reg::Rdi, Size::Qword);
+pub const RSP: Operand = Operand::Reg(Reg::Rsp, Size::Qword);
+pub const RBP: Operand = Operand::Reg(Reg::Rbp, Size::Qword);
+pub const EAX: Operand = Operand::Reg(Reg::Rax, Size::Dword);
+pub const EDX: Operand = Operand::Reg(Reg::Rdx, Size::Dword);
+pub const AL: Operand = Operand::Reg(Reg::Rax, Size::Byte);
+pub const DL: Operand = Operand::Reg(Reg::Rdx, Size::Byte);
+pub const XMM0: Operand = Operand::Xmm(0);
+pub const XMM1: Operand = Operand::Xmm(1);
+
+// 条件码，用于 jcc setcc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+    A,
+    Ae,
+    B,
+    Be,
+    P,
+    Np,
+}
+
+// 两个操作数的整数运算，结果写到左边 (cmp 只设置标志)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+    Cmp,
+}
+
+// 移位，位数在 cl 中或者是立即数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Shl,
+    Sar,
+}
+
+// SSE 指令，都是 目的, 来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SseOp {
+    Movss,
+    Movsd,
+    Movd,
+    Movq,
+    Addss,
+    Addsd,
+    Subss,
+    Subsd,
+    Mulss,
+    Mulsd,
+    Divss,
+    Divsd,
+    Ucomiss,
+    Ucomisd,
+    Xorps,
+    Xorpd,
+    Cvtss2sd,
+    Cvtsd2ss,
+    Cvtsi2ss,
+    Cvtsi2sd,
+    Cvttss2si,
+    Cvttsd2si,
+}
+
+// x87 运算: st(1) = st(1) op st(0)，然后弹出 st(0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X87Op {
+    Faddp,
+    Fsubp,
+    Fmulp,
+    Fdivp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    Global(String),
+    Text,
+    Rodata,
+    Align(u32), // 按 2 的幂对齐
+    Byte(Vec<u8>),
+    Long(u32),
+    Quad(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    Label(String),
+    Directive(Directive),
+    Mov(Operand, Operand),
+    Movsx(Operand, Operand),
+    Movzx(Operand, Operand),
+    Lea(Operand, Address),
+    Push(Operand),
+    Pop(Operand),
+    Alu(AluOp, Operand, Operand),
+    Neg(Operand),
+    Shift(ShiftOp, Operand),
+    ShiftImm(ShiftOp, Operand, u8),
+    Cqo,
+    Idiv(Operand),
+    Set(Cond, Operand),
+    Jmp(String),
+    Jcc(Cond, String),
+    Call(String),
+    Ret,
+    Syscall,
+    Sse(SseOp, Operand, Operand),
+    Fld(Operand),
+    Fild(Operand),
+    Fstp(Operand),
+    Fisttp(Operand),
+    Fchs,
+    Fxch, // 交换 st(0) st(1)
+    Fucomip, // 比较 st(0) st(1)，弹出 st(0)
+    FstpTop, // 弹出 st(0) 并丢弃
+    X87(X87Op),
+}
+
+impl Reg {
+    pub fn qword(self) -> Operand {
+        Operand::Reg(self, Size::Qword)
+    }
+
+    pub fn byte(self) -> Operand {
+        Operand::Reg(self, Size::Byte)
+    }
+
+    // 寄存器在各个大小下的名字
+    pub fn name(self, size: Size) -> &'static str {
+        const NAMES: [[&str; 3]; 16] = [
+            ["rax", "eax", "al"],
+            ["rcx", "ecx", "cl"],
+            ["rdx", "edx", "dl"],
+            ["rbx", "ebx", "bl"],
+            ["rsp", "esp", "spl"],
+            ["rbp", "ebp", "bpl"],
+            ["rsi", "esi", "sil"],
+            ["rdi", "edi", "dil"],
+            ["r8", "r8d", "r8b"],
+            ["r9", "r9d", "r9b"],
+            ["r10", "r10d", "r10b"],
+            ["r11", "r11d", "r11b"],
+            ["r12", "r12d", "r12b"],
+            ["r13", "r13d", "r13b"],
+            ["r14", "r14d", "r14b"],
+            ["r15", "r15d", "r15b"],
+        ];
+        let column = match size {
+            Size::Qword | Size::Tbyte => 0,
+            Size::Dword => 1,
+            Size::Byte => 2,
+        };
+        NAMES[self as usize][column]
+    }
+}
+
+impl Operand {
+    pub fn is_memory(&self) -> bool {
+        matches!(self, Operand::Mem(_, _))
+    }
+
+    // 读写这个操作数是否会用到寄存器 r (作为值或者地址)
+    pub fn uses(&self, r: Reg) -> bool {
+        match self {
+            Operand::Reg(reg, _) | Operand::Mem(_, Address::Base(reg, _)) => *reg == r,
+            _ => false,
+        }
+    }
+}
+
+impl Cond {
+    // 相反的条件
+    pub fn negate(self) -> Cond {
+        match self {
+            Cond::E => Cond::Ne,
+            Cond::Ne => Cond::E,
+            Cond::L => Cond::Ge,
+            Cond::Le => Cond::G,
+            Cond::G => Cond::Le,
+            Cond::Ge => Cond::L,
+            Cond::A => Cond::Be,
+            Cond::Ae => Cond::B,
+            Cond::B => Cond::Ae,
+            Cond::Be => Cond::A,
+            Cond::P => Cond::Np,
+            Cond::Np => Cond::P,
+        }
+    }
+}
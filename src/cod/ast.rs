@@ -1,4 +1,5 @@
 use super::token::*;
+use super::types::{FunctionSpecifiers, Signature, Type};
 // 抽象语法树
 
 // 源代码中只有函数
@@ -12,12 +13,14 @@ pub enum Ast { // 直接将语法树变成dag(有向无环图)
 
 /*
  * 函数名
- * 参数
+ * 函数签名 返回类型 参数类型 ...
+ * 说明符 static inline __attribute__
+ * 参数名称
  * 函数内元素
 */
 #[derive(Debug)]
 pub enum AstNode {
-    AstNode(String, Vec<String>, Option<Vec<Item>>),
+    AstNode(String, Signature, FunctionSpecifiers, Vec<String>, Option<Vec<Item>>),
 }
 
 /*
@@ -31,12 +34,13 @@ pub enum Item {
 }
 
 /*
+ * 类型
  * 名称
  * 值
 */
 #[derive(Debug)]
 pub enum Declaration {
-    Declaration(String, Option<Expression>)
+    Declaration(Type, String, Option<Expression>)
 }
 
 /*
@@ -45,7 +49,8 @@ pub enum Declaration {
 #[derive(Debug)]
 pub enum Statement {
     Expression(Option<Expression>), // 表达式语句可能不存在
-    Return(Expression), // return exp
+    Return(Option<Expression>), // return exp 或者 return;
+    TailReturn(Expression), // __attribute__((musttail)) return f(x);
     If(Expression, Box<Statement>, Option<Box<Statement>>), // if
     Compound(Vec<Item>), // += ...
     For(Option<Expression>, Expression, Option<Expression>, Box<Statement>), // for
@@ -63,10 +68,17 @@ pub enum Statement {
 #[derive(Debug)]
 pub enum Expression {
     Constant(i32),
+    FloatConstant(String, Type), // 1.5 1.5f 1.5L
     Variable(String),
     UnaryOperators(Operator, Box<Expression>), // |a
-    AssignmentOperators(Operator, String, Box<Expression>), // ___  __ ___ a = b
+    AssignmentOperators(Operator, Box<Expression>, Box<Expression>), // ___  __ ___ a = b
     BinaryOperators(Operator, Box<Expression>, Box<Expression>), // __ __ __ a + b
     TernaryOperators(Box<Expression>, Box<Expression>, Box<Expression>), // ?:
     FunctionCalls(String, Vec<Expression>), // 函数调用
+    Cast(Type, Box<Expression>), // (double)a
+    StringLiteral(Vec<u8>), // "hello"
+    VaStart(Box<Expression>), // va_start(ap, last)
+    VaArg(Box<Expression>, Type), // va_arg(ap, int)
+    VaEnd(Box<Expression>), // va_end(ap)
+    VaCopy(Box<Expression>, Box<Expression>), // va_copy(dest, src)
 }
\ No newline at end of file
@@ -0,0 +1,546 @@
+use std::collections::HashSet;
+
+use super::token::Operator;
+use super::typed_ast::{Expr, ExprKind, Function, Program, Stmt, VarId};
+use super::types::{FunctionSpecifiers, Signature, Type};
+
+/*
+ * 规范化的 C 源代码 (--emit=c)
+ * 从通过了语义检查的带类型的语法树输出，可以和输入对比，也可以交给别的编译器编译
+ * 隐式类型转换都写成显式的强制转换，和其他后端的语义一样
+ * 同一个函数中有同名的变量时，名字后面加上编号
+ * 调用的函数在调用之前没有定义时先输出原型
+ * 每个运算都加上括号，if 和循环的语句体都加上花括号
+ * for 改写成 while，复合赋值 a op= b 展开成 a = (a op b)
+ * 用到 va_list 时加上 #include <stdarg.h>
+*/
+
+// for 改写成的 while 中，是否已经执行过一次语句体 (之后每次先执行第三个表达式)
+const NEXT: &str = "__next";
+
+/*
+ * 输出的状态
+ * 已经输出的行
+ * 当前的缩进层数
+ * 当前函数中变量的名字和类型
+ * 当前函数的参数，va_start 的第二个参数是最后一个命名参数
+*/
+struct Writer<'a> {
+    lines: Vec<String>,
+    indent: usize,
+    names: Vec<String>,
+    locals: &'a [Type],
+    params: &'a [VarId],
+}
+
+impl Writer<'_> {
+    fn line(&mut self, s: String) {
+        self.lines.push(format!("{:width$}{}", "", s, width = self.indent * 4));
+    }
+}
+
+pub fn generate(program: &Program) -> String {
+    let mut writer = Writer { lines: Vec::new(), indent: 0, names: Vec::new(), locals: &[], params: &[] };
+
+    // 已经有声明的函数
+    let mut declared: HashSet<&str> = HashSet::new();
+    for function in program.functions.iter() {
+        let mut calls = Vec::new();
+        function.body.iter().for_each(|statement| statement_calls(statement, &mut calls));
+        let mut prototypes = Vec::new();
+        for (name, signature) in calls {
+            if !declared.insert(name) {
+                continue;
+            }
+            prototypes.push(match program.functions.iter().find(|f| f.name == name) {
+                Some(callee) => format!("{};", prototype(callee)),
+                None => format!("{};", function_header(name, signature, &FunctionSpecifiers::default(), &[])),
+            });
+        }
+        if !prototypes.is_empty() {
+            if !writer.lines.is_empty() {
+                writer.line(String::new());
+            }
+            prototypes.into_iter().for_each(|p| writer.line(p));
+        }
+
+        if !writer.lines.is_empty() {
+            writer.line(String::new());
+        }
+        declared.insert(&function.name);
+        writer.names = variable_names(function);
+        writer.locals = &function.locals;
+        writer.params = &function.params;
+        let params: Vec<String> = function.params.iter().map(|id| writer.names[*id].clone()).collect();
+        writer.line(format!("{} {{", function_header(&function.name, &function.signature, &function.specifiers, &params)));
+        generate_items(&function.body, &mut writer);
+        writer.line("}".to_string());
+    }
+
+    // 用到 va_ 的地方一定有 va_list 类型的变量或参数
+    let mut out = String::new();
+    if writer.lines.iter().any(|line| line.contains("va_list")) {
+        out.push_str("#include <stdarg.h>\n\n");
+    }
+    out + &writer.lines.join("\n") + "\n"
+}
+
+// 函数的原型 (不带分号)
+pub fn prototype(function: &Function) -> String {
+    let params: Vec<String> = function.params.iter().map(|id| function.names[*id].clone()).collect();
+    function_header(&function.name, &function.signature, &function.specifiers, &params)
+}
+
+// 变量的名字，和同一个函数中其他变量同名时加上编号
+fn variable_names(function: &Function) -> Vec<String> {
+    function.names.iter().enumerate().map(|(id, name)| {
+        match function.names.iter().filter(|n| *n == name).count() {
+            1 => name.clone(),
+            _ => format!("{}_{}", name, id),
+        }
+    }).collect()
+}
+
+// 语句中调用的函数，按出现的顺序
+fn statement_calls<'a>(statement: &'a Stmt, calls: &mut Vec<(&'a str, &'a Signature)>) {
+    match statement {
+        Stmt::Expression(expr) | Stmt::TailCall(expr) | Stmt::Declaration(_, Some(expr)) | Stmt::Return(Some(expr)) => {
+            expression_calls(expr, calls)
+        }
+        Stmt::Declaration(_, None) | Stmt::Return(None) | Stmt::Break | Stmt::Continue => {}
+        Stmt::If(condition, if_body, else_body) => {
+            expression_calls(condition, calls);
+            statement_calls(if_body, calls);
+            if let Some(body) = else_body {
+                statement_calls(body, calls);
+            }
+        }
+        Stmt::Block(statements) => statements.iter().for_each(|s| statement_calls(s, calls)),
+        Stmt::For(init, condition, modifier, body) => {
+            if let Some(init) = init {
+                statement_calls(init, calls);
+            }
+            expression_calls(condition, calls);
+            if let Some(modifier) = modifier {
+                expression_calls(modifier, calls);
+            }
+            statement_calls(body, calls);
+        }
+        Stmt::While(condition, body) | Stmt::DoWhile(body, condition) => {
+            expression_calls(condition, calls);
+            statement_calls(body, calls);
+        }
+    }
+}
+
+fn expression_calls<'a>(expression: &'a Expr, calls: &mut Vec<(&'a str, &'a Signature)>) {
+    match &expression.kind {
+        ExprKind::Call(name, signature, args) => {
+            calls.push((name, signature));
+            args.iter().for_each(|arg| expression_calls(arg, calls));
+        }
+        ExprKind::Constant(_) | ExprKind::FloatConstant(_) | ExprKind::StringLiteral(_) | ExprKind::Variable(_) | ExprKind::Address(_) => {}
+        ExprKind::Unary(_, e) | ExprKind::Cast(e) | ExprKind::VaStart(e) | ExprKind::VaArg(e) | ExprKind::VaEnd(e) => expression_calls(e, calls),
+        ExprKind::Binary(_, a, b) | ExprKind::Assign(a, b) | ExprKind::CompoundAssign(_, a, b) | ExprKind::VaCopy(a, b) => {
+            expression_calls(a, calls);
+            expression_calls(b, calls);
+        }
+        ExprKind::Ternary(a, b, c) => {
+            expression_calls(a, calls);
+            expression_calls(b, calls);
+            expression_calls(c, calls);
+        }
+    }
+}
+
+// static inline __attribute__((noinline)) int f(int a, char *b, ...)，没有参数名时只有类型
+fn function_header(name: &str, signature: &Signature, specifiers: &FunctionSpecifiers, params: &[String]) -> String {
+    let mut header = String::new();
+    if specifiers.is_static {
+        header.push_str("static ");
+    }
+    if specifiers.inline {
+        header.push_str("inline ");
+    }
+    if specifiers.noinline {
+        header.push_str("__attribute__((noinline)) ");
+    }
+    if specifiers.always_inline {
+        header.push_str("__attribute__((always_inline)) ");
+    }
+
+    let mut list: Vec<String> = signature.params.iter().enumerate().map(|(i, t)| {
+        let name = params.get(i).map_or("", |name| &name[..]);
+        match t {
+            // va_list 参数在语法树中已经是指针
+            t if is_va_list_pointer(t) => declarator(&Type::VaList, name),
+            t => declarator(t, name),
+        }
+    }).collect();
+    if signature.variadic {
+        list.push("...".to_string());
+    }
+    let list = match (list.is_empty(), signature.prototyped) {
+        (true, true) => "void".to_string(),
+        _ => list.join(", "),
+    };
+    header + &declarator(&signature.return_type, &format!("{}({})", name, list))
+}
+
+fn is_va_list_pointer(t: &Type) -> bool {
+    matches!(t, Type::Pointer(inner) if **inner == Type::VaList)
+}
+
+fn base_type(t: &Type) -> &'static str {
+    match t {
+        Type::Void => "void",
+        Type::Int => "int",
+        Type::Char => "char",
+        Type::Float => "float",
+        Type::Double => "double",
+        Type::LongDouble => "long double",
+        Type::VaList => "va_list",
+        Type::Pointer(inner) => base_type(inner),
+    }
+}
+
+fn pointer_depth(t: &Type) -> usize {
+    match t {
+        Type::Pointer(inner) => 1 + pointer_depth(inner),
+        _ => 0,
+    }
+}
+
+// 类型和名字 char **argv，名字为空时只有类型 char **
+pub fn declarator(t: &Type, name: &str) -> String {
+    let stars = "*".repeat(pointer_depth(t));
+    match (stars.is_empty(), name.is_empty()) {
+        (true, true) => base_type(t).to_string(),
+        (false, true) => format!("{} {}", base_type(t), stars),
+        _ => format!("{} {}{}", base_type(t), stars, name),
+    }
+}
+
+// 块中的语句
+fn generate_items(statements: &[Stmt], writer: &mut Writer) {
+    writer.indent += 1;
+    for statement in statements.iter() {
+        generate_statement(statement, writer);
+    }
+    writer.indent -= 1;
+}
+
+// 语句体，不是块时加上花括号
+fn generate_body(statement: &Stmt, writer: &mut Writer) {
+    match statement {
+        Stmt::Block(statements) => generate_items(statements, writer),
+        statement => {
+            writer.indent += 1;
+            generate_statement(statement, writer);
+            writer.indent -= 1;
+        }
+    }
+}
+
+fn generate_statement(statement: &Stmt, writer: &mut Writer) {
+    match statement {
+        Stmt::Expression(expr) => writer.line(format!("{};", top(expr, writer))),
+        Stmt::Declaration(id, init) => {
+            let declarator = declarator(&writer.locals[*id], &writer.names[*id]);
+            match init {
+                Some(expr) => writer.line(format!("{} = {};", declarator, top(expr, writer))),
+                None => writer.line(format!("{};", declarator)),
+            }
+        }
+        Stmt::Return(Some(expr)) => writer.line(format!("return {};", top(expr, writer))),
+        Stmt::Return(None) => writer.line("return;".to_string()),
+        Stmt::TailCall(expr) => writer.line(format!("__attribute__((musttail)) return {};", top(expr, writer))),
+
+        Stmt::If(condition, if_body, else_body) => {
+            writer.line(format!("if ({}) {{", top(condition, writer)));
+            generate_body(if_body, writer);
+            let mut else_body = else_body;
+            // else if 不再嵌套一层
+            while let Some(body) = else_body {
+                match &**body {
+                    Stmt::If(condition, if_body, next) => {
+                        writer.line(format!("}} else if ({}) {{", top(condition, writer)));
+                        generate_body(if_body, writer);
+                        else_body = next;
+                    }
+                    body => {
+                        writer.line("} else {".to_string());
+                        generate_body(body, writer);
+                        break;
+                    }
+                }
+            }
+            writer.line("}".to_string());
+        }
+
+        Stmt::Block(statements) => {
+            writer.line("{".to_string());
+            generate_items(statements, writer);
+            writer.line("}".to_string());
+        }
+
+        // for (init; condition; post) body
+        // => { init; while (condition) { body post; } }，有 continue 时见 generate_for_loop
+        Stmt::For(init, condition, modifier, body) => {
+            writer.line("{".to_string());
+            writer.indent += 1;
+            if let Some(init) = init {
+                generate_statement(init, writer);
+            }
+            generate_for_loop(condition, modifier, body, writer);
+            writer.indent -= 1;
+            writer.line("}".to_string());
+        }
+
+        Stmt::While(condition, body) => {
+            writer.line(format!("while ({}) {{", top(condition, writer)));
+            generate_body(body, writer);
+            writer.line("}".to_string());
+        }
+
+        Stmt::DoWhile(body, condition) => {
+            writer.line("do {".to_string());
+            generate_body(body, writer);
+            writer.line(format!("}} while ({});", top(condition, writer)));
+        }
+
+        Stmt::Break => writer.line("break;".to_string()),
+
+        Stmt::Continue => writer.line("continue;".to_string()),
+    }
+}
+
+// 语句体中有没有属于这个循环的 continue，内层循环中的不算
+fn has_continue(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Continue => true,
+        Stmt::If(_, if_body, else_body) => has_continue(if_body) || else_body.as_deref().is_some_and(has_continue),
+        Stmt::Block(statements) => statements.iter().any(has_continue),
+        _ => false,
+    }
+}
+
+/*
+ * for 改写成的 while
+ * 语句体中的声明不能遮住第三个表达式用到的变量，所以语句体有声明时单独成块
+ * 语句体中的 continue 要先执行第三个表达式，这时第三个表达式放到循环的开头，在语句体的作用域之外:
+ * int __next = 0; while (1) { if (__next) { post; } __next = 1; if (!condition) { break; } { body } }
+*/
+fn generate_for_loop(condition: &Expr, modifier: &Option<Expr>, body: &Stmt, writer: &mut Writer) {
+    if let (Some(modifier), true) = (modifier, has_continue(body)) {
+        writer.line(format!("int {} = 0;", NEXT));
+        writer.line("while (1) {".to_string());
+        writer.indent += 1;
+        writer.line(format!("if ({}) {{", NEXT));
+        writer.indent += 1;
+        writer.line(format!("{};", top(modifier, writer)));
+        writer.indent -= 1;
+        writer.line("}".to_string());
+        writer.line(format!("{} = 1;", NEXT));
+        writer.line(format!("if (!{}) {{", generate_expression(condition, writer)));
+        writer.indent += 1;
+        writer.line("break;".to_string());
+        writer.indent -= 1;
+        writer.line("}".to_string());
+        writer.indent -= 1;
+        generate_loop_body(body, writer);
+        writer.line("}".to_string());
+        return;
+    }
+
+    writer.line(format!("while ({}) {{", top(condition, writer)));
+    generate_loop_body(body, writer);
+    if let Some(modifier) = modifier {
+        writer.indent += 1;
+        writer.line(format!("{};", top(modifier, writer)));
+        writer.indent -= 1;
+    }
+    writer.line("}".to_string());
+}
+
+// 语句体中有声明时单独成块
+fn generate_loop_body(body: &Stmt, writer: &mut Writer) {
+    match body {
+        Stmt::Block(statements) if statements.iter().any(|s| matches!(s, Stmt::Declaration(..))) => {
+            writer.indent += 1;
+            generate_statement(body, writer);
+            writer.indent -= 1;
+        }
+        body => generate_body(body, writer),
+    }
+}
+fn operator(op: Operator) -> &'static str {
+    match op {
+        Operator::Plus => "+",
+        Operator::Minus => "-",
+        Operator::Multiplication => "*",
+        Operator::Division => "/",
+        Operator::Modulo => "%",
+        Operator::BitwiseShiftLeft => "<<",
+        Operator::BitwiseShiftRight => ">>",
+        Operator::BitwiseAnd => "&",
+        Operator::BitwiseOr => "|",
+        Operator::BitwiseXor => "^",
+        Operator::BitwiseComplement => "~",
+        Operator::LogicalNegation => "!",
+        Operator::LogicalAnd => "&&",
+        Operator::LogicalOr => "||",
+        Operator::Equal => "==",
+        Operator::NotEqual => "!=",
+        Operator::LessThan => "<",
+        Operator::LessThanOrEqual => "<=",
+        Operator::GreaterThan => ">",
+        Operator::GreaterThanOrEqual => ">=",
+        Operator::Assignment => "=",
+        Operator::AssignPlus => "+",
+        Operator::AssignMinus => "-",
+        Operator::AssignMult => "*",
+        Operator::AssignDiv => "/",
+        Operator::AssignMod => "%",
+    }
+}
+
+// 字符串字面量，不可打印的字节写成三位八进制，后面跟数字也不会连在一起
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| match b {
+        b'"' => "\\\"".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        0x20..=0x7e => (*b as char).to_string(),
+        _ => format!("\\{:03o}", b),
+    }).collect()
+}
+
+// 语句中最外层的表达式，不加括号
+fn top(expression: &Expr, writer: &Writer) -> String {
+    let s = generate_expression(expression, writer);
+    match &expression.kind {
+        ExprKind::Cast(inner) if is_va_list_pointer(&expression.ty) => top(inner, writer),
+        ExprKind::Unary(..) | ExprKind::Assign(..) | ExprKind::CompoundAssign(..) | ExprKind::Binary(..)
+        | ExprKind::Ternary(..) | ExprKind::Cast(..) => s[1..s.len() - 1].to_string(),
+        _ => s,
+    }
+}
+
+/*
+ * 表达式
+ * 运算都带括号，调用的参数和 va_ 的参数是最外层的表达式
+ * 常量不是 int 时 (折叠之后的强制转换) 加上强制转换
+*/
+fn generate_expression(expression: &Expr, writer: &Writer) -> String {
+    match &expression.kind {
+        ExprKind::Constant(n) if expression.ty != Type::Int => format!("(({}){})", declarator(&expression.ty, ""), n),
+        ExprKind::Constant(n) if *n < 0 => format!("({})", n),
+        ExprKind::Constant(n) => n.to_string(),
+        ExprKind::FloatConstant(f) => {
+            // 整数转换成的浮点常量没有小数点，加上 .0 才能带后缀
+            let f = if f.bytes().all(|b| b.is_ascii_digit()) { format!("{}.0", f) } else { f.clone() };
+            match expression.ty {
+                Type::Float => format!("{}f", f),
+                Type::LongDouble => format!("{}L", f),
+                _ => f,
+            }
+        }
+        ExprKind::Variable(id) | ExprKind::Address(id) => writer.names[*id].clone(),
+        ExprKind::StringLiteral(s) => format!("\"{}\"", escape(s)),
+        ExprKind::Unary(op, expr) => format!("({}{})", operator(*op), generate_expression(expr, writer)),
+        ExprKind::Assign(lhs, rhs) => format!("({} = {})", generate_expression(lhs, writer), generate_expression(rhs, writer)),
+        // a op= b => a = (a op b)
+        ExprKind::CompoundAssign(op, lhs, rhs) => {
+            let lhs = generate_expression(lhs, writer);
+            format!("({} = ({} {} {}))", lhs, lhs, operator(*op), generate_expression(rhs, writer))
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            format!("({} {} {})", generate_expression(lhs, writer), operator(*op), generate_expression(rhs, writer))
+        }
+        ExprKind::Ternary(e1, e2, e3) => format!(
+            "({} ? {} : {})",
+            generate_expression(e1, writer),
+            generate_expression(e2, writer),
+            generate_expression(e3, writer),
+        ),
+        ExprKind::Call(name, _, args) => {
+            let args: Vec<String> = args.iter().map(|arg| top(arg, writer)).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        // va_list 参数是数组退化成的指针，不能写成 va_list *
+        ExprKind::Cast(expr) if is_va_list_pointer(&expression.ty) => generate_expression(expr, writer),
+        ExprKind::Cast(expr) => format!("(({}){})", declarator(&expression.ty, ""), generate_expression(expr, writer)),
+        ExprKind::VaStart(ap) => {
+            let last = writer.params.last().map_or("", |id| &writer.names[*id][..]);
+            format!("va_start({}, {})", top(ap, writer), last)
+        }
+        ExprKind::VaArg(ap) => format!("va_arg({}, {})", top(ap, writer), declarator(&expression.ty, "")),
+        ExprKind::VaEnd(ap) => format!("va_end({})", top(ap, writer)),
+        ExprKind::VaCopy(dest, src) => format!("va_copy({}, {})", top(dest, writer), top(src, writer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::driver::{check, interpret, parse};
+    use super::super::options::Options;
+    use super::generate;
+
+    // 输出的 C 和输入的结果一样
+    fn round_trip(source: &str) -> (i64, i64) {
+        let options = Options { input: "test.c".to_string(), ..Options::default() };
+        let output = generate(&check(&parse(source), &options));
+        (interpret(source, &options).unwrap(), interpret(&output, &options).unwrap())
+    }
+
+    #[test]
+    fn continue_does_not_see_shadowing_declaration() {
+        let source = "int main() {
+            int n = 0;
+            for (int i = 0; i < 3; i = i + 1) {
+                int i = 100;
+                n = n + 1;
+                if (n > 50) return n;
+                continue;
+            }
+            return n;
+        }";
+        assert_eq!(round_trip(source), (3, 3));
+    }
+
+    #[test]
+    fn shadowed_variables_get_distinct_names() {
+        let source = "int main() {
+            int x = 1;
+            {
+                int x = 2;
+                x = x + 40;
+            }
+            return x;
+        }";
+        assert_eq!(round_trip(source), (1, 1));
+    }
+
+    #[test]
+    fn implicit_conversions_become_casts() {
+        let options = Options { input: "test.c".to_string(), ..Options::default() };
+        let source = "int main() { char c = 300; double d = c; return d / 4; }";
+        let output = generate(&check(&parse(source), &options));
+        assert!(output.contains("(char)"), "{}", output);
+        assert!(output.contains("(double)"), "{}", output);
+        assert_eq!(interpret(source, &options).unwrap(), interpret(&output, &options).unwrap());
+    }
+
+    #[test]
+    fn functions_called_before_definition_get_prototypes() {
+        let source = "int twice(int x);
+            int main() { return twice(4); }
+            int twice(int x) { return x * 2; }";
+        let options = Options { input: "test.c".to_string(), ..Options::default() };
+        let output = generate(&check(&parse(source), &options));
+        assert!(output.starts_with("int twice(int x);\n"), "{}", output);
+        assert_eq!(round_trip(source), (8, 8));
+    }
+}
@@ -1,49 +1,66 @@
-use std::collections::{HashMap, HashSet};
-
+use super::asm::{Address, Instr, Operand, Reg, Size};
+use super::frame::{Frame, Location};
+use super::ir::{BlockId, Function, SlotId, Ty, Value};
 
 /*
- * 变量集 + 类型
- * 变量
- * 所在地址
- * break位置
- * continue位置
+ * 生成一个函数时的上下文
+ * 正在生成的函数
+ * 栈帧布局
+ * 已经生成的指令
+ * 函数用到的只读数据 (浮点常量)，放在函数的指令之后
 */
-#[derive(Debug, Clone, Default)]
-pub struct Context {
-    pub var_map: HashMap<String, isize>,
-    pub current_scope: HashSet<String>,
-    pub stack_index: isize,
-    pub break_label: Option<String>,
-    pub continue_label: Option<String>,
+#[derive(Debug, Clone)]
+pub struct Context<'a> {
+    pub function: &'a Function,
+    pub frame: Frame,
+    pub code: Vec<Instr>,
+    pub data: Vec<Instr>,
 }
 
-impl Context {
-    // 初始话方法 传入参数
-    pub fn new(params: &[String]) -> Self {
-        let mut var_map = HashMap::new();
-        let mut current_scope = HashSet::new();
-        let mut param_offset = 16;
-
-        //  将参数加入，默认所占8位
-        params.iter().for_each(|id| {
-            var_map.insert(id.clone(), param_offset);
-            current_scope.insert(id.clone());
-            param_offset += 8;
-        });
-
-        Context {
-            var_map,
-            stack_index: -8,
-            current_scope,
-            ..Default::default()
+impl<'a> Context<'a> {
+    pub fn emit(&mut self, instr: Instr) {
+        self.code.push(instr);
+    }
+
+    pub fn ty(&self, v: Value) -> Ty {
+        self.function.ty(v)
+    }
+
+    fn location(&self, v: Value) -> Location {
+        self.frame.values[v.0].unwrap_or_else(|| unreachable!("Value {} has no location", v))
+    }
+
+    // 值在栈帧中的位置，浮点数和溢出的整数
+    pub fn home(&self, v: Value) -> Address {
+        match self.location(v) {
+            Location::Stack(offset) => Address::Base(Reg::Rbp, offset),
+            Location::Register(r) => unreachable!("Value {} is in register {:?}", v, r),
         }
     }
 
-    // 重置范围 重置为空
-    pub fn reset_scope(&self) -> Self {
-        Context {
-            current_scope: HashSet::new(),
-            ..self.clone()
+    // 值所在的寄存器
+    pub fn register(&self, v: Value) -> Option<Reg> {
+        match self.location(v) {
+            Location::Register(r) => Some(r),
+            Location::Stack(_) => None,
         }
     }
+
+    // 整数值的操作数，寄存器或者栈帧中的64位
+    pub fn operand(&self, v: Value) -> Operand {
+        match self.location(v) {
+            Location::Register(r) => r.qword(),
+            Location::Stack(offset) => Operand::Mem(Size::Qword, Address::Base(Reg::Rbp, offset)),
+        }
+    }
+
+    // 栈槽的位置
+    pub fn slot(&self, slot: SlotId) -> Address {
+        Address::Base(Reg::Rbp, self.frame.slots[slot.0])
+    }
+
+    // 基本块的标签
+    pub fn label(&self, block: BlockId) -> String {
+        format!(".L{}.bb{}", self.function.name, block.0)
+    }
 }
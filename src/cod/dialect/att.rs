@@ -0,0 +1,117 @@
+use super::super::asm::{Address, Instr, Operand, SseOp, X87Op};
+use super::super::asm::Size;
+use super::{mnemonic, AsmDialect};
+
+/*
+ * GNU as 的 AT&T 语法
+ * movq %rax, -8(%rbp)
+ * 来源在左边，目的在右边，寄存器加 %，立即数加 $
+ * 整数指令的后缀给出操作数大小，x87 访存指令的后缀给出内存中数的类型
+*/
+pub struct AttGas;
+
+impl AsmDialect for AttGas {
+    fn header(&self, _program: &[Instr]) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn instr(&self, instr: &Instr) -> String {
+        match instr {
+            Instr::Mov(a, b) => format!("mov{} {}, {}", suffix(size(a, b)), operand(b), operand(a)),
+            Instr::Movsx(a, b) => format!("movs{}{} {}, {}", suffix(size(b, b)), suffix(size(a, a)), operand(b), operand(a)),
+            Instr::Movzx(a, b) => format!("movz{}{} {}, {}", suffix(size(b, b)), suffix(size(a, a)), operand(b), operand(a)),
+            Instr::Lea(a, address) => format!("lea{} {}, {}", suffix(size(a, a)), self::address(address), operand(a)),
+            Instr::Push(a) => format!("pushq {}", operand(a)),
+            Instr::Pop(a) => format!("popq {}", operand(a)),
+            Instr::Alu(op, a, b) => format!("{}{} {}, {}", mnemonic(op), suffix(size(a, b)), operand(b), operand(a)),
+            Instr::Neg(a) => format!("neg{} {}", suffix(size(a, a)), operand(a)),
+            Instr::Shift(op, a) => format!("{}{} %cl, {}", mnemonic(op), suffix(size(a, a)), operand(a)),
+            Instr::ShiftImm(op, a, n) => format!("{}{} ${}, {}", mnemonic(op), suffix(size(a, a)), n, operand(a)),
+            Instr::Cqo => "cqto".to_string(),
+            Instr::Idiv(a) => format!("idiv{} {}", suffix(size(a, a)), operand(a)),
+            Instr::Set(cond, a) => format!("set{} {}", mnemonic(cond), operand(a)),
+            Instr::Jmp(label) => format!("jmp {}", label),
+            Instr::Jcc(cond, label) => format!("j{} {}", mnemonic(cond), label),
+            Instr::Call(name) => format!("call {}", name),
+            Instr::Ret => "ret".to_string(),
+            Instr::Syscall => "syscall".to_string(),
+            // 整数转浮点的来源可能在内存中，需要写明整数的大小
+            Instr::Sse(op @ (SseOp::Cvtsi2ss | SseOp::Cvtsi2sd), a, b) => {
+                format!("{}{} {}, {}", mnemonic(op), suffix(size(b, b)), operand(b), operand(a))
+            }
+            Instr::Sse(op, a, b) => format!("{} {}, {}", mnemonic(op), operand(b), operand(a)),
+            Instr::Fld(a) => format!("fld{} {}", float_suffix(a), operand(a)),
+            Instr::Fild(a) => format!("fild{} {}", integer_suffix(a), operand(a)),
+            Instr::Fstp(a) => format!("fstp{} {}", float_suffix(a), operand(a)),
+            Instr::Fisttp(a) => format!("fisttp{} {}", integer_suffix(a), operand(a)),
+            Instr::Fchs => "fchs".to_string(),
+            Instr::Fxch => "fxch %st(1)".to_string(),
+            Instr::Fucomip => "fucomip %st(1), %st".to_string(),
+            Instr::FstpTop => "fstp %st(0)".to_string(),
+            // AT&T 语法中目的是 st(i) 的 fsubp fdivp 和 intel 语法的含义相反
+            Instr::X87(op) => {
+                let name = match op {
+                    X87Op::Faddp => "faddp",
+                    X87Op::Fsubp => "fsubrp",
+                    X87Op::Fmulp => "fmulp",
+                    X87Op::Fdivp => "fdivrp",
+                };
+                format!("{} %st, %st(1)", name)
+            }
+            Instr::Label(_) | Instr::Directive(_) => unreachable!("{:?} is not an instruction", instr),
+        }
+    }
+}
+
+// 两个操作数中寄存器或者内存的大小，立即数没有大小
+fn size(a: &Operand, b: &Operand) -> Size {
+    match (a, b) {
+        (Operand::Reg(_, size) | Operand::Mem(size, _), _) | (_, Operand::Reg(_, size) | Operand::Mem(size, _)) => *size,
+        _ => unreachable!("Operands {:?} {:?} have no size", a, b),
+    }
+}
+
+fn suffix(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "b",
+        Size::Dword => "l",
+        Size::Qword => "q",
+        Size::Tbyte => "t",
+    }
+}
+
+// fld fstp 的后缀: s 单精度，l 双精度，t 扩展精度
+fn float_suffix(a: &Operand) -> &'static str {
+    match size(a, a) {
+        Size::Dword => "s",
+        Size::Qword => "l",
+        Size::Tbyte => "t",
+        Size::Byte => unreachable!("Byte sized float {:?}", a),
+    }
+}
+
+// fild fisttp 的后缀: l 32位，ll 64位
+fn integer_suffix(a: &Operand) -> &'static str {
+    match size(a, a) {
+        Size::Dword => "l",
+        Size::Qword => "ll",
+        size => unreachable!("{:?} sized integer {:?}", size, a),
+    }
+}
+
+fn address(address: &Address) -> String {
+    match address {
+        Address::Base(r, 0) => format!("(%{})", r.name(Size::Qword)),
+        Address::Base(r, offset) => format!("{}(%{})", offset, r.name(Size::Qword)),
+        Address::Rip(label) => format!("{}(%rip)", label),
+    }
+}
+
+fn operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Reg(r, size) => format!("%{}", r.name(*size)),
+        Operand::Xmm(n) => format!("%xmm{}", n),
+        Operand::Imm(n) => format!("${}", n),
+        Operand::Mem(_, a) => address(a),
+    }
+}
@@ -0,0 +1,102 @@
+use super::super::asm::{Address, Instr, Operand, Size};
+use super::{mnemonic, AsmDialect};
+
+/*
+ * GNU as 的 intel 语法
+ * mov qword ptr [rbp-8], rax
+ * 目的操作数在左边，内存操作数写明大小
+*/
+pub struct IntelGas;
+
+impl AsmDialect for IntelGas {
+    fn header(&self, _program: &[Instr]) -> Vec<String> {
+        vec![".intel_syntax noprefix".to_string()]
+    }
+
+    fn instr(&self, instr: &Instr) -> String {
+        match instr {
+            Instr::Fxch => "fxch st(1)".to_string(),
+            Instr::Fucomip => "fucomip st, st(1)".to_string(),
+            Instr::FstpTop => "fstp st(0)".to_string(),
+            Instr::X87(op) => format!("{} st(1), st", mnemonic(op)),
+            instr => SPELLING.instr(instr),
+        }
+    }
+}
+
+const SPELLING: Spelling = Spelling { memory, address, label: str::to_string };
+
+fn memory(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "byte ptr",
+        Size::Dword => "dword ptr",
+        Size::Qword => "qword ptr",
+        Size::Tbyte => "tbyte ptr",
+    }
+}
+
+fn address(address: &Address) -> String {
+    match address {
+        Address::Base(r, 0) => format!("[{}]", r.name(Size::Qword)),
+        Address::Base(r, offset) => format!("[{}{:+}]", r.name(Size::Qword), offset),
+        Address::Rip(label) => format!("[rip+{}]", label),
+    }
+}
+
+/*
+ * intel 和 NASM 语法的差别
+ * 内存操作数大小的写法
+ * 地址的写法
+ * 标签的名字
+*/
+pub(super) struct Spelling {
+    pub memory: fn(Size) -> &'static str,
+    pub address: fn(&Address) -> String,
+    pub label: fn(&str) -> String,
+}
+
+impl Spelling {
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Reg(r, size) => r.name(*size).to_string(),
+            Operand::Xmm(n) => format!("xmm{}", n),
+            Operand::Imm(n) => n.to_string(),
+            Operand::Mem(size, address) => format!("{} {}", (self.memory)(*size), (self.address)(address)),
+        }
+    }
+
+    // 除 x87 寄存器栈以外的指令，两种语法只有操作数的写法不同
+    pub(super) fn instr(&self, instr: &Instr) -> String {
+        let operand = |a: &Operand| self.operand(a);
+        let label = |l: &str| (self.label)(l);
+        match instr {
+            Instr::Mov(a, b) => format!("mov {}, {}", operand(a), operand(b)),
+            Instr::Movsx(a, b) => format!("movsx {}, {}", operand(a), operand(b)),
+            Instr::Movzx(a, b) => format!("movzx {}, {}", operand(a), operand(b)),
+            Instr::Lea(a, address) => format!("lea {}, {}", operand(a), (self.address)(address)),
+            Instr::Push(a) => format!("push {}", operand(a)),
+            Instr::Pop(a) => format!("pop {}", operand(a)),
+            Instr::Alu(op, a, b) => format!("{} {}, {}", mnemonic(op), operand(a), operand(b)),
+            Instr::Neg(a) => format!("neg {}", operand(a)),
+            Instr::Shift(op, a) => format!("{} {}, cl", mnemonic(op), operand(a)),
+            Instr::ShiftImm(op, a, n) => format!("{} {}, {}", mnemonic(op), operand(a), n),
+            Instr::Cqo => "cqo".to_string(),
+            Instr::Idiv(a) => format!("idiv {}", operand(a)),
+            Instr::Set(cond, a) => format!("set{} {}", mnemonic(cond), operand(a)),
+            Instr::Jmp(l) => format!("jmp {}", label(l)),
+            Instr::Jcc(cond, l) => format!("j{} {}", mnemonic(cond), label(l)),
+            Instr::Call(name) => format!("call {}", label(name)),
+            Instr::Ret => "ret".to_string(),
+            Instr::Syscall => "syscall".to_string(),
+            Instr::Sse(op, a, b) => format!("{} {}, {}", mnemonic(op), operand(a), operand(b)),
+            Instr::Fld(a) => format!("fld {}", operand(a)),
+            Instr::Fild(a) => format!("fild {}", operand(a)),
+            Instr::Fstp(a) => format!("fstp {}", operand(a)),
+            Instr::Fisttp(a) => format!("fisttp {}", operand(a)),
+            Instr::Fchs => "fchs".to_string(),
+            Instr::Label(_) | Instr::Directive(_) | Instr::Fxch | Instr::Fucomip | Instr::FstpTop | Instr::X87(_) => {
+                unreachable!("{:?} is printed by the dialect", instr)
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt::Debug;
+
+use super::asm::{Directive, Instr};
+use super::options::Syntax;
+
+pub mod att;
+pub mod intel;
+pub mod nasm;
+
+/*
+ * 汇编语法
+ * 生成器只产生指令序列，写成文本的方式由语法决定 (-masm=)
+ * intel: GNU as 的 intel 语法，默认
+ * att: GNU as 的 AT&T 语法
+ * nasm: NASM 的语法
+*/
+pub trait AsmDialect {
+    // 文件开头的内容，需要时可以查看整个文件
+    fn header(&self, program: &[Instr]) -> Vec<String>;
+
+    // 标签在这个语法下的名字
+    fn label(&self, name: &str) -> String {
+        name.to_string()
+    }
+
+    // GNU as 的两种语法使用相同的伪指令
+    fn directive(&self, directive: &Directive) -> String {
+        match directive {
+            Directive::Global(name) => format!(".global {}", name),
+            Directive::Text => "  .text".to_string(),
+            Directive::Rodata => "  .section .rodata".to_string(),
+            Directive::Align(n) => format!("  .p2align {}", n),
+            Directive::Byte(bytes) => format!("  .byte {}", join(bytes)),
+            Directive::Long(n) => format!("  .long {:#x}", n),
+            Directive::Quad(n) => format!("  .quad {:#x}", n),
+        }
+    }
+
+    // 一条指令 (不包括标签和伪指令)
+    fn instr(&self, instr: &Instr) -> String;
+}
+
+pub fn dialect(syntax: Syntax) -> Box<dyn AsmDialect> {
+    match syntax {
+        Syntax::Intel => Box::new(intel::IntelGas),
+        Syntax::Att => Box::new(att::AttGas),
+        Syntax::Nasm => Box::new(nasm::Nasm),
+    }
+}
+
+// 指令名都是枚举名的小写
+fn mnemonic(op: &dyn Debug) -> String {
+    format!("{:?}", op).to_lowercase()
+}
+
+fn join(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+    bytes.join(",")
+}
+
+// 整个汇编文件的文本
+pub fn assembly(program: &[Instr], dialect: &dyn AsmDialect) -> String {
+    let mut text = String::new();
+    for line in dialect.header(program) {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    for instr in program {
+        let line = match instr {
+            Instr::Label(label) => format!("{}:", dialect.label(label)),
+            Instr::Directive(directive) => dialect.directive(directive),
+            instr => format!("  {}", dialect.instr(instr)),
+        };
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeSet;
+
+use super::super::asm::{Address, Directive, Instr, Size};
+use super::intel::Spelling;
+use super::{join, mnemonic, AsmDialect};
+
+/*
+ * NASM 语法
+ * mov qword [rbp-8], rax
+ * 和 intel 语法相似，内存操作数不写 ptr，相对 rip 的地址写作 [rel label]
+ * 文件中没有定义的符号 (调用的库函数) 要用 extern 声明
+ *
+ * NASM 中以 . 开头的标签属于前一个普通标签，.LS0 这样在函数之前定义的标签就找不到了
+ * 所以 . 开头的标签改成 ..@ 开头，这样的标签不参与局部标签的规则
+*/
+pub struct Nasm;
+
+const SPELLING: Spelling = Spelling { memory, address, label };
+
+impl AsmDialect for Nasm {
+    fn header(&self, program: &[Instr]) -> Vec<String> {
+        let defined: BTreeSet<&String> = program.iter().filter_map(|instr| match instr {
+            Instr::Label(l) => Some(l),
+            _ => None,
+        }).collect();
+        // 跳转到其他函数的是尾调用
+        let external: BTreeSet<&String> = program.iter().filter_map(|instr| match instr {
+            Instr::Call(name) | Instr::Jmp(name) if !defined.contains(name) => Some(name),
+            _ => None,
+        }).collect();
+
+        let mut lines = vec!["bits 64".to_string()];
+        lines.extend(external.into_iter().map(|name| format!("extern {}", name)));
+        lines
+    }
+
+    fn label(&self, name: &str) -> String {
+        label(name)
+    }
+
+    fn directive(&self, directive: &Directive) -> String {
+        match directive {
+            Directive::Global(name) => format!("global {}", name),
+            Directive::Text => "  section .text".to_string(),
+            Directive::Rodata => "  section .rodata".to_string(),
+            Directive::Align(n) => format!("  align {}", 1u64 << n),
+            Directive::Byte(bytes) => format!("  db {}", join(bytes)),
+            Directive::Long(n) => format!("  dd {:#x}", n),
+            Directive::Quad(n) => format!("  dq {:#x}", n),
+        }
+    }
+
+    fn instr(&self, instr: &Instr) -> String {
+        match instr {
+            Instr::Fxch => "fxch st1".to_string(),
+            Instr::Fucomip => "fucomip st0, st1".to_string(),
+            Instr::FstpTop => "fstp st0".to_string(),
+            Instr::X87(op) => format!("{} st1, st0", mnemonic(op)),
+            instr => SPELLING.instr(instr),
+        }
+    }
+}
+
+fn label(name: &str) -> String {
+    match name.strip_prefix('.') {
+        Some(rest) => format!("..@{}", rest),
+        None => name.to_string(),
+    }
+}
+
+fn memory(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "byte",
+        Size::Dword => "dword",
+        Size::Qword => "qword",
+        Size::Tbyte => "tword",
+    }
+}
+
+fn address(address: &Address) -> String {
+    match address {
+        Address::Base(r, 0) => format!("[{}]", r.name(Size::Qword)),
+        Address::Base(r, offset) => format!("[{}{:+}]", r.name(Size::Qword), offset),
+        Address::Rip(l) => format!("[rel {}]", label(l)),
+    }
+}
@@ -0,0 +1,84 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::ast::Ast;
+use super::ir::pass::PassManager;
+use super::ir::Module;
+use super::options::Options;
+use super::typed_ast::Program;
+
+// 从源代码到语法树 词法分析 -> 语法分析
+pub fn parse(source: &str) -> Ast {
+    // 在这里将所有的字符串进行lex
+    let tokens = super::lex::lex(source);
+
+    super::parser::parser(&tokens)
+}
+
+/*
+ * 语法树 -> 带类型的语法树
+ * 语义分析 -> 常量折叠
+ * 用户的错误在语义分析中报告
+*/
+pub fn check(ast: &Ast, options: &Options) -> Program {
+    // 语义分析，用户的错误都在这里报告
+    let mut program = super::sema::analyze(ast, options);
+
+    // 常量折叠和代数化简
+    super::fold::fold(&mut program);
+    program
+}
+
+/*
+ * 带类型的语法树 -> 优化之后的中间表示
+ * 生成中间表示 -> 按优化级别运行 pass
+*/
+pub fn lower(program: &Program, options: &Options) -> Module {
+    // 生成中间表示，检查不通过说明编译器自己有错误
+    let mut module = super::ir::lower::lower(program);
+    if let Err(e) = super::ir::verify::verify_module(&module) {
+        panic!("IR verification failed: {}", e);
+    }
+
+    // 按优化级别运行 pass
+    PassManager::new(options.opt_level, options.target, &options.print_after, &options.remarks, &options.missed_remarks).run(&mut module);
+    module
+}
+
+// 从源代码到带类型的语法树
+pub fn analyze(source: &str, options: &Options) -> Program {
+    check(&parse(source), options)
+}
+
+// 从源代码到优化之后的中间表示
+pub fn compile(source: &str, options: &Options) -> Module {
+    lower(&analyze(source, options), options)
+}
+
+/*
+ * 解释执行 (my_rcc run)，返回 main 的返回值
+ * 不做常量折叠，未定义行为要在执行到的时候报告
+*/
+pub fn interpret(source: &str, options: &Options) -> Result<i64, String> {
+    let program = super::sema::analyze(&parse(source), options);
+    super::interp::run(program, &options.input)
+}
+
+// panic 的信息
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string())
+}
+
+/*
+ * 编译的错误 (语法 语义错误) 都是 panic，转换成 Err(错误信息)
+ * 执行 f 时不输出 panic 的信息
+*/
+pub fn catch_errors<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    result.map_err(|payload| panic_message(&*payload))
+}
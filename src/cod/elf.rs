@@ -0,0 +1,507 @@
+/*
+ * ELF64 目标文件
+ * 汇编器产生 Object，再写成可重定位文件 (.o)
+ * 链接器读入可重定位文件，输出静态链接的可执行文件
+ * 只支持 x86-64 小端
+*/
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+
+pub const SHF_WRITE: u64 = 1;
+pub const SHF_ALLOC: u64 = 2;
+pub const SHF_EXECINSTR: u64 = 4;
+pub const SHF_INFO_LINK: u64 = 0x40;
+
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_SECTION: u8 = 3;
+
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_PLT32: u32 = 4;
+
+const EHDR_SIZE: usize = 64;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const RELA_SIZE: usize = 24;
+const PHDR_SIZE: usize = 56;
+
+/*
+ * 节
+ * 名字 类型 标志 对齐
+ * NOBITS 的节 (.bss) 只有大小没有内容
+*/
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub flags: u64,
+    pub align: u64,
+    pub data: Vec<u8>,
+    pub size: u64,
+}
+
+/*
+ * 符号
+ * section 为 None 是未定义的符号，需要链接时解析
+*/
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub section: Option<usize>,
+    pub value: u64,
+    pub global: bool,
+}
+
+// 重定位的目标，符号或者节的开头 (本文件中的局部标签)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Symbol(usize),
+    Section(usize),
+}
+
+// 在 section 的 offset 处写入 target + addend - 位置
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub section: usize,
+    pub offset: u64,
+    pub target: Target,
+    pub kind: u32,
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Section {
+    pub fn new(name: &str, kind: u32, flags: u64, align: u64) -> Section {
+        Section { name: name.to_string(), kind, flags, align, data: Vec::new(), size: 0 }
+    }
+}
+
+// 字符串表，第一个字节是空字符串
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    fn new() -> StringTable {
+        StringTable { data: vec![0] }
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        if s.is_empty() {
+            return 0;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+fn put16(out: &mut Vec<u8>, n: u16) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn align_to(out: &mut Vec<u8>, align: u64) {
+    while !(out.len() as u64).is_multiple_of(align.max(1)) {
+        out.push(0);
+    }
+}
+
+/*
+ * 写成可重定位文件
+ * 节的顺序: 空节，object 中的节，各节的重定位 (.rela.xxx)，.symtab .strtab .shstrtab
+ * 符号表: 空符号，每个节的节符号，局部符号，全局符号 (链接器要求局部符号在前)
+*/
+pub fn write_relocatable(object: &Object) -> Vec<u8> {
+    let mut shstrtab = StringTable::new();
+    let mut strtab = StringTable::new();
+    let mut headers: Vec<SectionHeader> = Vec::new();
+    let mut out = vec![0; EHDR_SIZE];
+
+    // object 中第 i 个节在文件中是第 i+1 个
+    for section in object.sections.iter() {
+        align_to(&mut out, section.align);
+        let offset = out.len() as u64;
+        let size = if section.kind == SHT_NOBITS {
+            section.size
+        } else {
+            out.extend_from_slice(&section.data);
+            section.data.len() as u64
+        };
+        headers.push(SectionHeader {
+            name: shstrtab.add(&section.name),
+            kind: section.kind,
+            flags: section.flags,
+            offset,
+            size,
+            link: 0,
+            info: 0,
+            align: section.align,
+            entsize: 0,
+        });
+    }
+
+    // 符号表中的位置
+    let locals: Vec<usize> = (0..object.symbols.len()).filter(|&i| !object.symbols[i].global).collect();
+    let globals: Vec<usize> = (0..object.symbols.len()).filter(|&i| object.symbols[i].global).collect();
+    let first_symbol = 1 + object.sections.len();
+    let mut index = vec![0; object.symbols.len()];
+    for (position, &i) in locals.iter().chain(globals.iter()).enumerate() {
+        index[i] = first_symbol + position;
+    }
+
+    let symtab_index = (1 + object.sections.len() + object.sections.iter().enumerate()
+        .filter(|(i, _)| object.relocations.iter().any(|r| r.section == *i))
+        .count()) as u32;
+
+    for (i, section) in object.sections.iter().enumerate() {
+        let relocations: Vec<&Relocation> = object.relocations.iter().filter(|r| r.section == i).collect();
+        if relocations.is_empty() {
+            continue;
+        }
+        align_to(&mut out, 8);
+        let offset = out.len() as u64;
+        for r in relocations.iter() {
+            let symbol = match r.target {
+                Target::Symbol(s) => index[s],
+                Target::Section(s) => 1 + s,
+            };
+            put64(&mut out, r.offset);
+            put64(&mut out, (symbol as u64) << 32 | r.kind as u64);
+            put64(&mut out, r.addend as u64);
+        }
+        headers.push(SectionHeader {
+            name: shstrtab.add(&format!(".rela{}", section.name)),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset,
+            size: (relocations.len() * RELA_SIZE) as u64,
+            link: symtab_index,
+            info: (i + 1) as u32,
+            align: 8,
+            entsize: RELA_SIZE as u64,
+        });
+    }
+
+    // 符号表
+    align_to(&mut out, 8);
+    let symtab_offset = out.len() as u64;
+    out.extend_from_slice(&[0; SYM_SIZE]);
+    for i in 0..object.sections.len() {
+        put_symbol(&mut out, 0, STB_LOCAL << 4 | STT_SECTION, (i + 1) as u16, 0);
+    }
+    for &i in locals.iter().chain(globals.iter()) {
+        let symbol = &object.symbols[i];
+        let bind = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+        let section = symbol.section.map_or(0, |s| s + 1) as u16;
+        put_symbol(&mut out, strtab.add(&symbol.name), bind << 4 | STT_NOTYPE, section, symbol.value);
+    }
+    headers.push(SectionHeader {
+        name: shstrtab.add(".symtab"),
+        kind: SHT_SYMTAB,
+        flags: 0,
+        offset: symtab_offset,
+        size: out.len() as u64 - symtab_offset,
+        link: symtab_index + 1,
+        info: (first_symbol + locals.len()) as u32,
+        align: 8,
+        entsize: SYM_SIZE as u64,
+    });
+
+    // .shstrtab 包含自己的名字，先加入名字再写内容
+    let strtab_name = shstrtab.add(".strtab");
+    let shstrtab_name = shstrtab.add(".shstrtab");
+    for (name, data) in [(strtab_name, &strtab.data), (shstrtab_name, &shstrtab.data)] {
+        let offset = out.len() as u64;
+        out.extend_from_slice(data);
+        headers.push(SectionHeader { name, kind: SHT_STRTAB, flags: 0, offset, size: data.len() as u64, link: 0, info: 0, align: 1, entsize: 0 });
+    }
+
+    // 节头表
+    align_to(&mut out, 8);
+    let shoff = out.len() as u64;
+    out.extend_from_slice(&[0; SHDR_SIZE]);
+    for h in headers.iter() {
+        put32(&mut out, h.name);
+        put32(&mut out, h.kind);
+        put64(&mut out, h.flags);
+        put64(&mut out, 0);
+        put64(&mut out, h.offset);
+        put64(&mut out, h.size);
+        put32(&mut out, h.link);
+        put32(&mut out, h.info);
+        put64(&mut out, h.align);
+        put64(&mut out, h.entsize);
+    }
+
+    let mut header = Vec::with_capacity(EHDR_SIZE);
+    header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    put16(&mut header, 1); // ET_REL
+    put16(&mut header, 62); // EM_X86_64
+    put32(&mut header, 1);
+    put64(&mut header, 0); // 入口
+    put64(&mut header, 0); // 程序头
+    put64(&mut header, shoff);
+    put32(&mut header, 0);
+    put16(&mut header, EHDR_SIZE as u16);
+    put16(&mut header, 0);
+    put16(&mut header, 0);
+    put16(&mut header, SHDR_SIZE as u16);
+    put16(&mut header, (headers.len() + 1) as u16);
+    put16(&mut header, headers.len() as u16); // .shstrtab 是最后一个
+    out[..EHDR_SIZE].copy_from_slice(&header);
+    out
+}
+
+fn put_symbol(out: &mut Vec<u8>, name: u32, info: u8, section: u16, value: u64) {
+    put32(out, name);
+    out.push(info);
+    out.push(0);
+    put16(out, section);
+    put64(out, value);
+    put64(out, 0);
+}
+
+// 读取小端整数，越界说明文件不完整
+fn get(bytes: &[u8], offset: usize, size: usize) -> Result<u64, String> {
+    let field = bytes.get(offset..offset + size).ok_or("Truncated ELF file")?;
+    Ok(field.iter().rev().fold(0, |n, &b| n << 8 | b as u64))
+}
+
+fn get_string(table: &[u8], offset: usize) -> Result<String, String> {
+    let rest = table.get(offset..).ok_or("Bad string table offset")?;
+    let end = rest.iter().position(|&b| b == 0).ok_or("Unterminated string")?;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+/*
+ * 读取可重定位文件
+ * 文件中第 i 个节是 object 的第 i-1 个 (包括符号表和重定位这些节)
+ * 节符号不放进 symbols，相对于它们的重定位变成 Target::Section
+*/
+pub fn read_relocatable(bytes: &[u8]) -> Result<Object, String> {
+    if bytes.get(..4) != Some(&[0x7f, b'E', b'L', b'F'][..]) || get(bytes, 4, 2)? != 0x0102 {
+        return Err("Not an ELF64 little-endian file".to_string());
+    }
+    if get(bytes, 16, 2)? != 1 || get(bytes, 18, 2)? != 62 {
+        return Err("Not an x86-64 relocatable object".to_string());
+    }
+    let shoff = get(bytes, 40, 8)? as usize;
+    let shnum = get(bytes, 60, 2)? as usize;
+    let shstrndx = get(bytes, 62, 2)? as usize;
+
+    let header = |i: usize, offset: usize, size: usize| get(bytes, shoff + i * SHDR_SIZE + offset, size);
+    let contents = |i: usize| -> Result<&[u8], String> {
+        let offset = header(i, 24, 8)? as usize;
+        let size = header(i, 32, 8)? as usize;
+        if header(i, 4, 4)? as u32 == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        bytes.get(offset..offset + size).ok_or_else(|| "Truncated section".to_string())
+    };
+
+    let shstrtab = contents(shstrndx)?;
+    let mut object = Object::default();
+    for i in 1..shnum {
+        let mut section = Section::new(&get_string(shstrtab, header(i, 0, 4)? as usize)?, header(i, 4, 4)? as u32, header(i, 8, 8)?, header(i, 48, 8)?);
+        section.data = contents(i)?.to_vec();
+        section.size = header(i, 32, 8)?;
+        object.sections.push(section);
+    }
+
+    // 文件中的节号 -> object 中的下标，0 和超出范围的节号不对应任何节
+    let count = object.sections.len();
+    let index = |section: usize| section.checked_sub(1).filter(|i| *i < count);
+
+    // 文件中的符号编号 -> 重定位目标
+    let mut targets: Vec<Option<Target>> = Vec::new();
+    for i in 1..shnum {
+        if header(i, 4, 4)? as u32 != SHT_SYMTAB {
+            continue;
+        }
+        let symtab = contents(i)?;
+        let strtab = contents(header(i, 40, 4)? as usize)?;
+        for s in 0..symtab.len() / SYM_SIZE {
+            let entry = &symtab[s * SYM_SIZE..];
+            let info = entry[4];
+            let section = get(entry, 6, 2)? as usize;
+            let name = get_string(strtab, get(entry, 0, 4)? as usize)?;
+            let target = match info & 0xf {
+                _ if s == 0 => None,
+                STT_SECTION => Some(Target::Section(index(section).ok_or("Bad section symbol")?)),
+                // 文件名
+                4 => None,
+                _ if section >= 0xff00 => return Err(format!("Unsupported special symbol {}", name)),
+                _ => {
+                    object.symbols.push(Symbol {
+                        name,
+                        section: if section == 0 { None } else { Some(index(section).ok_or("Bad symbol section")?) },
+                        value: get(entry, 8, 8)?,
+                        global: info >> 4 != STB_LOCAL,
+                    });
+                    Some(Target::Symbol(object.symbols.len() - 1))
+                }
+            };
+            targets.push(target);
+        }
+    }
+
+    for i in 1..shnum {
+        if header(i, 4, 4)? as u32 != SHT_RELA {
+            continue;
+        }
+        let rela = contents(i)?;
+        let section = index(header(i, 44, 4)? as usize).ok_or("Bad relocation section")?;
+        for r in 0..rela.len() / RELA_SIZE {
+            let entry = &rela[r * RELA_SIZE..];
+            let info = get(entry, 8, 8)?;
+            let target = targets.get((info >> 32) as usize).copied().flatten().ok_or("Bad relocation symbol")?;
+            object.relocations.push(Relocation {
+                section,
+                offset: get(entry, 0, 8)?,
+                target,
+                kind: info as u32,
+                addend: get(entry, 16, 8)? as i64,
+            });
+        }
+    }
+
+    Ok(object)
+}
+
+// 可执行文件中从 BASE 开始的位置和文件中的位置相同，BASE 处放 ELF 头和程序头
+pub const BASE: u64 = 0x400000;
+pub const PAGE: u64 = 0x1000;
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+/*
+ * 可执行文件的段
+ * address 要在 BASE 之后的页上，data 之后到 memory_size 的部分 (.bss) 填 0
+*/
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub address: u64,
+    pub flags: u32,
+    pub data: Vec<u8>,
+    pub memory_size: u64,
+}
+
+// 写成静态链接的可执行文件，没有节头表
+pub fn write_executable(segments: &[Segment], entry: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    put16(&mut out, 2); // ET_EXEC
+    put16(&mut out, 62);
+    put32(&mut out, 1);
+    put64(&mut out, entry);
+    put64(&mut out, EHDR_SIZE as u64);
+    put64(&mut out, 0);
+    put32(&mut out, 0);
+    put16(&mut out, EHDR_SIZE as u16);
+    put16(&mut out, PHDR_SIZE as u16);
+    put16(&mut out, segments.len() as u16);
+    put16(&mut out, SHDR_SIZE as u16);
+    put16(&mut out, 0);
+    put16(&mut out, 0);
+
+    for segment in segments.iter() {
+        put32(&mut out, 1); // PT_LOAD
+        put32(&mut out, segment.flags);
+        put64(&mut out, segment.address - BASE);
+        put64(&mut out, segment.address);
+        put64(&mut out, segment.address);
+        put64(&mut out, segment.data.len() as u64);
+        put64(&mut out, segment.memory_size);
+        put64(&mut out, PAGE);
+    }
+
+    for segment in segments.iter() {
+        out.resize((segment.address - BASE) as usize, 0);
+        out.extend_from_slice(&segment.data);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::driver::analyze;
+    use super::super::options::{Arch, Options};
+    use super::super::target::target;
+    use super::*;
+
+    // 编译成可重定位文件
+    fn object(source: &str) -> Vec<u8> {
+        let options = Options { input: "test.c".to_string(), ..Options::default() };
+        target(Arch::X86_64).object(&analyze(source, &options), &options).unwrap()
+    }
+
+    // 类型是 kind 的第一个节的节头的位置
+    fn section_header(bytes: &[u8], kind: u32) -> usize {
+        let shoff = get(bytes, 40, 8).unwrap() as usize;
+        let shnum = get(bytes, 60, 2).unwrap() as usize;
+        (1..shnum).map(|i| shoff + i * SHDR_SIZE).find(|h| get(bytes, h + 4, 4).unwrap() as u32 == kind).unwrap()
+    }
+
+    const SOURCE: &str = "int puts(char *s); int main() { return puts(\"hi\"); }";
+
+    #[test]
+    fn reads_written_object() {
+        let object = read_relocatable(&object(SOURCE)).unwrap();
+        assert!(object.symbols.iter().any(|s| s.name == "main" && s.section.is_some()));
+        assert!(object.symbols.iter().any(|s| s.name == "puts" && s.section.is_none()));
+        assert!(!object.relocations.is_empty());
+    }
+
+    #[test]
+    fn relocation_section_zero_is_an_error() {
+        let mut bytes = object(SOURCE);
+        let rela = section_header(&bytes, SHT_RELA);
+        bytes[rela + 44..rela + 48].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(read_relocatable(&bytes).err().as_deref(), Some("Bad relocation section"));
+    }
+
+    #[test]
+    fn section_symbol_for_section_zero_is_an_error() {
+        let mut bytes = object(SOURCE);
+        let symtab = section_header(&bytes, SHT_SYMTAB);
+        let offset = get(&bytes, symtab + 24, 8).unwrap() as usize;
+        let size = get(&bytes, symtab + 32, 8).unwrap() as usize;
+        let symbol = (offset..offset + size).step_by(SYM_SIZE).find(|s| bytes[s + 4] & 0xf == STT_SECTION).unwrap();
+        bytes[symbol + 6..symbol + 8].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(read_relocatable(&bytes).err().as_deref(), Some("Bad section symbol"));
+    }
+}
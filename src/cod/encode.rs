@@ -0,0 +1,540 @@
+use std::collections::{HashMap, HashSet};
+
+use super::asm::{Address, AluOp, Cond, Directive, Instr, Operand, ShiftOp, Size, SseOp, X87Op};
+use super::elf::{Object, Relocation, Section, Symbol, Target};
+use super::elf::{R_X86_64_PC32, R_X86_64_PLT32, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS, SHT_PROGBITS};
+
+/*
+ * 汇编器
+ * 把生成器的指令序列直接编码成机器码，得到 ELF 目标文件的内容
+ * 跳转和调用都用 32 位偏移，不做长短跳转的选择
+ *
+ * 标签在本节中而且不是全局符号的，编码完之后直接填入偏移
+ * 其余的留给链接器: 调用和跳转用 R_X86_64_PLT32，数据用 R_X86_64_PC32
+ * .L 开头的标签不进入符号表，引用它们的重定位相对于所在的节
+*/
+pub fn assemble(program: &[Instr]) -> Object {
+    let mut assembler = Assembler::new();
+    for instr in program {
+        assembler.instr(instr);
+    }
+    assembler.finish()
+}
+
+const TEXT: usize = 0;
+const RODATA: usize = 3;
+
+// 需要填入标签位置的地方，offset 处是 4 字节的相对偏移
+#[derive(Debug, Clone)]
+struct Fixup {
+    section: usize,
+    offset: usize,
+    label: String,
+    branch: bool,
+    addend: i64,
+}
+
+// 操作数的 r/m 部分: 寄存器编号或者内存地址
+#[derive(Debug, Clone, Copy)]
+enum Rm<'a> {
+    Reg(u8),
+    Mem(&'a Address),
+}
+
+/*
+ * 带 ModRM 的指令的编码方式
+ * prefix: 66 F2 F3 这些要放在 REX 之前的前缀
+ * wide: REX.W，64 位操作数
+ * byte: 操作数是 8 位寄存器，编号 4-7 时要有 REX 才是 spl bpl sil dil
+*/
+#[derive(Debug, Clone, Copy)]
+struct Encoding<'a> {
+    prefix: Option<u8>,
+    wide: bool,
+    byte: bool,
+    opcode: &'a [u8],
+}
+
+impl<'a> Encoding<'a> {
+    // 按操作数大小选择 REX.W
+    fn sized(size: Size, opcode: &'a [u8]) -> Encoding<'a> {
+        Encoding { prefix: None, wide: size == Size::Qword, byte: size == Size::Byte, opcode }
+    }
+
+    fn plain(opcode: &'a [u8]) -> Encoding<'a> {
+        Encoding { prefix: None, wide: false, byte: false, opcode }
+    }
+}
+
+struct Assembler {
+    sections: Vec<Section>,
+    current: usize,
+    labels: HashMap<String, (usize, usize)>,
+    order: Vec<String>,
+    globals: HashSet<String>,
+    fixups: Vec<Fixup>,
+}
+
+fn register(op: &Operand) -> u8 {
+    match op {
+        Operand::Reg(r, _) => *r as u8,
+        Operand::Xmm(n) => *n,
+        _ => unreachable!("{:?} is not a register", op),
+    }
+}
+
+fn rm(op: &Operand) -> Rm<'_> {
+    match op {
+        Operand::Mem(_, address) => Rm::Mem(address),
+        op => Rm::Reg(register(op)),
+    }
+}
+
+fn size(op: &Operand) -> Size {
+    match op {
+        Operand::Reg(_, size) | Operand::Mem(size, _) => *size,
+        _ => unreachable!("{:?} has no size", op),
+    }
+}
+
+fn fits_i8(n: i64) -> bool {
+    n as i8 as i64 == n
+}
+
+fn fits_i32(n: i64) -> bool {
+    n as i32 as i64 == n
+}
+
+fn condition(cond: Cond) -> u8 {
+    match cond {
+        Cond::B => 0x2,
+        Cond::Ae => 0x3,
+        Cond::E => 0x4,
+        Cond::Ne => 0x5,
+        Cond::Be => 0x6,
+        Cond::A => 0x7,
+        Cond::P => 0xa,
+        Cond::Np => 0xb,
+        Cond::L => 0xc,
+        Cond::Ge => 0xd,
+        Cond::Le => 0xe,
+        Cond::G => 0xf,
+    }
+}
+
+// 运算在 ModRM 中的扩展操作码和 r/m, reg 形式的操作码
+fn alu(op: AluOp) -> (u8, u8) {
+    match op {
+        AluOp::Add => (0, 0x00),
+        AluOp::Or => (1, 0x08),
+        AluOp::And => (4, 0x20),
+        AluOp::Sub => (5, 0x28),
+        AluOp::Xor => (6, 0x30),
+        AluOp::Cmp => (7, 0x38),
+        AluOp::Imul => unreachable!("imul has its own encoding"),
+    }
+}
+
+// 移位指令 ModRM 中的 reg 字段
+fn shift(op: ShiftOp) -> u8 {
+    match op {
+        ShiftOp::Shl => 4,
+        ShiftOp::Sar => 7,
+    }
+}
+
+// SSE 指令的前缀和操作码 (0F 之后的字节)
+fn sse(op: SseOp) -> (Option<u8>, u8) {
+    match op {
+        SseOp::Movss => (Some(0xf3), 0x10),
+        SseOp::Movsd => (Some(0xf2), 0x10),
+        SseOp::Movd | SseOp::Movq => (Some(0x66), 0x6e),
+        SseOp::Addss => (Some(0xf3), 0x58),
+        SseOp::Addsd => (Some(0xf2), 0x58),
+        SseOp::Subss => (Some(0xf3), 0x5c),
+        SseOp::Subsd => (Some(0xf2), 0x5c),
+        SseOp::Mulss => (Some(0xf3), 0x59),
+        SseOp::Mulsd => (Some(0xf2), 0x59),
+        SseOp::Divss => (Some(0xf3), 0x5e),
+        SseOp::Divsd => (Some(0xf2), 0x5e),
+        SseOp::Ucomiss => (None, 0x2e),
+        SseOp::Ucomisd => (Some(0x66), 0x2e),
+        SseOp::Xorps => (None, 0x57),
+        SseOp::Xorpd => (Some(0x66), 0x57),
+        SseOp::Cvtss2sd => (Some(0xf3), 0x5a),
+        SseOp::Cvtsd2ss => (Some(0xf2), 0x5a),
+        SseOp::Cvtsi2ss => (Some(0xf3), 0x2a),
+        SseOp::Cvtsi2sd => (Some(0xf2), 0x2a),
+        SseOp::Cvttss2si => (Some(0xf3), 0x2c),
+        SseOp::Cvttsd2si => (Some(0xf2), 0x2c),
+    }
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        let sections = vec![
+            Section::new(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 1),
+            Section::new(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 1),
+            Section::new(".bss", SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 1),
+            Section::new(".rodata", SHT_PROGBITS, SHF_ALLOC, 1),
+            // 栈不可执行
+            Section::new(".note.GNU-stack", SHT_PROGBITS, 0, 1),
+        ];
+        Assembler {
+            sections,
+            current: TEXT,
+            labels: HashMap::new(),
+            order: Vec::new(),
+            globals: HashSet::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.sections[self.current].data.len()
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.sections[self.current].data.push(b);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.sections[self.current].data.extend_from_slice(bytes);
+    }
+
+    fn imm8(&mut self, n: i64) {
+        self.byte(n as i8 as u8);
+    }
+
+    fn imm32(&mut self, n: i64) {
+        self.bytes(&(n as u32).to_le_bytes());
+    }
+
+    // 4 字节的位置留给标签
+    fn fixup(&mut self, label: &str, branch: bool, addend: i64) {
+        self.fixups.push(Fixup { section: self.current, offset: self.offset(), label: label.to_string(), branch, addend });
+        self.imm32(0);
+    }
+
+    /*
+     * 带 ModRM 的指令: [前缀] [REX] 操作码 ModRM [SIB] [偏移]
+     * reg 是 ModRM 的 reg 字段 (寄存器编号或者扩展的操作码)
+     * trailing 是后面还有几个字节的立即数，相对 rip 的偏移从指令末尾算起
+    */
+    fn modrm(&mut self, e: Encoding, reg: u8, rm: Rm, trailing: i64) {
+        let base = match rm {
+            Rm::Reg(r) => r,
+            Rm::Mem(Address::Base(r, _)) => *r as u8,
+            Rm::Mem(Address::Rip(_)) => 0,
+        };
+        let rex = (e.wide as u8) << 3 | (reg >> 3) << 2 | (base >> 3);
+        let byte_register = |r: u8| (4..8).contains(&r);
+        let force = e.byte && (byte_register(reg) || matches!(rm, Rm::Reg(r) if byte_register(r)));
+
+        if let Some(prefix) = e.prefix {
+            self.byte(prefix);
+        }
+        if rex != 0 || force {
+            self.byte(0x40 | rex);
+        }
+        self.bytes(e.opcode);
+
+        let reg = (reg & 7) << 3;
+        match rm {
+            Rm::Reg(r) => self.byte(0xc0 | reg | (r & 7)),
+            Rm::Mem(Address::Base(_, offset)) => {
+                let low = base & 7;
+                // rbp r13 没有不带偏移的形式，rsp r12 需要 SIB
+                let mode = if *offset == 0 && low != 5 {
+                    0
+                } else if fits_i8(*offset as i64) {
+                    1
+                } else {
+                    2
+                };
+                self.byte(mode << 6 | reg | low);
+                if low == 4 {
+                    self.byte(0x24);
+                }
+                match mode {
+                    1 => self.imm8(*offset as i64),
+                    2 => self.imm32(*offset as i64),
+                    _ => {}
+                }
+            }
+            Rm::Mem(Address::Rip(label)) => {
+                self.byte(reg | 5);
+                self.fixup(label, false, -4 - trailing);
+            }
+        }
+    }
+
+    // 寄存器编号加在操作码上的指令 (push pop mov r, imm)
+    fn plus_register(&mut self, wide: bool, opcode: u8, r: u8) {
+        let rex = (wide as u8) << 3 | (r >> 3);
+        if rex != 0 {
+            self.byte(0x40 | rex);
+        }
+        self.byte(opcode + (r & 7));
+    }
+
+    fn directive(&mut self, directive: &Directive) {
+        match directive {
+            Directive::Global(name) => {
+                self.globals.insert(name.clone());
+            }
+            Directive::Text => self.current = TEXT,
+            Directive::Rodata => self.current = RODATA,
+            Directive::Align(n) => {
+                let align = 1u64 << n;
+                let section = &mut self.sections[self.current];
+                section.align = section.align.max(align);
+                while !(section.data.len() as u64).is_multiple_of(align) {
+                    section.data.push(0);
+                }
+            }
+            Directive::Byte(bytes) => self.bytes(bytes),
+            Directive::Long(n) => self.bytes(&n.to_le_bytes()),
+            Directive::Quad(n) => self.bytes(&n.to_le_bytes()),
+        }
+    }
+
+    fn mov(&mut self, a: &Operand, b: &Operand) {
+        match (a, b) {
+            (_, Operand::Reg(s, size)) => {
+                let opcode = if *size == Size::Byte { 0x88 } else { 0x89 };
+                self.modrm(Encoding::sized(*size, &[opcode]), *s as u8, rm(a), 0);
+            }
+            (Operand::Reg(d, size), Operand::Mem(_, _)) => {
+                let opcode = if *size == Size::Byte { 0x8a } else { 0x8b };
+                self.modrm(Encoding::sized(*size, &[opcode]), *d as u8, rm(b), 0);
+            }
+            // 64 位的立即数能用 32 位表示时用符号扩展或者零扩展的形式
+            (Operand::Reg(d, Size::Qword), Operand::Imm(n)) => {
+                let d = *d as u8;
+                if fits_i32(*n) {
+                    self.modrm(Encoding::sized(Size::Qword, &[0xc7]), 0, Rm::Reg(d), 4);
+                    self.imm32(*n);
+                } else if *n as u32 as i64 == *n {
+                    self.plus_register(false, 0xb8, d);
+                    self.imm32(*n);
+                } else {
+                    self.plus_register(true, 0xb8, d);
+                    self.bytes(&n.to_le_bytes());
+                }
+            }
+            (Operand::Reg(d, Size::Dword), Operand::Imm(n)) => {
+                self.plus_register(false, 0xb8, *d as u8);
+                self.imm32(*n);
+            }
+            (Operand::Reg(_, Size::Byte) | Operand::Mem(Size::Byte, _), Operand::Imm(n)) => {
+                self.modrm(Encoding::sized(Size::Byte, &[0xc6]), 0, rm(a), 1);
+                self.imm8(*n);
+            }
+            (Operand::Mem(size, _), Operand::Imm(n)) => {
+                self.modrm(Encoding::sized(*size, &[0xc7]), 0, rm(a), 4);
+                self.imm32(*n);
+            }
+            _ => unreachable!("Cannot encode mov {:?}, {:?}", a, b),
+        }
+    }
+
+    fn alu(&mut self, op: AluOp, a: &Operand, b: &Operand) {
+        let size = size(a);
+        if op == AluOp::Imul {
+            let d = register(a);
+            match b {
+                Operand::Imm(n) if fits_i8(*n) => {
+                    self.modrm(Encoding::sized(size, &[0x6b]), d, Rm::Reg(d), 1);
+                    self.imm8(*n);
+                }
+                Operand::Imm(n) => {
+                    self.modrm(Encoding::sized(size, &[0x69]), d, Rm::Reg(d), 4);
+                    self.imm32(*n);
+                }
+                _ => self.modrm(Encoding::sized(size, &[0x0f, 0xaf]), d, rm(b), 0),
+            }
+            return;
+        }
+
+        let (digit, base) = alu(op);
+        let byte = size == Size::Byte;
+        match b {
+            Operand::Imm(n) if byte => {
+                self.modrm(Encoding::sized(size, &[0x80]), digit, rm(a), 1);
+                self.imm8(*n);
+            }
+            Operand::Imm(n) if fits_i8(*n) => {
+                self.modrm(Encoding::sized(size, &[0x83]), digit, rm(a), 1);
+                self.imm8(*n);
+            }
+            Operand::Imm(n) => {
+                self.modrm(Encoding::sized(size, &[0x81]), digit, rm(a), 4);
+                self.imm32(*n);
+            }
+            Operand::Reg(s, _) => self.modrm(Encoding::sized(size, &[base + !byte as u8]), *s as u8, rm(a), 0),
+            _ => self.modrm(Encoding::sized(size, &[base + 2 + !byte as u8]), register(a), rm(b), 0),
+        }
+    }
+
+    fn sse(&mut self, op: SseOp, a: &Operand, b: &Operand) {
+        let (prefix, opcode) = sse(op);
+        // 写到内存或者通用寄存器的形式，操作数交换
+        let (opcode, reg, operand) = match op {
+            SseOp::Movss | SseOp::Movsd if a.is_memory() => (opcode + 1, b, a),
+            SseOp::Movd | SseOp::Movq if !matches!(a, Operand::Xmm(_)) => (0x7e, b, a),
+            _ => (opcode, a, b),
+        };
+        let wide = match op {
+            SseOp::Movq => true,
+            SseOp::Cvtsi2ss | SseOp::Cvtsi2sd => size(b) == Size::Qword,
+            SseOp::Cvttss2si | SseOp::Cvttsd2si => size(a) == Size::Qword,
+            _ => false,
+        };
+        let e = Encoding { prefix, wide, byte: false, opcode: &[0x0f, opcode] };
+        self.modrm(e, register(reg), rm(operand), 0);
+    }
+
+    // x87 访存指令，按内存中数的大小选择操作码
+    fn x87_memory(&mut self, a: &Operand, forms: &[(Size, u8, u8)]) {
+        let size = size(a);
+        let &(_, opcode, digit) = forms.iter().find(|(s, _, _)| *s == size).unwrap_or_else(|| unreachable!("{:?} sized x87 operand", size));
+        self.modrm(Encoding::plain(&[opcode]), digit, rm(a), 0);
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        match instr {
+            Instr::Label(label) => {
+                self.labels.insert(label.clone(), (self.current, self.offset()));
+                self.order.push(label.clone());
+            }
+            Instr::Directive(directive) => self.directive(directive),
+            Instr::Mov(a, b) => self.mov(a, b),
+            Instr::Movsx(a, b) => {
+                let e = match size(b) {
+                    Size::Dword => Encoding::sized(Size::Qword, &[0x63]),
+                    _ => Encoding { prefix: None, wide: size(a) == Size::Qword, byte: true, opcode: &[0x0f, 0xbe] },
+                };
+                self.modrm(e, register(a), rm(b), 0);
+            }
+            Instr::Movzx(a, b) => {
+                let e = Encoding { prefix: None, wide: size(a) == Size::Qword, byte: true, opcode: &[0x0f, 0xb6] };
+                self.modrm(e, register(a), rm(b), 0);
+            }
+            Instr::Lea(a, address) => self.modrm(Encoding::sized(size(a), &[0x8d]), register(a), Rm::Mem(address), 0),
+            Instr::Push(Operand::Reg(r, _)) => self.plus_register(false, 0x50, *r as u8),
+            Instr::Push(Operand::Imm(n)) if fits_i8(*n) => {
+                self.byte(0x6a);
+                self.imm8(*n);
+            }
+            Instr::Push(Operand::Imm(n)) => {
+                self.byte(0x68);
+                self.imm32(*n);
+            }
+            Instr::Push(a) => self.modrm(Encoding::plain(&[0xff]), 6, rm(a), 0),
+            Instr::Pop(Operand::Reg(r, _)) => self.plus_register(false, 0x58, *r as u8),
+            Instr::Pop(a) => self.modrm(Encoding::plain(&[0x8f]), 0, rm(a), 0),
+            Instr::Alu(op, a, b) => self.alu(*op, a, b),
+            Instr::Neg(a) => {
+                let opcode = if size(a) == Size::Byte { 0xf6 } else { 0xf7 };
+                self.modrm(Encoding::sized(size(a), &[opcode]), 3, rm(a), 0);
+            }
+            Instr::Idiv(a) => {
+                let opcode = if size(a) == Size::Byte { 0xf6 } else { 0xf7 };
+                self.modrm(Encoding::sized(size(a), &[opcode]), 7, rm(a), 0);
+            }
+            Instr::Shift(op, a) => {
+                let opcode = if size(a) == Size::Byte { 0xd2 } else { 0xd3 };
+                self.modrm(Encoding::sized(size(a), &[opcode]), shift(*op), rm(a), 0);
+            }
+            // 移 1 位有不带立即数的形式
+            Instr::ShiftImm(op, a, 1) => {
+                let opcode = if size(a) == Size::Byte { 0xd0 } else { 0xd1 };
+                self.modrm(Encoding::sized(size(a), &[opcode]), shift(*op), rm(a), 0);
+            }
+            Instr::ShiftImm(op, a, n) => {
+                let opcode = if size(a) == Size::Byte { 0xc0 } else { 0xc1 };
+                self.modrm(Encoding::sized(size(a), &[opcode]), shift(*op), rm(a), 1);
+                self.imm8(*n as i64);
+            }
+            Instr::Cqo => self.bytes(&[0x48, 0x99]),
+            Instr::Set(cond, a) => {
+                let e = Encoding { prefix: None, wide: false, byte: true, opcode: &[0x0f, 0x90 + condition(*cond)] };
+                self.modrm(e, 0, rm(a), 0);
+            }
+            Instr::Jmp(label) => {
+                self.byte(0xe9);
+                self.fixup(label, true, -4);
+            }
+            Instr::Jcc(cond, label) => {
+                self.bytes(&[0x0f, 0x80 + condition(*cond)]);
+                self.fixup(label, true, -4);
+            }
+            Instr::Call(name) => {
+                self.byte(0xe8);
+                self.fixup(name, true, -4);
+            }
+            Instr::Ret => self.byte(0xc3),
+            Instr::Syscall => self.bytes(&[0x0f, 0x05]),
+            Instr::Sse(op, a, b) => self.sse(*op, a, b),
+            Instr::Fld(a) => self.x87_memory(a, &[(Size::Dword, 0xd9, 0), (Size::Qword, 0xdd, 0), (Size::Tbyte, 0xdb, 5)]),
+            Instr::Fild(a) => self.x87_memory(a, &[(Size::Dword, 0xdb, 0), (Size::Qword, 0xdf, 5)]),
+            Instr::Fstp(a) => self.x87_memory(a, &[(Size::Dword, 0xd9, 3), (Size::Qword, 0xdd, 3), (Size::Tbyte, 0xdb, 7)]),
+            Instr::Fisttp(a) => self.x87_memory(a, &[(Size::Dword, 0xdb, 1), (Size::Qword, 0xdd, 1)]),
+            Instr::Fchs => self.bytes(&[0xd9, 0xe0]),
+            Instr::Fxch => self.bytes(&[0xd9, 0xc9]),
+            Instr::Fucomip => self.bytes(&[0xdf, 0xe9]),
+            Instr::FstpTop => self.bytes(&[0xdd, 0xd8]),
+            Instr::X87(op) => {
+                let modrm = match op {
+                    X87Op::Faddp => 0xc1,
+                    X87Op::Fmulp => 0xc9,
+                    X87Op::Fsubp => 0xe9,
+                    X87Op::Fdivp => 0xf9,
+                };
+                self.bytes(&[0xde, modrm]);
+            }
+        }
+    }
+
+    /*
+     * 填入标签的位置，得到符号表和重定位
+     * .L 开头的是局部标签，其余的标签都是符号
+    */
+    fn finish(mut self) -> Object {
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for label in self.order.iter().filter(|l| !l.starts_with(".L")) {
+            let (section, offset) = self.labels[label];
+            index.insert(label.clone(), symbols.len());
+            symbols.push(Symbol { name: label.clone(), section: Some(section), value: offset as u64, global: self.globals.contains(label) });
+        }
+
+        let mut relocations = Vec::new();
+        for fixup in std::mem::take(&mut self.fixups) {
+            let kind = if fixup.branch { R_X86_64_PLT32 } else { R_X86_64_PC32 };
+            let (target, addend) = match self.labels.get(&fixup.label) {
+                // 本节中的局部标签，直接填入相对偏移
+                Some(&(section, offset)) if section == fixup.section && !self.globals.contains(&fixup.label) => {
+                    let value = offset as i64 + fixup.addend - fixup.offset as i64;
+                    let data = &mut self.sections[fixup.section].data;
+                    data[fixup.offset..fixup.offset + 4].copy_from_slice(&(value as i32).to_le_bytes());
+                    continue;
+                }
+                Some(&(section, offset)) if fixup.label.starts_with(".L") => (Target::Section(section), offset as i64 + fixup.addend),
+                Some(_) => (Target::Symbol(index[&fixup.label]), fixup.addend),
+                // 未定义的符号
+                None => {
+                    let symbol = *index.entry(fixup.label.clone()).or_insert_with(|| {
+                        symbols.push(Symbol { name: fixup.label.clone(), section: None, value: 0, global: true });
+                        symbols.len() - 1
+                    });
+                    (Target::Symbol(symbol), fixup.addend)
+                }
+            };
+            relocations.push(Relocation { section: fixup.section, offset: fixup.offset as u64, target, kind, addend });
+        }
+
+        Object { sections: self.sections, symbols, relocations }
+    }
+}
@@ -0,0 +1,309 @@
+use std::cmp::Ordering;
+
+use super::ir::Ty;
+
+/*
+ * 浮点字面量 -> 二进制表示
+ * 支持十进制(1.5e3)和十六进制(0x1.8p3)写法
+ * 用大整数精确计算，按目标精度就近舍入(ties to even)
+*/
+
+/*
+ * 简单的无符号大整数，低位在前
+*/
+#[derive(Debug, Clone)]
+struct BigUint(Vec<u32>);
+
+impl BigUint {
+    fn from_u64(n: u64) -> Self {
+        let mut big = BigUint(vec![n as u32, (n >> 32) as u32]);
+        big.trim();
+        big
+    }
+
+    fn trim(&mut self) {
+        while let Some(&0) = self.0.last() {
+            self.0.pop();
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn bit_len(&self) -> usize {
+        match self.0.last() {
+            Some(top) => self.0.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn mul_add_small(&mut self, m: u32, a: u32) {
+        let mut carry = a as u64;
+        for digit in self.0.iter_mut() {
+            let v = *digit as u64 * m as u64 + carry;
+            *digit = v as u32;
+            carry = v >> 32;
+        }
+        if carry != 0 {
+            self.0.push(carry as u32);
+        }
+        self.trim();
+    }
+
+    fn shl(&mut self, n: usize) {
+        if self.is_zero() {
+            return;
+        }
+        let (words, bits) = (n / 32, n % 32);
+        if bits != 0 {
+            let mut carry = 0;
+            for digit in self.0.iter_mut() {
+                let v = *digit;
+                *digit = (v << bits) | carry;
+                carry = v >> (32 - bits);
+            }
+            if carry != 0 {
+                self.0.push(carry);
+            }
+        }
+        let mut shifted = vec![0; words];
+        shifted.append(&mut self.0);
+        self.0 = shifted;
+    }
+
+    fn compare(&self, other: &BigUint) -> Ordering {
+        if self.0.len() != other.0.len() {
+            return self.0.len().cmp(&other.0.len());
+        }
+        for (a, b) in self.0.iter().rev().zip(other.0.iter().rev()) {
+            if a != b {
+                return a.cmp(b);
+            }
+        }
+        Ordering::Equal
+    }
+
+    // self -= other, 要求 self >= other
+    fn sub(&mut self, other: &BigUint) {
+        let mut borrow = 0i64;
+        for i in 0..self.0.len() {
+            let b = *other.0.get(i).unwrap_or(&0) as i64;
+            let mut v = self.0[i] as i64 - b - borrow;
+            if v < 0 {
+                v += 1 << 32;
+                borrow = 1;
+            } else {
+                borrow = 0;
+            }
+            self.0[i] = v as u32;
+        }
+        self.trim();
+    }
+}
+
+/*
+ * 字面量的精确值 = num / den * 2^exp2
+*/
+struct Exact {
+    num: BigUint,
+    den: BigUint,
+    exp2: i64,
+}
+
+// 指数过大时直接视为无穷或0，避免大整数爆炸
+const EXPONENT_LIMIT: i64 = 6000;
+
+fn parse_exact(literal: &str) -> Option<Exact> {
+    let lower = literal.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        // 十六进制: 尾数 p 二进制指数
+        let (mantissa, exponent) = match hex.find('p') {
+            Some(i) => (&hex[..i], hex[i + 1..].parse::<i64>().unwrap_or(0)),
+            None => (hex, 0),
+        };
+        let mut num = BigUint::from_u64(0);
+        let mut frac_digits = 0;
+        let mut seen_point = false;
+        for c in mantissa.chars() {
+            if c == '.' {
+                seen_point = true;
+                continue;
+            }
+            num.mul_add_small(16, c.to_digit(16)?);
+            if seen_point {
+                frac_digits += 1;
+            }
+        }
+        return Some(Exact { num, den: BigUint::from_u64(1), exp2: exponent.clamp(-EXPONENT_LIMIT * 4, EXPONENT_LIMIT * 4) - 4 * frac_digits });
+    }
+
+    // 十进制: 尾数 e 十进制指数
+    let (mantissa, exponent) = match lower.find('e') {
+        Some(i) => (&lower[..i], lower[i + 1..].parse::<i64>().unwrap_or(0)),
+        None => (&lower[..], 0),
+    };
+    let mut num = BigUint::from_u64(0);
+    let mut frac_digits = 0;
+    let mut seen_point = false;
+    for c in mantissa.chars() {
+        if c == '.' {
+            seen_point = true;
+            continue;
+        }
+        num.mul_add_small(10, c.to_digit(10)?);
+        if seen_point {
+            frac_digits += 1;
+        }
+    }
+    let exp10 = (exponent - frac_digits).clamp(-EXPONENT_LIMIT, EXPONENT_LIMIT);
+
+    let mut den = BigUint::from_u64(1);
+    for _ in 0..exp10.abs() {
+        if exp10 > 0 {
+            num.mul_add_small(10, 0);
+        } else {
+            den.mul_add_small(10, 0);
+        }
+    }
+
+    Some(Exact { num, den, exp2: 0 })
+}
+
+/*
+ * 目标格式
+ * precision 包含整数位
+*/
+struct Format {
+    precision: i64,
+    emin: i64,
+    emax: i64,
+}
+
+const SINGLE: Format = Format { precision: 24, emin: -126, emax: 127 };
+const DOUBLE: Format = Format { precision: 53, emin: -1022, emax: 1023 };
+const EXTENDED: Format = Format { precision: 64, emin: -16382, emax: 16383 };
+const QUAD: Format = Format { precision: 113, emin: -16382, emax: 16383 };
+
+/*
+ * 取出 precision 位有效数字并舍入
+ * 返回 (尾数, 指数) 值 = 尾数 * 2^指数，尾数不超过 precision 位
+ * 尾数为0表示下溢
+*/
+fn round(exact: Exact, format: &Format) -> (u128, i64) {
+    let Exact { mut num, mut den, exp2 } = exact;
+
+    // 多取3位用于舍入
+    let keep = format.precision + 3;
+
+    // 让 num/den 落在 [2^(keep-1), 2^keep)
+    let mut shift = keep - 1 - (num.bit_len() as i64 - den.bit_len() as i64);
+    if shift >= 0 {
+        num.shl(shift as usize);
+    } else {
+        den.shl((-shift) as usize);
+    }
+    let mut top = den.clone();
+    top.shl((keep - 1) as usize);
+    if num.compare(&top) == Ordering::Less {
+        num.shl(1);
+        shift += 1;
+    }
+
+    // 逐位长除法
+    let mut q: u128 = 0;
+    for i in (0..keep).rev() {
+        let mut t = den.clone();
+        t.shl(i as usize);
+        if num.compare(&t) != Ordering::Less {
+            num.sub(&t);
+            q |= 1 << i;
+        }
+    }
+    let sticky = !num.is_zero();
+
+    // 值 ~= q * 2^e, 最高位对应的指数为 top_exp
+    let e = exp2 - shift;
+    let top_exp = e + keep - 1;
+
+    // 次正规数要丢掉更多的位
+    let mut drop = keep - format.precision;
+    if top_exp < format.emin {
+        drop += format.emin - top_exp;
+    }
+    if drop > keep {
+        return (0, 0);
+    }
+
+    let mantissa = q >> drop;
+    let rest = q & ((1u128 << drop) - 1);
+    let half = 1u128 << (drop - 1);
+    let round_up = rest > half || (rest == half && (sticky || mantissa & 1 == 1));
+    let mantissa = if round_up { mantissa + 1 } else { mantissa };
+
+    (mantissa, e + drop)
+}
+
+/*
+ * 返回字面量在目标类型下的位模式
+ * float 低32位，double 低64位，long double 低80位
+*/
+pub fn float_bits(literal: &str, ty: Ty) -> u128 {
+    match ty {
+        Ty::F32 => encode(literal, &SINGLE, false),
+        Ty::F64 => encode(literal, &DOUBLE, false),
+        // x87 扩展精度有显式的整数位
+        Ty::F80 => encode(literal, &EXTENDED, true),
+        _ => panic!("Not a floating type: {:?}", ty),
+    }
+}
+
+// AArch64 的 long double 是 IEEE 四精度，128位
+pub fn quad_bits(literal: &str) -> u128 {
+    encode(literal, &QUAD, false)
+}
+
+/*
+ * 按格式编码，explicit 表示整数位要写出来 (x87 扩展精度)
+*/
+fn encode(literal: &str, format: &Format, explicit: bool) -> u128 {
+    let exact = match parse_exact(literal) {
+        Some(exact) => exact,
+        None => panic!("Invalid floating constant {}", literal),
+    };
+    if exact.num.is_zero() {
+        return 0;
+    }
+
+    let (mut mantissa, mut exponent) = round(exact, format);
+    if mantissa == 0 {
+        return 0;
+    }
+    // 舍入进位后可能多出一位
+    if mantissa >> format.precision != 0 {
+        mantissa >>= 1;
+        exponent += 1;
+    }
+
+    let top_exp = exponent + (128 - mantissa.leading_zeros()) as i64 - 1;
+    let fraction_bits = if explicit { format.precision } else { format.precision - 1 };
+
+    if top_exp > format.emax {
+        // 溢出为无穷，指数域全为1
+        let infinity = ((format.emax - format.emin + 2) as u128) << fraction_bits;
+        return if explicit { infinity | 1 << (format.precision - 1) } else { infinity };
+    }
+
+    // 次正规数，指数域为0
+    if top_exp < format.emin {
+        return mantissa;
+    }
+
+    let biased = (top_exp - format.emin + 1) as u128;
+    if explicit {
+        (biased << fraction_bits) | mantissa
+    } else {
+        (biased << fraction_bits) | (mantissa & ((1 << fraction_bits) - 1))
+    }
+}
@@ -0,0 +1,217 @@
+use super::token::Operator;
+use super::typed_ast::*;
+use super::types::Type;
+
+/*
+ * 常量折叠和代数化简
+ * 在语义分析之后、生成 IR 之前，对带类型的语法树进行
+ * 操作数都是常量的一元、二元、条件表达式和类型转换在编译时求值，结果和运行时完全相同
+ *
+ * 现在的整数类型 (int char) 都是有符号的，int 是64位
+ * 有符号溢出时给出警告，按补码回绕 (和运行时的结果相同)
+ * 除以0、移位的位数超出范围时给出警告，不折叠，留到运行时
+ *
+ * 整数的 x*1 x+0 x-0 x/1 x|0 x^0 x<<0 x>>0 化简为 x，x*2 变成 x<<1
+ * 浮点数不化简，x+0.0 在 x 为 -0.0 时结果不同
+*/
+pub fn fold(program: &mut Program) {
+    for function in program.functions.iter_mut() {
+        let body = std::mem::take(&mut function.body);
+        function.body = body.into_iter().map(fold_statement).collect();
+    }
+}
+
+fn fold_statement(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Expression(expr) => Stmt::Expression(fold_expression(expr)),
+        Stmt::Declaration(id, init) => Stmt::Declaration(id, init.map(fold_expression)),
+        Stmt::Return(expr) => Stmt::Return(expr.map(fold_expression)),
+        Stmt::TailCall(expr) => Stmt::TailCall(fold_expression(expr)),
+        Stmt::If(condition, if_body, else_body) => Stmt::If(fold_expression(condition), fold_box(if_body), else_body.map(fold_box)),
+        Stmt::Block(stmts) => Stmt::Block(stmts.into_iter().map(fold_statement).collect()),
+        Stmt::For(init, condition, post_expression, body) => {
+            Stmt::For(init.map(fold_box), fold_expression(condition), post_expression.map(fold_expression), fold_box(body))
+        }
+        Stmt::While(condition, body) => Stmt::While(fold_expression(condition), fold_box(body)),
+        Stmt::DoWhile(body, condition) => Stmt::DoWhile(fold_box(body), fold_expression(condition)),
+        Stmt::Break | Stmt::Continue => stmt,
+    }
+}
+
+fn fold_box(stmt: Box<Stmt>) -> Box<Stmt> {
+    Box::new(fold_statement(*stmt))
+}
+
+fn constant(expr: &Expr) -> Option<i64> {
+    match expr.kind {
+        ExprKind::Constant(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::Int | Type::Char)
+}
+
+// 整数常量，char 截断到8位
+fn integer(n: i64, ty: Type) -> Expr {
+    let n = if ty == Type::Char { n as i8 as i64 } else { n };
+    Expr::new(ExprKind::Constant(n), ty)
+}
+
+fn fold_expression(expr: Expr) -> Expr {
+    let Expr { kind, ty } = expr;
+    let kind = match kind {
+        ExprKind::Unary(op, expr) => return fold_unary(op, fold_expression(*expr), ty),
+        ExprKind::Binary(op, lhs, rhs) => return fold_binary(op, fold_expression(*lhs), fold_expression(*rhs), ty),
+        ExprKind::Cast(expr) => return fold_cast(fold_expression(*expr), ty),
+
+        // 条件是常量时只留下会被求值的分支
+        ExprKind::Ternary(e1, e2, e3) => {
+            let e1 = fold_expression(*e1);
+            let e2 = fold_expression(*e2);
+            let e3 = fold_expression(*e3);
+            match constant(&e1) {
+                Some(0) => return e3,
+                Some(_) => return e2,
+                None => ExprKind::Ternary(Box::new(e1), Box::new(e2), Box::new(e3)),
+            }
+        }
+
+        ExprKind::Assign(lhs, rhs) => ExprKind::Assign(lhs, Box::new(fold_expression(*rhs))),
+        ExprKind::CompoundAssign(op, lhs, rhs) => ExprKind::CompoundAssign(op, lhs, Box::new(fold_expression(*rhs))),
+        ExprKind::Call(name, signature, args) => ExprKind::Call(name, signature, args.into_iter().map(fold_expression).collect()),
+        kind => kind,
+    };
+    Expr::new(kind, ty)
+}
+
+fn fold_unary(op: Operator, expr: Expr, ty: Type) -> Expr {
+    match (op, constant(&expr)) {
+        (Operator::LogicalNegation, Some(n)) => integer((n == 0) as i64, ty),
+        (Operator::BitwiseComplement, Some(n)) => integer(!n, ty),
+        (Operator::Minus, Some(n)) => {
+            let result = n.checked_neg().unwrap_or_else(|| {
+                eprintln!("warning: integer overflow in expression");
+                n.wrapping_neg()
+            });
+            integer(result, ty)
+        }
+        _ => Expr::new(ExprKind::Unary(op, Box::new(expr)), ty),
+    }
+}
+
+/*
+ * 常量的类型转换
+ * 整数之间直接截断，非负整数转换成浮点数时写成浮点常量，由生成代码时按类型舍入
+ * 转换成指针的保留 (空指针)
+*/
+fn fold_cast(expr: Expr, ty: Type) -> Expr {
+    match constant(&expr) {
+        Some(n) if is_integer(&ty) => integer(n, ty),
+        Some(n) if ty.is_floating() && n >= 0 => Expr::new(ExprKind::FloatConstant(n.to_string()), ty),
+        _ => Expr::new(ExprKind::Cast(Box::new(expr)), ty),
+    }
+}
+
+// 条件的真值 0 或 1
+fn truth(expr: Expr) -> Expr {
+    match constant(&expr) {
+        Some(n) => integer((n != 0) as i64, Type::Int),
+        None => {
+            let zero = Expr::new(ExprKind::Constant(0), expr.ty.clone());
+            Expr::new(ExprKind::Binary(Operator::NotEqual, Box::new(expr), Box::new(zero)), Type::Int)
+        }
+    }
+}
+
+fn fold_binary(op: Operator, lhs: Expr, rhs: Expr, ty: Type) -> Expr {
+    // && || 的左边是常量时，右边可能不会被求值
+    match (op, constant(&lhs)) {
+        (Operator::LogicalAnd, Some(0)) => return integer(0, ty),
+        (Operator::LogicalAnd, Some(_)) => return truth(rhs),
+        (Operator::LogicalOr, Some(0)) => return truth(rhs),
+        (Operator::LogicalOr, Some(_)) => return integer(1, ty),
+        _ => {}
+    }
+
+    if let (Some(a), Some(b)) = (constant(&lhs), constant(&rhs)) {
+        if let Some(n) = evaluate(op, a, b) {
+            return integer(n, ty);
+        }
+    }
+
+    if is_integer(&ty) {
+        return simplify(op, lhs, rhs, ty);
+    }
+    Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), ty)
+}
+
+/*
+ * 两个整数常量的运算
+ * 溢出时警告并回绕，除以0和移位超出范围时警告并返回 None
+*/
+fn evaluate(op: Operator, a: i64, b: i64) -> Option<i64> {
+    let overflow = |(n, overflowed): (i64, bool)| {
+        if overflowed {
+            eprintln!("warning: integer overflow in expression");
+        }
+        n
+    };
+
+    Some(match op {
+        Operator::Plus => overflow(a.overflowing_add(b)),
+        Operator::Minus => overflow(a.overflowing_sub(b)),
+        Operator::Multiplication => overflow(a.overflowing_mul(b)),
+        Operator::Division | Operator::Modulo if b == 0 => {
+            eprintln!("warning: division by zero");
+            return None;
+        }
+        Operator::Division => overflow(a.overflowing_div(b)),
+        Operator::Modulo => overflow(a.overflowing_rem(b)),
+        Operator::BitwiseShiftLeft | Operator::BitwiseShiftRight if !(0..64).contains(&b) => {
+            let direction = if op == Operator::BitwiseShiftLeft { "left" } else { "right" };
+            if b < 0 {
+                eprintln!("warning: {} shift count is negative", direction);
+            } else {
+                eprintln!("warning: {} shift count >= width of type", direction);
+            }
+            return None;
+        }
+        // 移出的位中有和符号位不同的就是溢出
+        Operator::BitwiseShiftLeft => overflow((a << b, (a << b) >> b != a)),
+        Operator::BitwiseShiftRight => a >> b,
+        Operator::BitwiseAnd => a & b,
+        Operator::BitwiseOr => a | b,
+        Operator::BitwiseXor => a ^ b,
+        Operator::Equal => (a == b) as i64,
+        Operator::NotEqual => (a != b) as i64,
+        Operator::LessThan => (a < b) as i64,
+        Operator::LessThanOrEqual => (a <= b) as i64,
+        Operator::GreaterThan => (a > b) as i64,
+        Operator::GreaterThanOrEqual => (a >= b) as i64,
+        _ => unreachable!("Unexpected binary operator {:?}", op),
+    })
+}
+
+/*
+ * 整数运算的代数化简
+ * 一边是常量时，结果就是另一边或者更便宜的运算
+*/
+fn simplify(op: Operator, lhs: Expr, rhs: Expr, ty: Type) -> Expr {
+    let shift_by_one = |expr: Expr| {
+        let one = Expr::new(ExprKind::Constant(1), ty.clone());
+        Expr::new(ExprKind::Binary(Operator::BitwiseShiftLeft, Box::new(expr), Box::new(one)), ty.clone())
+    };
+
+    match (op, constant(&lhs), constant(&rhs)) {
+        (Operator::Plus | Operator::Minus | Operator::BitwiseOr | Operator::BitwiseXor, _, Some(0))
+        | (Operator::BitwiseShiftLeft | Operator::BitwiseShiftRight, _, Some(0))
+        | (Operator::Multiplication | Operator::Division, _, Some(1)) => lhs,
+        (Operator::Plus | Operator::BitwiseOr | Operator::BitwiseXor, Some(0), _)
+        | (Operator::Multiplication, Some(1), _) => rhs,
+        (Operator::Multiplication, _, Some(2)) => shift_by_one(lhs),
+        (Operator::Multiplication, Some(2), _) => shift_by_one(rhs),
+        _ => Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), ty),
+    }
+}
@@ -0,0 +1,190 @@
+use super::asm::Reg;
+use super::ir::{Function, Ty};
+use super::regalloc::CALLEE_SAVED;
+use super::stack::{align_to, allocate_below};
+
+/*
+ * 栈帧布局
+ * 生成代码之前给每个栈槽和每个值分配一个固定的 rbp 偏移
+ * 不相交的作用域在生成 IR 时已经共用了栈槽
+ * 序言中只需要一条 sub rsp,N
+ *
+ * rbp+16 ...      栈上传来的参数
+ * rbp+8           返回地址
+ * rbp             调用者的 rbp
+ * rbp-176 ~ rbp   可变参数的寄存器保存区 (只有可变参数函数有)
+ * ...             保存的被调用者保存的寄存器
+ * ...             栈槽 (局部变量)
+ * ...             没有分到寄存器的值
+ * rbp-size        rsp
+*/
+
+// 整数参数寄存器的个数 rdi rsi rdx rcx r8 r9
+pub const GP_ARG_REGS: usize = 6;
+// 浮点参数寄存器的个数 xmm0 ~ xmm7
+pub const SSE_ARG_REGS: usize = 8;
+// 寄存器保存区的大小
+const VA_SAVE_SIZE: isize = GP_ARG_REGS as isize * 8 + SSE_ARG_REGS as isize * 16;
+
+/*
+ * 参数的位置
+ * 整数寄存器
+ * 浮点寄存器
+ * 栈上 (相对于第一个栈参数的偏移)
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum ArgLocation {
+    Gp(usize),
+    Sse(usize),
+    Stack(isize),
+}
+
+/*
+ * System V 调用约定
+ * 整数依次使用 rdi rsi rdx rcx r8 r9
+ * float double 依次使用 xmm0 ~ xmm7
+ * long double 和用完寄存器的参数放在栈上
+ * 返回每个参数的位置和栈参数占用的大小 (16字节对齐)
+*/
+pub fn classify_arguments(types: &[Ty]) -> (Vec<ArgLocation>, isize) {
+    let mut gp = 0;
+    let mut sse = 0;
+    let mut offset = 0;
+
+    let locations = types.iter().map(|t| match t {
+        Ty::F80 => {
+            // long double 按16字节对齐
+            offset = align_to(offset, 16) + 16;
+            ArgLocation::Stack(offset - 16)
+        }
+        Ty::F32 | Ty::F64 if sse < SSE_ARG_REGS => {
+            sse += 1;
+            ArgLocation::Sse(sse - 1)
+        }
+        t if !t.is_float() && gp < GP_ARG_REGS => {
+            gp += 1;
+            ArgLocation::Gp(gp - 1)
+        }
+        _ => {
+            offset += 8;
+            ArgLocation::Stack(offset - 8)
+        }
+    }).collect();
+
+    (locations, align_to(offset, 16))
+}
+
+/*
+ * 尾调用时栈参数写到调用者自己收到的栈参数的位置
+ * 被调用函数的栈参数不能比调用者的多
+*/
+pub fn tail_call_fits(caller: &[Ty], callee: &[Ty]) -> bool {
+    classify_arguments(callee).1 <= classify_arguments(caller).1
+}
+
+/*
+ * 可变参数函数的寄存器保存区
+ * 保存区相对 rbp 的位置 (6个整数寄存器 + 8个xmm寄存器，共176字节)
+ * 第一个未命名整数参数在保存区中的偏移 gp_offset
+ * 第一个未命名浮点参数在保存区中的偏移 fp_offset
+ * 第一个未命名栈参数相对 rbp 的位置
+*/
+#[derive(Debug, Clone, Default)]
+pub struct VaArea {
+    pub save_offset: isize,
+    pub gp_offset: isize,
+    pub fp_offset: isize,
+    pub overflow_offset: isize,
+}
+
+/*
+ * 值的位置
+ * 寄存器
+ * 栈帧中 (相对 rbp 的偏移)
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(Reg),
+    Stack(isize),
+}
+
+/*
+ * 一个函数的栈帧
+ * 每个栈槽相对 rbp 的偏移
+ * 每个值的位置，已经被删除的值没有位置
+ * 每个参数传进来的位置
+ * 序言中 rsp 要减去的大小 (16字节对齐)
+ * 可变参数的保存区
+ * 用到的被调用者保存的寄存器，以及保存它们的位置
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub slots: Vec<isize>,
+    pub values: Vec<Option<Location>>,
+    pub params: Vec<ArgLocation>,
+    pub size: isize,
+    pub va_area: Option<VaArea>,
+    pub saved: Vec<(Reg, isize)>,
+}
+
+/*
+ * 计算函数的栈帧
+ * registers 是寄存器分配的结果，没有分到寄存器的值放在栈帧中
+ * 值在寄存器中都是64位的，每个值占8字节，long double 占16字节
+*/
+pub fn layout(function: &Function, registers: &[Option<Reg>]) -> Frame {
+    let types: Vec<Ty> = function.params.iter().map(|p| function.ty(*p)).collect();
+    let (params, _) = classify_arguments(&types);
+    let mut used = 0;
+
+    let va_area = if function.variadic {
+        used += VA_SAVE_SIZE;
+
+        let gp = params.iter().filter(|l| matches!(l, ArgLocation::Gp(_))).count() as isize;
+        let sse = params.iter().filter(|l| matches!(l, ArgLocation::Sse(_))).count() as isize;
+        // 命名的栈参数之后就是未命名的栈参数
+        let named_stack = types.iter().zip(params.iter()).map(|(t, l)| match l {
+            ArgLocation::Stack(offset) => offset + t.size().max(8) as isize,
+            _ => 0,
+        }).max().unwrap_or(0);
+
+        Some(VaArea {
+            save_offset: -used,
+            gp_offset: gp * 8,
+            fp_offset: GP_ARG_REGS as isize * 8 + sse * 16,
+            overflow_offset: 16 + named_stack,
+        })
+    } else {
+        None
+    };
+
+    let mut saved: Vec<(Reg, isize)> = Vec::new();
+    for r in CALLEE_SAVED.iter() {
+        if registers.contains(&Some(*r)) {
+            saved.push((*r, allocate_below(&mut used, 8, 8)));
+        }
+    }
+
+    let slots = function.slots.iter().map(|slot| {
+        allocate_below(&mut used, slot.size as isize, slot.align as isize)
+    }).collect();
+
+    let defined = function.def_blocks();
+    let values = function.values.iter().zip(registers.iter()).zip(defined.iter()).map(|((ty, register), def)| match (register, def) {
+        (_, None) => None,
+        (Some(r), _) => Some(Location::Register(*r)),
+        (None, _) => {
+            let size = ty.size().max(8) as isize;
+            Some(Location::Stack(allocate_below(&mut used, size, size)))
+        }
+    }).collect();
+
+    Frame {
+        slots,
+        values,
+        params,
+        size: align_to(used, 16),
+        va_area,
+        saved,
+    }
+}
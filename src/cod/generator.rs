@@ -1,14 +1,24 @@
-use super::ast::*;
+use super::asm::{Address, AluOp, Cond, Directive, Operand, Reg, ShiftOp, Size, SseOp, X87Op};
+use super::asm::{AL, DL, EAX, EDX, RAX, RBP, RCX, RDI, RDX, RSP, XMM0, XMM1};
+use super::asm::Instr as X86;
 use super::context::Context;
-use super::token::*;
+use super::float::float_bits;
+use super::frame::{classify_arguments, layout, ArgLocation, SSE_ARG_REGS};
+use super::ir::*;
+use super::ir::phi::{has_phi, phi_moves, sequence};
+use super::peephole::optimize;
+use super::regalloc::allocate;
 
 static mut COUNTER: u32 = 0;
 
+// 整数参数寄存器
+const ARG_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
 /*
  * 创建唯一数
 */
 fn generate_suffix() -> String {
-    let n: u32; 
+    let n: u32;
     unsafe {
         n = COUNTER;
         COUNTER += 1;
@@ -35,453 +45,837 @@ pub fn add_suffix(word: &str, suffix: &str) -> String {
     word
 }
 
-/*
- * 层级遍历 
- * Ast->AstNodes
-*/
-pub fn generate(ast: &Ast) {
-    println!(".inter_syntax noprefix");
+// 内存操作数
+fn memory(size: Size, address: Address) -> Operand {
+    Operand::Mem(size, address)
+}
 
-    match ast {
-        Ast::Ast(asts) => {
-            generate_astnodes(asts);
-        }
-    }
+// 栈顶 [rsp+offset]
+fn stack(offset: isize) -> Address {
+    Address::Base(Reg::Rsp, offset)
 }
 
 /*
- * 层级遍历 
- * AstNodes->AstNode
+ * 层级遍历
+ * Module->Function
+ * IR 已经通过检查，这里只需要按类型选择指令
+ * 返回整个文件的指令，由调用者按选择的语法输出
 */
-fn generate_astnodes(asts: &Vec<AstNode>) {
-    asts.iter().for_each(|AstNode::AstNode(name,params, body) | {
-        generate_astnode(name, params, body)
-    });
+pub fn generate(module: &Module) -> Vec<X86> {
+    let mut program = Vec::new();
+
+    // 字符串常量
+    if !module.strings.is_empty() {
+        program.push(X86::Directive(Directive::Rodata));
+        for (i, s) in module.strings.iter().enumerate() {
+            let bytes = s.iter().copied().chain(std::iter::once(0)).collect();
+            program.push(X86::Label(format!(".LS{}", i)));
+            program.push(X86::Directive(Directive::Byte(bytes)));
+        }
+        program.push(X86::Directive(Directive::Text));
+    }
+
+    for function in module.functions.iter() {
+        program.extend(generate_function(function));
+    }
+
+    program
 }
 
 /*
- * 层级遍历 
- * AstNode->AstNode
+ * 层级遍历
+ * Function->Block
+ * 整数值尽量放在寄存器中，其余的值在栈帧中有自己的位置
+ * 指令从值的位置读出操作数，再把结果写回去
+ * 函数的指令经过窥孔优化，浮点常量放在函数之后
 */
-fn generate_astnode(name: &str, params: &[String], body: &Option<Vec<Item>>) {
-    if let Some(item) = body {
-        println!(".global {}", name);
-        println!("{}", name);
-
-        println!("  push rbp");
-        println!("  mov rbp,rsp");
+fn generate_function(function: &Function) -> Vec<X86> {
+    let mut context = Context {
+        function,
+        frame: layout(function, &allocate(function)),
+        code: Vec::new(),
+        data: Vec::new(),
+    };
 
-        let context = Context::new(params);
+    // static 函数只在本文件中可见
+    if !function.specifiers.is_static {
+        context.emit(X86::Directive(Directive::Global(function.name.clone())));
+    }
+    context.emit(X86::Label(function.name.clone()));
+    context.emit(X86::Push(RBP));
+    context.emit(X86::Mov(RBP, RSP));
 
-        generate_item(item, &context);
+    if context.frame.size > 0 {
+        context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(context.frame.size as i64)));
+    }
+    // 保存用到的被调用者保存的寄存器
+    for (r, offset) in context.frame.saved.clone() {
+        context.emit(X86::Mov(memory(Size::Qword, Address::Base(Reg::Rbp, offset)), r.qword()));
+    }
 
-        // 函数结束
-        println!("  mov rax,0");
-        generate_function_end();
+    generate_parameters(&mut context);
+
+    let uses = use_counts(function);
+    for b in function.block_ids() {
+        let label = context.label(b);
+        context.emit(X86::Label(label));
+        let block = &function.blocks[b.0];
+        let fused = fused_compare(block, &uses);
+        let instrs = if fused.is_some() { &block.instrs[..block.instrs.len() - 1] } else { &block.instrs[..] };
+        for instr in instrs.iter() {
+            generate_instr(instr, &mut context);
+        }
+        generate_terminator(b, &block.terminator, fused, &mut context);
+    }
 
+    let mut code = optimize(context.code);
+    if !context.data.is_empty() {
+        code.push(X86::Directive(Directive::Rodata));
+        code.extend(context.data);
+        code.push(X86::Directive(Directive::Text));
     }
+    code
 }
 
 /*
- * 层级遍历 
- * Item->Declaration + statement
+ * 参数保存到栈帧中
+ * 可变参数函数先把所有参数寄存器存到保存区，供 va_arg 使用
 */
-fn generate_item(item: &[Item], context: &Context) {
-    let mut context = context.clone();
-
-    for each_item in item {
-        match each_item {
-            // 声明
-            Item::Declaration(declaration) => {
-                generate_declaration(declaration, &mut context);
+fn generate_parameters(context: &mut Context) {
+    if let Some(va_area) = context.frame.va_area.clone() {
+        for (i, reg) in ARG_REGS.iter().enumerate() {
+            let address = Address::Base(Reg::Rbp, va_area.save_offset + i as isize * 8);
+            context.emit(X86::Mov(memory(Size::Qword, address), reg.qword()));
+        }
+        for i in 0..SSE_ARG_REGS as isize {
+            let address = Address::Base(Reg::Rbp, va_area.save_offset + 48 + i * 16);
+            context.emit(X86::Sse(SseOp::Movsd, memory(Size::Qword, address), Operand::Xmm(i as u8)));
+        }
+    }
+
+    let function = context.function;
+    for (param, location) in function.params.iter().zip(context.frame.params.clone()) {
+        let ty = context.ty(*param);
+        match location {
+            // char 只有低8位是有效的
+            ArgLocation::Gp(r) if ty == Ty::I8 => {
+                context.emit(X86::Movsx(RAX, ARG_REGS[r].byte()));
+                context.emit(X86::Mov(context.operand(*param), RAX));
+            }
+            ArgLocation::Gp(r) => {
+                context.emit(X86::Mov(context.operand(*param), ARG_REGS[r].qword()));
+            }
+            ArgLocation::Sse(r) => {
+                context.emit(X86::Sse(sse_move(ty), memory(memory_size(ty), context.home(*param)), Operand::Xmm(r as u8)));
             }
-            // 表达式
-            Item::Statement(statement) => {
-                generate_statement(statement, &mut context);
+            // 返回地址和 rbp 之后就是栈参数
+            ArgLocation::Stack(offset) => {
+                generate_load(ty, Address::Base(Reg::Rbp, 16 + offset), context);
+                generate_store_result(*param, context);
             }
         }
     }
 }
 
 /*
- * 处理声明
+ * 值在寄存器中的类型
+ * char 在寄存器中按64位处理
 */
-fn generate_declaration(decleration: &Declaration, context: &mut Context) {
-    match decleration {
-        Declaration::Declaration(name, expressione) => {
-            if context.current_scope.contains(name) { // 查看变量集中是否有这个变量
-                // 语法错误 变量出现了两次
-                panic!("Variable {} declared twice in same scope", name);
-            }
-            
-            if let Some(expr) = expressione {
-                // expression 存在，有返回值，处理expresssion
-                generate_expression(expr, &context);
-                println!("  push rax");
-            } else {
-                // expression 不存在，也就是没有返回值
-                println!("  push 0");
-            }
+fn register_type(ty: Ty) -> Ty {
+    match ty {
+        Ty::I8 => Ty::I64,
+        t => t,
+    }
+}
 
-            context.var_map.insert(name.clone(), context.stack_index);
-            context.current_scope.insert(name.clone());
-            context.stack_index -= 8;
-        },
+/*
+ * 读取第一个操作数
+ * 整数: rax  float double: xmm0  long double: st(0)
+*/
+fn generate_load_first(v: Value, context: &mut Context) {
+    match context.ty(v) {
+        t if t.is_float() => generate_load(t, context.home(v), context),
+        _ => context.emit(X86::Mov(RAX, context.operand(v))),
     }
 }
 
 /*
- * 表达式的处理
+ * 读取第二个操作数
+ * 整数: rdi  float double: xmm1  long double: st(0)，第一个操作数变成 st(1)
 */
-fn generate_statement(statement: &Statement, context: &Context) {
-    let context = context.reset_scope();
-    match statement {
-        Statement::Expression(expr) => {
-            // 非空
-            if let Some(e) = expr {
-                generate_expression(e, &context);
+fn generate_load_second(v: Value, context: &mut Context) {
+    match context.ty(v) {
+        Ty::F80 => context.emit(X86::Fld(memory(Size::Tbyte, context.home(v)))),
+        t @ Ty::F32 | t @ Ty::F64 => context.emit(X86::Sse(sse_move(t), XMM1, memory(memory_size(t), context.home(v)))),
+        _ => context.emit(X86::Mov(RDI, context.operand(v))),
+    }
+}
+
+// 结果写回值的位置，long double 从x87栈上弹出
+fn generate_store_result(v: Value, context: &mut Context) {
+    match context.ty(v) {
+        t if t.is_float() => generate_store_pop(t, context.home(v), context),
+        _ => context.emit(X86::Mov(context.operand(v), RAX)),
+    }
+}
+
+fn generate_instr(instr: &Instr, context: &mut Context) {
+    match instr {
+        Instr::Const(v, n) => match context.register(*v) {
+            Some(r) => context.emit(X86::Mov(r.qword(), Operand::Imm(*n))),
+            None => {
+                context.emit(X86::Mov(RAX, Operand::Imm(*n)));
+                generate_store_result(*v, context);
             }
         },
 
-        Statement::Return(expr) => {
-            generate_expression(expr, &context);
-            // 函数结束
-            generate_function_end();
+        Instr::FloatConst(v, f) => {
+            // 浮点常量放在 .rodata 中
+            let t = context.ty(*v);
+            let label = add_suffix(".LC", &unique_suffix());
+            let bits = float_bits(f, t);
+
+            let (align, words) = match t {
+                Ty::F32 => (2, vec![Directive::Long(bits as u32)]),
+                Ty::F64 => (3, vec![Directive::Quad(bits as u64)]),
+                _ => (4, vec![Directive::Quad(bits as u64), Directive::Quad((bits >> 64) as u64)]),
+            };
+            context.data.push(X86::Directive(Directive::Align(align)));
+            context.data.push(X86::Label(label.clone()));
+            context.data.extend(words.into_iter().map(X86::Directive));
+
+            generate_load(t, Address::Rip(label), context);
+            generate_store_result(*v, context);
+        }
+
+        Instr::StringAddr(v, index) => {
+            context.emit(X86::Lea(RAX, Address::Rip(format!(".LS{}", index))));
+            generate_store_result(*v, context);
+        }
+
+        Instr::SlotAddr(v, slot) => {
+            context.emit(X86::Lea(RAX, context.slot(*slot)));
+            generate_store_result(*v, context);
+        }
+
+        Instr::Load(v, slot) => {
+            generate_load(context.ty(*v), context.slot(*slot), context);
+            generate_store_result(*v, context);
+        }
+
+        Instr::Store(slot, v) => {
+            generate_load_first(*v, context);
+            generate_store_pop(context.ty(*v), context.slot(*slot), context);
+        }
+
+        Instr::Neg(v, a) => {
+            generate_load_first(*a, context);
+            match context.ty(*a) {
+                // 翻转符号位
+                Ty::F32 => {
+                    context.emit(X86::Mov(EAX, Operand::Imm(0x80000000)));
+                    context.emit(X86::Sse(SseOp::Movd, XMM1, EAX));
+                    context.emit(X86::Sse(SseOp::Xorps, XMM0, XMM1));
+                }
+                Ty::F64 => {
+                    context.emit(X86::Mov(RAX, Operand::Imm(i64::MIN)));
+                    context.emit(X86::Sse(SseOp::Movq, XMM1, RAX));
+                    context.emit(X86::Sse(SseOp::Xorpd, XMM0, XMM1));
+                }
+                Ty::F80 => context.emit(X86::Fchs),
+                _ => context.emit(X86::Neg(RAX)),
+            }
+            generate_store_result(*v, context);
+        }
+
+        Instr::Binary(v, op, a, b) => match context.ty(*a) {
+            t if t.is_float() => {
+                generate_load_first(*a, context);
+                generate_load_second(*b, context);
+                if t == Ty::F80 {
+                    context.emit(X86::X87(x87_operator(*op)));
+                } else {
+                    context.emit(X86::Sse(sse_operator(*op, t), XMM0, XMM1));
+                }
+                generate_store_result(*v, context);
+            }
+            _ => {
+                // 结果在寄存器中时直接在那里计算，除法只能用 rax
+                let target = match context.register(*v) {
+                    Some(r) if !matches!(op, BinOp::Div | BinOp::Rem) => r.qword(),
+                    _ => RAX,
+                };
+                context.emit(X86::Mov(target.clone(), context.operand(*a)));
+                generate_integer_operator(*op, target.clone(), *b, context);
+                if target == RAX {
+                    generate_store_result(*v, context);
+                }
+            }
         },
 
-        // 这里进行了修改
-        Statement::If(expr, if_body, else_body) => {
-            let suffix = unique_suffix();
-            let post_if_label = add_suffix("post_if", &suffix);
-            let post_else_label = add_suffix("post_else", &suffix);
-
-            generate_expression(expr, &context);
-            println!("  cmp rax,0");
-            println!("je {}", post_if_label);
-            
-
-            // 有没有else
-            if let Some(else_statement) = else_body {
-                println!("  jmp {}", post_else_label);
-                println!("{}:", post_if_label);
-                generate_statement(if_body, &context);
-                println!("{}:", post_else_label);
-                generate_statement(else_statement, &context);
+        Instr::Compare(v, op, a, b) => {
+            generate_compare(*op, *a, *b, context);
+            if context.ty(*a).is_float() {
+                generate_float_result(*op, context);
             } else {
-                println!("{}:", post_if_label);
-                generate_statement(if_body, &context);
+                context.emit(X86::Set(integer_condition(*op), AL));
             }
+            context.emit(X86::Movzx(EAX, AL));
+            generate_store_result(*v, context);
+        }
 
-            println!("{}:", post_if_label);
-            generate_statement(if_body, &context);
-            
-        },
+        Instr::Convert(v, a) => {
+            generate_load_first(*a, context);
+            generate_convert(context.ty(*a), context.ty(*v), context);
+            generate_store_result(*v, context);
+        }
 
-        Statement::Compound(item) => {
-            generate_item(item, &context);
-        },
+        Instr::Call(v, name, args, variadic) => generate_call(*v, name, args, variadic.is_some(), context),
+
+        Instr::VaStart(ap) => {
+            let va_area = context.frame.va_area.clone().expect("va_start outside variadic function");
+            context.emit(X86::Mov(RAX, context.operand(*ap)));
+            context.emit(X86::Mov(memory(Size::Dword, Address::Base(Reg::Rax, 0)), Operand::Imm(va_area.gp_offset as i64)));
+            context.emit(X86::Mov(memory(Size::Dword, Address::Base(Reg::Rax, 4)), Operand::Imm(va_area.fp_offset as i64)));
+            context.emit(X86::Lea(RDX, Address::Base(Reg::Rbp, va_area.overflow_offset)));
+            context.emit(X86::Mov(memory(Size::Qword, Address::Base(Reg::Rax, 8)), RDX));
+            context.emit(X86::Lea(RDX, Address::Base(Reg::Rbp, va_area.save_offset)));
+            context.emit(X86::Mov(memory(Size::Qword, Address::Base(Reg::Rax, 16)), RDX));
+        }
+
+        Instr::VaArg(v, ap) => {
+            context.emit(X86::Mov(RAX, context.operand(*ap)));
+            generate_va_arg(context.ty(*v), context);
+            generate_store_result(*v, context);
+        }
 
-        Statement::For(init, condition, post_expression, body) => {
-            // 有没有表达式
-            if let Some(expr) = init {
-                generate_expression(expr, &context);
+        Instr::MemCopy(dest, src, size) => {
+            context.emit(X86::Mov(RDI, context.operand(*src)));
+            context.emit(X86::Mov(RAX, context.operand(*dest)));
+            for offset in (0..*size as isize).step_by(8) {
+                context.emit(X86::Mov(RDX, memory(Size::Qword, Address::Base(Reg::Rdi, offset))));
+                context.emit(X86::Mov(memory(Size::Qword, Address::Base(Reg::Rax, offset)), RDX));
             }
+        }
 
-            // 
-            for_loop(condition, post_expression, body, &context);
-            println!("  pop rax");
-        },
-        
-        Statement::ForDeclaration(decl, condition, post_expression, body) => {
-            // 处理声明
-            let mut context = context.clone();
-            generate_declaration(decl, &mut context);
-
-            // 
-            for_loop(condition, post_expression, body, &context);
-            println!("  pop rax");
-        },
+        // phi 在前驱跳转过来之前赋值
+        Instr::Phi(_, _) => {}
+    }
+}
 
-        Statement::While(condition, body) => {
-            for_loop(condition, &None, body, &context);
-        },
+/*
+ * 函数调用
+ * 栈帧是16字节对齐的，栈参数的大小也是16的倍数，所以 call 时 rsp 是对齐的
+*/
+fn generate_call(dest: Option<Value>, name: &str, args: &[Value], variadic: bool, context: &mut Context) {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    let stack_size = classify_arguments(&types).1;
 
-        Statement::DoWhile(condition, body) => {
-            let suffix = unique_suffix();
-            let loop_label = add_suffix("loop", &suffix);
-            let break_label = add_suffix("post_loop", &suffix);
-            let continue_label = add_suffix("continue_do_while", &suffix);
-
-            println!("{}:", loop_label);
-
-            let body_context = Context {break_label: Some(break_label.clone()), continue_label: Some(continue_label.clone()), ..context.clone()};
-            
-            generate_statement(body, &body_context);
-            
-            generate_expression(condition, &context);
-            
-            println!("  cmp rax,0");
-            println!("  jne {}", loop_label);
-            println!("{}:", break_label);
-            
-        },
+    if stack_size > 0 {
+        context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(stack_size as i64)));
+    }
+    generate_arguments(args, variadic, stack, context);
+    context.emit(X86::Call(name.to_string()));
+    if stack_size > 0 {
+        context.emit(X86::Alu(AluOp::Add, RSP, Operand::Imm(stack_size as i64))); // 释放栈参数
+    }
 
-        Statement::Break => match context.break_label {
-            // 跳转到原来位置
-            Some(label) => println!("   jmp {}", label),
-            // 语法错误，没有break
-            None => panic!("Break statement not in loop"),
-        },
+    if let Some(v) = dest {
+        // 返回的 char 只有低8位是有效的
+        if context.ty(v) == Ty::I8 {
+            context.emit(X86::Movsx(RAX, AL));
+        }
+        generate_store_result(v, context);
+    }
+}
 
-        Statement::Continue => match context.continue_label {
-            // 跳转到原来位置
-            Some(label) => println!("   jmp {}", label),
-            // 语法错误，没有continue
-            None => panic!("Continue statement not in loop"),
-        },
+/*
+ * 尾调用
+ * 栈参数写到调用者自己收到栈参数的位置 [rbp+16+偏移]，已经检查过放得下
+ * 然后恢复调用者的栈帧，跳到被调用的函数，它直接返回到调用者的调用者
+ * 返回的 char 由调用者的调用者扩展
+*/
+fn generate_tail_call(name: &str, args: &[Value], variadic: bool, context: &mut Context) {
+    generate_arguments(args, variadic, |offset| Address::Base(Reg::Rbp, 16 + offset), context);
+    generate_epilogue(context);
+    context.emit(X86::Jmp(name.to_string()));
+}
+
+/*
+ * 准备调用的参数
+ * 栈上的参数写到 stack_slot(偏移)，寄存器参数直接从栈帧读到寄存器中
+*/
+fn generate_arguments(args: &[Value], variadic: bool, stack_slot: impl Fn(isize) -> Address, context: &mut Context) {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    let (locations, _) = classify_arguments(&types);
+
+    // 栈参数要经过 rax / xmm0 / st(0)，先于寄存器参数写好
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        if let ArgLocation::Stack(offset) = location {
+            generate_load_first(*arg, context);
+            generate_store_pop(register_type(context.ty(*arg)), stack_slot(*offset), context);
+        }
+    }
+
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        let ty = context.ty(*arg);
+        match location {
+            ArgLocation::Gp(r) => context.emit(X86::Mov(ARG_REGS[*r].qword(), context.operand(*arg))),
+            ArgLocation::Sse(r) => context.emit(X86::Sse(sse_move(ty), Operand::Xmm(*r as u8), memory(memory_size(ty), context.home(*arg)))),
+            ArgLocation::Stack(_) => {}
+        }
+    }
+
+    // 可变参数函数通过 al 知道用了几个向量寄存器
+    if variadic {
+        let sse = locations.iter().filter(|l| matches!(l, ArgLocation::Sse(_))).count();
+        context.emit(X86::Mov(EAX, Operand::Imm(sse as i64)));
     }
 }
 
-fn generate_expression(expression: &Expression, context: &Context) {
-    match expression {
-        Expression::Constant(n) => {
-            println!("  mov rax,{}", n);
-        },
+/*
+ * 跳到 to 之前给 to 中的 phi 赋值
+ * 浮点数经过栈，整数并行赋值，把一个目的的旧值存到 rax 中打破环
+ * 两边都在内存中时经过 rdi
+*/
+fn generate_phi_moves(from: BlockId, to: BlockId, context: &mut Context) {
+    let moves = phi_moves(context.function, from, to);
+    let (floats, integers): (Vec<_>, Vec<_>) = moves.into_iter().partition(|(v, _)| context.ty(*v).is_float());
+
+    // 浮点数都在栈帧中，先把来源全部压栈再依次弹出
+    if let [(v, a)] = floats[..] {
+        generate_load_first(a, context);
+        generate_store_result(v, context);
+    } else {
+        for (_, a) in floats.iter() {
+            generate_load_first(*a, context);
+            generate_push(context.ty(*a), context);
+        }
+        for (v, _) in floats.iter().rev() {
+            let ty = context.ty(*v);
+            generate_load(ty, stack(0), context);
+            context.emit(X86::Alu(AluOp::Add, RSP, Operand::Imm(ty.size().max(8) as i64)));
+            generate_store_result(*v, context);
+        }
+    }
 
-        Expression::Variable(name) => {
-            if !context.var_map.contains_key(name) {
-                panic!("Variable undeclared");
+    let moves = integers.iter().map(|(v, a)| (context.operand(*v), context.operand(*a), ())).collect();
+    for (dest, src, ()) in sequence(moves, RAX) {
+        if dest.is_memory() && src.is_memory() {
+            context.emit(X86::Mov(RDI, src));
+            context.emit(X86::Mov(dest, RDI));
+        } else {
+            context.emit(X86::Mov(dest, src));
+        }
+    }
+}
+
+fn generate_terminator(block: BlockId, terminator: &Terminator, fused: Option<&Instr>, context: &mut Context) {
+    match terminator {
+        Terminator::Jump(target) => {
+            generate_phi_moves(block, *target, context);
+            context.emit(X86::Jmp(context.label(*target)));
+        }
+
+        Terminator::Branch(c, if_true, if_false) => {
+            // 目标有 phi 时需要先在这条边上赋值
+            let false_edge = if has_phi(context.function, *if_false) {
+                format!("{}.to{}", context.label(block), if_false.0)
             } else {
-                let offset: isize = *context.var_map.get(name).expect("Missing offset");
-                println!("  mov rax, [rbp{:+}]", offset);
+                context.label(*if_false)
+            };
+            generate_branch_condition(block, *c, fused, &false_edge, context);
+            generate_phi_moves(block, *if_true, context);
+            context.emit(X86::Jmp(context.label(*if_true)));
+
+            if has_phi(context.function, *if_false) {
+                context.emit(X86::Label(false_edge));
+                generate_phi_moves(block, *if_false, context);
+                context.emit(X86::Jmp(context.label(*if_false)));
             }
-        },
+        }
 
-        Expression::UnaryOperators(op, expr) => {
-            generate_expression(expr, context);
+        Terminator::Return(v) => {
+            // 返回值放在 rax / xmm0 / st(0)
+            if let Some(v) = v {
+                generate_load_first(*v, context);
+            }
+            generate_function_end(context);
+        }
 
-            match op {
-                Operator::Minus => { // 非
-                    println!("  neg rax");
-                }
-                // TODO:我认为他写的有问题
-                Operator::LogicalNegation => { // ~ 二进制取反
-                    // println!("  mov rdi,0");
-                    // println!("  cmp rdi,rax");
-                    // println!("  sete al");
-                    // println!("  movzb rax,al");
+        Terminator::TailCall(name, args, variadic) => generate_tail_call(name, args, variadic.is_some(), context),
+    }
+}
+
+/*
+ * 从 va_list (地址在 rax) 中取出下一个参数
+ * 结构: gp_offset(4) fp_offset(4) overflow_arg_area(8) reg_save_area(8)
+ * 寄存器保存区用完之后从 overflow_arg_area 中取
+*/
+fn generate_va_arg(t: Ty, context: &mut Context) {
+    let suffix = unique_suffix();
+    let overflow_label = add_suffix("va_overflow", &suffix);
+    let load_label = add_suffix("va_load", &suffix);
+    let field = |offset: isize, size: Size| memory(size, Address::Base(Reg::Rcx, offset));
+
+    context.emit(X86::Mov(RCX, RAX));
+
+    match t {
+        Ty::F80 => {
+            // long double 总是在栈上，按16字节对齐
+            context.emit(X86::Mov(RAX, field(8, Size::Qword)));
+            context.emit(X86::Alu(AluOp::Add, RAX, Operand::Imm(15)));
+            context.emit(X86::Alu(AluOp::And, RAX, Operand::Imm(-16)));
+            context.emit(X86::Lea(RDX, Address::Base(Reg::Rax, 16)));
+            context.emit(X86::Mov(field(8, Size::Qword), RDX));
+        }
+        _ => {
+            // 整数在保存区的 [0, 48)，浮点数在 [48, 176)
+            let (offset, limit, step) = if t.is_float() { (4, 176, 16) } else { (0, 48, 8) };
+            context.emit(X86::Mov(EAX, field(offset, Size::Dword)));
+            context.emit(X86::Alu(AluOp::Cmp, EAX, Operand::Imm(limit)));
+            context.emit(X86::Jcc(Cond::Ae, overflow_label.clone()));
+            context.emit(X86::Lea(EDX, Address::Base(Reg::Rax, step)));
+            context.emit(X86::Mov(field(offset, Size::Dword), EDX));
+            context.emit(X86::Alu(AluOp::Add, RAX, field(16, Size::Qword)));
+            context.emit(X86::Jmp(load_label.clone()));
+
+            context.emit(X86::Label(overflow_label));
+            context.emit(X86::Mov(RAX, field(8, Size::Qword)));
+            context.emit(X86::Lea(RDX, Address::Base(Reg::Rax, 8)));
+            context.emit(X86::Mov(field(8, Size::Qword), RDX));
+        }
+    }
+
+    context.emit(X86::Label(load_label));
+    generate_load(t, Address::Base(Reg::Rax, 0), context);
+}
+
+/*
+ * 类型转换
+ * 值在 rax / xmm0 / st(0) 之间移动
+ * 整数和指针都在 rax 中，转换到 char 时截断
+*/
+fn generate_convert(from: Ty, to: Ty, context: &mut Context) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (Ty::F32, Ty::F64) => context.emit(X86::Sse(SseOp::Cvtss2sd, XMM0, XMM0)),
+        (Ty::F64, Ty::F32) => context.emit(X86::Sse(SseOp::Cvtsd2ss, XMM0, XMM0)),
+
+        (Ty::F32, Ty::F80) | (Ty::F64, Ty::F80) => {
+            context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(8)));
+            context.emit(X86::Sse(sse_move(from), memory(memory_size(from), stack(0)), XMM0));
+            context.emit(X86::Fld(memory(memory_size(from), stack(0))));
+            context.emit(X86::Alu(AluOp::Add, RSP, Operand::Imm(8)));
+        }
+        (Ty::F80, Ty::F32) | (Ty::F80, Ty::F64) => {
+            context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(8)));
+            context.emit(X86::Fstp(memory(memory_size(to), stack(0))));
+            context.emit(X86::Sse(sse_move(to), XMM0, memory(memory_size(to), stack(0))));
+            context.emit(X86::Alu(AluOp::Add, RSP, Operand::Imm(8)));
+        }
+
+        (_, Ty::F32) => context.emit(X86::Sse(SseOp::Cvtsi2ss, XMM0, RAX)),
+        (_, Ty::F64) => context.emit(X86::Sse(SseOp::Cvtsi2sd, XMM0, RAX)),
+        (_, Ty::F80) => {
+            context.emit(X86::Push(RAX));
+            context.emit(X86::Fild(memory(Size::Qword, stack(0))));
+            context.emit(X86::Alu(AluOp::Add, RSP, Operand::Imm(8)));
+        }
+
+        _ => {
+            match from {
+                Ty::F32 => context.emit(X86::Sse(SseOp::Cvttss2si, RAX, XMM0)),
+                Ty::F64 => context.emit(X86::Sse(SseOp::Cvttsd2si, RAX, XMM0)),
+                Ty::F80 => {
+                    context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(8)));
+                    context.emit(X86::Fisttp(memory(Size::Qword, stack(0))));
+                    context.emit(X86::Pop(RAX));
                 }
-                _ => panic!("Unexprected unary operator"),
+                _ => {}
             }
-        },
+            if to == Ty::I8 {
+                context.emit(X86::Movsx(RAX, AL));
+            }
+        }
+    }
+}
 
-        Expression::AssignmentOperators(op, name, expr) => {
-            generate_expression(expr, context);
+// 值由 const 定义时的常量
+fn constant(v: Value, context: &Context) -> Option<i64> {
+    context.function.blocks.iter().flat_map(|block| block.instrs.iter()).find_map(|instr| match instr {
+        Instr::Const(w, n) if *w == v => Some(*n),
+        _ => None,
+    })
+}
 
-            // 是否存储这个变量
-            if !context.var_map.contains_key(name) {
-                panic!("Variable undeclared");
-            } else {
-                let offset: isize = *context.var_map.get(name).expect("Missing offset");
-                match op {
-                    // rax 是expr的返回值
-
-                    // 计算+存储
-                    Operator::Assignment => {
-                        println!("  mov [rbp{:+}], rax", offset);
-                    },
-                    Operator::AssignPlus => {
-                        println!("  add [rbp{:+}], rax", offset);
-                        println!("  mov [rbp{:+}], rax", offset);
-                    },
-                    Operator::AssignMinus => {
-                        println!("  sub [rbp{:+}], rax", offset);
-                        println!("  mov [rbp{:+}], rax", offset);
-                    },
-                    Operator::AssignMult => {
-                        println!("  mov rdi, rax");
-                        println!("  mov rax, [rbp{:+}]", offset);
-                        println!("  mul rdi");
-                        println!("  mov [rbp{:+}], rax", offset);
-                    }
-                    Operator::AssignDiv => {
-                        println!("  mov rdi, rax");
-                        println!("  mov rax, [rbp{:+}]", offset);
-                        println!("  mov rdx, 0");
-                        println!("  div rdi");
-                        println!("  mov [rbp{:+}], rax", offset);
-                    }
-                    Operator::AssignMod => {
-                        println!("  mov rdi, rax");
-                        println!("  mov rax, [rbp{:+}]", offset);
-                        println!("  mov rdx, 0");
-                        println!("  div rdi");
-                        println!("  mov [rbp{:+}], rdx", offset);
-                    }
-
-
-                    _ => panic!("Unexpected assignment operator"),
-                }
+/*
+ * 整数运算
+ * 左边在 target 中，右边 b 是寄存器或者内存，结果在 target 中
+ * 除法的 target 是 rax
+ * 移位的位数是常量时用立即数，不用占用 rcx
+*/
+fn generate_integer_operator(op: BinOp, target: Operand, b: Value, context: &mut Context) {
+    let rhs = context.operand(b);
+    let alu = match op {
+        BinOp::Add => AluOp::Add,
+        BinOp::Sub => AluOp::Sub,
+        BinOp::Mul => AluOp::Imul,
+        BinOp::And => AluOp::And,
+        BinOp::Or => AluOp::Or,
+        BinOp::Xor => AluOp::Xor,
+        BinOp::Div | BinOp::Rem => {
+            context.emit(X86::Cqo);
+            context.emit(X86::Idiv(rhs));
+            if op == BinOp::Rem {
+                context.emit(X86::Mov(RAX, RDX));
             }
-        },
+            return;
+        }
+        BinOp::Shl | BinOp::Shr => {
+            let shift = if op == BinOp::Shl { ShiftOp::Shl } else { ShiftOp::Sar };
+            if let Some(n) = constant(b, context).filter(|n| (0..64).contains(n)) {
+                context.emit(X86::ShiftImm(shift, target, n as u8));
+                return;
+            }
+            context.emit(X86::Mov(RCX, rhs));
+            context.emit(X86::Shift(shift, target));
+            return;
+        }
+    };
+    context.emit(X86::Alu(alu, target, rhs));
+}
 
-        Expression::BinaryOperators(op, lhs, rhs) => {
-            // 这里仅仅只需要计算
-
-            generate_expression(rhs, context);
-            println!("  push rax");
-            generate_expression(lhs, context);
-            println!("  pop rdi");
-            // rax是lhs rdi是rhs
-
-            match op {
-                Operator::Plus => {
-                    println!("  add rax,rdi");
-                },
-                Operator::Minus => {
-                    println!("  sub rax,rdi");
-                },
-                Operator::Multiplication => {
-                    println!("  mul rdi");
-                },
-                Operator::Division => {
-                    println!("  mov rdx,0");
-                    println!("  div rdi");
-                },
-                Operator::Modulo => {
-                    println!("  mov rdx,0");
-                    println!("  div rdi");
-                    println!("  mov rax,rdx");
-                },
-                Operator::Equal => {
-                    println!("  sete al");
-                },
-                Operator::NotEqual => {
-                    println!("  setne al");
-                },
-                Operator::LessThan => {
-                    println!("  setl al");
-                },
-                Operator::LessThanOrEqual => {
-                    println!("  setle al");
-                }
-                Operator::GreaterThan => {
-                    println!("  setg al");
-                },
-                Operator::GreaterThanOrEqual => {
-                    println!("  setge al");
-                },
-                // 位运算要补充位数
-                Operator::LogicalOr => {
-                    println!("  or rdi,rax");
-                    println!("  setne al");
-                    println!("  movzb rax,al");
-                },
-                Operator::LogicalAnd => {
-                    println!("  cmp rdi,0");
-                    println!("  setne dil");
-                    println!("  cmp rax,0");
-                    println!("  setne al");
-                    println!("  movzb rax,al");
-                    println!("  movzb rax,al");
-                    println!("  and al,dil");
-                },
-
-                Operator::BitwiseAnd => {
-                    println!("  and rax,rdi");
-                },
-                Operator::BitwiseOr => {
-                    println!("  or rax,rdi");
-                },
-                Operator::BitwiseXor => {
-                    println!("  xor rax,rdi");
-                },
-                Operator::BitwiseShiftLeft => {
-                    println!("  mov rcx,rdi");
-                    println!("  shl rax,cl");
-                },
-                Operator::BitwiseShiftRight => {
-                    println!("  mov rcx,rdi");
-                    println!("  shr rax,cl");
-                }
-                _ => panic!("Unexprected binary operator"),
+/*
+ * 比较两个值，只设置标志
+ * 整数: 左边在 rax，和右边的寄存器或者内存比较
+ * 浮点数: 比较的结果和无符号比较一样在 CF ZF 中，NaN 时 PF 置位
+ * < <= 交换两边，用 > >= 的条件实现，这样 NaN 的时候为假
+*/
+fn generate_compare(op: CmpOp, a: Value, b: Value, context: &mut Context) {
+    generate_load_first(a, context);
+    let ty = context.ty(a);
+    if !ty.is_float() {
+        context.emit(X86::Alu(AluOp::Cmp, RAX, context.operand(b)));
+        return;
+    }
+
+    generate_load_second(b, context);
+    let swapped = matches!(op, CmpOp::Lt | CmpOp::Le);
+    match ty {
+        // st(1) 是左边 st(0) 是右边，比较时弹出两个操作数
+        Ty::F80 => {
+            if !swapped {
+                context.emit(X86::Fxch);
             }
-        },
+            context.emit(X86::Fucomip);
+            context.emit(X86::FstpTop);
+        }
+        _ if swapped => context.emit(X86::Sse(sse_compare(ty), XMM1, XMM0)),
+        _ => context.emit(X86::Sse(sse_compare(ty), XMM0, XMM1)),
+    }
+}
 
-        Expression::TernaryOperators(e1, e2, e3) => {
-            generate_expression(e1, context);
-            println!("  cmp rax, 0");
+// 整数比较成立的条件
+fn integer_condition(op: CmpOp) -> Cond {
+    match op {
+        CmpOp::Eq => Cond::E,
+        CmpOp::Ne => Cond::Ne,
+        CmpOp::Lt => Cond::L,
+        CmpOp::Le => Cond::Le,
+        CmpOp::Gt => Cond::G,
+        CmpOp::Ge => Cond::Ge,
+    }
+}
 
-            let suffix = unique_suffix();
-            let e_label = add_suffix("e", &suffix);
-            let e_conditional_label = add_suffix("e_conditional", &suffix);
+/*
+ * 分支的条件，不成立时跳到 false_label
+ * 条件是块中最后一条、只在这里使用的比较时，直接按比较的标志跳转，不再得到 0 1 再和 0 比较
+*/
+fn generate_branch_condition(block: BlockId, c: Value, fused: Option<&Instr>, false_label: &str, context: &mut Context) {
+    let Some(Instr::Compare(_, op, a, b)) = fused else {
+        context.emit(X86::Alu(AluOp::Cmp, context.operand(c), Operand::Imm(0)));
+        context.emit(X86::Jcc(Cond::E, false_label.to_string()));
+        return;
+    };
 
-            println!("  je {}", e_label); // 跳转e
-            generate_expression(e2, context);
+    generate_compare(*op, *a, *b, context);
+    if !context.ty(*a).is_float() {
+        context.emit(X86::Jcc(integer_condition(*op).negate(), false_label.to_string()));
+        return;
+    }
+    match op {
+        CmpOp::Eq => {
+            context.emit(X86::Jcc(Cond::Ne, false_label.to_string()));
+            context.emit(X86::Jcc(Cond::P, false_label.to_string()));
+        }
+        // 无序时不等成立
+        CmpOp::Ne => {
+            let taken = format!("{}.unordered", context.label(block));
+            context.emit(X86::Jcc(Cond::P, taken.clone()));
+            context.emit(X86::Jcc(Cond::E, false_label.to_string()));
+            context.emit(X86::Label(taken));
+        }
+        CmpOp::Gt | CmpOp::Lt => context.emit(X86::Jcc(Cond::Be, false_label.to_string())),
+        CmpOp::Ge | CmpOp::Le => context.emit(X86::Jcc(Cond::B, false_label.to_string())),
+    }
+}
+
+/*
+ * 块中最后一条指令是比较，结果只被块结尾的分支使用
+ * 这样的比较和分支一起生成
+*/
+fn fused_compare<'a>(block: &'a Block, uses: &[usize]) -> Option<&'a Instr> {
+    match (block.instrs.last(), &block.terminator) {
+        (Some(instr @ Instr::Compare(v, _, _, _)), Terminator::Branch(c, _, _)) if v == c && uses[v.0] == 1 => Some(instr),
+        _ => None,
+    }
+}
 
-            println!("  jmp {}", e_conditional_label); // 跳转e_conditional
+// 每个值被使用的次数
+fn use_counts(function: &Function) -> Vec<usize> {
+    let mut uses = vec![0; function.values.len()];
+    for block in function.blocks.iter() {
+        for v in block.instrs.iter().flat_map(|instr| instr.uses()).chain(block.terminator.uses()) {
+            uses[v.0] += 1;
+        }
+    }
+    uses
+}
 
-            println!("{}:", e_label);
-            generate_expression(e3, context);
+/*
+ * float double 使用 SSE 指令
+ * 左边在 xmm0，右边在 xmm1
+*/
+fn sse_operator(op: BinOp, operand_type: Ty) -> SseOp {
+    let single = operand_type == Ty::F32;
+
+    match op {
+        BinOp::Add => if single { SseOp::Addss } else { SseOp::Addsd },
+        BinOp::Sub => if single { SseOp::Subss } else { SseOp::Subsd },
+        BinOp::Mul => if single { SseOp::Mulss } else { SseOp::Mulsd },
+        BinOp::Div => if single { SseOp::Divss } else { SseOp::Divsd },
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
 
-            println!("{}:", e_conditional_label);
-        },
-        
-        Expression::FunctionCalls(id, args) => {
-            let arg_len = args.len();
+/*
+ * long double 使用 x87 指令
+ * st(1) 是左边 st(0) 是右边
+*/
+fn x87_operator(op: BinOp) -> X87Op {
+    match op {
+        BinOp::Add => X87Op::Faddp,
+        BinOp::Sub => X87Op::Fsubp,
+        BinOp::Mul => X87Op::Fmulp,
+        BinOp::Div => X87Op::Fdivp,
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
 
-            args.iter().rev().for_each(|e| {
-                generate_expression(e, context);
-                println!("  push rax");
-            });
+/*
+ * 浮点比较的结果 0 或 1 (放在 al 中)
+ * 相等要求不是 NaN，不等在 NaN 时也成立
+*/
+fn generate_float_result(op: CmpOp, context: &mut Context) {
+    match op {
+        CmpOp::Eq => {
+            context.emit(X86::Set(Cond::E, AL));
+            context.emit(X86::Set(Cond::Np, DL));
+            context.emit(X86::Alu(AluOp::And, AL, DL));
+        }
+        CmpOp::Ne => {
+            context.emit(X86::Set(Cond::Ne, AL));
+            context.emit(X86::Set(Cond::P, DL));
+            context.emit(X86::Alu(AluOp::Or, AL, DL));
+        }
+        CmpOp::Gt | CmpOp::Lt => context.emit(X86::Set(Cond::A, AL)),
+        CmpOp::Ge | CmpOp::Le => context.emit(X86::Set(Cond::Ae, AL)),
+    }
+}
 
-            println!("  call {}", id);
-            println!("  add rsp, {}", arg_len * 8); // 分配空间
-        },
+// 内存操作数的大小
+fn memory_size(t: Ty) -> Size {
+    match t {
+        Ty::I8 => Size::Byte,
+        Ty::F32 => Size::Dword,
+        Ty::F80 => Size::Tbyte,
+        _ => Size::Qword,
     }
 }
 
-fn for_loop(condition: &Expression, post_expression: &Option<Expression>, body: &Statement, context: &Context) {
-    let suffix = unique_suffix();
-    let loop_label = add_suffix("loop", &suffix);
-    let post_loop_label = add_suffix("post_loop", &suffix);
-    let continue_label = add_suffix("loop_continue", &suffix);
-
-    println!("{}:", loop_label);
-    generate_expression(condition, context);
-    println!("  cmp rax,0");
-    println!("  je {}", post_loop_label);
-
-    let body_context = Context {
-        break_label: Some(post_loop_label.clone()),
-        continue_label: Some(continue_label.clone()),
-        ..context.clone()
-    };
+// SSE 的传送指令
+fn sse_move(t: Ty) -> SseOp {
+    if t == Ty::F32 { SseOp::Movss } else { SseOp::Movsd }
+}
 
-    generate_statement(body, &body_context);
+// SSE 的比较指令
+fn sse_compare(t: Ty) -> SseOp {
+    if t == Ty::F32 { SseOp::Ucomiss } else { SseOp::Ucomisd }
+}
 
-    println!("{}:", continue_label);
+/*
+ * 从内存读取到结果的位置
+*/
+fn generate_load(t: Ty, address: Address, context: &mut Context) {
+    let source = memory(memory_size(t), address);
+    match t {
+        Ty::I8 => context.emit(X86::Movsx(RAX, source)),
+        Ty::F80 => context.emit(X86::Fld(source)),
+        Ty::F32 | Ty::F64 => context.emit(X86::Sse(sse_move(t), XMM0, source)),
+        _ => context.emit(X86::Mov(RAX, source)),
+    }
+}
 
-    if let Some(expr) = post_expression {
-        generate_expression(expr, &context);
+/*
+ * 把结果写到内存，long double 从x87栈上弹出
+*/
+fn generate_store_pop(t: Ty, address: Address, context: &mut Context) {
+    let target = memory(memory_size(t), address);
+    match t {
+        Ty::I8 => context.emit(X86::Mov(target, AL)),
+        Ty::F80 => context.emit(X86::Fstp(target)),
+        Ty::F32 | Ty::F64 => context.emit(X86::Sse(sse_move(t), target, XMM0)),
+        _ => context.emit(X86::Mov(target, RAX)),
     }
+}
 
-    println!("  jmp {}", loop_label);
-    println!("{}:", post_loop_label);
+/*
+ * 结果压栈
+*/
+fn generate_push(t: Ty, context: &mut Context) {
+    if t.is_float() {
+        context.emit(X86::Alu(AluOp::Sub, RSP, Operand::Imm(t.size().max(8) as i64)));
+        generate_store_pop(t, stack(0), context);
+    } else {
+        context.emit(X86::Push(RAX));
+    }
 }
 
 /*
  * 结束添加
+ * 先恢复被调用者保存的寄存器
 */
-fn generate_function_end() {
-    println!("  mov rsp, rbp");
-    println!("  pop rbp");
-    println!("  ret");
+fn generate_function_end(context: &mut Context) {
+    generate_epilogue(context);
+    context.emit(X86::Ret);
+}
+
+// 恢复被调用者保存的寄存器和调用者的栈帧
+fn generate_epilogue(context: &mut Context) {
+    for (r, offset) in context.frame.saved.clone() {
+        context.emit(X86::Mov(r.qword(), memory(Size::Qword, Address::Base(Reg::Rbp, offset))));
+    }
+    context.emit(X86::Mov(RSP, RBP));
+    context.emit(X86::Pop(RBP));
 }
 
-// 本解析器基于inter语法的x86_64
+// 本解析器基于intel语法的x86_64
 // 用到的汇编代码解析
-// .inter_syntax noprefix 代表inter语法的x86
+// .intel_syntax noprefix 代表intel语法的x86
 // .global 声明变量是全局可见的
 // push 压入栈
 // pop 出栈
@@ -495,37 +889,42 @@ fn generate_function_end() {
 // jne 不相等跳转
 // neg 非
 // sete 相等时设置 setne setl setg setle setge
-// add + sub - 
+// add + sub -
 // movzb 拷贝的时候会补充0或1
 // shl shr 左移右移
 // call 调用
+// movss movsd 传送 float double
+// addsd subsd mulsd divsd 浮点运算 (ss 为 float)
+// ucomisd 浮点比较，结果和无符号比较一样，NaN 时 PF 置位
+// cvtsi2sd cvttsd2si 整数和浮点之间转换
+// fld fstp x87 的入栈出栈，long double 使用
+// faddp fsubp fmulp fdivp x87 运算并出栈
 
 
 /*
- * 某些特定的结构：
- * 
+ * 基本块的结构：
+ *
  * if-else
- *      cmp ...         判断
- *      je L1           相等
- *      jmp L2          不相等
- * L1:  something
- * L2:  something
- * 
+ * .Lf.bb0:
+ *      cmp rax,0       判断
+ *      je .Lf.bb2      为假
+ *      jmp .Lf.bb1     为真
+ * .Lf.bb1:  something
+ *      jmp .Lf.bb3
+ * .Lf.bb2:  something
+ *      jmp .Lf.bb3
+ * .Lf.bb3:
+ *
  * while
- *      mov eax,val1
- * beginwhile:
- *      cmp eax,val2    判断
- *      jnl endwhile    跳转
+ * .Lf.bb1:             条件
+ *      cmp rax,0
+ *      je .Lf.bb3
+ *      jmp .Lf.bb2
+ * .Lf.bb2:             循环体
  *      something
- *      jmp beginwhile
- * endwhile:
+ *      jmp .Lf.bb1
+ * .Lf.bb3:
  *      something
- * 
- * for 和 while 差不多 就多执行一条语句而已
- * gebinfor:
- *      cmp 判断
- *      je L1
- *      jmp L2
- * L1：、、、
- * L2：outher
-*/
\ No newline at end of file
+ *
+ * for 和 while 差不多 就多一个执行 post 的块而已
+*/
@@ -0,0 +1,403 @@
+use std::io::Write;
+
+use super::{Machine, Stop, Type, Value};
+
+/*
+ * 解释器中没有定义的函数
+ * 和 glibc 的行为一致，int 是 64 位的，所以 %d 和 glibc 一样只读低32位
+*/
+
+// double f(double)
+fn unary_math(name: &str) -> Option<fn(f64) -> f64> {
+    let f: fn(f64) -> f64 = match name {
+        "sqrt" => f64::sqrt,
+        "fabs" => f64::abs,
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        "round" => f64::round,
+        "trunc" => f64::trunc,
+        "exp" => f64::exp,
+        "log" => f64::ln,
+        "log10" => f64::log10,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "atan" => f64::atan,
+        _ => return None,
+    };
+    Some(f)
+}
+
+// double f(double, double)
+fn binary_math(name: &str) -> Option<fn(f64, f64) -> f64> {
+    let f: fn(f64, f64) -> f64 = match name {
+        "pow" => f64::powf,
+        "fmod" => |a, b| a % b,
+        "atan2" => f64::atan2,
+        _ => return None,
+    };
+    Some(f)
+}
+
+fn error(name: &str, message: &str) -> Stop {
+    Stop::Error(format!("undefined behaviour in call to {}: {}", name, message))
+}
+
+// 第 i 个实参
+fn argument<'v>(name: &str, args: &'v [(Type, Value)], i: usize) -> Result<&'v Value, Stop> {
+    match args.get(i) {
+        Some((_, Value::Missing(f))) => Err(error(name, &format!("{} returned without a value that is used", f))),
+        Some((_, v)) => Ok(v),
+        None => Err(error(name, "too few arguments")),
+    }
+}
+
+fn int_argument(name: &str, args: &[(Type, Value)], i: usize) -> Result<i64, Stop> {
+    match argument(name, args, i)? {
+        Value::Int(n) => Ok(*n),
+        _ => Err(error(name, &format!("argument {} is not an integer", i + 1))),
+    }
+}
+
+fn float_argument(name: &str, args: &[(Type, Value)], i: usize) -> Result<f64, Stop> {
+    match argument(name, args, i)? {
+        Value::Float(x) => Ok(*x),
+        _ => Err(error(name, &format!("argument {} is not a floating-point number", i + 1))),
+    }
+}
+
+fn string_argument(machine: &Machine, name: &str, args: &[(Type, Value)], i: usize) -> Result<Vec<u8>, Stop> {
+    match argument(name, args, i)? {
+        Value::Pointer(p) => machine.read_string(*p).ok_or_else(|| error(name, &format!("argument {} is not a string", i + 1))),
+        _ => Err(error(name, &format!("argument {} is not a pointer", i + 1))),
+    }
+}
+
+fn write(machine: &mut Machine, bytes: &[u8]) -> Result<(), Stop> {
+    machine.out.write_all(bytes).map_err(|e| Stop::Error(e.to_string()))
+}
+
+/*
+ * 调用没有定义的函数
+ * 不认识的函数是错误
+*/
+pub(super) fn call(machine: &mut Machine, name: &str, args: &[(Type, Value)]) -> Result<Value, Stop> {
+    if let Some(f) = unary_math(name) {
+        return Ok(Value::Float(f(float_argument(name, args, 0)?)));
+    }
+    if let Some(f) = binary_math(name) {
+        return Ok(Value::Float(f(float_argument(name, args, 0)?, float_argument(name, args, 1)?)));
+    }
+
+    match name {
+        "printf" => {
+            let format = string_argument(machine, name, args, 0)?;
+            let output = printf(machine, &format, &args[1..])?;
+            write(machine, &output)?;
+            Ok(Value::Int(output.len() as i64))
+        }
+        // 从 va_list 中剩下的参数开始
+        "vprintf" => {
+            let format = string_argument(machine, name, args, 0)?;
+            let ap = argument(name, args, 1)?.clone();
+            let index = match ap {
+                Value::Pointer(p) => machine.slot(p),
+                _ => None,
+            };
+            let rest = match index.map(|i| &machine.stack[i]) {
+                Some(Some(Value::VaList(rest, next))) => rest[*next..].to_vec(),
+                _ => return Err(error(name, "invalid va_list")),
+            };
+            let output = printf(machine, &format, &rest)?;
+            write(machine, &output)?;
+            Ok(Value::Int(output.len() as i64))
+        }
+        "puts" => {
+            let mut s = string_argument(machine, name, args, 0)?;
+            s.push(b'\n');
+            write(machine, &s)?;
+            Ok(Value::Int(0))
+        }
+        "putchar" => {
+            let c = int_argument(name, args, 0)?;
+            write(machine, &[c as u8])?;
+            Ok(Value::Int(c as u8 as i64))
+        }
+        "strlen" => Ok(Value::Int(string_argument(machine, name, args, 0)?.len() as i64)),
+        "abs" | "labs" => match int_argument(name, args, 0)?.checked_abs() {
+            Some(n) => Ok(Value::Int(n)),
+            None => Err(error(name, "signed integer overflow")),
+        },
+        "exit" => Err(Stop::Exit(int_argument(name, args, 0)?)),
+        _ => Err(Stop::Error(format!("Call to undefined function {}", name))),
+    }
+}
+
+/*
+ * 一个转换说明 %[标志][宽度][.精度][长度]转换
+ * - 左对齐，+ 正数带 +，空格 正数带空格，# 另一种形式，0 用0填充
+*/
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    length: String,
+}
+
+/*
+ * printf 的格式化
+ * 支持 d i u o x X c s p f F e E g G %，长度修饰 hh h l ll L z j t
+ * 实参的个数和类型必须和格式一致
+*/
+fn printf(machine: &Machine, format: &[u8], args: &[(Type, Value)]) -> Result<Vec<u8>, Stop> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    let mut next = || match args.next() {
+        Some((_, v)) => Ok(v.clone()),
+        None => Err(error("printf", "too few arguments for the format")),
+    };
+
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            out.push(format[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        let mut spec = Spec::default();
+        while let Some(c) = format.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        // 宽度和精度可以是 *，从实参中读取
+        if format.get(i) == Some(&b'*') {
+            i += 1;
+            match next()? {
+                Value::Int(n) if n < 0 => {
+                    spec.left = true;
+                    spec.width = n.unsigned_abs() as usize;
+                }
+                Value::Int(n) => spec.width = n as usize,
+                _ => return Err(error("printf", "width is not an integer")),
+            }
+        } else {
+            while let Some(c) = format.get(i).filter(|c| c.is_ascii_digit()) {
+                spec.width = spec.width * 10 + (c - b'0') as usize;
+                i += 1;
+            }
+        }
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            if format.get(i) == Some(&b'*') {
+                i += 1;
+                spec.precision = match next()? {
+                    Value::Int(n) => (n >= 0).then_some(n as usize),
+                    _ => return Err(error("printf", "precision is not an integer")),
+                };
+            } else {
+                let mut precision = 0;
+                while let Some(c) = format.get(i).filter(|c| c.is_ascii_digit()) {
+                    precision = precision * 10 + (c - b'0') as usize;
+                    i += 1;
+                }
+                spec.precision = Some(precision);
+            }
+        }
+        while let Some(c) = format.get(i).filter(|c| b"hlLzjt".contains(c)) {
+            spec.length.push(*c as char);
+            i += 1;
+        }
+
+        let Some(&conversion) = format.get(i) else {
+            return Err(error("printf", "incomplete conversion at the end of the format"));
+        };
+        i += 1;
+
+        let text = match conversion {
+            b'%' => b"%".to_vec(),
+            b'd' | b'i' => {
+                let n = match next()? {
+                    Value::Int(n) => signed(n, &spec.length),
+                    _ => return Err(error("printf", "%d expects an integer")),
+                };
+                let sign = if n < 0 { "-" } else if spec.plus { "+" } else if spec.space { " " } else { "" };
+                integer(sign, "", n.unsigned_abs().to_string(), &spec)
+            }
+            b'u' | b'o' | b'x' | b'X' => {
+                let n = match next()? {
+                    Value::Int(n) => unsigned(n, &spec.length),
+                    _ => return Err(error("printf", "%u %o %x expect an integer")),
+                };
+                let (digits, prefix) = match conversion {
+                    b'u' => (n.to_string(), ""),
+                    b'o' => (format!("{:o}", n), ""),
+                    b'x' => (format!("{:x}", n), if spec.alternate && n != 0 { "0x" } else { "" }),
+                    _ => (format!("{:X}", n), if spec.alternate && n != 0 { "0X" } else { "" }),
+                };
+                // %#o 保证以0开头
+                let digits = match conversion {
+                    b'o' if spec.alternate && !digits.starts_with('0') => format!("0{}", digits),
+                    _ => digits,
+                };
+                integer("", prefix, digits, &spec)
+            }
+            b'c' => match next()? {
+                Value::Int(n) => pad(vec![n as u8], &spec),
+                _ => return Err(error("printf", "%c expects an integer")),
+            },
+            b's' => match next()? {
+                Value::Pointer(0) => pad(b"(null)".to_vec(), &spec),
+                Value::Pointer(p) => {
+                    let mut s = machine.read_string(p).ok_or_else(|| error("printf", "%s argument is not a string"))?;
+                    if let Some(precision) = spec.precision {
+                        s.truncate(precision);
+                    }
+                    pad(s, &spec)
+                }
+                _ => return Err(error("printf", "%s expects a pointer")),
+            },
+            b'p' => match next()? {
+                Value::Pointer(0) => pad(b"(nil)".to_vec(), &spec),
+                Value::Pointer(p) => pad(format!("0x{:x}", p).into_bytes(), &spec),
+                _ => return Err(error("printf", "%p expects a pointer")),
+            },
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => match next()? {
+                Value::Float(x) => floating(x, conversion, &spec),
+                _ => return Err(error("printf", "floating conversion expects a double")),
+            },
+            c => return Err(error("printf", &format!("unsupported conversion %{}", c as char))),
+        };
+        out.extend(text);
+    }
+    Ok(out)
+}
+
+// 按长度修饰截断有符号整数，没有修饰时是32位的 int
+fn signed(n: i64, length: &str) -> i64 {
+    match length {
+        "hh" => n as i8 as i64,
+        "h" => n as i16 as i64,
+        "" => n as i32 as i64,
+        _ => n,
+    }
+}
+
+fn unsigned(n: i64, length: &str) -> u64 {
+    match length {
+        "hh" => n as u8 as u64,
+        "h" => n as u16 as u64,
+        "" => n as u32 as u64,
+        _ => n as u64,
+    }
+}
+
+// 填充到宽度
+fn pad(text: Vec<u8>, spec: &Spec) -> Vec<u8> {
+    let fill = spec.width.saturating_sub(text.len());
+    let mut out = Vec::new();
+    if !spec.left {
+        out.resize(fill, b' ');
+    }
+    out.extend(text);
+    if spec.left {
+        out.resize(out.len() + fill, b' ');
+    }
+    out
+}
+
+// 数字: 符号 前缀 (用0填充时的0) 数字
+fn number(sign: &str, prefix: &str, digits: String, spec: &Spec, zero: bool) -> Vec<u8> {
+    let width = sign.len() + prefix.len() + digits.len();
+    let zeros = if zero && !spec.left { spec.width.saturating_sub(width) } else { 0 };
+    pad(format!("{}{}{}{}", sign, prefix, "0".repeat(zeros), digits).into_bytes(), spec)
+}
+
+// 整数，精度是最少的位数，有精度时不用0填充
+fn integer(sign: &str, prefix: &str, digits: String, spec: &Spec) -> Vec<u8> {
+    let digits = match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => format!("{}{}", "0".repeat(precision - digits.len()), digits),
+        _ => digits,
+    };
+    number(sign, prefix, digits, spec, spec.zero && spec.precision.is_none())
+}
+
+// %e: 指数至少两位，带符号
+fn exponent_form(x: f64, precision: usize, upper: bool) -> String {
+    let s = format!("{:.*e}", precision, x);
+    let (mantissa, exponent) = s.split_once('e').expect("Missing exponent");
+    let exponent: i32 = exponent.parse().expect("Invalid exponent");
+    let e = if upper { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", mantissa, e, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/*
+ * 浮点数 (x 是绝对值)
+ * %g 按 %e 的指数 X 选择: P > X >= -4 时用 %f，精度 P-1-X，否则用 %e，精度 P-1
+ * 没有 # 时 %g 去掉小数部分末尾的0
+*/
+fn floating(x: f64, conversion: u8, spec: &Spec) -> Vec<u8> {
+    let sign = if x.is_sign_negative() { "-" } else if spec.plus { "+" } else if spec.space { " " } else { "" };
+    let upper = conversion.is_ascii_uppercase();
+    let x = x.abs();
+
+    if !x.is_finite() {
+        let text = match (x.is_nan(), upper) {
+            (true, false) => "nan",
+            (true, true) => "NAN",
+            (false, false) => "inf",
+            (false, true) => "INF",
+        };
+        return number(sign, "", text.to_string(), spec, false);
+    }
+
+    let precision = spec.precision.unwrap_or(6);
+    let mut text = match conversion.to_ascii_lowercase() {
+        b'f' => format!("{:.*}", precision, x),
+        b'e' => exponent_form(x, precision, upper),
+        _ => {
+            let p = precision.max(1);
+            let exponent = if x == 0.0 {
+                0
+            } else {
+                let s = format!("{:.*e}", p - 1, x);
+                s.split_once('e').expect("Missing exponent").1.parse::<i32>().expect("Invalid exponent")
+            };
+            let mut text = if exponent < p as i32 && exponent >= -4 {
+                format!("{:.*}", (p as i32 - 1 - exponent) as usize, x)
+            } else {
+                exponent_form(x, p - 1, upper)
+            };
+            if !spec.alternate && text.contains('.') {
+                let (mantissa, rest) = match text.find(['e', 'E']) {
+                    Some(i) => text.split_at(i),
+                    None => (&text[..], ""),
+                };
+                text = format!("{}{}", mantissa.trim_end_matches('0').trim_end_matches('.'), rest);
+            }
+            text
+        }
+    };
+    if spec.alternate && !text.contains('.') {
+        let i = text.find(['e', 'E']).unwrap_or(text.len());
+        text.insert(i, '.');
+    }
+    number(sign, "", text, spec, spec.zero)
+}
@@ -0,0 +1,753 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::ptr;
+use std::rc::Rc;
+use std::thread;
+
+use super::c::declarator;
+use super::float::float_bits;
+use super::ir::Ty;
+use super::token::Operator;
+use super::typed_ast::{Expr, ExprKind, Function, Program, Stmt};
+use super::types::Type;
+
+mod host;
+mod session;
+
+pub use session::Session;
+
+/*
+ * 直接解释执行语义分析之后的语法树 (my_rcc run)
+ * 变量的作用域已经由语义分析解析成了编号，和编译时完全一样
+ * 每次调用在模拟的栈上分配函数的所有变量，字符串常量放在模拟的内存中
+ * 发现未定义行为时停止并报告: 读未初始化的变量 除以0 有符号整数溢出 移位越界 浮点数转换越界
+ * 没有定义的函数由 host 模块用 Rust 实现 (printf puts putchar exit 数学函数)
+ * 整数和 char 用 i64 表示，float 每次运算后舍入，double 和 long double 都是 f64
+*/
+
+// 字符串常量所在的地址
+const DATA_BASE: u64 = 0x10000;
+// 栈上第 i 个变量的地址是 STACK_BASE + 8 * i，只用来取 va_list 的地址
+const STACK_BASE: u64 = 0x7fff_0000_0000;
+// 解释器线程的栈
+const THREAD_STACK: usize = 512 << 20;
+// 剩下的栈少于这个值加上几次调用用掉的栈时报告栈溢出，留给正在进行的调用和宿主函数
+const STACK_RESERVE: usize = 1 << 20;
+// 按相邻两次调用之间用掉的最多的栈，再留下这么多次调用
+const RESERVED_CALLS: usize = 8;
+
+thread_local! {
+    // 解释器线程的栈的最低地址，0 表示不检查
+    static STACK_BOTTOM: Cell<usize> = const { Cell::new(0) };
+    // 上一次调用时的栈顶地址，相邻两次调用之间用掉的最多的栈
+    static CALL_STACK: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+// pthread_attr_t，glibc 中 x86-64 是 56 字节，aarch64 是 64 字节
+#[repr(C)]
+struct PthreadAttr([u64; 8]);
+
+extern "C" {
+    fn pthread_self() -> usize;
+    fn pthread_getattr_np(thread: usize, attr: *mut PthreadAttr) -> i32;
+    fn pthread_attr_getstack(attr: *const PthreadAttr, address: *mut *mut c_void, size: *mut usize) -> i32;
+    fn pthread_attr_destroy(attr: *mut PthreadAttr) -> i32;
+}
+
+// 当前的栈顶地址 (栈向下增长)
+fn stack_address() -> usize {
+    let marker = 0u8;
+    ptr::addr_of!(marker) as usize
+}
+
+/*
+ * 当前线程的栈的最低地址
+ * 取不到时按线程开始时的栈顶和 THREAD_STACK 估计
+*/
+fn stack_bottom() -> usize {
+    let mut attr = PthreadAttr([0; 8]);
+    let (mut address, mut size) = (ptr::null_mut(), 0);
+    unsafe {
+        if pthread_getattr_np(pthread_self(), &mut attr) == 0 {
+            let found = pthread_attr_getstack(&attr, &mut address, &mut size) == 0;
+            pthread_attr_destroy(&mut attr);
+            if found && !address.is_null() {
+                return address as usize;
+            }
+        }
+    }
+    stack_address() - THREAD_STACK
+}
+
+/*
+ * 调用之前检查栈是否够用
+ * 递归时每层用掉的栈取决于函数中语句和表达式的嵌套，debug 构建比 release 大很多
+ * 所以记下相邻两次调用之间用掉的最多的栈，剩下的不够 RESERVED_CALLS 次这样的调用时报告溢出
+*/
+fn stack_exhausted() -> bool {
+    let bottom = STACK_BOTTOM.with(Cell::get);
+    if bottom == 0 {
+        return false;
+    }
+    let here = stack_address();
+    let (last, largest) = CALL_STACK.with(Cell::get);
+    let largest = if last > here { largest.max(last - here) } else { largest };
+    CALL_STACK.with(|call| call.set((here, largest)));
+    here < bottom + STACK_RESERVE + RESERVED_CALLS * largest
+}
+
+// 可变参数，提升之后的类型和值
+type VaArgs = Rc<[(Type, Value)]>;
+
+/*
+ * 值
+ * int 和 char (已经截断到类型的范围)
+ * float double long double
+ * 指针
+ * va_list，剩下的可变参数 (提升之后的类型和值) 和下一个的下标
+ * void 表达式的值
+ * 没有 return 就结束的函数的返回值，使用时报告未定义行为
+*/
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    Float(f64),
+    Pointer(u64),
+    VaList(VaArgs, usize),
+    Void,
+    Missing(String),
+}
+
+/*
+ * 停止执行
+ * 调用了 exit
+ * 未定义行为或者运行时错误
+*/
+pub enum Stop {
+    Exit(i64),
+    Error(String),
+}
+
+/*
+ * 语句执行之后的去向
+ * 顺序执行下一条
+ * break continue
+ * return
+ * 必须的尾调用，由调用者替换当前的调用
+*/
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+    TailCall(String, Vec<(Type, Value)>),
+}
+
+/*
+ * 一次调用
+ * 被调用的函数
+ * 变量在栈中的起始位置
+ * 可变参数
+*/
+struct Frame<'a> {
+    function: &'a Function,
+    base: usize,
+    varargs: VaArgs,
+}
+
+impl Frame<'_> {
+    fn undefined(&self, message: String) -> Stop {
+        Stop::Error(format!("undefined behaviour in {}: {}", self.function.name, message))
+    }
+}
+
+/*
+ * 解释器的状态
+ * 定义了的函数
+ * 模拟的内存 (从 DATA_BASE 开始)，字符串常量 -> 地址
+ * 模拟的栈，None 表示没有初始化
+ * 标准输出
+*/
+struct Machine<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    memory: Vec<u8>,
+    strings: HashMap<Vec<u8>, u64>,
+    stack: Vec<Option<Value>>,
+    out: BufWriter<Stdout>,
+}
+
+/*
+ * 在栈足够大的线程中执行 f，深的递归也不会让解释器自己栈溢出
+ * 递归能有多深取决于每层调用实际用掉的栈，用完之前报告被解释的程序栈溢出
+*/
+pub fn on_large_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    let handle = thread::Builder::new()
+        .stack_size(THREAD_STACK)
+        .spawn(move || {
+            STACK_BOTTOM.with(|bottom| bottom.set(stack_bottom()));
+            f()
+        })
+        .map_err(|e| e.to_string())?;
+    handle.join().map_err(|_| "Interpreter panicked".to_string())
+}
+
+// 执行 main，返回它的返回值
+pub fn run(program: Program, input: &str) -> Result<i64, String> {
+    let input = input.to_string();
+    on_large_stack(move || run_main(&program, &input))?
+}
+
+fn run_main(program: &Program, input: &str) -> Result<i64, String> {
+    let mut machine = Machine::new(program, Vec::new(), HashMap::new());
+    let main = machine.functions.get("main").copied().ok_or("Undefined function main")?;
+
+    // main(argc, argv, envp)，argv 只有程序名
+    let argv0 = machine.string(input.as_bytes());
+    let argv = machine.store(&[argv0, 0]);
+    let envp = machine.store(&[0]);
+    let args: Vec<(Type, Value)> = [Value::Int(1), Value::Pointer(argv), Value::Pointer(envp)].iter()
+        .zip(main.signature.params.iter())
+        .map(|(v, t)| (t.clone(), v.clone()))
+        .collect();
+
+    let result = machine.call("main", args);
+    machine.out.flush().map_err(|e| e.to_string())?;
+    match result {
+        Ok(Value::Int(n)) => Ok(n),
+        // 没有 return 的 main 返回0
+        Ok(_) => Ok(0),
+        Err(Stop::Exit(n)) => Ok(n),
+        Err(Stop::Error(e)) => Err(e),
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Int(n) => *n != 0,
+        Value::Float(x) => *x != 0.0,
+        Value::Pointer(p) => *p != 0,
+        v => unreachable!("Condition has no value: {:?}", v),
+    }
+}
+
+// float 类型的值舍入到单精度
+fn round(ty: &Type, x: f64) -> f64 {
+    match ty {
+        Type::Float => x as f32 as f64,
+        _ => x,
+    }
+}
+
+fn float_constant(literal: &str, ty: &Type) -> f64 {
+    match ty {
+        Type::Float => f32::from_bits(float_bits(literal, Ty::F32) as u32) as f64,
+        _ => f64::from_bits(float_bits(literal, Ty::F64) as u64),
+    }
+}
+
+impl<'a> Machine<'a> {
+    fn new(program: &'a Program, memory: Vec<u8>, strings: HashMap<Vec<u8>, u64>) -> Self {
+        Machine {
+            functions: program.functions.iter().map(|f| (&f.name[..], f)).collect(),
+            memory,
+            strings,
+            stack: Vec::new(),
+            out: BufWriter::new(io::stdout()),
+        }
+    }
+
+    // 字符串常量的地址，相同的字符串只放一次
+    fn string(&mut self, s: &[u8]) -> u64 {
+        if let Some(address) = self.strings.get(s) {
+            return *address;
+        }
+        let address = DATA_BASE + self.memory.len() as u64;
+        self.memory.extend_from_slice(s);
+        self.memory.push(0);
+        self.strings.insert(s.to_vec(), address);
+        address
+    }
+
+    // 把 8 字节的值依次放进内存
+    fn store(&mut self, values: &[u64]) -> u64 {
+        let address = DATA_BASE + self.memory.len() as u64;
+        for v in values.iter() {
+            self.memory.extend_from_slice(&v.to_le_bytes());
+        }
+        address
+    }
+
+    // 读取内存中以0结尾的字符串
+    fn read_string(&self, p: u64) -> Option<Vec<u8>> {
+        let start = p.checked_sub(DATA_BASE)? as usize;
+        let bytes = self.memory.get(start..)?;
+        let end = bytes.iter().position(|b| *b == 0)?;
+        Some(bytes[..end].to_vec())
+    }
+
+    // 指针指向的栈中的位置 (va_list 变量)
+    fn slot(&self, p: u64) -> Option<usize> {
+        let offset = p.checked_sub(STACK_BASE)?;
+        let index = (offset / 8) as usize;
+        (offset.is_multiple_of(8) && index < self.stack.len()).then_some(index)
+    }
+
+    // 指针指向的 va_list 中剩下的参数
+    fn va_list(&self, p: &Value, frame: &Frame) -> Result<(usize, VaArgs, usize), Stop> {
+        let index = match p {
+            Value::Pointer(p) => self.slot(*p),
+            _ => None,
+        };
+        match index.map(|i| (i, &self.stack[i])) {
+            Some((i, Some(Value::VaList(args, next)))) => Ok((i, args.clone(), *next)),
+            Some((_, None)) => Err(frame.undefined("use of va_list before va_start".to_string())),
+            _ => Err(frame.undefined("invalid va_list".to_string())),
+        }
+    }
+
+    /*
+     * 调用函数
+     * 定义了的函数在栈上分配所有的变量，参数之外的实参是可变参数
+     * 必须的尾调用在同一个循环里替换当前调用，不占用更多的栈
+    */
+    fn call(&mut self, name: &str, args: Vec<(Type, Value)>) -> Result<Value, Stop> {
+        if stack_exhausted() {
+            return Err(Stop::Error(format!("stack overflow calling {}", name)));
+        }
+
+        let (mut name, mut args) = (name.to_string(), args);
+        loop {
+            let Some(function) = self.functions.get(&name[..]).copied() else {
+                break host::call(self, &name, &args);
+            };
+            if args.len() < function.params.len() {
+                return Err(Stop::Error(format!("undefined behaviour: too few arguments in call to {}", name)));
+            }
+
+            let base = self.stack.len();
+            self.stack.resize(base + function.locals.len(), None);
+            let mut values = args.into_iter();
+            for id in function.params.iter() {
+                self.stack[base + id] = values.next().map(|(_, v)| v);
+            }
+            let frame = Frame { function, base, varargs: values.collect() };
+
+            let flow = self.execute_block(&function.body, &frame)?;
+            self.stack.truncate(base);
+            match flow {
+                Flow::TailCall(callee, callee_args) => {
+                    name = callee;
+                    args = callee_args;
+                }
+                Flow::Return(v) => break Ok(v),
+                _ if function.signature.return_type == Type::Void => break Ok(Value::Void),
+                _ => break Ok(Value::Missing(name)),
+            }
+        }
+    }
+
+    fn execute_block(&mut self, block: &[Stmt], frame: &Frame) -> Result<Flow, Stop> {
+        for statement in block.iter() {
+            match self.execute(statement, frame)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // 执行循环体，返回是否要结束循环 (break 或者 return)
+    fn execute_loop_body(&mut self, body: &Stmt, frame: &Frame) -> Result<Option<Flow>, Stop> {
+        match self.execute(body, frame)? {
+            Flow::Break => Ok(Some(Flow::Normal)),
+            Flow::Normal | Flow::Continue => Ok(None),
+            flow => Ok(Some(flow)),
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr, frame: &Frame) -> Result<bool, Stop> {
+        let v = self.evaluate(condition, frame)?;
+        Ok(truthy(&self.used(v, frame)?))
+    }
+
+    fn execute(&mut self, statement: &Stmt, frame: &Frame) -> Result<Flow, Stop> {
+        match statement {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr, frame)?;
+            }
+
+            // 没有初始值的变量每次执行到声明时都是未初始化的
+            Stmt::Declaration(id, init) => {
+                let v = match init {
+                    Some(expr) => Some(self.evaluate(expr, frame)?),
+                    None => None,
+                };
+                self.stack[frame.base + id] = v;
+            }
+
+            Stmt::Return(expr) => {
+                let v = match expr {
+                    Some(expr) => self.evaluate(expr, frame)?,
+                    None => Value::Void,
+                };
+                return Ok(Flow::Return(v));
+            }
+
+            Stmt::TailCall(expr) => {
+                let ExprKind::Call(name, _, args) = &expr.kind else {
+                    unreachable!("Tail call of a non-call expression");
+                };
+                let args = self.arguments(args, frame)?;
+                return Ok(Flow::TailCall(name.clone(), args));
+            }
+
+            Stmt::If(condition, if_body, else_body) => {
+                if self.condition(condition, frame)? {
+                    return self.execute(if_body, frame);
+                } else if let Some(else_body) = else_body {
+                    return self.execute(else_body, frame);
+                }
+            }
+
+            Stmt::Block(block) => return self.execute_block(block, frame),
+
+            Stmt::For(init, condition, post_expression, body) => {
+                if let Some(init) = init {
+                    self.execute(init, frame)?;
+                }
+                while self.condition(condition, frame)? {
+                    if let Some(flow) = self.execute_loop_body(body, frame)? {
+                        return Ok(flow);
+                    }
+                    if let Some(expr) = post_expression {
+                        self.evaluate(expr, frame)?;
+                    }
+                }
+            }
+
+            Stmt::While(condition, body) => {
+                while self.condition(condition, frame)? {
+                    if let Some(flow) = self.execute_loop_body(body, frame)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+
+            Stmt::DoWhile(body, condition) => loop {
+                if let Some(flow) = self.execute_loop_body(body, frame)? {
+                    return Ok(flow);
+                }
+                if !self.condition(condition, frame)? {
+                    break;
+                }
+            },
+
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    // 要用到的值，没有 return 的函数的返回值不能用
+    fn used(&self, v: Value, frame: &Frame) -> Result<Value, Stop> {
+        match v {
+            Value::Missing(name) => Err(frame.undefined(format!("{} returned without a value that is used", name))),
+            v => Ok(v),
+        }
+    }
+
+    fn value(&mut self, expr: &Expr, frame: &Frame) -> Result<Value, Stop> {
+        let v = self.evaluate(expr, frame)?;
+        self.used(v, frame)
+    }
+
+    fn arguments(&mut self, args: &[Expr], frame: &Frame) -> Result<Vec<(Type, Value)>, Stop> {
+        args.iter().map(|arg| Ok((arg.ty.clone(), self.value(arg, frame)?))).collect()
+    }
+
+    fn variable(&self, id: usize, frame: &Frame) -> Result<Value, Stop> {
+        match &self.stack[frame.base + id] {
+            Some(v) => Ok(v.clone()),
+            None => Err(frame.undefined(format!("read of uninitialised variable {}", frame.function.names[id]))),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &Expr, frame: &Frame) -> Result<Value, Stop> {
+        let ty = &expression.ty;
+        let v = match &expression.kind {
+            ExprKind::Constant(n) => match ty {
+                Type::Pointer(_) => Value::Pointer(*n as u64),
+                t if t.is_floating() => Value::Float(round(t, *n as f64)),
+                _ => Value::Int(*n),
+            },
+
+            ExprKind::FloatConstant(f) => Value::Float(float_constant(f, ty)),
+
+            ExprKind::StringLiteral(s) => Value::Pointer(self.string(s)),
+
+            ExprKind::Variable(id) => self.variable(*id, frame)?,
+
+            ExprKind::Address(id) => Value::Pointer(STACK_BASE + 8 * (frame.base + id) as u64),
+
+            ExprKind::Unary(Operator::LogicalNegation, expr) => {
+                let v = self.value(expr, frame)?;
+                Value::Int(!truthy(&v) as i64)
+            }
+
+            ExprKind::Unary(Operator::BitwiseComplement, expr) => match self.value(expr, frame)? {
+                Value::Int(n) => Value::Int(!n),
+                v => unreachable!("Complement of {:?}", v),
+            },
+
+            ExprKind::Unary(_, expr) => match self.value(expr, frame)? {
+                Value::Int(n) => match n.checked_neg() {
+                    Some(n) => Value::Int(n),
+                    None => return Err(frame.undefined(format!("signed integer overflow in -({})", n))),
+                },
+                Value::Float(x) => Value::Float(-x),
+                v => unreachable!("Negation of {:?}", v),
+            },
+
+            ExprKind::Binary(Operator::LogicalAnd, lhs, rhs) => {
+                Value::Int((self.condition(lhs, frame)? && self.condition(rhs, frame)?) as i64)
+            }
+
+            ExprKind::Binary(Operator::LogicalOr, lhs, rhs) => {
+                Value::Int((self.condition(lhs, frame)? || self.condition(rhs, frame)?) as i64)
+            }
+
+            ExprKind::Binary(op, lhs, rhs) => {
+                let a = self.value(lhs, frame)?;
+                let b = self.value(rhs, frame)?;
+                binary(*op, a, b, ty, frame)?
+            }
+
+            ExprKind::Assign(lhs, rhs) => {
+                let v = self.evaluate(rhs, frame)?;
+                self.stack[frame.base + lvalue(lhs)] = Some(v.clone());
+                v
+            }
+
+            // a op= b，a 先转换成 b 的类型，结果再转换回 a 的类型
+            ExprKind::CompoundAssign(op, lhs, rhs) => {
+                let b = self.value(rhs, frame)?;
+                let id = lvalue(lhs);
+                let a = self.variable(id, frame)?;
+                let a = convert(a, &lhs.ty, &rhs.ty, frame)?;
+                let v = convert(binary(*op, a, b, &rhs.ty, frame)?, &rhs.ty, &lhs.ty, frame)?;
+                self.stack[frame.base + id] = Some(v.clone());
+                v
+            }
+
+            ExprKind::Ternary(e1, e2, e3) => {
+                if self.condition(e1, frame)? {
+                    self.evaluate(e2, frame)?
+                } else {
+                    self.evaluate(e3, frame)?
+                }
+            }
+
+            ExprKind::Call(name, _, args) => {
+                let args = self.arguments(args, frame)?;
+                self.call(name, args)?
+            }
+
+            ExprKind::Cast(expr) => {
+                let v = self.evaluate(expr, frame)?;
+                if *ty == Type::Void {
+                    Value::Void
+                } else {
+                    let v = self.used(v, frame)?;
+                    convert(v, &expr.ty, ty, frame)?
+                }
+            }
+
+            ExprKind::VaStart(ap) => {
+                let index = match self.value(ap, frame)? {
+                    Value::Pointer(p) => self.slot(p),
+                    _ => None,
+                };
+                let index = index.ok_or_else(|| frame.undefined("invalid va_list in va_start".to_string()))?;
+                self.stack[index] = Some(Value::VaList(frame.varargs.clone(), 0));
+                Value::Void
+            }
+
+            // 读取下一个可变参数，类型必须和提升之后的实参相同
+            ExprKind::VaArg(ap) => {
+                let ap = self.value(ap, frame)?;
+                let (index, args, next) = self.va_list(&ap, frame)?;
+                let Some((arg_type, v)) = args.get(next) else {
+                    return Err(frame.undefined("va_arg after the last variadic argument".to_string()));
+                };
+                let matches = match (ty, arg_type) {
+                    (Type::Pointer(_), Type::Pointer(_)) => true,
+                    (Type::Char, Type::Int) | (Type::Float, Type::Double) => true,
+                    (a, b) => a == b,
+                };
+                if !matches {
+                    return Err(frame.undefined(format!("va_arg of type {} reads an argument of type {}", declarator(ty, ""), declarator(arg_type, ""))));
+                }
+                let v = convert(v.clone(), arg_type, ty, frame)?;
+                self.stack[index] = Some(Value::VaList(args, next + 1));
+                v
+            }
+
+            ExprKind::VaEnd(ap) => {
+                let ap = self.value(ap, frame)?;
+                let (index, _, _) = self.va_list(&ap, frame)?;
+                self.stack[index] = None;
+                Value::Void
+            }
+
+            ExprKind::VaCopy(dest, src) => {
+                let dest = self.value(dest, frame)?;
+                let src = self.value(src, frame)?;
+                let (_, args, next) = self.va_list(&src, frame)?;
+                let index = match dest {
+                    Value::Pointer(p) => self.slot(p),
+                    _ => None,
+                };
+                let index = index.ok_or_else(|| frame.undefined("invalid va_list in va_copy".to_string()))?;
+                self.stack[index] = Some(Value::VaList(args, next));
+                Value::Void
+            }
+        };
+        Ok(v)
+    }
+}
+
+fn lvalue(expr: &Expr) -> usize {
+    match expr.kind {
+        ExprKind::Variable(id) => id,
+        _ => unreachable!("Not an lvalue: {:?}", expr),
+    }
+}
+
+/*
+ * 二元运算，两边已经是同一类型
+ * 有符号整数溢出、除以0、移位越界都是未定义行为
+*/
+fn binary(op: Operator, a: Value, b: Value, ty: &Type, frame: &Frame) -> Result<Value, Stop> {
+    if op.is_comparison_operators() {
+        let ordering = match (&a, &b) {
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+            (Value::Pointer(a), Value::Pointer(b)) => a.partial_cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            _ => unreachable!("Comparison of {:?} and {:?}", a, b),
+        };
+        let result = match op {
+            Operator::Equal => ordering.is_some_and(|o| o.is_eq()),
+            Operator::NotEqual => !ordering.is_some_and(|o| o.is_eq()),
+            Operator::LessThan => ordering.is_some_and(|o| o.is_lt()),
+            Operator::LessThanOrEqual => ordering.is_some_and(|o| o.is_le()),
+            Operator::GreaterThan => ordering.is_some_and(|o| o.is_gt()),
+            _ => ordering.is_some_and(|o| o.is_ge()),
+        };
+        return Ok(Value::Int(result as i64));
+    }
+
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => {
+            let x = match op {
+                Operator::Plus => a + b,
+                Operator::Minus => a - b,
+                Operator::Multiplication => a * b,
+                Operator::Division => a / b,
+                _ => unreachable!("Unexpected floating operator {:?}", op),
+            };
+            Ok(Value::Float(round(ty, x)))
+        }
+        (Value::Int(a), Value::Int(b)) => {
+            let overflow = |symbol: &str| frame.undefined(format!("signed integer overflow in {} {} {}", a, symbol, b));
+            let n = match op {
+                Operator::Plus => a.checked_add(b).ok_or_else(|| overflow("+"))?,
+                Operator::Minus => a.checked_sub(b).ok_or_else(|| overflow("-"))?,
+                Operator::Multiplication => a.checked_mul(b).ok_or_else(|| overflow("*"))?,
+                Operator::Division | Operator::Modulo if b == 0 => {
+                    return Err(frame.undefined(format!("division by zero in {} {} 0", a, if op == Operator::Division { "/" } else { "%" })));
+                }
+                Operator::Division => a.checked_div(b).ok_or_else(|| overflow("/"))?,
+                Operator::Modulo => a.checked_rem(b).ok_or_else(|| overflow("%"))?,
+                Operator::BitwiseAnd => a & b,
+                Operator::BitwiseOr => a | b,
+                Operator::BitwiseXor => a ^ b,
+                Operator::BitwiseShiftLeft | Operator::BitwiseShiftRight if !(0..64).contains(&b) => {
+                    return Err(frame.undefined(format!("shift count {} out of range", b)));
+                }
+                // 负数左移和移出符号位都是溢出
+                Operator::BitwiseShiftLeft => match a.checked_shl(b as u32) {
+                    Some(n) if a >= 0 && n >> b == a && n >= 0 => n,
+                    _ => return Err(overflow("<<")),
+                },
+                Operator::BitwiseShiftRight => a >> b,
+                _ => unreachable!("Unexpected integer operator {:?}", op),
+            };
+            Ok(Value::Int(n))
+        }
+        (a, b) => unreachable!("Operator {:?} on {:?} and {:?}", op, a, b),
+    }
+}
+
+/*
+ * 类型转换
+ * 整数转换成 char 时截断，浮点数转换成整数时超出范围是未定义行为
+*/
+fn convert(v: Value, from: &Type, to: &Type, frame: &Frame) -> Result<Value, Stop> {
+    let v = match (v, to) {
+        (v, _) if from == to => v,
+        (Value::Int(n), Type::Char) => Value::Int(n as i8 as i64),
+        (Value::Int(n), Type::Pointer(_)) => Value::Pointer(n as u64),
+        (Value::Int(n), t) if t.is_floating() => Value::Float(round(t, n as f64)),
+        (Value::Int(n), _) => Value::Int(n),
+        (Value::Pointer(p), Type::Char) => Value::Int(p as i8 as i64),
+        (Value::Pointer(p), Type::Int) => Value::Int(p as i64),
+        (Value::Float(x), t) if t.is_floating() => Value::Float(round(t, x)),
+        (Value::Float(x), _) => {
+            let (min, max) = match to {
+                Type::Char => (-129.0, 128.0),
+                _ => (-9223372036854777856.0, 9223372036854775808.0),
+            };
+            if !(x > min && x < max) {
+                return Err(frame.undefined(format!("conversion of {:?} to {} out of range", x, declarator(to, ""))));
+            }
+            Value::Int(x as i64)
+        }
+        (v, _) => v,
+    };
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::driver::interpret;
+    use super::super::options::Options;
+
+    fn run(source: &str) -> Result<i64, String> {
+        interpret(source, &Options { input: "test.c".to_string(), ..Options::default() })
+    }
+
+    #[test]
+    fn exit_status() {
+        assert_eq!(run("int main() { return 42; }"), Ok(42));
+        assert_eq!(run("int exit(int status); int main() { exit(3); return 1; }"), Ok(3));
+        assert_eq!(run("int main() { }"), Ok(0));
+    }
+
+    #[test]
+    fn undefined_behaviour() {
+        assert_eq!(run("int main() { int x; return x; }"),
+            Err("undefined behaviour in main: read of uninitialised variable x".to_string()));
+        assert_eq!(run("int main() { int z = 0; return 7 / z; }"),
+            Err("undefined behaviour in main: division by zero in 7 / 0".to_string()));
+        assert_eq!(run("int main() { int m = 2147483647; m = m * m * m; return 0; }"),
+            Err("undefined behaviour in main: signed integer overflow in 4611686014132420609 * 2147483647".to_string()));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        assert_eq!(run("int f(int n) { return f(n + 1) + 1; } int main() { return f(0); }"),
+            Err("stack overflow calling f".to_string()));
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+
+use super::super::c::declarator;
+use super::super::driver::{catch_errors, check, lower, parse};
+use super::super::encode::assemble;
+use super::super::generator::generate;
+use super::super::jit::{load, Jit};
+use super::super::options::Options;
+use super::{Flow, Frame, Machine, Stop, Value};
+use super::super::typed_ast::{Program, Stmt};
+use super::super::types::Type;
+
+/*
+ * 交互式执行 (my_rcc repl) 的状态，在多次输入之间保留
+ * 之前声明的变量: 名字 类型 值 (None 表示没有初始化)
+ * 模拟的内存和字符串常量，变量中的指针还指向它们
+*/
+#[derive(Default)]
+pub struct Session {
+    variables: Vec<(String, Type, Option<Value>)>,
+    memory: Vec<u8>,
+    strings: HashMap<Vec<u8>, u64>,
+}
+
+// 在 repl 中显示的值: (类型) 值
+fn show(v: &Value, ty: &Type, machine: &Machine) -> String {
+    let value = match (v, ty) {
+        (Value::Int(n), Type::Char) if (0x20..0x7f).contains(n) => format!("{} '{}'", n, *n as u8 as char),
+        (Value::Int(n), _) => n.to_string(),
+        (Value::Float(x), _) => format!("{:?}", x),
+        (Value::Pointer(p), _) => match machine.read_string(*p) {
+            Some(s) => format!("0x{:x} {:?}", p, String::from_utf8_lossy(&s)),
+            None => format!("0x{:x}", p),
+        },
+        (v, _) => unreachable!("Cannot show {:?}", v),
+    };
+    format!("({}) {}", declarator(ty, ""), value)
+}
+
+impl Session {
+    // 已经声明的变量的名字和类型，va_list 不保留
+    pub fn variables(&self) -> Vec<(String, Type)> {
+        self.variables.iter().map(|(name, ty, _)| (name.clone(), ty.clone())).collect()
+    }
+
+    /*
+     * 执行 entry 的函数体
+     * entry 的参数是之前声明的变量 (按名字取值)，函数体最外层的声明是新的变量
+     * 最后一条语句是有值的表达式时返回它的值
+     * 执行成功才更新变量，出错时和执行之前一样
+    */
+    pub fn execute(&mut self, program: &Program, entry: &str) -> Result<Option<String>, Stop> {
+        let function = program.functions.iter().find(|f| f.name == entry).unwrap_or_else(|| unreachable!("Missing {}", entry));
+        let mut machine = Machine::new(program, mem::take(&mut self.memory), mem::take(&mut self.strings));
+
+        machine.stack.resize(function.locals.len(), None);
+        for id in function.params.iter() {
+            let name = &function.names[*id];
+            machine.stack[*id] = self.variables.iter().find(|(n, _, _)| n == name).and_then(|(_, _, v)| v.clone());
+        }
+        let frame = Frame { function, base: 0, varargs: Rc::from(Vec::new()) };
+
+        let mut result = Ok(None);
+        for (i, statement) in function.body.iter().enumerate() {
+            let step = match statement {
+                Stmt::Expression(expr) if i + 1 == function.body.len() && expr.ty != Type::Void => {
+                    machine.value(expr, &frame).map(|v| {
+                        result = Ok(Some(show(&v, &expr.ty, &machine)));
+                        Flow::Normal
+                    })
+                }
+                statement => machine.execute(statement, &frame),
+            };
+            match step {
+                Ok(Flow::Normal) => {}
+                // return 结束这次输入
+                Ok(_) => break,
+                Err(stop) => {
+                    result = Err(stop);
+                    break;
+                }
+            }
+        }
+        let _ = machine.out.flush();
+
+        if result.is_ok() {
+            let declared = function.body.iter().filter_map(|statement| match statement {
+                Stmt::Declaration(id, _) => Some(*id),
+                _ => None,
+            });
+            self.variables = function.params.iter().copied().chain(declared)
+                .filter(|id| function.locals[*id] != Type::VaList)
+                .map(|id| (function.names[id].clone(), function.locals[id].clone(), machine.stack[id].clone()))
+                .collect();
+        }
+        self.memory = machine.memory;
+        self.strings = machine.strings;
+        result
+    }
+
+    /*
+     * 即时编译: 源代码编译成 x86-64 机器码直接装入内存，返回可以调用的函数
+     * 按 -O2 优化，和解释执行的变量无关
+     * 编译错误和装入时的错误 (未定义的符号等) 都作为 Err 返回
+    */
+    pub fn jit(source: &str) -> Result<Jit, String> {
+        let options = Options { input: "<jit>".to_string(), opt_level: 2, ..Options::default() };
+        let object = catch_errors(|| assemble(&generate(&lower(&check(&parse(source), &options), &options))))?;
+        load(&object)
+    }
+}
@@ -0,0 +1,218 @@
+use super::*;
+
+/*
+ * 控制流图的辅助函数
+ * 前驱、逆后序、支配树
+*/
+
+// 每个块的前驱
+pub fn predecessors(function: &Function) -> Vec<Vec<BlockId>> {
+    let mut preds = vec![Vec::new(); function.blocks.len()];
+    for b in function.block_ids() {
+        for succ in function.blocks[b.0].terminator.successors() {
+            if !preds[succ.0].contains(&b) {
+                preds[succ.0].push(b);
+            }
+        }
+    }
+    preds
+}
+
+// 从入口可以到达的块，按逆后序排列
+pub fn reverse_postorder(function: &Function) -> Vec<BlockId> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut order = Vec::new();
+    // 用显式的栈代替递归，(块, 下一个要访问的后继)
+    let mut stack = vec![(BlockId(0), 0)];
+    visited[0] = true;
+
+    while let Some((b, i)) = stack.pop() {
+        let succs = function.blocks[b.0].terminator.successors();
+        if i < succs.len() {
+            stack.push((b, i + 1));
+            let succ = succs[i];
+            if !visited[succ.0] {
+                visited[succ.0] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            order.push(b);
+        }
+    }
+
+    order.reverse();
+    order
+}
+
+/*
+ * 直接支配者
+ * Cooper, Harvey, Kennedy: A Simple, Fast Dominance Algorithm
+ * 入口的直接支配者是它自己，不可到达的块为 None
+*/
+pub fn immediate_dominators(function: &Function) -> Vec<Option<BlockId>> {
+    let rpo = reverse_postorder(function);
+    let preds = predecessors(function);
+    let mut index = vec![usize::MAX; function.blocks.len()];
+    for (i, b) in rpo.iter().enumerate() {
+        index[b.0] = i;
+    }
+
+    let mut idom: Vec<Option<BlockId>> = vec![None; function.blocks.len()];
+    idom[0] = Some(BlockId(0));
+
+    let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+        while a != b {
+            while index[a.0] > index[b.0] {
+                a = idom[a.0].expect("Missing dominator");
+            }
+            while index[b.0] > index[a.0] {
+                b = idom[b.0].expect("Missing dominator");
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &b in rpo.iter().skip(1) {
+            let mut new_idom = None;
+            for &p in preds[b.0].iter() {
+                if idom[p.0].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => p,
+                    Some(d) => intersect(&idom, p, d),
+                });
+            }
+            if new_idom.is_some() && idom[b.0] != new_idom {
+                idom[b.0] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom
+}
+
+// a 是否支配 b
+pub fn dominates(idom: &[Option<BlockId>], a: BlockId, mut b: BlockId) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idom[b.0] {
+            Some(d) if d != b => b = d,
+            _ => return false,
+        }
+    }
+}
+
+/*
+ * 删除不可到达的块并重新编号
+ * phi 中来自被删除块的值也一起删除
+*/
+pub fn remove_unreachable_blocks(function: &mut Function) {
+    let mut reachable = vec![false; function.blocks.len()];
+    for b in reverse_postorder(function) {
+        reachable[b.0] = true;
+    }
+    if reachable.iter().all(|r| *r) {
+        return;
+    }
+
+    // 旧编号 -> 新编号
+    let mut renumber = vec![None; function.blocks.len()];
+    let mut next = 0;
+    for (i, r) in reachable.iter().enumerate() {
+        if *r {
+            renumber[i] = Some(BlockId(next));
+            next += 1;
+        }
+    }
+    let map = |b: BlockId| renumber[b.0].expect("Jump to unreachable block");
+
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks.into_iter().zip(reachable).filter(|(_, r)| *r).map(|(mut block, _)| {
+        for instr in block.instrs.iter_mut() {
+            if let Instr::Phi(_, incoming) = instr {
+                incoming.retain(|(b, _)| renumber[b.0].is_some());
+                for (b, _) in incoming.iter_mut() {
+                    *b = map(*b);
+                }
+            }
+        }
+        block.terminator = match block.terminator {
+            Terminator::Jump(b) => Terminator::Jump(map(b)),
+            Terminator::Branch(v, t, f) => Terminator::Branch(v, map(t), map(f)),
+            t => t,
+        };
+        block
+    }).collect();
+}
+
+// 支配树，每个块直接支配的块
+pub fn dominator_tree(idom: &[Option<BlockId>]) -> Vec<Vec<BlockId>> {
+    let mut children = vec![Vec::new(); idom.len()];
+    for (b, d) in idom.iter().enumerate() {
+        match d {
+            Some(d) if d.0 != b => children[d.0].push(BlockId(b)),
+            _ => {}
+        }
+    }
+    children
+}
+
+/*
+ * 支配边界
+ * b 支配 x 的某个前驱但不严格支配 x 时，x 在 b 的支配边界中
+*/
+pub fn dominance_frontiers(function: &Function, idom: &[Option<BlockId>]) -> Vec<Vec<BlockId>> {
+    let preds = predecessors(function);
+    let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); function.blocks.len()];
+
+    for b in function.block_ids() {
+        let Some(d) = idom[b.0] else { continue };
+        if preds[b.0].len() < 2 {
+            continue;
+        }
+        for &p in preds[b.0].iter() {
+            if idom[p.0].is_none() {
+                continue;
+            }
+            // 从前驱沿支配树向上走到 b 的直接支配者
+            let mut runner = p;
+            while runner != d {
+                if !frontiers[runner.0].contains(&b) {
+                    frontiers[runner.0].push(b);
+                }
+                runner = idom[runner.0].expect("Missing dominator");
+            }
+        }
+    }
+
+    frontiers
+}
+
+// block 的 phi 中来自 from 的值改为来自 to
+pub fn redirect_phis(function: &mut Function, block: BlockId, from: BlockId, to: BlockId) {
+    for instr in function.blocks[block.0].instrs.iter_mut() {
+        if let Instr::Phi(_, incoming) = instr {
+            for (b, _) in incoming.iter_mut() {
+                if *b == from {
+                    *b = to;
+                }
+            }
+        }
+    }
+}
+
+// 删除 block 的 phi 中来自 pred 的值
+pub fn remove_phi_incoming(function: &mut Function, block: BlockId, pred: BlockId) {
+    for instr in function.blocks[block.0].instrs.iter_mut() {
+        if let Instr::Phi(_, incoming) = instr {
+            incoming.retain(|(b, _)| *b != pred);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 删除死代码
+ * 有副作用的指令和结尾用到的值是活的，活的指令用到的值也是活的
+ * 其余没有副作用的指令都删除 (包括互相引用的 phi)
+*/
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        // 每个值的定义
+        let mut defs: Vec<Option<&Instr>> = vec![None; function.values.len()];
+        for block in function.blocks.iter() {
+            for instr in block.instrs.iter() {
+                if let Some(v) = instr.def() {
+                    defs[v.0] = Some(instr);
+                }
+            }
+        }
+
+        let mut live = vec![false; function.values.len()];
+        let mut work: Vec<Value> = Vec::new();
+        for block in function.blocks.iter() {
+            for instr in block.instrs.iter().filter(|instr| !instr.is_pure()) {
+                work.extend(instr.uses());
+            }
+            work.extend(block.terminator.uses());
+        }
+
+        while let Some(v) = work.pop() {
+            if live[v.0] {
+                continue;
+            }
+            live[v.0] = true;
+            if let Some(instr) = defs[v.0] {
+                work.extend(instr.uses());
+            }
+        }
+
+        let mut changed = false;
+        for block in function.blocks.iter_mut() {
+            let before = block.instrs.len();
+            block.instrs.retain(|instr| match instr.def() {
+                Some(v) if instr.is_pure() => live[v.0],
+                _ => true,
+            });
+            changed |= before != block.instrs.len();
+        }
+        changed
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use super::cfg::{dominator_tree, immediate_dominators};
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 全局值编号 (基于支配树的公共子表达式消除)
+ * 沿支配树从上往下走，没有副作用的指令如果和支配它的某条指令算的是同一个东西，
+ * 就用那条指令的值代替
+ * 交换律的运算先把操作数排好序
+*/
+pub struct GlobalValueNumbering;
+
+// 指令算的东西，和定义的值无关
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Const(Ty, i64),
+    FloatConst(Ty, String),
+    StringAddr(usize),
+    SlotAddr(SlotId),
+    Neg(Value),
+    Binary(BinOp, Value, Value),
+    Compare(CmpOp, Value, Value),
+    Convert(Ty, Value),
+}
+
+fn key(function: &Function, instr: &Instr) -> Option<Key> {
+    // 满足交换律时小的放前面
+    let order = |commutative: bool, a: Value, b: Value| if commutative && b < a { (b, a) } else { (a, b) };
+
+    Some(match instr {
+        Instr::Const(v, n) => Key::Const(function.ty(*v), *n),
+        Instr::FloatConst(v, f) => Key::FloatConst(function.ty(*v), f.clone()),
+        Instr::StringAddr(_, index) => Key::StringAddr(*index),
+        Instr::SlotAddr(_, slot) => Key::SlotAddr(*slot),
+        Instr::Neg(_, a) => Key::Neg(*a),
+        Instr::Binary(_, op, a, b) => {
+            let (a, b) = order(matches!(op, BinOp::Add | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor), *a, *b);
+            Key::Binary(*op, a, b)
+        }
+        Instr::Compare(_, op, a, b) => {
+            let (a, b) = order(matches!(op, CmpOp::Eq | CmpOp::Ne), *a, *b);
+            Key::Compare(*op, a, b)
+        }
+        Instr::Convert(v, a) => Key::Convert(function.ty(*v), *a),
+        _ => return None,
+    })
+}
+
+struct Numbering<'a> {
+    children: &'a [Vec<BlockId>],
+    table: HashMap<Key, Value>,
+    replace: HashMap<Value, Value>,
+}
+
+impl<'a> Numbering<'a> {
+    fn visit(&mut self, function: &mut Function, b: BlockId) {
+        let mut added = Vec::new();
+
+        let instrs = std::mem::take(&mut function.blocks[b.0].instrs);
+        let mut kept = Vec::with_capacity(instrs.len());
+        for mut instr in instrs {
+            // 先换掉已经被代替的操作数
+            for u in instr.uses_mut() {
+                if let Some(r) = self.replace.get(u) {
+                    *u = *r;
+                }
+            }
+
+            if let (Some(v), Some(k)) = (instr.def(), key(function, &instr)) {
+                if let Some(existing) = self.table.get(&k) {
+                    self.replace.insert(v, *existing);
+                    continue;
+                }
+                self.table.insert(k.clone(), v);
+                added.push(k);
+            }
+            kept.push(instr);
+        }
+        function.blocks[b.0].instrs = kept;
+
+        for &child in self.children[b.0].iter() {
+            self.visit(function, child);
+        }
+
+        // 离开这棵子树，这里的值不再支配后面的块
+        for k in added {
+            self.table.remove(&k);
+        }
+    }
+}
+
+impl Pass for GlobalValueNumbering {
+    fn name(&self) -> &'static str {
+        "gvn"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let idom = immediate_dominators(function);
+        let children = dominator_tree(&idom);
+
+        let mut numbering = Numbering {
+            children: &children,
+            table: HashMap::new(),
+            replace: HashMap::new(),
+        };
+        numbering.visit(function, BlockId(0));
+
+        // phi 和结尾可能用到后面才被代替的值
+        let replace = numbering.replace;
+        function.replace_uses(|v| replace.get(&v).copied());
+        !replace.is_empty()
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{predecessors, redirect_phis};
+use super::*;
+
+/*
+ * 函数内联
+ * 把调用换成被调用函数的函数体，省掉传参、call 和栈帧
+ * 按调用图自底向上处理，被调用的函数先优化完再内联到调用者中
+ * 同一个强连通分量中的函数互相递归，不内联
+ *
+ * 是否内联由函数体的大小决定，大小不超过阈值的才内联
+ * noinline 的函数从不内联，always_inline 的函数在任何优化级别都内联
+ * inline 的函数阈值加倍，只被调用一次的 static 函数几乎总是内联
+ * -O1 以上，内联之后不再被调用的 static 函数会被删除
+ *
+ * -Rpass=inline 输出内联了的调用，-Rpass-missed=inline 输出没有内联的调用和原因
+*/
+
+// --print-after -Rpass 中使用的名字
+pub const NAME: &str = "inline";
+
+const THRESHOLD_O1: usize = 15;
+const THRESHOLD_O2: usize = 40;
+const THRESHOLD_CALLED_ONCE: usize = 1000;
+// 调用者超过这个大小之后不再内联
+const CALLER_LIMIT: usize = 2000;
+// 调用比普通的指令贵
+const CALL_COST: usize = 5;
+
+pub struct Inliner {
+    level: u8,
+    report_inlined: bool,
+    report_missed: bool,
+}
+
+// 内联的决定，附带输出时说明的理由
+enum Decision {
+    Inline(String),
+    Skip(String),
+}
+
+impl Inliner {
+    pub fn new(level: u8, remarks: &[String], missed_remarks: &[String]) -> Inliner {
+        Inliner {
+            level,
+            report_inlined: remarks.iter().any(|name| name == NAME),
+            report_missed: missed_remarks.iter().any(|name| name == NAME),
+        }
+    }
+
+    /*
+     * 在 caller 中内联调用，scc 是 caller 所在的强连通分量
+     * 内联进来的块中的调用已经在被调用的函数中决定过，不再检查
+     * 返回函数是否被修改
+    */
+    pub fn run(&self, module: &mut Module, caller: usize, scc: &[usize]) -> bool {
+        let index: HashMap<String, usize> = module.functions.iter().enumerate().map(|(i, f)| (f.name.clone(), i)).collect();
+        let mut size = function_size(&module.functions[caller]);
+        let mut work: Vec<BlockId> = module.functions[caller].block_ids().rev().collect();
+        let mut changed = false;
+
+        while let Some(b) = work.pop() {
+            let mut i = 0;
+            while i < module.functions[caller].blocks[b.0].instrs.len() {
+                let call = module.functions[caller].blocks[b.0].instrs[i].clone();
+                i += 1;
+                let Instr::Call(_, name, _, _) = &call else { continue };
+                // 没有定义的函数 (库函数) 不能内联
+                let Some(&callee) = index.get(name) else { continue };
+
+                let caller_name = &module.functions[caller].name;
+                match self.decide(module, caller, callee, &call, scc, size) {
+                    Decision::Skip(reason) => {
+                        if self.report_missed {
+                            eprintln!("remark: '{}' not inlined into '{}': {} [-Rpass-missed={}]", name, caller_name, reason, NAME);
+                        }
+                    }
+                    Decision::Inline(reason) => {
+                        if self.report_inlined {
+                            eprintln!("remark: '{}' inlined into '{}' ({}) [-Rpass={}]", name, caller_name, reason, NAME);
+                        }
+                        let callee = module.functions[callee].clone();
+                        size += function_size(&callee);
+                        let rest = inline_call(&mut module.functions[caller], b, i - 1, &callee);
+                        // 调用之后的指令移到了新的块中
+                        work.push(rest);
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    fn decide(&self, module: &Module, caller: usize, callee: usize, call: &Instr, scc: &[usize], caller_size: usize) -> Decision {
+        let Instr::Call(dest, _, args, _) = call else {
+            unreachable!("Deciding on a non-call instruction");
+        };
+        let function = &module.functions[caller];
+        let target = &module.functions[callee];
+        let specifiers = target.specifiers;
+
+        if specifiers.noinline {
+            return Decision::Skip("callee is noinline".to_string());
+        }
+        if scc.contains(&callee) {
+            return Decision::Skip("recursive call".to_string());
+        }
+        if target.variadic {
+            return Decision::Skip("callee is variadic".to_string());
+        }
+        let arg_types = args.iter().map(|a| function.ty(*a));
+        if args.len() != target.params.len() || !arg_types.eq(target.params.iter().map(|p| target.ty(*p))) {
+            return Decision::Skip("argument types do not match".to_string());
+        }
+        // 每个返回的值的类型，尾调用返回的是函数的返回类型
+        let returns: Vec<Option<Ty>> = target.blocks.iter().filter_map(|block| match &block.terminator {
+            Terminator::Return(v) => Some(v.map(|v| target.ty(v))),
+            Terminator::TailCall(_, _, _) => Some(target.return_type),
+            _ => None,
+        }).collect();
+        if returns.is_empty() {
+            return Decision::Skip("callee never returns".to_string());
+        }
+        if let Some(dest) = *dest {
+            if !returns.iter().all(|ty| *ty == Some(function.ty(dest))) {
+                return Decision::Skip("return type does not match".to_string());
+            }
+        }
+        if !predecessors(target)[0].is_empty() {
+            return Decision::Skip("callee entry block is a loop header".to_string());
+        }
+
+        if specifiers.always_inline {
+            return Decision::Inline("always_inline".to_string());
+        }
+        if self.level == 0 {
+            return Decision::Skip("optimization is disabled".to_string());
+        }
+        if caller_size > CALLER_LIMIT {
+            return Decision::Skip(format!("caller is too large (size={})", caller_size));
+        }
+
+        let cost = function_size(target);
+        let threshold = if specifiers.is_static && call_sites(module, &target.name) == 1 {
+            THRESHOLD_CALLED_ONCE
+        } else {
+            let threshold = if self.level == 1 { THRESHOLD_O1 } else { THRESHOLD_O2 };
+            if specifiers.inline { threshold * 2 } else { threshold }
+        };
+        if cost > threshold {
+            return Decision::Skip(format!("too costly (cost={}, threshold={})", cost, threshold));
+        }
+        Decision::Inline(format!("cost={}, threshold={}", cost, threshold))
+    }
+}
+
+/*
+ * 函数的大小
+ * phi 不生成指令，调用算 CALL_COST，其余指令和每个块的结尾算1
+*/
+fn function_size(function: &Function) -> usize {
+    function.blocks.iter().map(|block| {
+        let instrs: usize = block.instrs.iter().map(|instr| match instr {
+            Instr::Phi(_, _) => 0,
+            Instr::Call(_, _, _, _) => CALL_COST,
+            _ => 1,
+        }).sum();
+        instrs + 1
+    }).sum()
+}
+
+// 整个程序中调用 name 的次数
+fn call_sites(module: &Module, name: &str) -> usize {
+    module.functions.iter()
+        .flat_map(|f| f.blocks.iter())
+        .flat_map(|block| block.instrs.iter())
+        .filter(|instr| matches!(instr, Instr::Call(_, callee, _, _) if callee == name))
+        .count()
+}
+
+/*
+ * 内联 block 中第 index 条指令 (调用)
+ * block 在调用处分成两半，后一半放到新的块中
+ * 被调用函数的值、栈槽、块都复制到调用者中，参数换成实际的参数
+ * 返回变成跳到后一半，返回值由 phi 合在一起 (只有一个返回时直接替换)
+ * 尾调用变回普通的调用和返回
+ * 返回后一半所在的块
+*/
+fn inline_call(caller: &mut Function, block: BlockId, index: usize, callee: &Function) -> BlockId {
+    let rest = caller.blocks[block.0].instrs.split_off(index + 1);
+    let Some(Instr::Call(dest, _, args, _)) = caller.blocks[block.0].instrs.pop() else {
+        unreachable!("Inlining a non-call instruction");
+    };
+    let terminator = std::mem::replace(&mut caller.blocks[block.0].terminator, Terminator::Return(None));
+    let after = caller.new_block(terminator);
+    caller.blocks[after.0].instrs = rest;
+    for succ in caller.blocks[after.0].terminator.successors() {
+        redirect_phis(caller, succ, block, after);
+    }
+
+    // 被调用函数的值在调用者中对应的值
+    let mut values: Vec<Option<Value>> = vec![None; callee.values.len()];
+    for (param, arg) in callee.params.iter().zip(args.iter()) {
+        values[param.0] = Some(*arg);
+    }
+    for instr in callee.blocks.iter().flat_map(|block| block.instrs.iter()) {
+        if let Some(v) = instr.def() {
+            values[v.0] = Some(caller.new_value(callee.ty(v)));
+        }
+    }
+    let value = |v: Value| values[v.0].unwrap_or_else(|| unreachable!("Value {} has no definition", v));
+
+    let slot_base = caller.slots.len();
+    caller.slots.extend(callee.slots.iter().copied());
+    let block_base = caller.blocks.len();
+    let map_block = |b: BlockId| BlockId(b.0 + block_base);
+
+    let mut returns = Vec::new();
+    for (i, callee_block) in callee.blocks.iter().enumerate() {
+        let mut instrs = callee_block.instrs.clone();
+        for instr in instrs.iter_mut() {
+            if let Some(d) = instr.def_mut() {
+                *d = value(*d);
+            }
+            for u in instr.uses_mut() {
+                *u = value(*u);
+            }
+            match instr {
+                Instr::SlotAddr(_, slot) | Instr::Load(_, slot) | Instr::Store(slot, _) => slot.0 += slot_base,
+                Instr::Phi(_, incoming) => {
+                    for (p, _) in incoming.iter_mut() {
+                        *p = map_block(*p);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut terminator = callee_block.terminator.clone();
+        for u in terminator.uses_mut() {
+            *u = value(*u);
+        }
+        for s in terminator.successors_mut() {
+            *s = map_block(*s);
+        }
+        // 内联之后不再是尾调用
+        if let Terminator::TailCall(name, args, variadic) = terminator {
+            let v = callee.return_type.map(|ty| caller.new_value(ty));
+            instrs.push(Instr::Call(v, name, args, variadic));
+            terminator = Terminator::Return(v);
+        }
+        if let Terminator::Return(v) = terminator {
+            returns.push((map_block(BlockId(i)), v));
+            terminator = Terminator::Jump(after);
+        }
+        caller.blocks.push(Block { instrs, terminator });
+    }
+    caller.blocks[block.0].terminator = Terminator::Jump(map_block(BlockId(0)));
+
+    if let Some(dest) = dest {
+        let incoming: Vec<(BlockId, Value)> = returns.into_iter()
+            .map(|(b, v)| (b, v.expect("Missing return value")))
+            .collect();
+        if let [(_, v)] = incoming[..] {
+            caller.replace_uses(|u| (u == dest).then_some(v));
+        } else {
+            caller.blocks[after.0].instrs.insert(0, Instr::Phi(dest, incoming));
+        }
+    }
+
+    after
+}
+
+/*
+ * 调用图的强连通分量 (Tarjan)
+ * 被调用的函数所在的分量排在调用者之前，也就是自底向上的顺序
+*/
+pub fn call_graph_order(module: &Module) -> Vec<Vec<usize>> {
+    let index: HashMap<&str, usize> = module.functions.iter().enumerate().map(|(i, f)| (f.name.as_str(), i)).collect();
+    let callees: Vec<Vec<usize>> = module.functions.iter().map(|f| {
+        let mut callees: Vec<usize> = f.blocks.iter()
+            .flat_map(|block| block.instrs.iter())
+            .filter_map(|instr| match instr {
+                Instr::Call(_, name, _, _) => index.get(name.as_str()).copied(),
+                _ => None,
+            })
+            .collect();
+        callees.sort();
+        callees.dedup();
+        callees
+    }).collect();
+
+    let mut tarjan = Tarjan {
+        callees: &callees,
+        index: vec![None; callees.len()],
+        lowlink: vec![0; callees.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; callees.len()],
+        next: 0,
+        sccs: Vec::new(),
+    };
+    for f in 0..callees.len() {
+        if tarjan.index[f].is_none() {
+            tarjan.visit(f);
+        }
+    }
+    tarjan.sccs
+}
+
+struct Tarjan<'a> {
+    callees: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    next: usize,
+    sccs: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, f: usize) {
+        self.index[f] = Some(self.next);
+        self.lowlink[f] = self.next;
+        self.next += 1;
+        self.stack.push(f);
+        self.on_stack[f] = true;
+
+        for &g in self.callees[f].iter() {
+            match self.index[g] {
+                None => {
+                    self.visit(g);
+                    self.lowlink[f] = self.lowlink[f].min(self.lowlink[g]);
+                }
+                Some(i) if self.on_stack[g] => self.lowlink[f] = self.lowlink[f].min(i),
+                Some(_) => {}
+            }
+        }
+
+        // f 是分量的根
+        if Some(self.lowlink[f]) == self.index[f] {
+            let mut scc = Vec::new();
+            loop {
+                let g = self.stack.pop().expect("Missing function on stack");
+                self.on_stack[g] = false;
+                scc.push(g);
+                if g == f {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+/*
+ * 删除不再被其他函数调用的 static 函数
+ * 删除一个函数之后，它调用的 static 函数也可能不再被调用
+*/
+pub fn remove_unused_static(module: &mut Module) {
+    loop {
+        let called: HashSet<&str> = module.functions.iter()
+            .flat_map(|f| f.blocks.iter().flat_map(|block| block.instrs.iter()).filter_map(move |instr| match instr {
+                Instr::Call(_, name, _, _) if *name != f.name => Some(name.as_str()),
+                _ => None,
+            }))
+            .collect();
+        let unused: Vec<usize> = module.functions.iter().enumerate()
+            .filter(|(_, f)| f.specifiers.is_static && !called.contains(f.name.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        if unused.is_empty() {
+            return;
+        }
+        for i in unused.into_iter().rev() {
+            module.functions.remove(i);
+        }
+    }
+}
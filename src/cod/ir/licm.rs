@@ -0,0 +1,188 @@
+use super::cfg::{dominates, immediate_dominators, predecessors, reverse_postorder};
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 循环不变量外提
+ * 找到自然循环 (回边 t -> h，h 支配 t)，给循环头建一个前置块，
+ * 循环中操作数都在循环外定义的、没有副作用的指令移到前置块中
+ * 从内层循环开始，外提到内层前置块的指令还可以继续外提
+*/
+pub struct LoopInvariantCodeMotion;
+
+/*
+ * 一个自然循环
+ * 循环头
+ * 循环中的块
+*/
+struct Loop {
+    header: BlockId,
+    blocks: Vec<bool>,
+}
+
+// 所有自然循环，同一个循环头的回边合并成一个循环
+fn find_loops(function: &Function) -> Vec<Loop> {
+    let idom = immediate_dominators(function);
+    let preds = predecessors(function);
+    let mut loops: Vec<Loop> = Vec::new();
+
+    for h in function.block_ids() {
+        let latches: Vec<BlockId> = preds[h.0].iter().copied().filter(|p| idom[p.0].is_some() && dominates(&idom, h, *p)).collect();
+        if latches.is_empty() {
+            continue;
+        }
+
+        // 从回边的起点往回走，直到循环头
+        let mut blocks = vec![false; function.blocks.len()];
+        blocks[h.0] = true;
+        let mut work = latches;
+        while let Some(b) = work.pop() {
+            if blocks[b.0] {
+                continue;
+            }
+            blocks[b.0] = true;
+            work.extend(preds[b.0].iter().copied());
+        }
+        loops.push(Loop { header: h, blocks });
+    }
+
+    // 内层循环先处理
+    loops.sort_by_key(|l| l.blocks.iter().filter(|b| **b).count());
+    loops
+}
+
+/*
+ * 循环的前置块: 循环外跳到循环头的唯一的块，并且只跳到循环头
+ * 没有的话新建一个，循环外的前驱都改为跳到它
+ * 循环头的 phi 中来自循环外的值移到前置块的 phi 中
+*/
+fn preheader(function: &mut Function, l: &Loop) -> BlockId {
+    let preds = predecessors(function);
+    let outside: Vec<BlockId> = preds[l.header.0].iter().copied().filter(|p| !l.blocks[p.0]).collect();
+    if let [p] = outside[..] {
+        if function.blocks[p.0].terminator == Terminator::Jump(l.header) {
+            return p;
+        }
+    }
+
+    let pre = function.new_block(Terminator::Jump(l.header));
+    for &p in outside.iter() {
+        for target in function.blocks[p.0].terminator.successors_mut() {
+            if *target == l.header {
+                *target = pre;
+            }
+        }
+    }
+
+    let mut header_instrs = std::mem::take(&mut function.blocks[l.header.0].instrs);
+    for instr in header_instrs.iter_mut() {
+        let Instr::Phi(v, incoming) = instr else { continue };
+        let (from_outside, mut inside): (Vec<_>, Vec<_>) = incoming.drain(..).partition(|(p, _)| outside.contains(p));
+        let value = if let [(_, a)] = from_outside[..] {
+            a
+        } else {
+            let phi = function.new_value(function.ty(*v));
+            function.blocks[pre.0].instrs.push(Instr::Phi(phi, from_outside));
+            phi
+        };
+        inside.push((pre, value));
+        *incoming = inside;
+    }
+    function.blocks[l.header.0].instrs = header_instrs;
+
+    pre
+}
+
+/*
+ * 是否可以外提
+ * 没有副作用，phi 不行
+ * 除法只有除数是不为0和-1的常量时才能提前执行
+ * load 只有栈槽在循环中不会被修改时才行，clobbered 是循环中 store 的和取了地址的栈槽
+*/
+fn can_hoist(instr: &Instr, constants: &[Option<i64>], clobbered: &[SlotId]) -> bool {
+    match instr {
+        Instr::Phi(_, _) => false,
+        Instr::Load(_, slot) => !clobbered.contains(slot),
+        Instr::Binary(_, BinOp::Div | BinOp::Rem, _, b) => matches!(constants[b.0], Some(n) if n != 0 && n != -1),
+        instr => instr.is_pure(),
+    }
+}
+
+impl Pass for LoopInvariantCodeMotion {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        // 先给所有循环建好前置块，之后控制流图不再变化
+        let mut loops = find_loops(function);
+        let mut preheaders = Vec::new();
+        for i in 0..loops.len() {
+            let pre = preheader(function, &loops[i]);
+            preheaders.push(pre);
+            // 循环头在外层循环中时，新建的前置块也在外层循环中
+            let header = loops[i].header;
+            for outer in loops.iter_mut().skip(i + 1) {
+                outer.blocks.resize(function.blocks.len(), false);
+                if outer.blocks[header.0] {
+                    outer.blocks[pre.0] = true;
+                }
+            }
+        }
+
+        let mut constants = vec![None; function.values.len()];
+        for block in function.blocks.iter() {
+            for instr in block.instrs.iter() {
+                if let Instr::Const(v, n) = instr {
+                    constants[v.0] = Some(*n);
+                }
+            }
+        }
+
+        // 取了地址的栈槽可能通过指针修改 (调用 va_start memcpy)
+        let escaped: Vec<SlotId> = function.blocks.iter().flat_map(|block| block.instrs.iter()).filter_map(|instr| match instr {
+            Instr::SlotAddr(_, slot) => Some(*slot),
+            _ => None,
+        }).collect();
+
+        let mut changed = false;
+        for (l, pre) in loops.iter().zip(preheaders) {
+            let in_loop = |b: BlockId| l.blocks.get(b.0).copied().unwrap_or(false);
+            let mut clobbered = escaped.clone();
+            for b in function.block_ids().filter(|b| in_loop(*b)) {
+                clobbered.extend(function.blocks[b.0].instrs.iter().filter_map(|instr| match instr {
+                    Instr::Store(slot, _) => Some(*slot),
+                    _ => None,
+                }));
+            }
+            let def_blocks = function.def_blocks();
+            let mut hoisted = vec![false; function.values.len()];
+            let invariant = |v: &Value, hoisted: &[bool]| hoisted[v.0] || def_blocks[v.0].is_none_or(|b| !in_loop(b));
+
+            // 按逆后序处理，定义在使用之前，外提的指令按原来的顺序排列
+            let mut moved = Vec::new();
+            for b in reverse_postorder(function) {
+                if !in_loop(b) {
+                    continue;
+                }
+                let instrs = std::mem::take(&mut function.blocks[b.0].instrs);
+                let mut kept = Vec::with_capacity(instrs.len());
+                for instr in instrs {
+                    if can_hoist(&instr, &constants, &clobbered) && instr.uses().iter().all(|u| invariant(u, &hoisted)) {
+                        if let Some(v) = instr.def() {
+                            hoisted[v.0] = true;
+                        }
+                        moved.push(instr);
+                    } else {
+                        kept.push(instr);
+                    }
+                }
+                function.blocks[b.0].instrs = kept;
+            }
+
+            changed |= !moved.is_empty();
+            function.blocks[pre.0].instrs.extend(moved);
+        }
+        changed
+    }
+}
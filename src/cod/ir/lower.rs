@@ -0,0 +1,578 @@
+use super::super::token::Operator;
+use super::super::typed_ast::{self, Expr, ExprKind, Stmt, VarId};
+use super::super::types::{Signature, Type};
+use super::cfg::remove_unreachable_blocks;
+use super::*;
+
+/*
+ * 语义分析之后的语法树 -> IR
+ * 每个局部变量一个栈槽，不相交的作用域共用栈槽
+ * 控制流语句变成基本块和跳转
+*/
+pub fn lower(program: &typed_ast::Program) -> Module {
+    let mut module = Module::default();
+
+    for function in program.functions.iter() {
+        let function = lower_function(function, &mut module.strings);
+        module.functions.push(function);
+    }
+
+    module
+}
+
+/*
+ * 生成一个函数时的状态
+ * 正在生成的函数
+ * 每个块的指令和结尾，结尾为 None 表示还没有结束
+ * 当前的块
+ * 每个变量的栈槽
+ * 已经离开作用域、可以重新使用的栈槽
+ * 作用域栈，每层是这层声明的变量的栈槽
+ * 循环栈 (break 的目标, continue 的目标)
+ * 字符串常量
+*/
+struct Builder<'a> {
+    function: Function,
+    blocks: Vec<(Vec<Instr>, Option<Terminator>)>,
+    current: BlockId,
+    var_slots: Vec<Option<SlotId>>,
+    locals: &'a [Type],
+    free_slots: Vec<SlotId>,
+    scopes: Vec<Vec<SlotId>>,
+    loops: Vec<(BlockId, BlockId)>,
+    strings: &'a mut Vec<Vec<u8>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.blocks[self.current.0].0.push(instr);
+    }
+
+    // 新建一个值，并用 instr 定义它
+    fn emit_value(&mut self, ty: Ty, instr: impl FnOnce(Value) -> Instr) -> Value {
+        let v = self.function.new_value(ty);
+        self.emit(instr(v));
+        v
+    }
+
+    // 结束当前块
+    fn set_terminator(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0].1 = Some(terminator);
+    }
+
+    /*
+     * 结束当前块 (return break continue)
+     * 之后的代码放在一个新的块里，不可到达的块最后会被删除
+    */
+    fn terminate(&mut self, terminator: Terminator) {
+        self.set_terminator(terminator);
+        let next = self.new_block();
+        self.switch_to(next);
+    }
+
+    // 跳到 target 并在 target 中继续
+    fn jump(&mut self, target: BlockId) {
+        self.set_terminator(Terminator::Jump(target));
+        self.switch_to(target);
+    }
+
+    /*
+     * 给变量分配栈槽
+     * 优先使用已经离开作用域的同样大小的栈槽
+    */
+    fn allocate_slot(&mut self, id: VarId) -> SlotId {
+        let slot = match &self.locals[id] {
+            // va_list 是结构体，按最大的目标分配
+            Type::VaList => Slot { size: VA_LIST_SIZE, align: 8 },
+            t => {
+                let size = Ty::from_type(t).expect("Variable without value type").size();
+                Slot { size, align: size }
+            }
+        };
+
+        let slot_id = match self.free_slots.iter().position(|s| self.function.slots[s.0] == slot) {
+            Some(i) => self.free_slots.remove(i),
+            None => {
+                self.function.slots.push(slot);
+                SlotId(self.function.slots.len() - 1)
+            }
+        };
+
+        self.var_slots[id] = Some(slot_id);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(slot_id);
+        }
+        slot_id
+    }
+
+    fn var_slot(&self, id: VarId) -> SlotId {
+        self.var_slots[id].expect("Variable used before declaration")
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    // 离开作用域，栈槽可以给后面的变量使用
+    fn pop_scope(&mut self) {
+        let slots = self.scopes.pop().expect("Missing scope");
+        self.free_slots.extend(slots);
+    }
+
+    fn zero(&mut self, ty: Ty) -> Value {
+        if ty.is_float() {
+            self.emit_value(ty, |v| Instr::FloatConst(v, "0".to_string()))
+        } else {
+            self.emit_value(ty, |v| Instr::Const(v, 0))
+        }
+    }
+}
+
+fn value_type(t: &Type) -> Ty {
+    Ty::from_type(t).expect("Expression without value")
+}
+
+fn lower_function(function: &typed_ast::Function, strings: &mut Vec<Vec<u8>>) -> Function {
+    let signature = &function.signature;
+    let mut builder = Builder {
+        function: Function {
+            name: function.name.clone(),
+            specifiers: function.specifiers,
+            params: Vec::new(),
+            return_type: Ty::from_type(&signature.return_type),
+            variadic: signature.variadic,
+            values: Vec::new(),
+            slots: Vec::new(),
+            blocks: Vec::new(),
+        },
+        blocks: Vec::new(),
+        current: BlockId(0),
+        var_slots: vec![None; function.locals.len()],
+        locals: &function.locals,
+        free_slots: Vec::new(),
+        scopes: Vec::new(),
+        loops: Vec::new(),
+        strings,
+    };
+    let entry = builder.new_block();
+    builder.switch_to(entry);
+
+    // 参数保存到栈槽中
+    for id in function.params.iter() {
+        let param = builder.function.new_value(value_type(&function.locals[*id]));
+        builder.function.params.push(param);
+        let slot = builder.allocate_slot(*id);
+        builder.emit(Instr::Store(slot, param));
+    }
+
+    for statement in function.body.iter() {
+        lower_statement(statement, &mut builder);
+    }
+
+    // 没有 return 就结束的函数返回0
+    let return_value = builder.function.return_type.map(|ty| builder.zero(ty));
+    builder.set_terminator(Terminator::Return(return_value));
+
+    let Builder { mut function, blocks, .. } = builder;
+    function.blocks = blocks.into_iter().map(|(instrs, terminator)| Block {
+        instrs,
+        terminator: terminator.unwrap_or(Terminator::Return(None)),
+    }).collect();
+    remove_unreachable_blocks(&mut function);
+
+    function
+}
+
+// 调用的参数，以及可变参数函数的命名参数个数
+fn lower_arguments(signature: &Signature, args: &[Expr], builder: &mut Builder) -> (Vec<Value>, Option<usize>) {
+    let args: Vec<Value> = args.iter().map(|arg| lower_value(arg, builder)).collect();
+    // 没有原型的函数可能是可变参数的，所有参数都按命名参数传
+    let named = if !signature.prototyped {
+        Some(args.len())
+    } else if signature.variadic {
+        Some(signature.params.len())
+    } else {
+        None
+    };
+    (args, named)
+}
+
+fn lower_statement(statement: &Stmt, builder: &mut Builder) {
+    match statement {
+        Stmt::Expression(expr) => {
+            lower_expression(expr, builder);
+        }
+
+        Stmt::Declaration(id, init) => {
+            let slot = builder.allocate_slot(*id);
+            if let Some(expr) = init {
+                let v = lower_value(expr, builder);
+                builder.emit(Instr::Store(slot, v));
+            }
+        }
+
+        Stmt::Return(expr) => {
+            let v = expr.as_ref().map(|expr| lower_value(expr, builder));
+            builder.terminate(Terminator::Return(v));
+        }
+
+        Stmt::TailCall(expr) => {
+            let ExprKind::Call(name, signature, args) = &expr.kind else {
+                unreachable!("Tail call of a non-call expression");
+            };
+            let (args, variadic) = lower_arguments(signature, args, builder);
+            builder.terminate(Terminator::TailCall(name.clone(), args, variadic));
+        }
+
+        Stmt::If(condition, if_body, else_body) => {
+            let then_block = builder.new_block();
+            let else_block = builder.new_block();
+            let post_if = builder.new_block();
+            lower_condition(condition, then_block, else_block, builder);
+
+            builder.switch_to(then_block);
+            lower_statement(if_body, builder);
+            builder.jump(post_if);
+
+            builder.switch_to(else_block);
+            if let Some(else_body) = else_body {
+                lower_statement(else_body, builder);
+            }
+            builder.jump(post_if);
+        }
+
+        Stmt::Block(block) => {
+            builder.push_scope();
+            for statement in block.iter() {
+                lower_statement(statement, builder);
+            }
+            builder.pop_scope();
+        }
+
+        // for 中声明的变量只在循环里可见
+        Stmt::For(init, condition, post_expression, body) => {
+            builder.push_scope();
+            if let Some(init) = init {
+                lower_statement(init, builder);
+            }
+
+            let header = builder.new_block();
+            let body_block = builder.new_block();
+            let continue_block = builder.new_block();
+            let post_loop = builder.new_block();
+
+            builder.jump(header);
+            lower_condition(condition, body_block, post_loop, builder);
+
+            builder.switch_to(body_block);
+            lower_loop_body(body, post_loop, continue_block, builder);
+            builder.jump(continue_block);
+
+            if let Some(expr) = post_expression {
+                lower_expression(expr, builder);
+            }
+            builder.set_terminator(Terminator::Jump(header));
+            builder.switch_to(post_loop);
+            builder.pop_scope();
+        }
+
+        Stmt::While(condition, body) => {
+            let header = builder.new_block();
+            let body_block = builder.new_block();
+            let post_loop = builder.new_block();
+
+            builder.jump(header);
+            lower_condition(condition, body_block, post_loop, builder);
+
+            builder.switch_to(body_block);
+            lower_loop_body(body, post_loop, header, builder);
+            builder.set_terminator(Terminator::Jump(header));
+            builder.switch_to(post_loop);
+        }
+
+        Stmt::DoWhile(body, condition) => {
+            let body_block = builder.new_block();
+            let continue_block = builder.new_block();
+            let post_loop = builder.new_block();
+
+            builder.jump(body_block);
+            lower_loop_body(body, post_loop, continue_block, builder);
+            builder.jump(continue_block);
+
+            lower_condition(condition, body_block, post_loop, builder);
+            builder.switch_to(post_loop);
+        }
+
+        Stmt::Break => {
+            let (break_block, _) = *builder.loops.last().expect("Break outside loop");
+            builder.terminate(Terminator::Jump(break_block));
+        }
+
+        Stmt::Continue => {
+            let (_, continue_block) = *builder.loops.last().expect("Continue outside loop");
+            builder.terminate(Terminator::Jump(continue_block));
+        }
+    }
+}
+
+fn lower_loop_body(body: &Stmt, break_block: BlockId, continue_block: BlockId, builder: &mut Builder) {
+    builder.loops.push((break_block, continue_block));
+    lower_statement(body, builder);
+    builder.loops.pop();
+}
+
+// 有值的表达式
+fn lower_value(expression: &Expr, builder: &mut Builder) -> Value {
+    lower_expression(expression, builder).expect("Void value used")
+}
+
+fn binary_operator(op: Operator) -> BinOp {
+    match op {
+        Operator::Plus => BinOp::Add,
+        Operator::Minus => BinOp::Sub,
+        Operator::Multiplication => BinOp::Mul,
+        Operator::Division => BinOp::Div,
+        Operator::Modulo => BinOp::Rem,
+        Operator::BitwiseAnd => BinOp::And,
+        Operator::BitwiseOr => BinOp::Or,
+        Operator::BitwiseXor => BinOp::Xor,
+        Operator::BitwiseShiftLeft => BinOp::Shl,
+        Operator::BitwiseShiftRight => BinOp::Shr,
+        _ => unreachable!("Unexpected binary operator {:?}", op),
+    }
+}
+
+fn compare_operator(op: Operator) -> CmpOp {
+    match op {
+        Operator::Equal => CmpOp::Eq,
+        Operator::NotEqual => CmpOp::Ne,
+        Operator::LessThan => CmpOp::Lt,
+        Operator::LessThanOrEqual => CmpOp::Le,
+        Operator::GreaterThan => CmpOp::Gt,
+        Operator::GreaterThanOrEqual => CmpOp::Ge,
+        _ => unreachable!("Unexpected comparison operator {:?}", op),
+    }
+}
+
+// 值不为0时为1
+/*
+ * 按条件跳转，成立时跳到 if_true，否则跳到 if_false
+ * && || 短路求值，右边放在新的块中，左边已经决定结果时直接跳走
+ * ! 交换两个目标
+ * 其他的条件求值之后 br，比较和 br 在生成代码时合成一条 jcc
+*/
+fn lower_condition(condition: &Expr, if_true: BlockId, if_false: BlockId, builder: &mut Builder) {
+    match &condition.kind {
+        ExprKind::Binary(Operator::LogicalAnd, lhs, rhs) => {
+            let rhs_block = builder.new_block();
+            lower_condition(lhs, rhs_block, if_false, builder);
+            builder.switch_to(rhs_block);
+            lower_condition(rhs, if_true, if_false, builder);
+        }
+        ExprKind::Binary(Operator::LogicalOr, lhs, rhs) => {
+            let rhs_block = builder.new_block();
+            lower_condition(lhs, if_true, rhs_block, builder);
+            builder.switch_to(rhs_block);
+            lower_condition(rhs, if_true, if_false, builder);
+        }
+        ExprKind::Unary(Operator::LogicalNegation, expr) => lower_condition(expr, if_false, if_true, builder),
+        _ => {
+            let c = lower_value(condition, builder);
+            // 浮点数不能直接作为分支的条件
+            let c = if builder.function.ty(c).is_float() { lower_truth(c, builder) } else { c };
+            builder.set_terminator(Terminator::Branch(c, if_true, if_false));
+        }
+    }
+}
+
+fn lower_truth(v: Value, builder: &mut Builder) -> Value {
+    let ty = builder.function.ty(v);
+    let zero = builder.zero(ty);
+    builder.emit_value(Ty::I64, |d| Instr::Compare(d, CmpOp::Ne, v, zero))
+}
+
+// 左值所在的栈槽
+fn lower_lvalue(lvalue: &Expr, builder: &Builder) -> SlotId {
+    match lvalue.kind {
+        ExprKind::Variable(id) => builder.var_slot(id),
+        _ => unreachable!("Not an lvalue: {:?}", lvalue),
+    }
+}
+
+/*
+ * 表达式
+ * 返回表达式的值，void 表达式返回 None
+*/
+fn lower_expression(expression: &Expr, builder: &mut Builder) -> Option<Value> {
+    let ty = Ty::from_type(&expression.ty);
+
+    let v = match &expression.kind {
+        ExprKind::Constant(n) => builder.emit_value(value_type(&expression.ty), |v| Instr::Const(v, *n)),
+
+        ExprKind::FloatConstant(f) => builder.emit_value(value_type(&expression.ty), |v| Instr::FloatConst(v, f.clone())),
+
+        ExprKind::StringLiteral(s) => {
+            builder.strings.push(s.clone());
+            let index = builder.strings.len() - 1;
+            builder.emit_value(Ty::Ptr, |v| Instr::StringAddr(v, index))
+        }
+
+        ExprKind::Variable(id) => {
+            let slot = builder.var_slot(*id);
+            builder.emit_value(value_type(&expression.ty), |v| Instr::Load(v, slot))
+        }
+
+        ExprKind::Address(id) => {
+            let slot = builder.var_slot(*id);
+            builder.emit_value(Ty::Ptr, |v| Instr::SlotAddr(v, slot))
+        }
+
+        ExprKind::Unary(Operator::LogicalNegation, expr) => {
+            let a = lower_value(expr, builder);
+            let zero = builder.zero(builder.function.ty(a));
+            builder.emit_value(Ty::I64, |v| Instr::Compare(v, CmpOp::Eq, a, zero))
+        }
+
+        // ~a 是 a ^ -1
+        ExprKind::Unary(Operator::BitwiseComplement, expr) => {
+            let a = lower_value(expr, builder);
+            let ty = value_type(&expression.ty);
+            let ones = builder.emit_value(ty, |v| Instr::Const(v, -1));
+            builder.emit_value(ty, |v| Instr::Binary(v, BinOp::Xor, a, ones))
+        }
+
+        ExprKind::Unary(_, expr) => {
+            let a = lower_value(expr, builder);
+            builder.emit_value(value_type(&expression.ty), |v| Instr::Neg(v, a))
+        }
+
+        ExprKind::Assign(lhs, rhs) => {
+            let v = lower_value(rhs, builder);
+            let slot = lower_lvalue(lhs, builder);
+            builder.emit(Instr::Store(slot, v));
+            v
+        }
+
+        // a op= b 相当于 a = a op b，运算类型就是右边的类型
+        ExprKind::CompoundAssign(op, lhs, rhs) => {
+            let b = lower_value(rhs, builder);
+            let slot = lower_lvalue(lhs, builder);
+            let var_type = value_type(&lhs.ty);
+            let common_type = value_type(&rhs.ty);
+
+            let a = builder.emit_value(var_type, |v| Instr::Load(v, slot));
+            let a = lower_convert(a, common_type, builder);
+            let result = builder.emit_value(common_type, |v| Instr::Binary(v, binary_operator(*op), a, b));
+            let result = lower_convert(result, var_type, builder);
+            builder.emit(Instr::Store(slot, result));
+            result
+        }
+
+        // 短路求值，两个分支分别得到 1 和 0
+        ExprKind::Binary(Operator::LogicalAnd | Operator::LogicalOr, _, _) => {
+            let true_block = builder.new_block();
+            let false_block = builder.new_block();
+            let join = builder.new_block();
+            lower_condition(expression, true_block, false_block, builder);
+
+            builder.switch_to(true_block);
+            let one = builder.emit_value(Ty::I64, |v| Instr::Const(v, 1));
+            builder.jump(join);
+            builder.switch_to(false_block);
+            let zero = builder.emit_value(Ty::I64, |v| Instr::Const(v, 0));
+            builder.jump(join);
+            builder.emit_value(Ty::I64, |v| Instr::Phi(v, vec![(true_block, one), (false_block, zero)]))
+        }
+
+        ExprKind::Binary(op, lhs, rhs) => {
+            let a = lower_value(lhs, builder);
+            let b = lower_value(rhs, builder);
+            if op.is_comparison_operators() {
+                builder.emit_value(Ty::I64, |v| Instr::Compare(v, compare_operator(*op), a, b))
+            } else {
+                builder.emit_value(value_type(&expression.ty), |v| Instr::Binary(v, binary_operator(*op), a, b))
+            }
+        }
+
+        ExprKind::Ternary(e1, e2, e3) => {
+            let then_block = builder.new_block();
+            let else_block = builder.new_block();
+            let join = builder.new_block();
+            lower_condition(e1, then_block, else_block, builder);
+
+            builder.switch_to(then_block);
+            let a = lower_expression(e2, builder);
+            let a_block = builder.current;
+            builder.jump(join);
+
+            builder.switch_to(else_block);
+            let b = lower_expression(e3, builder);
+            let b_block = builder.current;
+            builder.jump(join);
+
+            match (ty, a, b) {
+                (Some(ty), Some(a), Some(b)) => builder.emit_value(ty, |v| Instr::Phi(v, vec![(a_block, a), (b_block, b)])),
+                _ => return None,
+            }
+        }
+
+        ExprKind::Call(name, signature, args) => {
+            let (args, variadic) = lower_arguments(signature, args, builder);
+            let dest = ty.map(|ty| builder.function.new_value(ty));
+            builder.emit(Instr::Call(dest, name.clone(), args, variadic));
+            return dest;
+        }
+
+        ExprKind::Cast(expr) => {
+            let a = lower_expression(expr, builder);
+            return match (ty, a) {
+                (Some(ty), Some(a)) => Some(lower_convert(a, ty, builder)),
+                // 转换成 void 只求值
+                _ => None,
+            };
+        }
+
+        ExprKind::VaStart(ap) => {
+            let ap = lower_value(ap, builder);
+            builder.emit(Instr::VaStart(ap));
+            return None;
+        }
+
+        ExprKind::VaArg(ap) => {
+            let ap = lower_value(ap, builder);
+            builder.emit_value(value_type(&expression.ty), |v| Instr::VaArg(v, ap))
+        }
+
+        ExprKind::VaEnd(ap) => {
+            lower_value(ap, builder);
+            return None;
+        }
+
+        ExprKind::VaCopy(dest, src) => {
+            let dest = lower_value(dest, builder);
+            let src = lower_value(src, builder);
+            builder.emit(Instr::MemCopy(dest, src, VA_LIST_SIZE));
+            return None;
+        }
+    };
+
+    Some(v)
+}
+
+// 类型转换，类型相同时不变
+fn lower_convert(a: Value, ty: Ty, builder: &mut Builder) -> Value {
+    if builder.function.ty(a) == ty {
+        a
+    } else {
+        builder.emit_value(ty, |v| Instr::Convert(v, a))
+    }
+}
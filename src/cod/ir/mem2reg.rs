@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use super::cfg::{dominance_frontiers, dominator_tree, immediate_dominators, remove_unreachable_blocks};
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 把局部变量从栈槽提升到 SSA 值
+ * Cytron 等: Efficiently Computing Static Single Assignment Form
+ * 在写入的块的支配边界放 phi，再沿支配树重命名
+ *
+ * 只提升地址没有被取过的栈槽
+ * 不相交的作用域共用栈槽时同一个栈槽可能有不同的类型，
+ * 这时 (栈槽, 类型) 当作不同的变量，读到另一个类型的值只可能是读了未初始化的变量
+*/
+pub struct Mem2Reg;
+
+impl Pass for Mem2Reg {
+    fn name(&self) -> &'static str {
+        "mem2reg"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        remove_unreachable_blocks(function);
+
+        // 地址被取过的栈槽不能提升
+        let mut escaped = vec![false; function.slots.len()];
+        for block in function.blocks.iter() {
+            for instr in block.instrs.iter() {
+                if let Instr::SlotAddr(_, slot) = instr {
+                    escaped[slot.0] = true;
+                }
+            }
+        }
+
+        // 要提升的变量，以及写入它们的块
+        let mut variables: Vec<(SlotId, Ty)> = Vec::new();
+        let mut def_blocks: Vec<Vec<BlockId>> = Vec::new();
+        let mut index: HashMap<(SlotId, Ty), usize> = HashMap::new();
+        for b in function.block_ids() {
+            for instr in function.blocks[b.0].instrs.iter() {
+                let key = match instr {
+                    Instr::Store(slot, v) => (*slot, function.ty(*v)),
+                    Instr::Load(v, slot) => (*slot, function.ty(*v)),
+                    _ => continue,
+                };
+                if escaped[key.0 .0] {
+                    continue;
+                }
+                let i = *index.entry(key).or_insert_with(|| {
+                    variables.push(key);
+                    def_blocks.push(Vec::new());
+                    variables.len() - 1
+                });
+                if let Instr::Store(_, _) = instr {
+                    if !def_blocks[i].contains(&b) {
+                        def_blocks[i].push(b);
+                    }
+                }
+            }
+        }
+        if variables.is_empty() {
+            return false;
+        }
+
+        let idom = immediate_dominators(function);
+        let frontiers = dominance_frontiers(function, &idom);
+        let children = dominator_tree(&idom);
+
+        // 放置 phi，phis[b] 是块 b 开头新加的 (变量, phi 的值)
+        let mut phis: Vec<Vec<(usize, Value)>> = vec![Vec::new(); function.blocks.len()];
+        for (i, defs) in def_blocks.iter().enumerate() {
+            let mut has_phi = vec![false; function.blocks.len()];
+            let mut work = defs.clone();
+            while let Some(b) = work.pop() {
+                for &f in frontiers[b.0].iter() {
+                    if has_phi[f.0] {
+                        continue;
+                    }
+                    has_phi[f.0] = true;
+                    let v = function.new_value(variables[i].1);
+                    phis[f.0].push((i, v));
+                    if !defs.contains(&f) {
+                        work.push(f);
+                    }
+                }
+            }
+        }
+
+        let mut renamer = Renamer {
+            variables: &variables,
+            index: &index,
+            phis: &phis,
+            children: &children,
+            stacks: vec![Vec::new(); variables.len()],
+            replace: HashMap::new(),
+            incoming: vec![Vec::new(); function.blocks.len()],
+            undefined: HashMap::new(),
+        };
+        renamer.rename(function, BlockId(0));
+
+        // 未初始化的变量读到 0
+        let mut undefined: Vec<(Ty, Value)> = renamer.undefined.iter().map(|(ty, v)| (*ty, *v)).collect();
+        undefined.sort_by_key(|(_, v)| *v);
+        let zeros: Vec<Instr> = undefined.into_iter().map(|(ty, v)| match ty {
+            t if t.is_float() => Instr::FloatConst(v, "0".to_string()),
+            _ => Instr::Const(v, 0),
+        }).collect();
+
+        // phi 放在块的开头
+        let incoming = renamer.incoming;
+        for b in function.block_ids() {
+            let mut new_instrs: Vec<Instr> = phis[b.0].iter().map(|(i, v)| {
+                let sources = incoming[b.0].iter().filter(|(j, _, _)| j == i).map(|(_, p, a)| (*p, *a)).collect();
+                Instr::Phi(*v, sources)
+            }).collect();
+            if b.0 == 0 {
+                new_instrs.extend(zeros.iter().cloned());
+            }
+            new_instrs.append(&mut function.blocks[b.0].instrs);
+            function.blocks[b.0].instrs = new_instrs;
+        }
+
+        let replace = renamer.replace;
+        function.replace_uses(|v| replace.get(&v).copied());
+
+        remove_promoted_slots(function, &escaped);
+        true
+    }
+}
+
+/*
+ * 重命名时的状态
+ * 每个变量当前的值 (栈顶)
+ * 被删除的 load -> 它读到的值
+ * 每个块的 phi 从前驱得到的值 (变量, 前驱, 值)
+ * 未初始化变量的值，每个类型一个
+*/
+struct Renamer<'a> {
+    variables: &'a [(SlotId, Ty)],
+    index: &'a HashMap<(SlotId, Ty), usize>,
+    phis: &'a [Vec<(usize, Value)>],
+    children: &'a [Vec<BlockId>],
+    stacks: Vec<Vec<Value>>,
+    replace: HashMap<Value, Value>,
+    incoming: Vec<Vec<(usize, BlockId, Value)>>,
+    undefined: HashMap<Ty, Value>,
+}
+
+impl<'a> Renamer<'a> {
+    // 变量当前的值
+    fn current(&mut self, function: &mut Function, i: usize) -> Value {
+        if let Some(v) = self.stacks[i].last() {
+            return *v;
+        }
+        let ty = self.variables[i].1;
+        *self.undefined.entry(ty).or_insert_with(|| function.new_value(ty))
+    }
+
+    fn resolve(&self, v: Value) -> Value {
+        match self.replace.get(&v) {
+            Some(r) => *r,
+            None => v,
+        }
+    }
+
+    fn rename(&mut self, function: &mut Function, b: BlockId) {
+        let mut pushed = Vec::new();
+
+        for (i, v) in self.phis[b.0].iter() {
+            self.stacks[*i].push(*v);
+            pushed.push(*i);
+        }
+
+        let instrs = std::mem::take(&mut function.blocks[b.0].instrs);
+        let mut kept = Vec::with_capacity(instrs.len());
+        for instr in instrs {
+            match instr {
+                Instr::Load(v, slot) => match self.index.get(&(slot, function.ty(v))) {
+                    Some(&i) => {
+                        let current = self.current(function, i);
+                        self.replace.insert(v, current);
+                    }
+                    None => kept.push(instr),
+                },
+                Instr::Store(slot, v) => match self.index.get(&(slot, function.ty(v))) {
+                    Some(&i) => {
+                        let v = self.resolve(v);
+                        self.stacks[i].push(v);
+                        pushed.push(i);
+                    }
+                    None => kept.push(instr),
+                },
+                _ => kept.push(instr),
+            }
+        }
+        function.blocks[b.0].instrs = kept;
+
+        // 后继的 phi 从这个块得到当前的值
+        for succ in function.blocks[b.0].terminator.successors() {
+            for (i, _) in self.phis[succ.0].iter() {
+                let current = self.current(function, *i);
+                self.incoming[succ.0].push((*i, b, current));
+            }
+        }
+
+        for &child in self.children[b.0].iter() {
+            self.rename(function, child);
+        }
+
+        for i in pushed {
+            self.stacks[i].pop();
+        }
+    }
+}
+
+/*
+ * 删除已经提升的栈槽，剩下的重新编号
+*/
+fn remove_promoted_slots(function: &mut Function, escaped: &[bool]) {
+    let mut used = escaped.to_vec();
+    for block in function.blocks.iter() {
+        for instr in block.instrs.iter() {
+            if let Instr::Load(_, slot) | Instr::Store(slot, _) = instr {
+                used[slot.0] = true;
+            }
+        }
+    }
+
+    let mut renumber = vec![None; function.slots.len()];
+    let mut slots = Vec::new();
+    for (i, slot) in function.slots.iter().enumerate() {
+        if used[i] {
+            renumber[i] = Some(SlotId(slots.len()));
+            slots.push(*slot);
+        }
+    }
+    function.slots = slots;
+
+    for block in function.blocks.iter_mut() {
+        for instr in block.instrs.iter_mut() {
+            if let Instr::Load(_, slot) | Instr::Store(slot, _) | Instr::SlotAddr(_, slot) = instr {
+                *slot = renumber[slot.0].expect("Removed slot still in use");
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use super::types::{FunctionSpecifiers, Type};
+
+pub mod cfg;
+pub mod lower;
+pub mod print;
+pub mod verify;
+pub mod pass;
+pub mod mem2reg;
+pub mod sccp;
+pub mod dce;
+pub mod gvn;
+pub mod simplify_cfg;
+pub mod licm;
+pub mod inline;
+pub mod tailcall;
+pub mod phi;
+
+/*
+ * 中间表示 (三地址码)
+ * 语义分析之后的语法树 -> IR -> 汇编
+ * 每个函数由若干基本块组成，每个基本块是一串指令加上一个结尾的跳转
+ * 每条指令最多定义一个值，每个值只定义一次
+ * 局部变量放在栈槽 (slot) 中，用 load store 访问
+*/
+
+/*
+ * IR 中的类型
+ * I8 是 char，在寄存器中按64位有符号数处理
+ * I64 是 int
+ * Ptr 是指针
+ * F32 F64 F80 是 float double long double
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ty {
+    I8,
+    I64,
+    Ptr,
+    F32,
+    F64,
+    F80,
+}
+
+impl Ty {
+    // C 的类型对应的 IR 类型，void 和 va_list 没有对应的值
+    pub fn from_type(t: &Type) -> Option<Ty> {
+        match t {
+            Type::Void | Type::VaList => None,
+            Type::Char => Some(Ty::I8),
+            Type::Int => Some(Ty::I64),
+            Type::Pointer(_) => Some(Ty::Ptr),
+            Type::Float => Some(Ty::F32),
+            Type::Double => Some(Ty::F64),
+            Type::LongDouble => Some(Ty::F80),
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Ty::F32 | Ty::F64 | Ty::F80)
+    }
+
+    // 在内存中占用的字节数，long double 按16字节存放
+    pub fn size(self) -> usize {
+        match self {
+            Ty::I8 => 1,
+            Ty::F32 => 4,
+            Ty::I64 | Ty::Ptr | Ty::F64 => 8,
+            Ty::F80 => 16,
+        }
+    }
+}
+
+// 值的编号，也是 Function::values 的下标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+// 基本块的编号，也是 Function::blocks 的下标，0 是入口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+// 栈槽的编号，也是 Function::slots 的下标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SlotId(pub usize);
+
+/*
+ * 栈槽
+ * 局部变量 (包括 va_list 这样的数组) 占用的内存
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub size: usize,
+    pub align: usize,
+}
+
+// va_list 的大小: x86-64 是24字节，AArch64 是32字节
+pub const VA_LIST_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+// 比较的结果是 I64 的 0 或 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/*
+ * 指令
+ * 第一个 Value 是定义的值，类型记录在 Function::values 中
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Const(Value, i64), // %v = const 1
+    FloatConst(Value, String), // %v = fconst 1.5，精度由值的类型决定
+    StringAddr(Value, usize), // %v = 字符串常量 Module::strings[i] 的地址
+    SlotAddr(Value, SlotId), // %v = 栈槽的地址
+    Load(Value, SlotId), // %v = load $s，按值的类型读取
+    Store(SlotId, Value), // store $s, %v，按值的类型写入
+    Neg(Value, Value), // %v = -%a
+    Binary(Value, BinOp, Value, Value), // %v = %a op %b，三个值的类型相同
+    Compare(Value, CmpOp, Value, Value), // %v = %a cmp %b，%a %b 的类型相同
+    Convert(Value, Value), // %v = (类型) %a
+    Call(Option<Value>, String, Vec<Value>, Option<usize>), // %v = call f(args)，被调用的函数接受可变参数时最后是命名参数的个数
+    VaStart(Value), // va_start(%ap)
+    VaArg(Value, Value), // %v = va_arg(%ap)
+    MemCopy(Value, Value, usize), // memcpy(%dest, %src, n)
+    Phi(Value, Vec<(BlockId, Value)>), // %v = phi [bb, %a] ...，只能出现在块的开头
+}
+
+impl Instr {
+    // 定义的值
+    pub fn def(&self) -> Option<Value> {
+        match self {
+            Instr::Const(v, _)
+            | Instr::FloatConst(v, _)
+            | Instr::StringAddr(v, _)
+            | Instr::SlotAddr(v, _)
+            | Instr::Load(v, _)
+            | Instr::Neg(v, _)
+            | Instr::Binary(v, _, _, _)
+            | Instr::Compare(v, _, _, _)
+            | Instr::Convert(v, _)
+            | Instr::VaArg(v, _)
+            | Instr::Phi(v, _) => Some(*v),
+            Instr::Call(v, _, _, _) => *v,
+            Instr::Store(_, _) | Instr::VaStart(_) | Instr::MemCopy(_, _, _) => None,
+        }
+    }
+
+    // 定义的值，可以修改
+    pub fn def_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            Instr::Const(v, _)
+            | Instr::FloatConst(v, _)
+            | Instr::StringAddr(v, _)
+            | Instr::SlotAddr(v, _)
+            | Instr::Load(v, _)
+            | Instr::Neg(v, _)
+            | Instr::Binary(v, _, _, _)
+            | Instr::Compare(v, _, _, _)
+            | Instr::Convert(v, _)
+            | Instr::VaArg(v, _)
+            | Instr::Phi(v, _) => Some(v),
+            Instr::Call(v, _, _, _) => v.as_mut(),
+            Instr::Store(_, _) | Instr::VaStart(_) | Instr::MemCopy(_, _, _) => None,
+        }
+    }
+
+    // 使用的值
+    pub fn uses(&self) -> Vec<Value> {
+        match self {
+            Instr::Const(_, _) | Instr::FloatConst(_, _) | Instr::StringAddr(_, _) | Instr::SlotAddr(_, _) | Instr::Load(_, _) => Vec::new(),
+            Instr::Store(_, v) | Instr::Neg(_, v) | Instr::Convert(_, v) | Instr::VaStart(v) | Instr::VaArg(_, v) => vec![*v],
+            Instr::Binary(_, _, a, b) | Instr::Compare(_, _, a, b) | Instr::MemCopy(a, b, _) => vec![*a, *b],
+            Instr::Call(_, _, args, _) => args.clone(),
+            Instr::Phi(_, incoming) => incoming.iter().map(|(_, v)| *v).collect(),
+        }
+    }
+
+    // 使用的值，可以修改
+    pub fn uses_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Instr::Const(_, _) | Instr::FloatConst(_, _) | Instr::StringAddr(_, _) | Instr::SlotAddr(_, _) | Instr::Load(_, _) => Vec::new(),
+            Instr::Store(_, v) | Instr::Neg(_, v) | Instr::Convert(_, v) | Instr::VaStart(v) | Instr::VaArg(_, v) => vec![v],
+            Instr::Binary(_, _, a, b) | Instr::Compare(_, _, a, b) | Instr::MemCopy(a, b, _) => vec![a, b],
+            Instr::Call(_, _, args, _) => args.iter_mut().collect(),
+            Instr::Phi(_, incoming) => incoming.iter_mut().map(|(_, v)| v).collect(),
+        }
+    }
+
+    /*
+     * 是否没有副作用
+     * 没有副作用的指令在结果没有被使用时可以删除
+     * 除以0是未定义行为，所以除法也算
+     * load 也算，但是它读的栈槽可能被 store 修改，移动它的时候要另外检查
+    */
+    pub fn is_pure(&self) -> bool {
+        !matches!(self, Instr::Store(_, _) | Instr::Call(_, _, _, _) | Instr::VaStart(_) | Instr::VaArg(_, _) | Instr::MemCopy(_, _, _))
+    }
+}
+
+/*
+ * 基本块的结尾
+ * 无条件跳转
+ * 条件跳转，值不为0时跳到第一个块
+ * 返回
+ * 尾调用，调用函数并直接返回它的返回值，被调用的函数使用调用者的栈帧的位置
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch(Value, BlockId, BlockId),
+    Return(Option<Value>),
+    TailCall(String, Vec<Value>, Option<usize>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![*b],
+            Terminator::Branch(_, t, f) => vec![*t, *f],
+            Terminator::Return(_) | Terminator::TailCall(_, _, _) => Vec::new(),
+        }
+    }
+
+    pub fn uses(&self) -> Vec<Value> {
+        match self {
+            Terminator::Branch(v, _, _) | Terminator::Return(Some(v)) => vec![*v],
+            Terminator::TailCall(_, args, _) => args.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Branch(v, _, _) | Terminator::Return(Some(v)) => vec![v],
+            Terminator::TailCall(_, args, _) => args.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![b],
+            Terminator::Branch(_, t, f) => vec![t, f],
+            Terminator::Return(_) | Terminator::TailCall(_, _, _) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub instrs: Vec<Instr>,
+    pub terminator: Terminator,
+}
+
+/*
+ * 函数名
+ * static inline noinline always_inline
+ * 参数对应的值
+ * 返回类型，None 是 void
+ * 是否有可变参数 ...
+ * 每个值的类型
+ * 栈槽
+ * 基本块，第一个是入口
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub specifiers: FunctionSpecifiers,
+    pub params: Vec<Value>,
+    pub return_type: Option<Ty>,
+    pub variadic: bool,
+    pub values: Vec<Ty>,
+    pub slots: Vec<Slot>,
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn ty(&self, v: Value) -> Ty {
+        self.values[v.0]
+    }
+
+    // 新建一个值
+    pub fn new_value(&mut self, ty: Ty) -> Value {
+        self.values.push(ty);
+        Value(self.values.len() - 1)
+    }
+
+    pub fn block_ids(&self) -> impl DoubleEndedIterator<Item = BlockId> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    // 新建一个空的块
+    pub fn new_block(&mut self, terminator: Terminator) -> BlockId {
+        self.blocks.push(Block { instrs: Vec::new(), terminator });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /*
+     * 把所有使用 v 的地方换成 replace(v)
+     * replace 返回 None 表示不替换
+    */
+    pub fn replace_uses(&mut self, replace: impl Fn(Value) -> Option<Value>) {
+        for block in self.blocks.iter_mut() {
+            let uses = block.instrs.iter_mut().flat_map(|instr| instr.uses_mut()).chain(block.terminator.uses_mut());
+            for u in uses {
+                if let Some(r) = replace(*u) {
+                    *u = r;
+                }
+            }
+        }
+    }
+
+    // 每个值定义所在的块，参数在入口
+    pub fn def_blocks(&self) -> Vec<Option<BlockId>> {
+        let mut defs = vec![None; self.values.len()];
+        for p in self.params.iter() {
+            defs[p.0] = Some(BlockId(0));
+        }
+        for b in self.block_ids() {
+            for instr in self.blocks[b.0].instrs.iter() {
+                if let Some(v) = instr.def() {
+                    defs[v.0] = Some(b);
+                }
+            }
+        }
+        defs
+    }
+}
+
+/*
+ * 整个程序
+ * 定义了的函数
+ * 字符串常量
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub strings: Vec<Vec<u8>>,
+}
@@ -0,0 +1,111 @@
+use super::dce::DeadCodeElimination;
+use super::gvn::GlobalValueNumbering;
+use super::inline::{self, call_graph_order, remove_unused_static, Inliner};
+use super::licm::LoopInvariantCodeMotion;
+use super::mem2reg::Mem2Reg;
+use super::sccp::ConstantPropagation;
+use super::tailcall::TailCallElimination;
+use super::super::options::Arch;
+use super::simplify_cfg::SimplifyCfg;
+use super::verify::verify;
+use super::*;
+
+/*
+ * 优化 pass
+ * 每个 pass 处理一个函数，返回函数是否被修改
+ * pass 之后的 IR 仍然要通过检查
+*/
+pub trait Pass {
+    // 命令行中使用的名字 (--print-after=<name>)
+    fn name(&self) -> &'static str;
+
+    fn run(&self, function: &mut Function) -> bool;
+}
+
+// 按名字找到 pass，尾调用要按目标机器的调用约定
+pub fn lookup(name: &str, arch: Arch) -> Option<Box<dyn Pass>> {
+    let pass: Box<dyn Pass> = match name {
+        "mem2reg" => Box::new(Mem2Reg),
+        "sccp" => Box::new(ConstantPropagation),
+        "dce" => Box::new(DeadCodeElimination),
+        "gvn" => Box::new(GlobalValueNumbering),
+        "simplify-cfg" => Box::new(SimplifyCfg),
+        "licm" => Box::new(LoopInvariantCodeMotion),
+        "tailcall" => Box::new(TailCallElimination { arch }),
+        _ => return None,
+    };
+    Some(pass)
+}
+
+// 是否是 pass 的名字，内联处理整个程序，不是 Pass
+pub fn is_pass(name: &str) -> bool {
+    name == inline::NAME || lookup(name, Arch::default()).is_some()
+}
+
+/*
+ * 不同优化级别运行的 pass
+ * -O0 不优化
+ * -O1 变成 SSA，常量传播，删除死代码，化简控制流
+ * -O2 再加上公共子表达式消除和循环不变量外提，最后把尾位置的调用变成尾调用
+*/
+pub fn pipeline(level: u8) -> Vec<&'static str> {
+    match level {
+        0 => vec![],
+        1 => vec!["mem2reg", "sccp", "simplify-cfg", "dce"],
+        _ => vec!["mem2reg", "sccp", "simplify-cfg", "gvn", "licm", "sccp", "simplify-cfg", "dce", "tailcall"],
+    }
+}
+
+/*
+ * 依次运行 pass
+ * 函数按调用图自底向上处理，每个函数先内联调用，再运行 pipeline 中的 pass
+ * 这样内联进来的函数体已经优化过，内联之后还会和调用者一起再优化
+ * print_after 中的 pass 运行之后把 IR 输出到标准错误
+*/
+pub struct PassManager {
+    level: u8,
+    inliner: Inliner,
+    passes: Vec<Box<dyn Pass>>,
+    print_after: Vec<String>,
+}
+
+impl PassManager {
+    pub fn new(level: u8, arch: Arch, print_after: &[String], remarks: &[String], missed_remarks: &[String]) -> PassManager {
+        PassManager {
+            level,
+            inliner: Inliner::new(level, remarks, missed_remarks),
+            passes: pipeline(level).into_iter().map(|name| lookup(name, arch).expect("Unknown pass in pipeline")).collect(),
+            print_after: print_after.to_vec(),
+        }
+    }
+
+    pub fn run(&self, module: &mut Module) {
+        for scc in call_graph_order(module) {
+            for &i in scc.iter() {
+                self.inliner.run(module, i, &scc);
+                self.check(module, i, inline::NAME);
+
+                for pass in self.passes.iter() {
+                    pass.run(&mut module.functions[i]);
+                    self.check(module, i, pass.name());
+                }
+            }
+        }
+
+        // 内联之后不再被调用的 static 函数不用生成
+        if self.level > 0 {
+            remove_unused_static(module);
+        }
+    }
+
+    fn check(&self, module: &Module, i: usize, name: &str) {
+        // pass 的错误是编译器自己的错误
+        if let Err(e) = verify(&module.functions[i], module) {
+            panic!("IR verification failed after {} in function {}: {}", name, module.functions[i].name, e);
+        }
+        if self.print_after.iter().any(|pass| pass == name) {
+            eprintln!("; IR after {}", name);
+            eprint!("{}", module.functions[i]);
+        }
+    }
+}
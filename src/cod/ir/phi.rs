@@ -0,0 +1,48 @@
+use super::*;
+
+/*
+ * 生成代码时消去 phi
+ * 跳到有 phi 的块之前，在这条边上给所有 phi 赋值
+ * 所有 phi 同时赋值，一个 phi 的来源可能是另一个 phi，所以要排好赋值的顺序
+*/
+
+// 块的开头是否有 phi
+pub fn has_phi(function: &Function, block: BlockId) -> bool {
+    matches!(function.blocks[block.0].instrs.first(), Some(Instr::Phi(_, _)))
+}
+
+// 从 from 跳到 to 时 to 中的 phi 的赋值 (phi, 来源)
+pub fn phi_moves(function: &Function, from: BlockId, to: BlockId) -> Vec<(Value, Value)> {
+    function.blocks[to.0].instrs.iter().filter_map(|instr| match instr {
+        Instr::Phi(v, incoming) => incoming.iter().find(|(b, _)| *b == from).map(|(_, a)| (*v, *a)),
+        _ => None,
+    }).collect()
+}
+
+/*
+ * 并行赋值 (目的, 来源, 附带的信息) 的执行顺序
+ * 目的不是其他赋值的来源时可以先赋值
+ * 剩下的都在环中，把一个目的的旧值存到临时位置 scratch 打破环，之后从 scratch 读
+ * 返回依次执行的赋值，目的和来源相同的赋值去掉
+*/
+pub fn sequence<L: Clone + PartialEq, T: Copy>(mut moves: Vec<(L, L, T)>, scratch: L) -> Vec<(L, L, T)> {
+    moves.retain(|(dest, src, _)| dest != src);
+
+    let mut order = Vec::new();
+    while !moves.is_empty() {
+        let ready = moves.iter().position(|(dest, _, _)| !moves.iter().any(|(_, src, _)| src == dest));
+        match ready {
+            Some(i) => order.push(moves.remove(i)),
+            None => {
+                let (dest, _, info) = moves[0].clone();
+                order.push((scratch.clone(), dest.clone(), info));
+                for (_, src, _) in moves.iter_mut() {
+                    if *src == dest {
+                        *src = scratch.clone();
+                    }
+                }
+            }
+        }
+    }
+    order
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use super::*;
+
+/*
+ * IR 的文本格式
+ *
+ * @str0 = "hello\n"
+ *
+ * function i64 @add(i64 %0, i64 %1) {
+ *   slot $0 8 align 8
+ * bb0:
+ *   store i64 $0, %0
+ *   %2 = load i64 $0
+ *   %3 = add i64 %2, %1
+ *   ret i64 %3
+ * }
+*/
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Ty::I8 => "i8",
+            Ty::I64 => "i64",
+            Ty::Ptr => "ptr",
+            Ty::F32 => "f32",
+            Ty::F64 => "f64",
+            Ty::F80 => "f80",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for SlotId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Xor => "xor",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CmpOp::Eq => "eq",
+            CmpOp::Ne => "ne",
+            CmpOp::Lt => "lt",
+            CmpOp::Le => "le",
+            CmpOp::Gt => "gt",
+            CmpOp::Ge => "ge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// 字符串常量按C的写法转义
+pub fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| match b {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'"' => "\\\"".to_string(),
+        b'\\' => "\\\\".to_string(),
+        0x20..=0x7e => (*b as char).to_string(),
+        _ => format!("\\x{:02x}", b),
+    }).collect()
+}
+
+fn join(values: &[Value]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// 一条指令，值的类型从函数中取
+pub fn format_instr(function: &Function, instr: &Instr) -> String {
+    let ty = |v: &Value| function.ty(*v);
+
+    match instr {
+        Instr::Const(v, n) => format!("{} = const {} {}", v, ty(v), n),
+        Instr::FloatConst(v, literal) => format!("{} = fconst {} {}", v, ty(v), literal),
+        Instr::StringAddr(v, index) => format!("{} = addr @str{}", v, index),
+        Instr::SlotAddr(v, slot) => format!("{} = addr {}", v, slot),
+        Instr::Load(v, slot) => format!("{} = load {} {}", v, ty(v), slot),
+        Instr::Store(slot, v) => format!("store {} {}, {}", ty(v), slot, v),
+        Instr::Neg(v, a) => format!("{} = neg {} {}", v, ty(v), a),
+        Instr::Binary(v, op, a, b) => format!("{} = {} {} {}, {}", v, op, ty(v), a, b),
+        Instr::Compare(v, op, a, b) => format!("{} = cmp {} {} {}, {}", v, op, ty(a), a, b),
+        Instr::Convert(v, a) => format!("{} = convert {} {} to {}", v, ty(a), a, ty(v)),
+        Instr::Call(v, name, args, variadic) => {
+            let call = format!("call @{}({}{})", name, join(args), if variadic.is_some() { ", ..." } else { "" });
+            match v {
+                Some(v) => format!("{} = {} {}", v, ty(v), call),
+                None => call,
+            }
+        }
+        Instr::VaStart(ap) => format!("va_start {}", ap),
+        Instr::VaArg(v, ap) => format!("{} = va_arg {} {}", v, ty(v), ap),
+        Instr::MemCopy(dest, src, size) => format!("memcpy {}, {}, {}", dest, src, size),
+        Instr::Phi(v, incoming) => {
+            let incoming: Vec<String> = incoming.iter().map(|(b, a)| format!("[{}, {}]", b, a)).collect();
+            format!("{} = phi {} {}", v, ty(v), incoming.join(" "))
+        }
+    }
+}
+
+pub fn format_terminator(function: &Function, terminator: &Terminator) -> String {
+    match terminator {
+        Terminator::Jump(b) => format!("jmp {}", b),
+        Terminator::Branch(v, t, f) => format!("br {}, {}, {}", v, t, f),
+        Terminator::Return(Some(v)) => format!("ret {} {}", function.ty(*v), v),
+        Terminator::Return(None) => "ret".to_string(),
+        Terminator::TailCall(name, args, variadic) => format!("tail call @{}({}{})", name, join(args), if variadic.is_some() { ", ..." } else { "" }),
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let return_type = match self.return_type {
+            Some(ty) => ty.to_string(),
+            None => "void".to_string(),
+        };
+        let mut params: Vec<String> = self.params.iter().map(|p| format!("{} {}", self.ty(*p), p)).collect();
+        if self.variadic {
+            params.push("...".to_string());
+        }
+        // static inline 写在返回类型前面，属性写在参数后面
+        let mut prefix = String::new();
+        if self.specifiers.is_static {
+            prefix.push_str("static ");
+        }
+        if self.specifiers.inline {
+            prefix.push_str("inline ");
+        }
+        let mut attributes = String::new();
+        if self.specifiers.noinline {
+            attributes.push_str(" noinline");
+        }
+        if self.specifiers.always_inline {
+            attributes.push_str(" always_inline");
+        }
+        writeln!(f, "function {}{} @{}({}){} {{", prefix, return_type, self.name, params.join(", "), attributes)?;
+
+        for (i, slot) in self.slots.iter().enumerate() {
+            writeln!(f, "  slot {} {} align {}", SlotId(i), slot.size, slot.align)?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for instr in block.instrs.iter() {
+                writeln!(f, "  {}", format_instr(self, instr))?;
+            }
+            writeln!(f, "  {}", format_terminator(self, &block.terminator))?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, s) in self.strings.iter().enumerate() {
+            writeln!(f, "@str{} = \"{}\"", i, escape(s))?;
+        }
+        if !self.strings.is_empty() {
+            writeln!(f)?;
+        }
+
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{remove_phi_incoming, remove_unreachable_blocks};
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 稀疏条件常量传播
+ * Wegman, Zadeck: Constant Propagation with Conditional Branches
+ * 只沿可能执行的边传播，条件是常量的分支只走一边
+ * 只计算整数，浮点数都当作不是常量
+*/
+pub struct ConstantPropagation;
+
+/*
+ * 值的格
+ * Unknown 还没有算出来 (可能是任何常量)
+ * Constant 常量
+ * Overdefined 不是常量
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    Unknown,
+    Constant(i64),
+    Overdefined,
+}
+
+fn meet(a: Lattice, b: Lattice) -> Lattice {
+    match (a, b) {
+        (Lattice::Unknown, x) | (x, Lattice::Unknown) => x,
+        (Lattice::Constant(x), Lattice::Constant(y)) if x == y => a,
+        _ => Lattice::Overdefined,
+    }
+}
+
+/*
+ * 整数运算，和生成的机器指令结果相同
+ * 除以0和溢出的除法留到运行时
+*/
+pub fn fold_binary(op: BinOp, a: i64, b: i64) -> Option<i64> {
+    Some(match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Div => a.checked_div(b)?,
+        BinOp::Rem => a.checked_rem(b)?,
+        BinOp::And => a & b,
+        BinOp::Or => a | b,
+        BinOp::Xor => a ^ b,
+        // 移位的位数只取低6位
+        BinOp::Shl => a.wrapping_shl(b as u32 & 63),
+        BinOp::Shr => a.wrapping_shr(b as u32 & 63),
+    })
+}
+
+pub fn fold_compare(op: CmpOp, a: i64, b: i64) -> i64 {
+    let result = match op {
+        CmpOp::Eq => a == b,
+        CmpOp::Ne => a != b,
+        CmpOp::Lt => a < b,
+        CmpOp::Le => a <= b,
+        CmpOp::Gt => a > b,
+        CmpOp::Ge => a >= b,
+    };
+    result as i64
+}
+
+struct Solver<'a> {
+    function: &'a Function,
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+    // 使用每个值的块
+    users: Vec<Vec<BlockId>>,
+    block_work: Vec<BlockId>,
+    value_work: Vec<Value>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(function: &'a Function) -> Solver<'a> {
+        let mut users = vec![Vec::new(); function.values.len()];
+        for b in function.block_ids() {
+            let block = &function.blocks[b.0];
+            for v in block.instrs.iter().flat_map(|instr| instr.uses()).chain(block.terminator.uses()) {
+                if !users[v.0].contains(&b) {
+                    users[v.0].push(b);
+                }
+            }
+        }
+
+        let mut values = vec![Lattice::Unknown; function.values.len()];
+        for p in function.params.iter() {
+            values[p.0] = Lattice::Overdefined;
+        }
+
+        Solver {
+            function,
+            values,
+            executable: vec![false; function.blocks.len()],
+            edges: HashSet::new(),
+            users,
+            block_work: vec![BlockId(0)],
+            value_work: Vec::new(),
+        }
+    }
+
+    fn set(&mut self, v: Value, value: Lattice) {
+        // 只会往下走
+        let new = meet(self.values[v.0], value);
+        if new != self.values[v.0] {
+            self.values[v.0] = new;
+            self.value_work.push(v);
+        }
+    }
+
+    fn mark_edge(&mut self, from: BlockId, to: BlockId) {
+        if !self.edges.insert((from, to)) {
+            return;
+        }
+        // 新的边会改变 phi 的值，块要重新计算
+        self.block_work.push(to);
+    }
+
+    fn evaluate(&self, instr: &Instr) -> Lattice {
+        let get = |v: &Value| self.values[v.0];
+        let function = self.function;
+
+        match instr {
+            Instr::Const(_, n) => Lattice::Constant(*n),
+            Instr::Binary(v, op, a, b) if !function.ty(*v).is_float() => match (get(a), get(b)) {
+                (Lattice::Constant(x), Lattice::Constant(y)) => match fold_binary(*op, x, y) {
+                    Some(n) => Lattice::Constant(n),
+                    None => Lattice::Overdefined,
+                },
+                (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                _ => Lattice::Unknown,
+            },
+            Instr::Compare(_, op, a, b) if !function.ty(*a).is_float() => match (get(a), get(b)) {
+                (Lattice::Constant(x), Lattice::Constant(y)) => Lattice::Constant(fold_compare(*op, x, y)),
+                (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                _ => Lattice::Unknown,
+            },
+            Instr::Neg(v, a) if !function.ty(*v).is_float() => match get(a) {
+                Lattice::Constant(x) => Lattice::Constant(x.wrapping_neg()),
+                l => l,
+            },
+            // 整数之间的转换，转换到 char 时截断
+            Instr::Convert(v, a) if matches!(function.ty(*v), Ty::I8 | Ty::I64) && matches!(function.ty(*a), Ty::I8 | Ty::I64) => match get(a) {
+                Lattice::Constant(x) if function.ty(*v) == Ty::I8 => Lattice::Constant(x as i8 as i64),
+                l => l,
+            },
+            _ => Lattice::Overdefined,
+        }
+    }
+
+    // 计算块中的一条指令
+    fn visit_instr(&mut self, b: BlockId, instr: &Instr) {
+        let Some(v) = instr.def() else { return };
+        let value = match instr {
+            // 只看可能执行的边
+            Instr::Phi(_, incoming) => incoming.iter()
+                .filter(|(p, _)| self.edges.contains(&(*p, b)))
+                .fold(Lattice::Unknown, |acc, (_, a)| meet(acc, self.values[a.0])),
+            _ => self.evaluate(instr),
+        };
+        self.set(v, value);
+    }
+
+    fn visit_terminator(&mut self, b: BlockId) {
+        match self.function.blocks[b.0].terminator {
+            Terminator::Jump(t) => self.mark_edge(b, t),
+            Terminator::Branch(c, t, f) => match self.values[c.0] {
+                Lattice::Constant(0) => self.mark_edge(b, f),
+                Lattice::Constant(_) => self.mark_edge(b, t),
+                Lattice::Overdefined => {
+                    self.mark_edge(b, t);
+                    self.mark_edge(b, f);
+                }
+                Lattice::Unknown => {}
+            },
+            Terminator::Return(_) | Terminator::TailCall(_, _, _) => {}
+        }
+    }
+
+    fn visit_block(&mut self, b: BlockId) {
+        self.executable[b.0] = true;
+        let function = self.function;
+        for instr in function.blocks[b.0].instrs.iter() {
+            self.visit_instr(b, instr);
+        }
+        self.visit_terminator(b);
+    }
+
+    fn solve(&mut self) {
+        loop {
+            if let Some(b) = self.block_work.pop() {
+                self.visit_block(b);
+            } else if let Some(v) = self.value_work.pop() {
+                // 值变了，重新计算用到它的块
+                for b in self.users[v.0].clone() {
+                    if self.executable[b.0] {
+                        self.visit_block(b);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl Pass for ConstantPropagation {
+    fn name(&self) -> &'static str {
+        "sccp"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut solver = Solver::new(function);
+        solver.solve();
+        let values = solver.values;
+        let edges = solver.edges;
+        let executable = solver.executable;
+
+        let mut changed = false;
+
+        // 结果是常量的指令换成常量
+        for block in function.blocks.iter_mut() {
+            for instr in block.instrs.iter_mut() {
+                let Some(v) = instr.def() else { continue };
+                if let (Lattice::Constant(n), true) = (values[v.0], instr.is_pure()) {
+                    if *instr != Instr::Const(v, n) {
+                        *instr = Instr::Const(v, n);
+                        changed = true;
+                    }
+                }
+            }
+            // phi 换成常量之后，剩下的 phi 要回到块的开头
+            let (phis, others): (Vec<Instr>, Vec<Instr>) = block.instrs.drain(..).partition(|instr| matches!(instr, Instr::Phi(_, _)));
+            block.instrs = phis.into_iter().chain(others).collect();
+        }
+
+        // 不会执行的边删掉
+        let mut removed: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        for b in function.block_ids() {
+            if !executable[b.0] {
+                continue;
+            }
+            if let Terminator::Branch(_, t, f) = function.blocks[b.0].terminator {
+                let target = match (edges.contains(&(b, t)), edges.contains(&(b, f))) {
+                    (true, false) => t,
+                    (false, true) => f,
+                    _ => continue,
+                };
+                let other = if target == t { f } else { t };
+                function.blocks[b.0].terminator = Terminator::Jump(target);
+                if other != target {
+                    removed.entry(other).or_default().push(b);
+                }
+                changed = true;
+            }
+        }
+        for (block, preds) in removed {
+            for p in preds {
+                remove_phi_incoming(function, block, p);
+            }
+        }
+
+        let blocks = function.blocks.len();
+        remove_unreachable_blocks(function);
+        changed || blocks != function.blocks.len()
+    }
+}
@@ -0,0 +1,135 @@
+use super::cfg::{predecessors, redirect_phis, remove_phi_incoming, remove_unreachable_blocks};
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 化简控制流图，直到不能再化简
+ * 条件是常量或者两边相同的分支变成跳转
+ * 只有一个前驱、前驱只跳到它的块合并到前驱中
+ * 空的、只有一个跳转的块让前驱直接跳过去
+ * 删除不可到达的块
+*/
+pub struct SimplifyCfg;
+
+impl Pass for SimplifyCfg {
+    fn name(&self) -> &'static str {
+        "simplify-cfg"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let mut changed = false;
+        loop {
+            let blocks = function.blocks.len();
+            remove_unreachable_blocks(function);
+            let removed = blocks != function.blocks.len();
+            let step = removed | fold_branches(function) | merge_blocks(function) | skip_empty_blocks(function);
+            if !step {
+                return changed;
+            }
+            changed = true;
+        }
+    }
+}
+
+// 条件确定的分支变成跳转
+fn fold_branches(function: &mut Function) -> bool {
+    let mut constants = vec![None; function.values.len()];
+    for block in function.blocks.iter() {
+        for instr in block.instrs.iter() {
+            if let Instr::Const(v, n) = instr {
+                constants[v.0] = Some(*n);
+            }
+        }
+    }
+
+    let mut changed = false;
+    for b in function.block_ids() {
+        let Terminator::Branch(c, t, f) = function.blocks[b.0].terminator else { continue };
+        let (target, other) = match constants[c.0] {
+            _ if t == f => (t, None),
+            Some(0) => (f, Some(t)),
+            Some(_) => (t, Some(f)),
+            None => continue,
+        };
+        function.blocks[b.0].terminator = Terminator::Jump(target);
+        if let Some(other) = other {
+            remove_phi_incoming(function, other, b);
+        }
+        changed = true;
+    }
+    changed
+}
+
+/*
+ * a 只跳到 b，b 只有 a 一个前驱时，b 合并到 a 中
+ * b 的 phi 只有一个来源，直接换成那个值
+*/
+fn merge_blocks(function: &mut Function) -> bool {
+    let mut changed = false;
+    for a in function.block_ids() {
+        while let Terminator::Jump(b) = function.blocks[a.0].terminator {
+            let preds = predecessors(function);
+            if b == a || b.0 == 0 || preds[b.0].len() != 1 {
+                break;
+            }
+
+            let block = std::mem::replace(&mut function.blocks[b.0], Block { instrs: Vec::new(), terminator: Terminator::Return(None) });
+            let mut replace = Vec::new();
+            for instr in block.instrs {
+                match instr {
+                    Instr::Phi(v, incoming) => replace.push((v, incoming[0].1)),
+                    instr => function.blocks[a.0].instrs.push(instr),
+                }
+            }
+            for succ in block.terminator.successors() {
+                redirect_phis(function, succ, b, a);
+            }
+            function.blocks[a.0].terminator = block.terminator;
+            // b 没有前驱了，之后会被删除
+            function.blocks[b.0].terminator = Terminator::Jump(b);
+            function.replace_uses(|v| replace.iter().find(|(p, _)| *p == v).map(|(_, r)| *r));
+            changed = true;
+        }
+    }
+    changed
+}
+
+/*
+ * b 是空块，只跳到 c，前驱改为直接跳到 c
+ * c 有 phi 时，前驱不能已经是 c 的前驱 (否则同一个前驱会有两个值)
+*/
+fn skip_empty_blocks(function: &mut Function) -> bool {
+    let mut changed = false;
+    for b in function.block_ids().skip(1) {
+        let Terminator::Jump(c) = function.blocks[b.0].terminator else { continue };
+        if c == b || !function.blocks[b.0].instrs.is_empty() {
+            continue;
+        }
+
+        let preds = predecessors(function);
+        let c_has_phi = matches!(function.blocks[c.0].instrs.first(), Some(Instr::Phi(_, _)));
+        if preds[b.0].is_empty() || (c_has_phi && preds[b.0].iter().any(|p| preds[c.0].contains(p))) {
+            continue;
+        }
+
+        for &p in preds[b.0].iter() {
+            for target in function.blocks[p.0].terminator.successors_mut() {
+                if *target == b {
+                    *target = c;
+                }
+            }
+        }
+
+        // c 的 phi 中来自 b 的值改为来自 b 的每个前驱
+        for instr in function.blocks[c.0].instrs.iter_mut() {
+            if let Instr::Phi(_, incoming) = instr {
+                if let Some(i) = incoming.iter().position(|(p, _)| *p == b) {
+                    let (_, v) = incoming.remove(i);
+                    incoming.extend(preds[b.0].iter().map(|p| (*p, v)));
+                }
+            }
+        }
+        changed = true;
+    }
+    changed
+}
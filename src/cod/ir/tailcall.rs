@@ -0,0 +1,54 @@
+use super::super::options::Arch;
+use super::super::target::target;
+use super::pass::Pass;
+use super::*;
+
+/*
+ * 尾调用和兄弟调用
+ * 块的最后是调用，然后直接返回调用的结果 (或者什么都不返回) 时，变成尾调用
+ * 生成代码时先恢复栈帧再 jmp 到被调用的函数，递归不再占用栈
+ *
+ * 被调用的函数会覆盖调用者的栈帧，所以:
+ * 函数中不能有栈槽的地址 (可能被传给被调用的函数)
+ * 栈参数要写到调用者收到的栈参数的位置，不能比调用者的多 (按目标机器的调用约定)
+*/
+pub struct TailCallElimination {
+    pub arch: Arch,
+}
+
+impl Pass for TailCallElimination {
+    fn name(&self) -> &'static str {
+        "tailcall"
+    }
+
+    fn run(&self, function: &mut Function) -> bool {
+        let escapes = function.blocks.iter().flat_map(|block| block.instrs.iter()).any(|instr| matches!(instr, Instr::SlotAddr(_, _)));
+        if escapes {
+            return false;
+        }
+        let params: Vec<Ty> = function.params.iter().map(|p| function.ty(*p)).collect();
+
+        let mut changed = false;
+        for b in function.block_ids() {
+            let block = &function.blocks[b.0];
+            let Some(Instr::Call(dest, _, args, named)) = block.instrs.last() else { continue };
+            let Terminator::Return(v) = block.terminator else { continue };
+            // void 函数可以丢掉调用的返回值
+            if v.is_some() && v != *dest {
+                continue;
+            }
+            let types: Vec<Ty> = args.iter().map(|a| function.ty(*a)).collect();
+            if !target(self.arch).tail_call_fits(&params, &types, *named) {
+                continue;
+            }
+
+            let block = &mut function.blocks[b.0];
+            let Some(Instr::Call(_, name, args, variadic)) = block.instrs.pop() else {
+                unreachable!("Missing call at the end of {}", b);
+            };
+            block.terminator = Terminator::TailCall(name, args, variadic);
+            changed = true;
+        }
+        changed
+    }
+}
@@ -0,0 +1,202 @@
+use super::cfg::{dominates, immediate_dominators, predecessors};
+use super::print::format_instr;
+use super::*;
+
+/*
+ * 检查 IR 是否合法
+ * 块和栈槽的编号都存在
+ * 每个值只定义一次，使用之前已经定义 (定义所在的块支配使用的位置)
+ * 指令两边的类型一致
+ * phi 只出现在块的开头，来源正好是所有前驱
+ * 不合法说明编译器自己有错误，不是用户的错误
+*/
+pub fn verify_module(module: &Module) -> Result<(), String> {
+    for function in module.functions.iter() {
+        verify(function, module).map_err(|e| format!("in function {}: {}", function.name, e))?;
+    }
+    Ok(())
+}
+
+// 每个值定义的位置 (块, 块中的第几条指令)，参数定义在入口之前
+#[derive(Debug, Clone, Copy)]
+enum Def {
+    Param,
+    At(BlockId, usize),
+}
+
+pub fn verify(function: &Function, module: &Module) -> Result<(), String> {
+    if function.blocks.is_empty() {
+        return Err("function has no blocks".to_string());
+    }
+
+    let defs = collect_definitions(function)?;
+    let preds = predecessors(function);
+    let idom = immediate_dominators(function);
+
+    for b in function.block_ids() {
+        let block = &function.blocks[b.0];
+
+        for succ in block.terminator.successors() {
+            if succ.0 >= function.blocks.len() {
+                return Err(format!("{} jumps to missing block {}", b, succ));
+            }
+        }
+
+        let mut in_phis = true;
+        for (i, instr) in block.instrs.iter().enumerate() {
+            let context = || format!("{}: {}", b, format_instr(function, instr));
+
+            // 使用的值必须在这里之前定义
+            for v in instr.uses() {
+                // phi 的值在对应的前驱中检查
+                if let Instr::Phi(_, _) = instr {
+                    break;
+                }
+                check_defined(function, &defs, &idom, v, b, i).map_err(|e| format!("{} ({})", e, context()))?;
+            }
+
+            match instr {
+                Instr::Phi(_, incoming) => {
+                    if !in_phis {
+                        return Err(format!("phi after other instructions ({})", context()));
+                    }
+                    let mut sources: Vec<BlockId> = incoming.iter().map(|(p, _)| *p).collect();
+                    let mut expected = preds[b.0].clone();
+                    sources.sort();
+                    expected.sort();
+                    if sources != expected {
+                        return Err(format!("phi sources {:?} do not match predecessors {:?} ({})", sources, expected, context()));
+                    }
+                    for (p, v) in incoming.iter() {
+                        // 值要在前驱的末尾可用
+                        let end = function.blocks[p.0].instrs.len();
+                        check_defined(function, &defs, &idom, *v, *p, end).map_err(|e| format!("{} ({})", e, context()))?;
+                    }
+                }
+                _ => in_phis = false,
+            }
+
+            check_types(function, module, instr).map_err(|e| format!("{} ({})", e, context()))?;
+        }
+
+        let end = block.instrs.len();
+        for v in block.terminator.uses() {
+            check_defined(function, &defs, &idom, v, b, end).map_err(|e| format!("{} in terminator of {}", e, b))?;
+        }
+        match &block.terminator {
+            Terminator::Branch(v, _, _) if function.ty(*v).is_float() => {
+                return Err(format!("branch on float value {} in {}", v, b));
+            }
+            Terminator::Return(v) if v.map(|v| function.ty(v)) != function.return_type => {
+                return Err(format!("return type does not match in {}", b));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_definitions(function: &Function) -> Result<Vec<Option<Def>>, String> {
+    let mut defs = vec![None; function.values.len()];
+
+    let mut define = |v: Value, def: Def| {
+        match defs.get(v.0) {
+            None => Err(format!("value {} has no type", v)),
+            Some(Some(_)) => Err(format!("value {} defined twice", v)),
+            Some(None) => {
+                defs[v.0] = Some(def);
+                Ok(())
+            }
+        }
+    };
+
+    for p in function.params.iter() {
+        define(*p, Def::Param)?;
+    }
+    for b in function.block_ids() {
+        for (i, instr) in function.blocks[b.0].instrs.iter().enumerate() {
+            if let Some(v) = instr.def() {
+                define(v, Def::At(b, i))?;
+            }
+        }
+    }
+
+    Ok(defs)
+}
+
+// v 在 b 的第 i 条指令之前是否已经定义
+fn check_defined(function: &Function, defs: &[Option<Def>], idom: &[Option<BlockId>], v: Value, b: BlockId, i: usize) -> Result<(), String> {
+    if v.0 >= function.values.len() {
+        return Err(format!("use of unknown value {}", v));
+    }
+    match defs[v.0] {
+        None => Err(format!("use of undefined value {}", v)),
+        Some(Def::Param) => Ok(()),
+        Some(Def::At(def_block, def_index)) => {
+            // 不可到达的块不检查支配关系
+            let dominated = if def_block == b {
+                def_index < i
+            } else {
+                idom[b.0].is_none() || dominates(idom, def_block, b)
+            };
+            if dominated {
+                Ok(())
+            } else {
+                Err(format!("value {} does not dominate its use", v))
+            }
+        }
+    }
+}
+
+fn check_slot(function: &Function, slot: SlotId) -> Result<(), String> {
+    if slot.0 >= function.slots.len() {
+        return Err(format!("missing slot {}", slot));
+    }
+    Ok(())
+}
+
+fn check_types(function: &Function, module: &Module, instr: &Instr) -> Result<(), String> {
+    let ty = |v: &Value| function.ty(*v);
+    let same = |a: &Value, b: &Value| {
+        if ty(a) == ty(b) {
+            Ok(())
+        } else {
+            Err(format!("type mismatch between {} and {}", a, b))
+        }
+    };
+
+    match instr {
+        Instr::Const(v, _) if ty(v).is_float() => Err("integer constant with float type".to_string()),
+        Instr::FloatConst(v, _) if !ty(v).is_float() => Err("float constant with integer type".to_string()),
+        Instr::StringAddr(_, index) if *index >= module.strings.len() => Err(format!("missing string {}", index)),
+        Instr::StringAddr(v, _) | Instr::SlotAddr(v, _) if ty(v) != Ty::Ptr => Err("address is not a pointer".to_string()),
+        Instr::SlotAddr(_, slot) | Instr::Load(_, slot) | Instr::Store(slot, _) => check_slot(function, *slot),
+        Instr::Neg(v, a) => same(v, a),
+        Instr::Binary(v, op, a, b) => {
+            same(v, a)?;
+            same(a, b)?;
+            let integer_only = !matches!(op, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div);
+            if integer_only && ty(v).is_float() {
+                return Err(format!("{} on float values", op));
+            }
+            Ok(())
+        }
+        Instr::Compare(v, _, a, b) => {
+            same(a, b)?;
+            if ty(v) != Ty::I64 {
+                return Err("comparison result is not i64".to_string());
+            }
+            Ok(())
+        }
+        Instr::VaStart(ap) | Instr::VaArg(_, ap) if ty(ap) != Ty::Ptr => Err("va_list is not a pointer".to_string()),
+        Instr::MemCopy(dest, src, _) if ty(dest) != Ty::Ptr || ty(src) != Ty::Ptr => Err("memcpy on non-pointers".to_string()),
+        Instr::Phi(v, incoming) => {
+            for (_, a) in incoming.iter() {
+                same(v, a)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use super::elf::{Object, Target, PAGE, R_X86_64_PC32, R_X86_64_PLT32};
+use super::link::{align_up, classify, BSS, DATA, RODATA, TEXT};
+
+/*
+ * 即时编译 (Linux x86-64)
+ * 编码器输出的目标文件直接装入 mmap 的内存，不需要汇编器、链接器和临时文件
+ * 节的布局和重定位和 link.rs 一样，未定义的符号只能是白名单中的宿主函数 (libc)
+*/
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, length: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, length: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, length: usize) -> i32;
+
+    fn putchar(c: i32) -> i32;
+    fn puts(s: *const u8) -> i32;
+    fn printf(format: *const u8, ...) -> i32;
+    fn vprintf(format: *const u8, ap: *mut c_void) -> i32;
+    fn strlen(s: *const u8) -> usize;
+    fn abs(n: i32) -> i32;
+    fn labs(n: i64) -> i64;
+    fn exit(status: i32) -> !;
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(p: *mut c_void);
+    fn memcpy(dest: *mut c_void, src: *const c_void, n: usize) -> *mut c_void;
+    fn memset(dest: *mut c_void, c: i32, n: usize) -> *mut c_void;
+    fn sqrt(x: f64) -> f64;
+    fn fabs(x: f64) -> f64;
+    fn floor(x: f64) -> f64;
+    fn ceil(x: f64) -> f64;
+    fn exp(x: f64) -> f64;
+    fn log(x: f64) -> f64;
+    fn sin(x: f64) -> f64;
+    fn cos(x: f64) -> f64;
+    fn pow(x: f64, y: f64) -> f64;
+}
+
+// 可以调用的宿主函数的地址
+fn host(name: &str) -> Option<usize> {
+    let address = match name {
+        "putchar" => putchar as *const (),
+        "puts" => puts as *const (),
+        "printf" => printf as *const (),
+        "vprintf" => vprintf as *const (),
+        "strlen" => strlen as *const (),
+        "abs" => abs as *const (),
+        "labs" => labs as *const (),
+        "exit" => exit as *const (),
+        "malloc" => malloc as *const (),
+        "free" => free as *const (),
+        "memcpy" => memcpy as *const (),
+        "memset" => memset as *const (),
+        "sqrt" => sqrt as *const (),
+        "fabs" => fabs as *const (),
+        "floor" => floor as *const (),
+        "ceil" => ceil as *const (),
+        "exp" => exp as *const (),
+        "log" => log as *const (),
+        "sin" => sin as *const (),
+        "cos" => cos as *const (),
+        "pow" => pow as *const (),
+        _ => return None,
+    };
+    Some(address as usize)
+}
+
+// 跳板: jmp [rip + 0] 后面是 8 字节的地址，宿主函数可能离得太远，rel32 放不下
+const STUB_SIZE: u64 = 16;
+
+/*
+ * 装入内存的代码
+ * mmap 的起始地址和长度，定义的符号的地址
+ * 释放时 munmap，之后不能再调用取出的函数
+*/
+pub struct Jit {
+    region: *mut u8,
+    length: usize,
+    symbols: HashMap<String, usize>,
+}
+
+impl Jit {
+    /*
+     * 按名字取出函数，F 是 extern "C" fn 类型
+     * 调用者要保证 F 和 C 函数的类型一致，并且调用时 Jit 还没有释放
+    */
+    pub unsafe fn function<F: Copy>(&self, name: &str) -> Option<F> {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<usize>(), "Function type must be a pointer");
+        self.symbols.get(name).map(|address| mem::transmute_copy(address))
+    }
+
+    // 改变 [start, end) 所在页的权限
+    fn protect(&self, start: u64, end: u64, prot: i32) -> Result<(), String> {
+        if start == end {
+            return Ok(());
+        }
+        let length = align_up(end, PAGE) - start;
+        if unsafe { mprotect(self.region.add(start as usize) as *mut c_void, length as usize, prot) } != 0 {
+            return Err(format!("mprotect failed: {}", io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.region as *mut c_void, self.length);
+        }
+    }
+}
+
+/*
+ * 把目标文件装入内存
+ * 1. 布局: 输入节像 link.rs 一样按类别合并，每类从新的一页开始，.bss 接在 .data 之后
+ *    .text 之后每个用到的宿主函数一个跳板
+ * 2. mmap 可读写的内存，复制节的内容，写入跳板
+ * 3. 重定位: 定义的符号用装入的地址，未定义的符号用跳板的地址
+ * 4. .text 改成可读可执行，.rodata 改成只读
+*/
+pub fn load(object: &Object) -> Result<Jit, String> {
+    let mut sizes = [0u64; 4];
+    let placement: Vec<Option<(usize, u64)>> = object.sections.iter().map(|section| {
+        classify(section.kind, section.flags).map(|area| {
+            let offset = align_up(sizes[area], section.align);
+            let size = if area == BSS { section.size } else { section.data.len() as u64 };
+            sizes[area] = offset + size;
+            (area, offset)
+        })
+    }).collect();
+
+    let mut stubs: HashMap<&str, u64> = HashMap::new();
+    let mut hosts: Vec<(u64, usize)> = Vec::new();
+    let stub_start = align_up(sizes[TEXT], STUB_SIZE);
+    for symbol in object.symbols.iter().filter(|s| s.section.is_none()) {
+        let address = host(&symbol.name).ok_or_else(|| format!("Undefined reference to {}", symbol.name))?;
+        let offset = stub_start + STUB_SIZE * hosts.len() as u64;
+        stubs.insert(&symbol.name, offset);
+        hosts.push((offset, address));
+    }
+    sizes[TEXT] = stub_start + STUB_SIZE * hosts.len() as u64;
+
+    let mut addresses = [0u64; 4];
+    addresses[RODATA] = align_up(sizes[TEXT], PAGE);
+    addresses[DATA] = align_up(addresses[RODATA] + sizes[RODATA], PAGE);
+    addresses[BSS] = align_up(addresses[DATA] + sizes[DATA], 16);
+    let length = align_up(addresses[BSS] + sizes[BSS], PAGE).max(PAGE) as usize;
+
+    let region = unsafe { mmap(ptr::null_mut(), length, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) };
+    if region as isize == -1 {
+        return Err(format!("mmap failed: {}", io::Error::last_os_error()));
+    }
+    let mut jit = Jit { region: region as *mut u8, length, symbols: HashMap::new() };
+    let base = jit.region as u64;
+    // 匿名映射的内容都是 0，.bss 不用再清零
+    let memory = unsafe { slice::from_raw_parts_mut(jit.region, length) };
+
+    for (section, place) in object.sections.iter().zip(placement.iter()) {
+        if let Some((area, offset)) = *place {
+            if area != BSS {
+                let at = (addresses[area] + offset) as usize;
+                memory[at..at + section.data.len()].copy_from_slice(&section.data);
+            }
+        }
+    }
+    for (offset, address) in hosts.iter() {
+        let at = *offset as usize;
+        memory[at..at + 6].copy_from_slice(&[0xff, 0x25, 0, 0, 0, 0]);
+        memory[at + 6..at + 14].copy_from_slice(&(*address as u64).to_le_bytes());
+    }
+
+    let section_address = |section: usize| -> Option<u64> {
+        placement[section].map(|(area, offset)| base + addresses[area] + offset)
+    };
+    for symbol in object.symbols.iter() {
+        if let Some(section) = symbol.section {
+            let address = section_address(section).ok_or_else(|| format!("Symbol {} is not in a loadable section", symbol.name))?;
+            jit.symbols.insert(symbol.name.clone(), (address + symbol.value) as usize);
+        }
+    }
+
+    for r in object.relocations.iter() {
+        let Some(section) = section_address(r.section) else { continue };
+        let place = section + r.offset;
+        let s = match r.target {
+            Target::Section(section) => section_address(section).ok_or("Relocation against a section that is not loaded")?,
+            Target::Symbol(s) => {
+                let symbol = &object.symbols[s];
+                match symbol.section {
+                    Some(_) => jit.symbols[&symbol.name] as u64,
+                    None => base + stubs[&symbol.name[..]],
+                }
+            }
+        };
+        let relative = (s as i64).wrapping_add(r.addend).wrapping_sub(place as i64);
+        match r.kind {
+            R_X86_64_PC32 | R_X86_64_PLT32 if relative == relative as i32 as i64 => {}
+            R_X86_64_PC32 | R_X86_64_PLT32 => return Err("Relocation out of range".to_string()),
+            kind => return Err(format!("Unsupported relocation type {}", kind)),
+        }
+        let at = (place - base) as usize;
+        memory[at..at + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+    }
+
+    jit.protect(0, sizes[TEXT], PROT_READ | PROT_EXEC)?;
+    jit.protect(addresses[RODATA], addresses[RODATA] + sizes[RODATA], PROT_READ)?;
+    Ok(jit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::driver::analyze;
+    use super::super::elf::read_relocatable;
+    use super::super::options::{Arch, Options};
+    use super::super::target::target;
+    use super::load;
+
+    #[test]
+    fn calls_compiled_and_host_functions() {
+        let source = "int putchar(int c);
+            int twice(int x) { return x * 2; }
+            int answer() { putchar('j'); putchar('\\n'); return twice(21); }";
+        let options = Options { input: "test.c".to_string(), opt_level: 2, ..Options::default() };
+        let bytes = target(Arch::X86_64).object(&analyze(source, &options), &options).unwrap();
+        let jit = load(&read_relocatable(&bytes).unwrap()).unwrap();
+
+        let answer = unsafe { jit.function::<extern "C" fn() -> i64>("answer") }.unwrap();
+        assert_eq!(answer(), 42);
+        assert!(unsafe { jit.function::<extern "C" fn() -> i64>("missing") }.is_none());
+    }
+}
@@ -1,5 +1,20 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
 use super::token::*;
+use super::types::Type;
+
 
+// 块注释，到 */ 为止
+fn skip_block_comment(input: &mut Peekable<Chars>) {
+    while let Some(c) = input.next() {
+        if c == '*' && input.peek() == Some(&'/') {
+            input.next();
+            return;
+        }
+    }
+    panic!("Unterminated comment");
+}
 
 /*
 识别模块
@@ -20,7 +35,9 @@ pub fn lex(input: &str) -> Vec<Token> {
             ',' => tokens.push(Token::Punctuator(Punctuator::Comma)),
             ':' => tokens.push(Token::Punctuator(Punctuator::Colon)),
             ';' => tokens.push(Token::Punctuator(Punctuator::Semicolon)),
+            '?' => tokens.push(Token::Punctuator(Punctuator::QuestionMark)),
             '^' => tokens.push(Token::Operator(Operator::BitwiseXor)),
+            '~' => tokens.push(Token::Operator(Operator::BitwiseComplement)),
             // 不处理
             // ' ' | '\t' | '\n' | '\r' => {}
 
@@ -54,6 +71,12 @@ pub fn lex(input: &str) -> Vec<Token> {
                 if let Some(&'=') = input.peek() {
                     input.next();
                     tokens.push(Token::Operator(Operator::AssignDiv));
+                } else if let Some(&'/') = input.peek() {
+                    // 行注释，到行尾
+                    input.by_ref().find(|&c| c == '\n');
+                } else if let Some(&'*') = input.peek() {
+                    input.next();
+                    skip_block_comment(&mut input);
                 } else {
                     tokens.push(Token::Operator(Operator::Division));
                 }
@@ -67,7 +90,7 @@ pub fn lex(input: &str) -> Vec<Token> {
                 }
             }
             '!' => {
-                if let Some(&'!') = input.peek() {
+                if let Some(&'=') = input.peek() {
                     input.next();
                     tokens.push(Token::Operator(Operator::NotEqual));
                 } else {
@@ -109,6 +132,55 @@ pub fn lex(input: &str) -> Vec<Token> {
                     tokens.push(Token::Operator(Operator::LessThan));
                 }
             }
+            // .5 这样的浮点数
+            '.' => match input.peek() {
+                Some(c) if c.is_ascii_digit() => tokens.push(lex_number('.', &mut input)),
+                // ...
+                Some(&'.') => {
+                    input.next();
+                    match input.next() {
+                        Some('.') => tokens.push(Token::Punctuator(Punctuator::Ellipsis)),
+                        _ => panic!("Unexpected character .."),
+                    }
+                }
+                _ => panic!("Unexpected character ."),
+            },
+            // 预处理指令没有预处理器，整行跳过 #include <stdarg.h>
+            '#' => {
+                for c in input.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            // 字符串 "hello\n"
+            '"' => {
+                let mut s = Vec::new();
+                loop {
+                    match input.next() {
+                        Some('"') => break,
+                        Some('\\') => s.push(lex_escape(&mut input)),
+                        Some(c) => {
+                            let mut buf = [0; 4];
+                            s.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        }
+                        None => panic!("Unterminated string literal"),
+                    }
+                }
+                tokens.push(Token::StringLiteral(s));
+            }
+            // 字符常量 'a'
+            '\'' => {
+                let c = match input.next() {
+                    Some('\\') => lex_escape(&mut input),
+                    Some(c) => c as u8,
+                    None => panic!("Unterminated character constant"),
+                };
+                match input.next() {
+                    Some('\'') => tokens.push(Token::Constant(c as i8 as i32)),
+                    _ => panic!("Unterminated character constant"),
+                }
+            }
             '>' => {
                 if let Some(&'=') = input.peek() {
                     input.next();
@@ -122,7 +194,7 @@ pub fn lex(input: &str) -> Vec<Token> {
             }
             // keyword identifier constant stringliteral
             _ => {
-                if c.is_alphabetic() {
+                if c.is_alphabetic() || c == '_' {
                     let mut s = c.to_string();
 
                     loop {
@@ -143,25 +215,242 @@ pub fn lex(input: &str) -> Vec<Token> {
                         "do" => tokens.push(Token::Keyword(Keyword::Do)),
                         "if" => tokens.push(Token::Keyword(Keyword::If)),
                         "while" => tokens.push(Token::Keyword(Keyword::While)),
+                        "float" => tokens.push(Token::Keyword(Keyword::Float)),
+                        "double" => tokens.push(Token::Keyword(Keyword::Double)),
+                        "long" => tokens.push(Token::Keyword(Keyword::Long)),
+                        "char" => tokens.push(Token::Keyword(Keyword::Char)),
+                        "void" => tokens.push(Token::Keyword(Keyword::Void)),
+                        "const" => tokens.push(Token::Keyword(Keyword::Const)),
+                        "static" => tokens.push(Token::Keyword(Keyword::Static)),
+                        "inline" | "__inline" | "__inline__" => tokens.push(Token::Keyword(Keyword::Inline)),
+                        "__attribute__" | "__attribute" => tokens.push(Token::Keyword(Keyword::Attribute)),
+                        // <stdarg.h> 中的类型直接内置
+                        "va_list" | "__builtin_va_list" => tokens.push(Token::Keyword(Keyword::VaList)),
                         _ => tokens.push(Token::Identifier(s)),
                     }
-                } else if c.is_digit(10) {
-                    let mut n = c.to_string();
+                } else if c.is_ascii_digit() {
+                    tokens.push(lex_number(c, &mut input));
+                } else if !c.is_whitespace() {
+                    panic!("Unexpected character {}", c);
+                }
+            }
+        }
+    }
 
-                    loop {
-                        match input.peek() {
-                            Some(c) if c.is_digit(10) => n.push(*c),
-                            _ => break,
-                        }
+    tokens
+}
+
+/*
+ * 转义字符 \n \t \0 \x41 ...
+*/
+fn lex_escape(input: &mut Peekable<Chars>) -> u8 {
+    match input.next() {
+        Some('n') => b'\n',
+        Some('t') => b'\t',
+        Some('r') => b'\r',
+        Some('a') => 7,
+        Some('b') => 8,
+        Some('f') => 12,
+        Some('v') => 11,
+        Some('e') => 27,
+        Some('x') => {
+            let mut n = 0u32;
+            while let Some(c) = input.peek().and_then(|c| c.to_digit(16)) {
+                n = n * 16 + c;
+                input.next();
+            }
+            n as u8
+        }
+        // 八进制 \0 \012
+        Some(c) if c.is_digit(8) => {
+            let mut n = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match input.peek().and_then(|c| c.to_digit(8)) {
+                    Some(d) => {
+                        n = n * 8 + d;
                         input.next();
                     }
+                    None => break,
+                }
+            }
+            n as u8
+        }
+        Some(c) => c as u8,
+        None => panic!("Unexpected end of input in escape sequence"),
+    }
+}
+
+/*
+ * 数字常量
+ * 整数: 123 0x1f，可以有 u l ll 后缀 (都按 int 处理)
+ * 浮点: 1.5 .5 1e10 1.5f 1.5L 0x1.8p3
+ * 超出 int 范围的整数是错误，不会截断
+*/
+fn lex_number(first: char, input: &mut Peekable<Chars>) -> Token {
+    let mut n = first.to_string();
+    let mut is_float = first == '.';
+    let mut is_hex = false;
+    let mut in_exponent = false;
+    // 十六进制前缀之后、指数之后是否有数字 (以 . 开头时后面一定是数字)
+    let mut has_digits = true;
+
+    if first == '0' {
+        if let Some(&x) = input.peek() {
+            if x == 'x' || x == 'X' {
+                n.push(x);
+                input.next();
+                is_hex = true;
+                has_digits = false;
+            }
+        }
+    }
 
-                    let n = n.parse::<i32>().unwrap();
-                    tokens.push(Token::Constant(n));
+    loop {
+        match input.peek() {
+            Some(&c) if c.is_ascii_digit() || (is_hex && !in_exponent && c.is_ascii_hexdigit()) => {
+                n.push(c);
+                has_digits = true;
+            }
+            // 第二个小数点，或者指数中的小数点
+            Some(&'.') if is_float => panic!("Too many decimal points in number {}.", n),
+            Some(&'.') => {
+                is_float = true;
+                n.push('.');
+            }
+            // 指数部分，十进制为 e 十六进制为 p
+            Some(&c) if !in_exponent && ((!is_hex && (c == 'e' || c == 'E')) || (is_hex && (c == 'p' || c == 'P'))) => {
+                if !has_digits {
+                    panic!("Invalid number {}{}", n, c);
                 }
+                is_float = true;
+                in_exponent = true;
+                has_digits = false;
+                n.push(c);
+                input.next();
+                if let Some(&sign) = input.peek() {
+                    if sign == '+' || sign == '-' {
+                        n.push(sign);
+                        input.next();
+                    }
+                }
+                continue;
             }
+            _ => break,
         }
+        input.next();
     }
 
-    tokens
-}
\ No newline at end of file
+    if !has_digits {
+        match in_exponent {
+            true => panic!("Exponent has no digits in {}", n),
+            false => panic!("Invalid number {}", n),
+        }
+    }
+    if is_hex && is_float && !in_exponent {
+        panic!("Hexadecimal floating constant {} requires an exponent", n);
+    }
+
+    // 后缀
+    let mut suffix = String::new();
+    while let Some(&c) = input.peek() {
+        if !c.is_alphanumeric() && c != '_' {
+            break;
+        }
+        suffix.push(c);
+        input.next();
+    }
+
+    if is_float {
+        let ty = match &suffix.to_ascii_lowercase()[..] {
+            "" => Type::Double,
+            "f" => Type::Float,
+            "l" => Type::LongDouble,
+            _ => panic!("Invalid suffix {} on floating constant", suffix),
+        };
+        return Token::FloatConstant(n, ty);
+    }
+
+    if !matches!(&suffix[..], "" | "u" | "U" | "l" | "L" | "ll" | "LL" | "ul" | "uL" | "Ul" | "UL" | "lu" | "lU" | "Lu" | "LU"
+        | "ull" | "uLL" | "Ull" | "ULL" | "llu" | "llU" | "LLu" | "LLU") {
+        panic!("Invalid suffix {} on integer constant", suffix);
+    }
+    let value = if is_hex {
+        i32::from_str_radix(&n[2..], 16).ok()
+    } else {
+        n.parse::<i32>().ok()
+    };
+    match value {
+        Some(value) => Token::Constant(value),
+        None => panic!("Integer constant {} is too large", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::token::{Operator, Token};
+    use super::super::types::Type;
+    use super::lex;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(lex("2147483647"), vec![Token::Constant(i32::MAX)]);
+        assert_eq!(lex("0x7fffffff"), vec![Token::Constant(i32::MAX)]);
+        assert_eq!(lex("10UL 3ll"), vec![Token::Constant(10), Token::Constant(3)]);
+        assert_eq!(lex("0x1.8p3"), vec![Token::FloatConstant("0x1.8p3".to_string(), Type::Double)]);
+        assert_eq!(lex("1.5e-3f"), vec![Token::FloatConstant("1.5e-3".to_string(), Type::Float)]);
+        assert_eq!(lex(".5L"), vec![Token::FloatConstant(".5".to_string(), Type::LongDouble)]);
+    }
+
+    #[test]
+    fn complement() {
+        assert_eq!(lex("~x"), vec![Token::Operator(Operator::BitwiseComplement), Token::Identifier("x".to_string())]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected character @")]
+    fn unknown_character() {
+        lex("a @ b");
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer constant 5000000000 is too large")]
+    fn decimal_overflow() {
+        lex("5000000000");
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer constant 0xffffffffff is too large")]
+    fn hex_overflow() {
+        lex("0xffffffffff");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid number 0x")]
+    fn hex_without_digits() {
+        lex("0x");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid suffix abc on integer constant")]
+    fn invalid_integer_suffix() {
+        lex("12abc");
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many decimal points")]
+    fn second_decimal_point() {
+        lex("1.5.3");
+    }
+
+    #[test]
+    #[should_panic(expected = "Exponent has no digits")]
+    fn empty_exponent() {
+        lex("1e");
+    }
+
+    #[test]
+    #[should_panic(expected = "requires an exponent")]
+    fn hex_float_without_exponent() {
+        lex("0x1.8");
+    }
+}
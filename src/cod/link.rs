@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use super::elf::{write_executable, Object, Segment, Target, BASE, PAGE, PF_R, PF_W, PF_X};
+use super::elf::{R_X86_64_PC32, R_X86_64_PLT32, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS};
+
+const R_X86_64_64: u32 = 1;
+const R_X86_64_32: u32 = 10;
+const R_X86_64_32S: u32 = 11;
+
+/*
+ * 输出的节，同类的输入节按顺序合并
+ * .text 可执行，.rodata 只读，.data .bss 可写 (.bss 不占文件空间)
+*/
+pub(crate) const TEXT: usize = 0;
+pub(crate) const RODATA: usize = 1;
+pub(crate) const DATA: usize = 2;
+pub(crate) const BSS: usize = 3;
+
+#[derive(Debug, Clone, Default)]
+struct Output {
+    data: Vec<u8>,
+    size: u64,
+    address: u64,
+}
+
+pub(crate) fn align_up(n: u64, align: u64) -> u64 {
+    n.div_ceil(align.max(1)) * align.max(1)
+}
+
+// 输入节合并到哪个输出节，不需要装入内存的节 (符号表 重定位等) 忽略
+pub(crate) fn classify(kind: u32, flags: u64) -> Option<usize> {
+    if flags & SHF_ALLOC == 0 {
+        None
+    } else if kind == SHT_NOBITS {
+        Some(BSS)
+    } else if flags & SHF_EXECINSTR != 0 {
+        Some(TEXT)
+    } else if flags & SHF_WRITE != 0 {
+        Some(DATA)
+    } else {
+        Some(RODATA)
+    }
+}
+
+/*
+ * 像静态库一样选择库中的目标文件
+ * 库中的目标文件定义了还没有定义的符号时才链接进来，直到不再有新的目标文件
+*/
+fn select<'a>(objects: &'a [Object], library: &'a [Object]) -> Vec<&'a Object> {
+    let mut selected: Vec<&Object> = objects.iter().collect();
+    let mut used = vec![false; library.len()];
+    loop {
+        let defined: HashSet<&str> = selected.iter()
+            .flat_map(|o| o.symbols.iter())
+            .filter(|s| s.global && s.section.is_some())
+            .map(|s| &s.name[..])
+            .collect();
+        let undefined: HashSet<&str> = selected.iter()
+            .flat_map(|o| o.symbols.iter())
+            .filter(|s| s.section.is_none() && !defined.contains(&s.name[..]))
+            .map(|s| &s.name[..])
+            .collect();
+        let member = (0..library.len()).find(|&i| {
+            !used[i] && library[i].symbols.iter().any(|s| s.global && s.section.is_some() && undefined.contains(&s.name[..]))
+        });
+        match member {
+            Some(i) => {
+                used[i] = true;
+                selected.push(&library[i]);
+            }
+            None => return selected,
+        }
+    }
+}
+
+/*
+ * 静态链接
+ * 0. 从库中选出需要的目标文件
+ * 1. 合并节: 每个输入节按对齐放到输出节的末尾，记下它的位置
+ * 2. 分配地址: .text .rodata .data(.bss) 各占从新的一页开始的段
+ * 3. 解析符号: 全局符号不能重复定义，引用的符号必须有定义
+ * 4. 重定位: 在输出节中写入符号的地址
+ * 入口是 _start
+*/
+pub fn link(objects: &[Object], library: &[Object]) -> Result<Vec<u8>, String> {
+    let objects = select(objects, library);
+    let mut outputs: Vec<Output> = vec![Output::default(); 4];
+    // 每个输入节在输出节中的 (输出节, 偏移)
+    let mut placement: Vec<Vec<Option<(usize, u64)>>> = Vec::new();
+
+    for object in objects.iter() {
+        let mut places = Vec::new();
+        for section in object.sections.iter() {
+            let place = classify(section.kind, section.flags).map(|o| {
+                let output = &mut outputs[o];
+                let offset = align_up(output.size, section.align);
+                if o == BSS {
+                    output.size = offset + section.size;
+                } else {
+                    output.data.resize(offset as usize, 0);
+                    output.data.extend_from_slice(&section.data);
+                    output.size = output.data.len() as u64;
+                }
+                (o, offset)
+            });
+            places.push(place);
+        }
+        placement.push(places);
+    }
+
+    // .bss 接在 .data 之后，同一个段
+    let mut address = BASE + PAGE;
+    for o in [TEXT, RODATA, DATA] {
+        outputs[o].address = address;
+        address = align_up(address + outputs[o].size, PAGE);
+    }
+    outputs[BSS].address = align_up(outputs[DATA].address + outputs[DATA].size, 16);
+
+    let addresses: Vec<u64> = outputs.iter().map(|o| o.address).collect();
+    let section_address = |object: usize, section: usize| -> Option<u64> {
+        placement[object][section].map(|(o, offset)| addresses[o] + offset)
+    };
+
+    let mut globals: HashMap<&str, u64> = HashMap::new();
+    for (i, object) in objects.iter().enumerate() {
+        for symbol in object.symbols.iter().filter(|s| s.global) {
+            let Some(section) = symbol.section else { continue };
+            let address = section_address(i, section).ok_or_else(|| format!("Symbol {} is not in a loadable section", symbol.name))?;
+            if globals.insert(&symbol.name, address + symbol.value).is_some() {
+                return Err(format!("Duplicate symbol {}", symbol.name));
+            }
+        }
+    }
+
+    for (i, object) in objects.iter().enumerate() {
+        for r in object.relocations.iter() {
+            let Some(section) = section_address(i, r.section) else { continue };
+            let place = section + r.offset;
+            let s = match r.target {
+                Target::Section(section) => section_address(i, section).ok_or("Relocation against a section that is not loaded")?,
+                Target::Symbol(s) => {
+                    let symbol = &object.symbols[s];
+                    match symbol.section {
+                        Some(section) if !symbol.global => section_address(i, section).ok_or("Relocation against a symbol that is not loaded")? + symbol.value,
+                        _ => *globals.get(&symbol.name[..]).ok_or_else(|| format!("Undefined reference to {}", symbol.name))?,
+                    }
+                }
+            };
+            let value = (s as i64).wrapping_add(r.addend);
+            let (bytes, value): (usize, i64) = match r.kind {
+                R_X86_64_PC32 | R_X86_64_PLT32 => {
+                    let relative = value - place as i64;
+                    if relative != relative as i32 as i64 {
+                        return Err("Relocation out of range".to_string());
+                    }
+                    (4, relative)
+                }
+                R_X86_64_64 => (8, value),
+                // 32 零扩展到 64 位，32S 符号扩展
+                R_X86_64_32 if value != value as u32 as i64 => return Err("Relocation out of range".to_string()),
+                R_X86_64_32S if value != value as i32 as i64 => return Err("Relocation out of range".to_string()),
+                R_X86_64_32 | R_X86_64_32S => (4, value),
+                kind => return Err(format!("Unsupported relocation type {}", kind)),
+            };
+            let (o, offset) = placement[i][r.section].unwrap_or_else(|| unreachable!());
+            let at = (offset + r.offset) as usize;
+            outputs[o].data[at..at + bytes].copy_from_slice(&value.to_le_bytes()[..bytes]);
+        }
+    }
+
+    let entry = *globals.get("_start").ok_or("Undefined reference to _start")?;
+    let data = std::mem::take(&mut outputs[DATA].data);
+    let data_end = outputs[BSS].address + outputs[BSS].size;
+    let segments = vec![
+        Segment { address: outputs[TEXT].address, flags: PF_R | PF_X, memory_size: outputs[TEXT].size, data: std::mem::take(&mut outputs[TEXT].data) },
+        Segment { address: outputs[RODATA].address, flags: PF_R, memory_size: outputs[RODATA].size, data: std::mem::take(&mut outputs[RODATA].data) },
+        Segment { address: outputs[DATA].address, flags: PF_R | PF_W, memory_size: data_end - outputs[DATA].address, data },
+    ];
+    let segments: Vec<Segment> = segments.into_iter().filter(|s| s.memory_size > 0).collect();
+    Ok(write_executable(&segments, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    use super::super::driver::analyze;
+    use super::super::elf::{read_relocatable, Relocation, Section, SHT_PROGBITS};
+    use super::super::options::{Arch, Options};
+    use super::super::runtime::{libc, startup};
+    use super::super::target::target;
+    use super::*;
+
+    // 只有 4 字节的 .rodata，在开头写入 .rodata + addend
+    fn absolute(kind: u32, addend: i64) -> Object {
+        let mut section = Section::new(".rodata", SHT_PROGBITS, SHF_ALLOC, 4);
+        section.data = vec![0; 4];
+        section.size = 4;
+        let relocation = Relocation { section: 0, offset: 0, target: Target::Section(0), kind, addend };
+        Object { sections: vec![section], symbols: Vec::new(), relocations: vec![relocation] }
+    }
+
+    // 编译成可重定位文件再读回来
+    fn compile(source: &str) -> Object {
+        let options = Options { input: "test.c".to_string(), ..Options::default() };
+        let bytes = target(Arch::X86_64).object(&analyze(source, &options), &options).unwrap();
+        read_relocatable(&bytes).unwrap()
+    }
+
+    #[test]
+    fn absolute_32_bit_relocations_must_fit() {
+        let link_with = |kind, addend| link(&[startup(), compile("int main() { return 0; }"), absolute(kind, addend)], &[libc()]).err();
+        assert_eq!(link_with(R_X86_64_32, 0x8000_0000), None);
+        assert_eq!(link_with(R_X86_64_32, 0x1_0000_0000).as_deref(), Some("Relocation out of range"));
+        assert_eq!(link_with(R_X86_64_32S, 0x8000_0000).as_deref(), Some("Relocation out of range"));
+        assert_eq!(link_with(R_X86_64_32S, -0x1000), None);
+    }
+
+    #[test]
+    fn links_and_runs_program() {
+        let source = "int printf(char *format, ...);
+            int putchar(int c);
+            int twice(int x) { return x * 2; }
+            int main() { printf(\"%d\\n\", twice(21)); putchar('!'); return 7; }";
+        let executable = link(&[startup(), compile(source)], &[libc()]).unwrap();
+
+        let path = std::env::temp_dir().join(format!("my_rcc_link_test_{}", std::process::id()));
+        fs::write(&path, executable).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let output = Command::new(&path).output();
+        let _ = fs::remove_file(&path);
+        let output = output.unwrap();
+        assert_eq!(output.status.code(), Some(7));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n!");
+    }
+}
@@ -0,0 +1,866 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::float::{float_bits, quad_bits};
+use super::ir::Ty;
+use super::options::Arch;
+use super::token::Operator;
+use super::typed_ast::{Expr, ExprKind, Function, Program, Stmt};
+use super::types::{Signature, Type};
+
+/*
+ * LLVM IR 文本 (--emit=llvm)
+ * 从带类型的语法树生成，可以交给 opt llc lli 检查语义
+ * 每个局部变量 (包括参数) 在入口块中 alloca，用 load store 访问，留给 mem2reg 变成 SSA
+ * 条件是 icmp fcmp 得到的 i1 加上 br，&& || ?: 用分支和 phi
+ * 语言中没有解引用，所有的指针都是 i8* (LLVM 14 的类型化指针语法)
+ * 整数的运算按补码回绕，不加 nsw
+*/
+
+/*
+ * 目标相关的部分
+ * 目标三元组
+ * long double 的类型
+ * va_list 的类型
+*/
+struct Layout {
+    triple: &'static str,
+    datalayout: &'static str,
+    long_double: &'static str,
+    va_list: &'static str,
+    arch: Arch,
+}
+
+fn layout(arch: Arch) -> Layout {
+    let (triple, datalayout, long_double, va_list) = match arch {
+        Arch::X86_64 => ("x86_64-pc-linux-gnu", "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128",
+            "x86_fp80", "[1 x { i32, i32, i8*, i8* }]"),
+        Arch::Aarch64 => ("aarch64-unknown-linux-gnu", "e-m:e-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128",
+            "fp128", "{ i8*, i8*, i8*, i32, i32 }"),
+        Arch::Riscv64 => ("riscv64-unknown-linux-gnu", "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128", "fp128", "i8*"),
+        // 和 wasm32 后端一样 long double 按 double 处理
+        Arch::Wasm32 => ("wasm32-unknown-unknown", "e-m:e-p:32:32-p10:8:8-p20:8:8-i64:64-n32:64-S128-ni:1:10:20", "double", "i8*"),
+    };
+    Layout { triple, datalayout, long_double, va_list, arch }
+}
+
+/*
+ * 生成一个函数时的状态
+ * 正在生成的函数
+ * 目标相关的类型
+ * 定义了的函数的签名
+ * 用到的没有定义的函数和 intrinsic 的声明 (按名字排序)
+ * 字符串常量
+ * 已经生成的行
+ * 下一个临时值和标签的编号
+ * 当前的块，块已经结束时之后的指令放在新的块里
+ * 循环栈 (break 的目标, continue 的目标)
+*/
+struct Builder<'a> {
+    function: &'a Function,
+    layout: &'a Layout,
+    signatures: &'a HashMap<&'a str, &'a Signature>,
+    declarations: &'a mut BTreeMap<String, String>,
+    strings: &'a mut Vec<Vec<u8>>,
+    code: Vec<String>,
+    next: usize,
+    current: String,
+    terminated: bool,
+    loops: Vec<(String, String)>,
+}
+
+impl<'a> Builder<'a> {
+    fn emit(&mut self, instr: String) {
+        if self.terminated {
+            let label = self.new_label();
+            self.start_block(label);
+        }
+        self.code.push(format!("  {}", instr));
+    }
+
+    // 定义一个新的临时值
+    fn emit_value(&mut self, instr: String) -> String {
+        self.next += 1;
+        let value = format!("%t{}", self.next);
+        self.emit(format!("{} = {}", value, instr));
+        value
+    }
+
+    fn new_label(&mut self) -> String {
+        self.next += 1;
+        format!("L{}", self.next)
+    }
+
+    fn start_block(&mut self, label: String) {
+        self.code.push(format!("{}:", label));
+        self.current = label;
+        self.terminated = false;
+    }
+
+    // 结束当前块，已经结束时不再生成
+    fn terminate(&mut self, instr: String) {
+        if !self.terminated {
+            self.emit(instr);
+            self.terminated = true;
+        }
+    }
+
+    fn jump(&mut self, label: &str) {
+        self.terminate(format!("br label %{}", label));
+    }
+
+    fn ty(&self, t: &Type) -> String {
+        match t {
+            Type::Void => "void".to_string(),
+            Type::Int => "i64".to_string(),
+            Type::Char => "i8".to_string(),
+            Type::Float => "float".to_string(),
+            Type::Double => "double".to_string(),
+            Type::LongDouble => self.layout.long_double.to_string(),
+            Type::Pointer(_) => "i8*".to_string(),
+            Type::VaList => self.layout.va_list.to_string(),
+        }
+    }
+
+    fn declare(&mut self, name: &str, declaration: String) {
+        self.declarations.entry(name.to_string()).or_insert(declaration);
+    }
+}
+
+// 函数类型 ret (params, ...)
+fn function_type(ret: &str, params: &[String], variadic: bool) -> String {
+    let mut params = params.to_vec();
+    if variadic {
+        params.push("...".to_string());
+    }
+    format!("{} ({})", ret, params.join(", "))
+}
+
+pub fn generate(program: &Program, arch: Arch) -> String {
+    let layout = layout(arch);
+    let signatures: HashMap<&str, &Signature> = program.functions.iter().map(|f| (&f.name[..], &f.signature)).collect();
+    let mut declarations = BTreeMap::new();
+    let mut strings = Vec::new();
+
+    let mut functions = Vec::new();
+    for function in program.functions.iter() {
+        functions.push(generate_function(function, &layout, &signatures, &mut declarations, &mut strings));
+    }
+
+    let mut lines = vec![
+        format!("target datalayout = \"{}\"", layout.datalayout),
+        format!("target triple = \"{}\"", layout.triple),
+        String::new(),
+    ];
+    for (i, s) in strings.iter().enumerate() {
+        lines.push(format!("@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"", i, s.len() + 1, escape(s)));
+    }
+    if !strings.is_empty() {
+        lines.push(String::new());
+    }
+    for function in functions {
+        lines.extend(function);
+        lines.push(String::new());
+    }
+    for name in declarations.keys().filter(|name| !signatures.contains_key(&name[..])) {
+        lines.push(declarations[name].clone());
+    }
+
+    // Linux 上 gcc 默认链接成 PIE，模块标记为 PIC 和 PIE (llc 还要加 -relocation-model=pic)
+    if arch != Arch::Wasm32 {
+        if lines.last().is_some_and(|line| !line.is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push("!llvm.module.flags = !{!0, !1}".to_string());
+        lines.push("!0 = !{i32 7, !\"PIC Level\", i32 2}".to_string());
+        lines.push("!1 = !{i32 7, !\"PIE Level\", i32 2}".to_string());
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// 可打印的 ASCII 原样输出，其余写成 \十六进制
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| match b {
+        b'"' | b'\\' => format!("\\{:02X}", b),
+        0x20..=0x7e => (*b as char).to_string(),
+        _ => format!("\\{:02X}", b),
+    }).collect()
+}
+
+fn generate_function(
+    function: &Function,
+    layout: &Layout,
+    signatures: &HashMap<&str, &Signature>,
+    declarations: &mut BTreeMap<String, String>,
+    strings: &mut Vec<Vec<u8>>,
+) -> Vec<String> {
+    let mut builder = Builder {
+        function,
+        layout,
+        signatures,
+        declarations,
+        strings,
+        code: Vec::new(),
+        next: 0,
+        current: "entry".to_string(),
+        terminated: false,
+        loops: Vec::new(),
+    };
+
+    let signature = &function.signature;
+    let ret = builder.ty(&signature.return_type);
+    let mut params: Vec<String> = function.params.iter().enumerate().map(|(i, id)| {
+        format!("{} %p{}", builder.ty(&function.locals[*id]), i)
+    }).collect();
+    if signature.variadic {
+        params.push("...".to_string());
+    }
+
+    let linkage = if function.specifiers.is_static { "internal " } else { "" };
+    let specifiers = &function.specifiers;
+    let attributes: String = [
+        (specifiers.noinline, " noinline"),
+        (specifiers.always_inline, " alwaysinline"),
+        (specifiers.inline && !specifiers.noinline && !specifiers.always_inline, " inlinehint"),
+    ].iter().filter(|(on, _)| *on).map(|(_, a)| *a).collect();
+    let header = format!("define {}{} @{}({}){} {{", linkage, ret, function.name, params.join(", "), attributes);
+
+    // 所有的变量在入口块中分配，参数存到自己的变量中
+    builder.code.push("entry:".to_string());
+    for (id, t) in function.locals.iter().enumerate() {
+        let ty = builder.ty(t);
+        builder.emit(format!("%v{} = alloca {}", id, ty));
+    }
+    for (i, id) in function.params.iter().enumerate() {
+        let ty = builder.ty(&function.locals[*id]);
+        builder.emit(format!("store {} %p{}, {}* %v{}", ty, i, ty, id));
+    }
+
+    for statement in function.body.iter() {
+        generate_statement(statement, &mut builder);
+    }
+
+    // 没有 return 就结束的函数返回0
+    if !builder.terminated {
+        let instr = match &signature.return_type {
+            Type::Void => "ret void".to_string(),
+            t => format!("ret {} {}", ret, zero(t)),
+        };
+        builder.terminate(instr);
+    }
+
+    let mut lines = vec![header];
+    lines.extend(builder.code);
+    lines.push("}".to_string());
+    lines
+}
+
+fn zero(t: &Type) -> &'static str {
+    match t {
+        Type::Pointer(_) => "null",
+        t if t.is_floating() => "0.0",
+        _ => "0",
+    }
+}
+
+fn generate_statement(statement: &Stmt, builder: &mut Builder) {
+    match statement {
+        Stmt::Expression(expr) => {
+            generate_expression(expr, builder);
+        }
+
+        Stmt::Declaration(id, init) => {
+            if let Some(expr) = init {
+                let v = generate_value(expr, builder);
+                let ty = builder.ty(&expr.ty);
+                builder.emit(format!("store {} {}, {}* %v{}", ty, v, ty, id));
+            }
+        }
+
+        Stmt::Return(expr) => {
+            let instr = match expr {
+                Some(expr) => {
+                    let v = generate_value(expr, builder);
+                    format!("ret {} {}", builder.ty(&expr.ty), v)
+                }
+                None => "ret void".to_string(),
+            };
+            builder.terminate(instr);
+        }
+
+        Stmt::TailCall(expr) => {
+            let ExprKind::Call(name, signature, args) = &expr.kind else {
+                unreachable!("Tail call of a non-call expression");
+            };
+            let v = generate_call(name, signature, args, true, builder);
+            let instr = match v {
+                Some(v) => format!("ret {} {}", builder.ty(&expr.ty), v),
+                None => "ret void".to_string(),
+            };
+            builder.terminate(instr);
+        }
+
+        Stmt::If(condition, if_body, else_body) => {
+            let then_label = builder.new_label();
+            let else_label = builder.new_label();
+            let post_if = builder.new_label();
+            generate_condition(condition, &then_label, &else_label, builder);
+
+            builder.start_block(then_label);
+            generate_statement(if_body, builder);
+            builder.jump(&post_if);
+
+            builder.start_block(else_label);
+            if let Some(else_body) = else_body {
+                generate_statement(else_body, builder);
+            }
+            builder.jump(&post_if);
+            builder.start_block(post_if);
+        }
+
+        Stmt::Block(block) => {
+            for statement in block.iter() {
+                generate_statement(statement, builder);
+            }
+        }
+
+        Stmt::For(init, condition, post_expression, body) => {
+            if let Some(init) = init {
+                generate_statement(init, builder);
+            }
+            let header = builder.new_label();
+            let body_label = builder.new_label();
+            let continue_label = builder.new_label();
+            let post_loop = builder.new_label();
+
+            builder.jump(&header);
+            builder.start_block(header.clone());
+            generate_condition(condition, &body_label, &post_loop, builder);
+
+            builder.start_block(body_label);
+            generate_loop_body(body, &post_loop, &continue_label, builder);
+            builder.jump(&continue_label);
+
+            builder.start_block(continue_label);
+            if let Some(expr) = post_expression {
+                generate_expression(expr, builder);
+            }
+            builder.jump(&header);
+            builder.start_block(post_loop);
+        }
+
+        Stmt::While(condition, body) => {
+            let header = builder.new_label();
+            let body_label = builder.new_label();
+            let post_loop = builder.new_label();
+
+            builder.jump(&header);
+            builder.start_block(header.clone());
+            generate_condition(condition, &body_label, &post_loop, builder);
+
+            builder.start_block(body_label);
+            generate_loop_body(body, &post_loop, &header, builder);
+            builder.jump(&header);
+            builder.start_block(post_loop);
+        }
+
+        Stmt::DoWhile(body, condition) => {
+            let body_label = builder.new_label();
+            let continue_label = builder.new_label();
+            let post_loop = builder.new_label();
+
+            builder.jump(&body_label);
+            builder.start_block(body_label.clone());
+            generate_loop_body(body, &post_loop, &continue_label, builder);
+            builder.jump(&continue_label);
+
+            builder.start_block(continue_label);
+            generate_condition(condition, &body_label, &post_loop, builder);
+            builder.start_block(post_loop);
+        }
+
+        Stmt::Break => {
+            let (break_label, _) = builder.loops.last().cloned().expect("Break outside loop");
+            builder.jump(&break_label);
+        }
+
+        Stmt::Continue => {
+            let (_, continue_label) = builder.loops.last().cloned().expect("Continue outside loop");
+            builder.jump(&continue_label);
+        }
+    }
+}
+
+fn generate_loop_body(body: &Stmt, break_label: &str, continue_label: &str, builder: &mut Builder) {
+    builder.loops.push((break_label.to_string(), continue_label.to_string()));
+    generate_statement(body, builder);
+    builder.loops.pop();
+}
+
+/*
+ * 按条件跳转
+ * && || 短路求值，! 交换两个目标，其余的求出 i1 之后 br
+*/
+fn generate_condition(condition: &Expr, if_true: &str, if_false: &str, builder: &mut Builder) {
+    match &condition.kind {
+        ExprKind::Binary(Operator::LogicalAnd, lhs, rhs) => {
+            let rhs_label = builder.new_label();
+            generate_condition(lhs, &rhs_label, if_false, builder);
+            builder.start_block(rhs_label);
+            generate_condition(rhs, if_true, if_false, builder);
+        }
+        ExprKind::Binary(Operator::LogicalOr, lhs, rhs) => {
+            let rhs_label = builder.new_label();
+            generate_condition(lhs, if_true, &rhs_label, builder);
+            builder.start_block(rhs_label);
+            generate_condition(rhs, if_true, if_false, builder);
+        }
+        ExprKind::Unary(Operator::LogicalNegation, expr) => generate_condition(expr, if_false, if_true, builder),
+        _ => {
+            let c = generate_truth(condition, builder);
+            builder.terminate(format!("br i1 {}, label %{}, label %{}", c, if_true, if_false));
+        }
+    }
+}
+
+// 条件的 i1 值，比较直接用比较的结果，其余的和0比较
+fn generate_truth(condition: &Expr, builder: &mut Builder) -> String {
+    if let ExprKind::Binary(op, lhs, rhs) = &condition.kind {
+        if op.is_comparison_operators() {
+            let a = generate_value(lhs, builder);
+            let b = generate_value(rhs, builder);
+            let ty = builder.ty(&lhs.ty);
+            return builder.emit_value(format!("{} {} {}, {}", compare_operator(*op, &lhs.ty), ty, a, b));
+        }
+    }
+
+    let v = generate_value(condition, builder);
+    let ty = builder.ty(&condition.ty);
+    if condition.ty.is_floating() {
+        builder.emit_value(format!("fcmp une {} {}, {}", ty, v, zero(&condition.ty)))
+    } else {
+        builder.emit_value(format!("icmp ne {} {}, {}", ty, v, zero(&condition.ty)))
+    }
+}
+
+fn binary_operator(op: Operator, floating: bool) -> &'static str {
+    match (op, floating) {
+        (Operator::Plus, false) => "add",
+        (Operator::Minus, false) => "sub",
+        (Operator::Multiplication, false) => "mul",
+        (Operator::Division, false) => "sdiv",
+        (Operator::Modulo, false) => "srem",
+        (Operator::BitwiseAnd, false) => "and",
+        (Operator::BitwiseOr, false) => "or",
+        (Operator::BitwiseXor, false) => "xor",
+        (Operator::BitwiseShiftLeft, false) => "shl",
+        (Operator::BitwiseShiftRight, false) => "ashr",
+        (Operator::Plus, true) => "fadd",
+        (Operator::Minus, true) => "fsub",
+        (Operator::Multiplication, true) => "fmul",
+        (Operator::Division, true) => "fdiv",
+        _ => unreachable!("Unexpected binary operator {:?}", op),
+    }
+}
+
+// 整数按有符号数比较，指针按无符号数比较，浮点数的 != 在有 NaN 时成立
+fn compare_operator(op: Operator, ty: &Type) -> &'static str {
+    let names = match ty {
+        Type::Pointer(_) => ["icmp eq", "icmp ne", "icmp ult", "icmp ule", "icmp ugt", "icmp uge"],
+        t if t.is_floating() => ["fcmp oeq", "fcmp une", "fcmp olt", "fcmp ole", "fcmp ogt", "fcmp oge"],
+        _ => ["icmp eq", "icmp ne", "icmp slt", "icmp sle", "icmp sgt", "icmp sge"],
+    };
+    match op {
+        Operator::Equal => names[0],
+        Operator::NotEqual => names[1],
+        Operator::LessThan => names[2],
+        Operator::LessThanOrEqual => names[3],
+        Operator::GreaterThan => names[4],
+        Operator::GreaterThanOrEqual => names[5],
+        _ => unreachable!("Unexpected comparison operator {:?}", op),
+    }
+}
+
+// 有值的表达式
+fn generate_value(expression: &Expr, builder: &mut Builder) -> String {
+    generate_expression(expression, builder).expect("Void value used")
+}
+
+/*
+ * 浮点常量
+ * float double 写成 double 的十六进制位模式 (float 的值一定能精确表示)
+ * x86_fp80 是 0xK 加80位，fp128 是 0xL 加低64位和高64位
+*/
+fn float_constant(literal: &str, ty: &Type, layout: &Layout) -> String {
+    match (ty, layout.long_double) {
+        (Type::Float, _) => format!("0x{:016X}", (f32::from_bits(float_bits(literal, Ty::F32) as u32) as f64).to_bits()),
+        (Type::LongDouble, "x86_fp80") => format!("0xK{:020X}", float_bits(literal, Ty::F80)),
+        (Type::LongDouble, "fp128") => {
+            let bits = quad_bits(literal);
+            format!("0xL{:016X}{:016X}", bits as u64, (bits >> 64) as u64)
+        }
+        _ => format!("0x{:016X}", float_bits(literal, Ty::F64) as u64),
+    }
+}
+
+/*
+ * 表达式
+ * 返回表达式的值 (临时值或者常量)，void 表达式返回 None
+*/
+fn generate_expression(expression: &Expr, builder: &mut Builder) -> Option<String> {
+    let ty = builder.ty(&expression.ty);
+
+    let v = match &expression.kind {
+        ExprKind::Constant(n) => match &expression.ty {
+            Type::Pointer(_) if *n == 0 => "null".to_string(),
+            Type::Pointer(_) => format!("inttoptr (i64 {} to i8*)", n),
+            t if t.is_floating() => float_constant(&n.to_string(), t, builder.layout),
+            _ => n.to_string(),
+        },
+
+        ExprKind::FloatConstant(f) => float_constant(f, &expression.ty, builder.layout),
+
+        ExprKind::StringLiteral(s) => {
+            builder.strings.push(s.clone());
+            let index = builder.strings.len() - 1;
+            let array = format!("[{} x i8]", s.len() + 1);
+            format!("getelementptr inbounds ({}, {}* @.str.{}, i64 0, i64 0)", array, array, index)
+        }
+
+        ExprKind::Variable(id) => builder.emit_value(format!("load {}, {}* %v{}", ty, ty, id)),
+
+        // 数组退化成指针
+        ExprKind::Address(id) => {
+            let va_list = builder.layout.va_list;
+            builder.emit_value(format!("bitcast {}* %v{} to i8*", va_list, id))
+        }
+
+        ExprKind::Unary(Operator::LogicalNegation, _) | ExprKind::Binary(Operator::LogicalAnd | Operator::LogicalOr, _, _) => {
+            generate_boolean(expression, builder)
+        }
+
+        ExprKind::Unary(Operator::BitwiseComplement, expr) => {
+            let a = generate_value(expr, builder);
+            builder.emit_value(format!("xor {} {}, -1", ty, a))
+        }
+
+        ExprKind::Unary(_, expr) => {
+            let a = generate_value(expr, builder);
+            if expression.ty.is_floating() {
+                builder.emit_value(format!("fneg {} {}", ty, a))
+            } else {
+                builder.emit_value(format!("sub {} 0, {}", ty, a))
+            }
+        }
+
+        ExprKind::Assign(lhs, rhs) => {
+            let v = generate_value(rhs, builder);
+            let id = lvalue(lhs);
+            builder.emit(format!("store {} {}, {}* %v{}", ty, v, ty, id));
+            v
+        }
+
+        // a op= b 相当于 a = a op b，运算类型就是右边的类型
+        ExprKind::CompoundAssign(op, lhs, rhs) => {
+            let b = generate_value(rhs, builder);
+            let id = lvalue(lhs);
+            let a = builder.emit_value(format!("load {}, {}* %v{}", ty, ty, id));
+            let a = generate_convert(a, &lhs.ty, &rhs.ty, builder);
+            let common = builder.ty(&rhs.ty);
+            let result = builder.emit_value(format!("{} {} {}, {}", binary_operator(*op, rhs.ty.is_floating()), common, a, b));
+            let result = generate_convert(result, &rhs.ty, &lhs.ty, builder);
+            builder.emit(format!("store {} {}, {}* %v{}", ty, result, ty, id));
+            result
+        }
+
+        ExprKind::Binary(op, lhs, rhs) => {
+            if op.is_comparison_operators() {
+                let c = generate_truth(expression, builder);
+                builder.emit_value(format!("zext i1 {} to i64", c))
+            } else {
+                let a = generate_value(lhs, builder);
+                let b = generate_value(rhs, builder);
+                builder.emit_value(format!("{} {} {}, {}", binary_operator(*op, expression.ty.is_floating()), ty, a, b))
+            }
+        }
+
+        ExprKind::Ternary(e1, e2, e3) => {
+            let then_label = builder.new_label();
+            let else_label = builder.new_label();
+            let join = builder.new_label();
+            generate_condition(e1, &then_label, &else_label, builder);
+
+            builder.start_block(then_label);
+            let a = generate_expression(e2, builder);
+            let a_label = builder.current.clone();
+            builder.jump(&join);
+
+            builder.start_block(else_label);
+            let b = generate_expression(e3, builder);
+            let b_label = builder.current.clone();
+            builder.jump(&join);
+
+            builder.start_block(join);
+            match (a, b) {
+                (Some(a), Some(b)) if expression.ty != Type::Void => {
+                    builder.emit_value(format!("phi {} [ {}, %{} ], [ {}, %{} ]", ty, a, a_label, b, b_label))
+                }
+                _ => return None,
+            }
+        }
+
+        ExprKind::Call(name, signature, args) => return generate_call(name, signature, args, false, builder),
+
+        ExprKind::Cast(expr) => {
+            let a = generate_expression(expr, builder);
+            return match a {
+                Some(a) if expression.ty != Type::Void => Some(generate_convert(a, &expr.ty, &expression.ty, builder)),
+                // 转换成 void 只求值
+                _ => None,
+            };
+        }
+
+        ExprKind::VaStart(ap) => {
+            let ap = generate_value(ap, builder);
+            builder.declare("llvm.va_start", "declare void @llvm.va_start(i8*)".to_string());
+            builder.emit(format!("call void @llvm.va_start(i8* {})", ap));
+            return None;
+        }
+
+        ExprKind::VaArg(ap) => {
+            let ap = generate_value(ap, builder);
+            generate_va_arg(ap, &expression.ty, builder)
+        }
+
+        ExprKind::VaEnd(ap) => {
+            let ap = generate_value(ap, builder);
+            builder.declare("llvm.va_end", "declare void @llvm.va_end(i8*)".to_string());
+            builder.emit(format!("call void @llvm.va_end(i8* {})", ap));
+            return None;
+        }
+
+        ExprKind::VaCopy(dest, src) => {
+            let dest = generate_value(dest, builder);
+            let src = generate_value(src, builder);
+            builder.declare("llvm.va_copy", "declare void @llvm.va_copy(i8*, i8*)".to_string());
+            builder.emit(format!("call void @llvm.va_copy(i8* {}, i8* {})", dest, src));
+            return None;
+        }
+    };
+
+    Some(v)
+}
+
+// ! && || 的值，两个分支分别得到 1 和 0
+fn generate_boolean(expression: &Expr, builder: &mut Builder) -> String {
+    let true_label = builder.new_label();
+    let false_label = builder.new_label();
+    let join = builder.new_label();
+    generate_condition(expression, &true_label, &false_label, builder);
+
+    builder.start_block(true_label.clone());
+    builder.jump(&join);
+    builder.start_block(false_label.clone());
+    builder.jump(&join);
+    builder.start_block(join);
+    builder.emit_value(format!("phi i64 [ 1, %{} ], [ 0, %{} ]", true_label, false_label))
+}
+
+fn lvalue(expr: &Expr) -> usize {
+    match expr.kind {
+        ExprKind::Variable(id) => id,
+        _ => unreachable!("Not an lvalue: {:?}", expr),
+    }
+}
+
+// 浮点类型的等级，转换时决定 fpext 还是 fptrunc
+fn float_rank(t: &Type) -> u8 {
+    match t {
+        Type::Float => 1,
+        Type::Double => 2,
+        _ => 3,
+    }
+}
+
+/*
+ * 类型转换，类型相同时不变
+ * char 按有符号数扩展，指针转换成整数时截断或者扩展
+*/
+fn generate_convert(a: String, from: &Type, to: &Type, builder: &mut Builder) -> String {
+    let (from_ty, to_ty) = (builder.ty(from), builder.ty(to));
+    if from_ty == to_ty {
+        return a;
+    }
+
+    let instr = match (from, to) {
+        (Type::Pointer(_), _) => "ptrtoint",
+        (Type::Char, Type::Pointer(_)) => {
+            let a = builder.emit_value(format!("sext i8 {} to i64", a));
+            return builder.emit_value(format!("inttoptr i64 {} to i8*", a));
+        }
+        (_, Type::Pointer(_)) => "inttoptr",
+        (Type::Char, Type::Int) => "sext",
+        (Type::Int, Type::Char) => "trunc",
+        (f, t) if f.is_floating() && t.is_floating() => {
+            if float_rank(f) < float_rank(t) { "fpext" } else { "fptrunc" }
+        }
+        (f, _) if f.is_floating() => "fptosi",
+        _ => "sitofp",
+    };
+    builder.emit_value(format!("{} {} {} to {}", instr, from_ty, a, to_ty))
+}
+
+/*
+ * 调用
+ * 没有定义的函数第一次调用时声明，没有原型的函数声明成 ret (...)
+ * 调用的类型和声明的类型不同时 (没有原型的调用) 把函数 bitcast 成调用的类型
+ * 必须的尾调用在两边的类型完全相同时用 musttail，否则用 tail
+*/
+fn generate_call(name: &str, signature: &Signature, args: &[Expr], tail: bool, builder: &mut Builder) -> Option<String> {
+    let values: Vec<String> = args.iter().map(|arg| {
+        let v = generate_value(arg, builder);
+        format!("{} {}", builder.ty(&arg.ty), v)
+    }).collect();
+    let arg_types: Vec<String> = args.iter().map(|arg| builder.ty(&arg.ty)).collect();
+
+    let callee = builder.signatures.get(name).copied().unwrap_or(signature);
+    let ret = builder.ty(&callee.return_type);
+    let declared = if callee.prototyped {
+        let params: Vec<String> = callee.params.iter().map(|t| builder.ty(t)).collect();
+        function_type(&ret, &params, callee.variadic)
+    } else {
+        function_type(&ret, &[], true)
+    };
+    if !builder.signatures.contains_key(name) {
+        builder.declare(name, format!("declare {} @{}({})", ret, name, &declared[ret.len() + 2..declared.len() - 1]));
+    }
+
+    let called = if signature.prototyped {
+        let params: Vec<String> = signature.params.iter().map(|t| builder.ty(t)).collect();
+        function_type(&ret, &params, signature.variadic)
+    } else {
+        function_type(&ret, &arg_types, false)
+    };
+    let function = if called == declared {
+        format!("@{}", name)
+    } else {
+        format!("bitcast ({}* @{} to {}*)", declared, name, called)
+    };
+
+    let caller = builder.function.signature.clone();
+    let caller_params: Vec<String> = caller.params.iter().map(|t| builder.ty(t)).collect();
+    let caller_type = function_type(&builder.ty(&caller.return_type), &caller_params, caller.variadic);
+    let kind = match tail {
+        true if called == caller_type && !caller.variadic => "musttail call",
+        true => "tail call",
+        false => "call",
+    };
+
+    let instr = format!("{} {} {}({})", kind, called, function, values.join(", "));
+    if callee.return_type == Type::Void {
+        builder.emit(instr);
+        None
+    } else {
+        Some(builder.emit_value(instr))
+    }
+}
+
+/*
+ * va_arg
+ * x86-64 的 long double 和 AArch64 的 va_list 由 LLVM 的 va_arg 处理不了，在这里展开
+ * 其余的用 va_arg 指令，char 按提升之后的 int 读取再截断
+*/
+fn generate_va_arg(ap: String, ty: &Type, builder: &mut Builder) -> String {
+    match (builder.layout.arch, ty) {
+        (Arch::Aarch64, _) => generate_aapcs_va_arg(ap, ty, builder),
+        (Arch::X86_64, Type::LongDouble) => {
+            let area_field = field(&ap, 8, "i8*", builder);
+            let area = builder.emit_value(format!("load i8*, i8** {}", area_field));
+            let address = align_pointer(area, 16, builder);
+            let next = builder.emit_value(format!("getelementptr i8, i8* {}, i64 16", address));
+            builder.emit(format!("store i8* {}, i8** {}", next, area_field));
+            load_from(&address, ty, builder)
+        }
+        (_, Type::Char) => {
+            let v = builder.emit_value(format!("va_arg i8* {}, i64", ap));
+            builder.emit_value(format!("trunc i64 {} to i8", v))
+        }
+        _ => {
+            let t = builder.ty(ty);
+            builder.emit_value(format!("va_arg i8* {}, {}", ap, t))
+        }
+    }
+}
+
+// va_list 中 offset 处的字段的指针
+fn field(ap: &str, offset: usize, ty: &str, builder: &mut Builder) -> String {
+    let p = builder.emit_value(format!("getelementptr i8, i8* {}, i64 {}", ap, offset));
+    builder.emit_value(format!("bitcast i8* {} to {}*", p, ty))
+}
+
+fn align_pointer(p: String, align: i64, builder: &mut Builder) -> String {
+    let n = builder.emit_value(format!("ptrtoint i8* {} to i64", p));
+    let n = builder.emit_value(format!("add i64 {}, {}", n, align - 1));
+    let n = builder.emit_value(format!("and i64 {}, {}", n, -align));
+    builder.emit_value(format!("inttoptr i64 {} to i8*", n))
+}
+
+// 从地址读取一个 va_arg 的值，char 读取提升之后的 int 再截断
+fn load_from(address: &str, ty: &Type, builder: &mut Builder) -> String {
+    let read = if *ty == Type::Char { Type::Int } else { ty.clone() };
+    let t = builder.ty(&read);
+    let p = builder.emit_value(format!("bitcast i8* {} to {}*", address, t));
+    let v = builder.emit_value(format!("load {}, {}* {}", t, t, p));
+    if *ty == Type::Char {
+        builder.emit_value(format!("trunc i64 {} to i8", v))
+    } else {
+        v
+    }
+}
+
+/*
+ * AAPCS64 的 va_arg
+ * va_list 是 { __stack, __gr_top, __vr_top, __gr_offs, __vr_offs }
+ * 整数和指针在整数寄存器保存区 (每个8字节)，浮点数在浮点寄存器保存区 (每个16字节)
+ * offs 是负数时参数还在保存区中 (top + offs)，否则在栈上
+*/
+fn generate_aapcs_va_arg(ap: String, ty: &Type, builder: &mut Builder) -> String {
+    let (offs_offset, top_offset, register_size) = if ty.is_floating() { (28, 16, 16) } else { (24, 8, 8) };
+    let (stack_size, stack_align) = if *ty == Type::LongDouble { (16, 16) } else { (8, 8) };
+
+    let maybe_register = builder.new_label();
+    let in_register = builder.new_label();
+    let on_stack = builder.new_label();
+    let join = builder.new_label();
+
+    let offs_field = field(&ap, offs_offset, "i32", builder);
+    let offs = builder.emit_value(format!("load i32, i32* {}", offs_field));
+    let used_up = builder.emit_value(format!("icmp sge i32 {}, 0", offs));
+    builder.terminate(format!("br i1 {}, label %{}, label %{}", used_up, on_stack, maybe_register));
+
+    builder.start_block(maybe_register);
+    let new_offs = builder.emit_value(format!("add i32 {}, {}", offs, register_size));
+    builder.emit(format!("store i32 {}, i32* {}", new_offs, offs_field));
+    let overflow = builder.emit_value(format!("icmp sgt i32 {}, 0", new_offs));
+    builder.terminate(format!("br i1 {}, label %{}, label %{}", overflow, on_stack, in_register));
+
+    builder.start_block(in_register.clone());
+    let top_field = field(&ap, top_offset, "i8*", builder);
+    let top = builder.emit_value(format!("load i8*, i8** {}", top_field));
+    let offs64 = builder.emit_value(format!("sext i32 {} to i64", offs));
+    let register_address = builder.emit_value(format!("getelementptr i8, i8* {}, i64 {}", top, offs64));
+    builder.jump(&join);
+
+    builder.start_block(on_stack.clone());
+    let stack_field = builder.emit_value(format!("bitcast i8* {} to i8**", ap));
+    let stack = builder.emit_value(format!("load i8*, i8** {}", stack_field));
+    let stack = if stack_align > 8 { align_pointer(stack, stack_align, builder) } else { stack };
+    let next = builder.emit_value(format!("getelementptr i8, i8* {}, i64 {}", stack, stack_size));
+    builder.emit(format!("store i8* {}, i8** {}", next, stack_field));
+    let stack_label = builder.current.clone();
+    builder.jump(&join);
+
+    builder.start_block(join);
+    let address = builder.emit_value(format!("phi i8* [ {}, %{} ], [ {}, %{} ]", register_address, in_register, stack, stack_label));
+    load_from(&address, ty, builder)
+}
@@ -2,5 +2,32 @@ pub mod token;
 pub mod lex;
 pub mod parser;
 pub mod ast;
+pub mod sema;
+pub mod typed_ast;
+pub mod fold;
+pub mod ir;
+pub mod target;
 pub mod generator;
-pub mod context;
\ No newline at end of file
+pub mod asm;
+pub mod dialect;
+pub mod encode;
+pub mod elf;
+pub mod link;
+pub mod peephole;
+pub mod context;
+pub mod frame;
+pub mod stack;
+pub mod regalloc;
+pub mod aarch64;
+pub mod riscv64;
+pub mod wasm;
+pub mod llvm;
+pub mod c;
+pub mod interp;
+pub mod jit;
+pub mod repl;
+pub mod types;
+pub mod float;
+pub mod options;
+pub mod driver;
+pub mod runtime;
@@ -0,0 +1,171 @@
+use super::ir::pass::is_pass;
+
+/*
+ * 输出的内容
+ * 汇编
+ * 中间表示
+ * LLVM IR 文本
+ * 规范化的 C 源代码
+ * 目标文件 (-c)
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Emit {
+    #[default]
+    Asm,
+    Ir,
+    Llvm,
+    C,
+    Object,
+}
+
+/*
+ * 汇编的语法 (-masm=)
+ * GNU as 的 intel 语法
+ * GNU as 的 AT&T 语法
+ * NASM
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Intel,
+    Att,
+    Nasm,
+}
+
+/*
+ * 目标机器 (--target=)
+ * x86-64 Linux，System V 调用约定
+ * AArch64 Linux，AAPCS64 调用约定
+ * RISC-V RV64GC Linux，LP64D 调用约定
+ * WebAssembly，32位的线性内存
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arch {
+    #[default]
+    X86_64,
+    Aarch64,
+    Riscv64,
+    Wasm32,
+}
+
+/*
+ * 命令行参数
+ * 要编译的文件
+ * 输出的文件 (-o)，没有时汇编和中间表示输出到标准输出，目标文件写到 输入文件名.o (wasm32 是 .wasm)
+ * 是否允许隐式声明函数 (C89)
+ * 输出汇编还是中间表示
+ * 汇编的语法
+ * 目标机器
+ * 优化级别 0 1 2
+ * 运行之后输出 IR 的 pass
+ * 输出优化报告的 pass (-Rpass=<pass> 成功的优化，-Rpass-missed=<pass> 没有进行的优化)
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub input: String,
+    pub output: Option<String>,
+    pub implicit_declarations: bool,
+    pub emit: Emit,
+    pub syntax: Syntax,
+    pub target: Arch,
+    pub opt_level: u8,
+    pub print_after: Vec<String>,
+    pub remarks: Vec<String>,
+    pub missed_remarks: Vec<String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut input = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                // C89 允许调用没有声明过的函数，默认返回 int
+                "-std=c89" | "-std=c90" | "-std=gnu89" | "-ansi" => options.implicit_declarations = true,
+                "-std=c99" | "-std=c11" | "-std=c17" => options.implicit_declarations = false,
+                "--emit=asm" => options.emit = Emit::Asm,
+                "--emit=ir" => options.emit = Emit::Ir,
+                "--emit=llvm" => options.emit = Emit::Llvm,
+                "--emit=c" => options.emit = Emit::C,
+                "-c" => options.emit = Emit::Object,
+                "-o" => match args.next() {
+                    Some(output) => options.output = Some(output.clone()),
+                    None => return Err("Missing file name after -o".to_string()),
+                },
+                "-masm=intel" => options.syntax = Syntax::Intel,
+                "-masm=att" => options.syntax = Syntax::Att,
+                "-masm=nasm" => options.syntax = Syntax::Nasm,
+                s if s.starts_with("-masm=") => return Err(format!("Unknown assembler dialect {}", &s["-masm=".len()..])),
+                "--target=x86_64-linux" | "--target=x86_64-linux-gnu" => options.target = Arch::X86_64,
+                "--target=aarch64-linux" | "--target=aarch64-linux-gnu" => options.target = Arch::Aarch64,
+                "--target=riscv64-linux" | "--target=riscv64-linux-gnu" => options.target = Arch::Riscv64,
+                "--target=wasm32" | "--target=wasm32-unknown-unknown" => options.target = Arch::Wasm32,
+                s if s.starts_with("--target=") => return Err(format!("Unknown target {}", &s["--target=".len()..])),
+                "-O0" => options.opt_level = 0,
+                "-O" | "-O1" => options.opt_level = 1,
+                "-O2" | "-O3" => options.opt_level = 2,
+                s if s.starts_with("--print-after=") => options.print_after.push(pass_name(&s["--print-after=".len()..])?),
+                s if s.starts_with("-Rpass=") => options.remarks.push(pass_name(&s["-Rpass=".len()..])?),
+                s if s.starts_with("-Rpass-missed=") => options.missed_remarks.push(pass_name(&s["-Rpass-missed=".len()..])?),
+                s if s.starts_with('-') => return Err(format!("Unknown option {}", s)),
+                s => {
+                    if input.is_some() {
+                        return Err("More than one input file".to_string());
+                    }
+                    input = Some(s.to_string());
+                }
+            }
+        }
+
+        match input {
+            Some(input) => options.input = input,
+            None => return Err("No input file".to_string()),
+        }
+
+        Ok(options)
+    }
+}
+
+// 选项中的 pass 名字必须存在
+fn pass_name(name: &str) -> Result<String, String> {
+    if is_pass(name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!("Unknown pass {}", name))
+    }
+}
+
+/*
+ * my_rcc link [-o 输出] 目标文件...
+ * 默认输出 a.out
+*/
+#[derive(Debug, Clone)]
+pub struct LinkOptions {
+    pub inputs: Vec<String>,
+    pub output: String,
+}
+
+impl LinkOptions {
+    pub fn parse(args: &[String]) -> Result<LinkOptions, String> {
+        let mut options = LinkOptions { inputs: Vec::new(), output: "a.out".to_string() };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "-o" => match args.next() {
+                    Some(output) => options.output = output.clone(),
+                    None => return Err("Missing file name after -o".to_string()),
+                },
+                s if s.starts_with('-') => return Err(format!("Unknown option {}", s)),
+                s => options.inputs.push(s.to_string()),
+            }
+        }
+
+        if options.inputs.is_empty() {
+            return Err("No input file".to_string());
+        }
+        Ok(options)
+    }
+}
@@ -1,5 +1,4 @@
 use core::panic;
-use std::collections::HashMap;
 use std::slice::Iter;
 
 use peek_nth::{IteratorExt, PeekableNth};
@@ -8,27 +7,23 @@ use crate::cod::token::Punctuator;
 
 use super::token::*;
 use super::ast::*;
-
-type AstMap = HashMap<String, (usize, bool)>;
+use super::types::{FunctionSpecifiers, Signature, Type};
 
 // 梯度下降
+// 只检查语法，声明和类型的检查交给 sema
 pub fn parser(tokens: &[Token]) -> Ast {
-    let mut ast_map = AstMap::new();
-    
-    let ast = parser_functions(&mut tokens.iter().peekable_nth(), &mut ast_map);
-
-    Ast::Ast(ast)
+    Ast::Ast(parser_functions(&mut tokens.iter().peekable_nth()))
 }
 
 /*
  * 遍历所有内容，
  * 返回一个Vec<AstNode> 也就是Ast
 */
-fn parser_functions(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &mut AstMap) -> Vec<AstNode> {
+fn parser_functions(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<AstNode> {
     let mut fun1 = Vec::new();
 
-    while let Some(_) = tokens.peek() {
-        let f = parser_function(tokens, ast_map);
+    while tokens.peek().is_some() {
+        let f = parser_function(tokens);
         fun1.push(f);
     }
     
@@ -39,41 +34,37 @@ fn parser_functions(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &mut AstMap)
  * 先判断是不是一个函数
  * 如果是则返回AstNode
 */
-fn parser_function(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &mut AstMap) -> AstNode {
-    match tokens.next() {
-        Some(Token::Keyword(Keyword::Int)) => match tokens.next() { // int 
-            Some(Token::Identifier(id)) => match tokens.next() { // name main add ...
+fn parser_function(tokens: &mut PeekableNth<Iter<Token>>) -> AstNode {
+    let specifiers = parser_function_specifiers(tokens, FunctionSpecifiers::default());
+    match tokens.peek() {
+        Some(token) if is_type(token) => match (parser_type(tokens), tokens.next()) { // int double ...
+            (return_type, Some(Token::Identifier(id))) => match tokens.next() { // name main add ...
                 Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
-                    let params = parser_function_parameters(tokens); // 去获取函数参数
-                    let nparams = params.len(); // 有多少参数
+                    let (params, variadic, prototyped) = parser_function_parameters(tokens); // 去获取函数参数
+                    let specifiers = parser_function_specifiers(tokens, specifiers); // 参数列表之后的 __attribute__
                     let has_body = tokens.peek() == Some(&&Token::Punctuator(Punctuator::OpenBrace)); // 是否是 {
-                    // 哈希表中是否有当前函数存储
-                    if let Some(&(orig_nparams, orig_has_body)) = ast_map.get(id) {
-                        if orig_nparams != nparams {
-                            // 参数数量不同
-                            panic!("Number of parameters in function conflicts with earlier declaration");
-                        } else if orig_has_body && has_body {
-                            // 是否是前大括号
-                            panic!("Redefinition of function");
-                        } else {
-                            // 哈希表中有同名函数，但内容不同
-                            ast_map.insert(id.clone(), (nparams, has_body));
-                        }
-                    } else {
-                        // 哈希表中没有当前内容
-                        ast_map.insert(id.clone(), (nparams, has_body));
+                    let (param_types, names): (Vec<Type>, Vec<String>) = params.into_iter().unzip();
+                    if has_body && names.iter().any(|name| name.is_empty()) {
+                        panic!("Parameter name omitted in definition of {}", id);
                     }
+                    // 函数定义中的 () 就是没有参数
+                    let signature = Signature {
+                        return_type,
+                        params: param_types,
+                        variadic,
+                        prototyped: prototyped || has_body,
+                    };
                     // 解析函数内部内容
                     let body = match tokens.next() {
                         Some(Token::Punctuator(Punctuator::OpenBrace)) => { // {
-                            Some(parser_items(tokens, ast_map))
+                            Some(parser_items(tokens))
                         }
                         Some(Token::Punctuator(Punctuator::Semicolon)) => None, // ;
                         // 开头错误
                         _ => panic!("Unexpected token after function declaration"),
                     };
-                    // 返回内容：函数名， 函数参数列表， 函数内容的迭代器
-                    AstNode::AstNode(id.clone(), params, body)
+                    // 返回内容：函数名， 函数签名， 说明符， 函数参数列表， 函数内容的迭代器
+                    AstNode::AstNode(id.clone(), signature, specifiers, names, body)
                 }
                 // 错误
                 e => panic!("Expected opening parenthesis at {:?}", e),
@@ -81,23 +72,186 @@ fn parser_function(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &mut AstMap)
             // Token类型不是Identifier，有可能是用了关键字当名称
             _ => panic!("Expected name for function"),
         },
-        // type不是int，而是其他的
+        // 不是类型开头
         _ => panic!("Expected type for function"),
     }
 }
 
+/*
+ * 函数说明符 static inline __attribute__((...))
+ * 可以出现在返回类型之前，__attribute__ 也可以出现在参数列表之后
+*/
+fn parser_function_specifiers(tokens: &mut PeekableNth<Iter<Token>>, mut specifiers: FunctionSpecifiers) -> FunctionSpecifiers {
+    loop {
+        match tokens.peek() {
+            Some(Token::Keyword(Keyword::Static)) => {
+                tokens.next();
+                specifiers.is_static = true;
+            }
+            Some(Token::Keyword(Keyword::Inline)) => {
+                tokens.next();
+                specifiers.inline = true;
+            }
+            Some(Token::Keyword(Keyword::Attribute)) => {
+                tokens.next();
+                for name in parser_attributes(tokens) {
+                    match name.as_str() {
+                        "noinline" => specifiers.noinline = true,
+                        "always_inline" => specifiers.always_inline = true,
+                        _ => eprintln!("warning: '{}' attribute ignored", name),
+                    }
+                }
+            }
+            _ => return specifiers,
+        }
+    }
+}
+
+/*
+ * __attribute__((name, name(args), ...))
+ * 返回属性的名字，__name__ 和 name 相同
+ * 属性的参数跳过，括号要配对
+*/
+fn parser_attributes(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<String> {
+    let mut names = Vec::new();
+    for _ in 0..2 {
+        if tokens.next() != Some(&Token::Punctuator(Punctuator::OpenParen)) {
+            panic!("Expected '((' after __attribute__");
+        }
+    }
+
+    loop {
+        match tokens.next() {
+            Some(Token::Identifier(name)) => {
+                let name = name.strip_prefix("__").and_then(|n| n.strip_suffix("__")).unwrap_or(name);
+                names.push(name.to_string());
+                // 跳过参数
+                if tokens.peek() == Some(&&Token::Punctuator(Punctuator::OpenParen)) {
+                    let mut depth = 0;
+                    loop {
+                        match tokens.next() {
+                            Some(Token::Punctuator(Punctuator::OpenParen)) => depth += 1,
+                            Some(Token::Punctuator(Punctuator::CloseParen)) => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            Some(_) => {}
+                            None => panic!("Unterminated attribute arguments"),
+                        }
+                    }
+                }
+                match tokens.next() {
+                    Some(Token::Punctuator(Punctuator::Comma)) => {}
+                    Some(Token::Punctuator(Punctuator::CloseParen)) => break,
+                    e => panic!("Expected ',' or ')' in attribute list, found {:?}", e),
+                }
+            }
+            // __attribute__(()) 空的属性列表
+            Some(Token::Punctuator(Punctuator::CloseParen)) => break,
+            e => panic!("Expected attribute name, found {:?}", e),
+        }
+    }
+
+    if tokens.next() != Some(&Token::Punctuator(Punctuator::CloseParen)) {
+        panic!("Expected '))' after attribute list");
+    }
+    names
+}
+
+/*
+ * 是否是类型说明符的开头
+*/
+fn is_type(token: &Token) -> bool {
+    matches!(token,
+        Token::Keyword(Keyword::Int)
+        | Token::Keyword(Keyword::Float)
+        | Token::Keyword(Keyword::Double)
+        | Token::Keyword(Keyword::Long)
+        | Token::Keyword(Keyword::Char)
+        | Token::Keyword(Keyword::Void)
+        | Token::Keyword(Keyword::Const)
+        | Token::Keyword(Keyword::VaList))
+}
+
+/*
+ * 类型
+ * 基本类型后面可以跟若干个 * 表示指针
+ * const 直接忽略
+*/
+fn parser_type(tokens: &mut PeekableNth<Iter<Token>>) -> Type {
+    let mut base = parser_base_type(tokens);
+
+    loop {
+        match tokens.peek() {
+            Some(Token::Operator(Operator::Multiplication)) => { // *
+                tokens.next();
+                base = base.pointer_to();
+            }
+            Some(Token::Keyword(Keyword::Const)) => {
+                tokens.next();
+            }
+            _ => break,
+        }
+    }
+
+    base
+}
+
+/*
+ * 类型说明符
+ * int float double long long int long double char void va_list
+ * long 按 int 处理，整数本来就放在64位寄存器里
+*/
+fn parser_base_type(tokens: &mut PeekableNth<Iter<Token>>) -> Type {
+    while let Some(Token::Keyword(Keyword::Const)) = tokens.peek() {
+        tokens.next();
+    }
+
+    match tokens.next() {
+        Some(Token::Keyword(Keyword::Char)) => Type::Char,
+        Some(Token::Keyword(Keyword::Void)) => Type::Void,
+        Some(Token::Keyword(Keyword::VaList)) => Type::VaList,
+        Some(Token::Keyword(Keyword::Int)) => Type::Int,
+        Some(Token::Keyword(Keyword::Float)) => Type::Float,
+        Some(Token::Keyword(Keyword::Double)) => Type::Double,
+        Some(Token::Keyword(Keyword::Long)) => match tokens.peek() {
+            Some(Token::Keyword(Keyword::Double)) => {
+                tokens.next();
+                Type::LongDouble
+            }
+            Some(Token::Keyword(Keyword::Int)) => {
+                tokens.next();
+                Type::Int
+            }
+            _ => Type::Int,
+        },
+        _ => panic!("Expected type"),
+    }
+}
+
 /*
  * 获取函数参数
- * 返回值为以函数参数的名称的数组
+ * 返回值为函数参数的类型和名称的数组，是否以 ... 结尾，是否是原型
+ * 只有 () 的声明不是原型
 */
-fn parser_function_parameters(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<String> {
+fn parser_function_parameters(tokens: &mut PeekableNth<Iter<Token>>) -> (Vec<(Type, String)>, bool, bool) {
     let mut params = Vec::new();
+    let void_params = tokens.peek_nth(0) == Some(&&Token::Keyword(Keyword::Void))
+        && tokens.peek_nth(1) == Some(&&Token::Punctuator(Punctuator::CloseParen));
 
     match tokens.peek() {
         // 如果没有参数
         Some(Token::Punctuator(Punctuator::CloseParen)) => { // )
             tokens.next();
-            return params;
+            return (params, false, false);
+        },
+        // (void) 也是没有参数
+        Some(Token::Keyword(Keyword::Void)) if void_params => {
+            tokens.next();
+            tokens.next();
+            return (params, false, true);
         },
         // 如果有参数
         Some(_) => {
@@ -109,6 +263,14 @@ fn parser_function_parameters(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<Stri
                 match tokens.next() {
                     // 没有多余的参数了
                     Some(Token::Punctuator(Punctuator::CloseParen)) => break,
+                    // 可变参数 ... 必须是最后一个
+                    Some(Token::Punctuator(Punctuator::Comma)) if tokens.peek() == Some(&&Token::Punctuator(Punctuator::Ellipsis)) => {
+                        tokens.next();
+                        match tokens.next() {
+                            Some(Token::Punctuator(Punctuator::CloseParen)) => return (params, true, true),
+                            _ => panic!("Expected closing parenthesis after ..."),
+                        }
+                    }
                     // 有多余的参数
                     Some(Token::Punctuator(Punctuator::Comma)) => { // ,
                         let param = parser_next_parameter(tokens);
@@ -123,22 +285,33 @@ fn parser_function_parameters(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<Stri
         None => panic!("Expected closing parenthesis"),
     }
     // 返回函数参数的字符串
-    params
+    (params, false, true)
 }
 
 /*
  * 多个参数的处理
- * 返回值是函数参数名称
+ * 返回值是函数参数类型和名称
+ * 原型中参数名可以省略，此时名称为空
 */
-fn parser_next_parameter(tokens: &mut PeekableNth<Iter<Token>>) -> String {
-    match tokens.next() {
-        Some(Token::Keyword(Keyword::Int)) => match tokens.next() {
-            Some(Token::Identifier(id)) => id.clone(),
-            // 如果函数int后面没有参数 或者不是参数的时候
-            _ => panic!("Expected identifiter for function paramter"),
+fn parser_next_parameter(tokens: &mut PeekableNth<Iter<Token>>) -> (Type, String) {
+    match tokens.peek() {
+        Some(token) if is_type(token) => {
+            let param_type = match parser_type(tokens) {
+                // va_list 参数实际传的是指针
+                Type::VaList => Type::VaList.pointer_to(),
+                Type::Void => panic!("Parameter has void type"),
+                t => t,
+            };
+            match tokens.peek() {
+                Some(Token::Identifier(id)) => {
+                    tokens.next();
+                    (param_type, id.clone())
+                }
+                _ => (param_type, String::new()),
+            }
         },
-        // 如果函数参数不是以int开头
-        _ => panic!("Expected int keyword for function paramter"),
+        // 如果函数参数不是以类型开头
+        _ => panic!("Expected type for function paramter"),
     }
 }
 
@@ -147,11 +320,11 @@ fn parser_next_parameter(tokens: &mut PeekableNth<Iter<Token>>) -> String {
  * 遍历到 } 退出 
  * 返回每一条语句的集合
 */
-fn parser_items(tokens:&mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Vec<Item> {
+fn parser_items(tokens:&mut PeekableNth<Iter<Token>>) -> Vec<Item> {
     let mut block = Vec::new();
     
     while tokens.peek() != Some(&&Token::Punctuator(Punctuator::CloseBrace)) { // 遍历到 }
-        block.push(parser_items_item(tokens, ast_map));
+        block.push(parser_items_item(tokens));
     }
 
     match tokens.next() {
@@ -165,47 +338,39 @@ fn parser_items(tokens:&mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Vec<I
  * 确认是表达式还是声明
  * 返回这一段语句的item
 */
-fn parser_items_item(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Item {
-    let item: Item;
-    
+fn parser_items_item(tokens: &mut PeekableNth<Iter<Token>>) -> Item {
     match tokens.peek() {
-        Some(Token::Keyword(Keyword::Int)) => {
+        Some(token) if is_type(token) => {
             // 声明
-            item = Item::Declaration(parser_declaration(tokens, ast_map));
+            Item::Declaration(parser_declaration(tokens))
         },
         Some(_) => {
             // 表达式
-            item = Item::Statement(parser_statement(tokens, ast_map));
+            Item::Statement(parser_statement(tokens))
         },
         None => panic!("Expected block"),
     }
-
-    item
 }
 
 /*
  * int ... = expression;
  * 解析声明
 */
-fn parser_declaration(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Declaration {
-    let declaration: Declaration;
-    match tokens.next() {
-        Some(Token::Keyword(Keyword::Int)) => match tokens.next() { // int
-            Some(Token::Identifier(id)) => { // ...
-                if let Some(&&Token::Operator(Operator::Assignment)) = tokens.peek() { // =
-                    tokens.next();
-                    declaration = Declaration::Declaration (id.clone(), Some(parser_expression(tokens, ast_map)), );
-                } else {
-                    // 声明不定义: int i;
-                    declaration = Declaration::Declaration(id.clone(), None, );
-                }
+fn parser_declaration(tokens: &mut PeekableNth<Iter<Token>>) -> Declaration {
+    let var_type = parser_type(tokens); // int double ...
+    let declaration = match tokens.next() {
+        Some(Token::Identifier(id)) => { // ...
+            if let Some(&&Token::Operator(Operator::Assignment)) = tokens.peek() { // =
+                tokens.next();
+                Declaration::Declaration(var_type, id.clone(), Some(parser_expression(tokens)))
+            } else {
+                // 声明不定义: int i;
+                Declaration::Declaration(var_type, id.clone(), None)
             }
-            // 只有int，后面没有变量名
-            _ => panic!("Expected identifier"),
         }
-        // 我认为这个错误从来不会出现
-        _ => panic!("Expected int keyword"),
-    }
+        // 只有类型，后面没有变量名
+        _ => panic!("Expected identifier"),
+    };
 
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::Semicolon)) => declaration,
@@ -216,47 +381,62 @@ fn parser_declaration(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -
 /*
  * 解析表达式
 */
-fn parser_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Statement {
-    let statement: Statement;
-
-    match tokens.peek() {
+fn parser_statement(tokens: &mut PeekableNth<Iter<Token>>) -> Statement {
+    let statement = match tokens.peek() {
         Some(Token::Keyword(Keyword::Return)) => { // return expersion;
             tokens.next();
-            statement = Statement::Return(parser_expression(tokens, ast_map));
+            Statement::Return(parser_optional_expression(tokens, Punctuator::Semicolon))
+        }
+        // 语句的属性，只认识 __attribute__((musttail)) return f(x);
+        Some(Token::Keyword(Keyword::Attribute)) => {
+            tokens.next();
+            let mut musttail = false;
+            for name in parser_attributes(tokens) {
+                match name.as_str() {
+                    "musttail" => musttail = true,
+                    _ => eprintln!("warning: '{}' attribute ignored", name),
+                }
+            }
+            let statement = parser_statement(tokens);
+            return match statement {
+                Statement::Return(Some(expr)) if musttail => Statement::TailReturn(expr),
+                _ if musttail => panic!("musttail attribute can only be applied to a return statement with a value"),
+                statement => statement,
+            };
         }
         Some(Token::Keyword(Keyword::If)) => { // if 
             tokens.next();
-            return parser_if_statement(tokens, ast_map);
+            return parser_if_statement(tokens);
         }
         Some(Token::Keyword(Keyword::For)) => { // for
             tokens.next();
-            return parser_for_statement(tokens, ast_map);
+            return parser_for_statement(tokens);
         }
         Some(Token::Keyword(Keyword::While)) => { // while
             tokens.next();
-            return parser_while_statement(tokens, ast_map);
+            return parser_while_statement(tokens);
         }
         Some(Token::Keyword(Keyword::Do)) =>{ // do while
             tokens.next();
-            statement = parser_do_statement(tokens, ast_map);
+            parser_do_statement(tokens)
         }
         Some(Token::Keyword(Keyword::Break)) => { // break;
             tokens.next();
-            statement = Statement::Break;
+            Statement::Break
         }
         Some(Token::Keyword(Keyword::Continue)) => { // continue;
             tokens.next();
-            statement = Statement::Continue;
+            Statement::Continue
         }
         // 一个新的块
         Some(Token::Punctuator(Punctuator::OpenBrace)) => { // { 
             tokens.next();
-            return Statement::Compound(parser_items(tokens, ast_map));
+            return Statement::Compound(parser_items(tokens));
         }
         _ => {
-            statement = Statement::Expression(parser_optional_expression(tokens, Punctuator::Semicolon, ast_map));
+            Statement::Expression(parser_optional_expression(tokens, Punctuator::Semicolon))
         }
-    }
+    };
     
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::Semicolon)) => statement, // ;
@@ -272,19 +452,19 @@ fn parser_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) ->
  *      else_statement
  * }
 */
-fn parser_if_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Statement {
+fn parser_if_statement(tokens: &mut PeekableNth<Iter<Token>>) -> Statement {
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
-            let expression = parser_expression(tokens, ast_map);
+            let expression = parser_expression(tokens);
             match tokens.next() {
                 Some(Token::Punctuator(Punctuator::CloseParen)) => { // )
                     // if 中的表达式
-                    let if_statement = parser_statement(tokens, ast_map);
+                    let if_statement = parser_statement(tokens);
                     match tokens.peek() {
                         // 有else
                         Some(Token::Keyword(Keyword::Else)) => {
                             tokens.next();
-                            let else_statement = parser_statement(tokens, ast_map);
+                            let else_statement = parser_statement(tokens);
                             Statement::If(expression, Box::new(if_statement), Some(Box::new(else_statement)))
                         }
                         // 无else
@@ -306,18 +486,18 @@ fn parser_if_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap)
  * }
  * 这个函数主要是区分第一个参数是声明还是表达式
 */
-fn parser_for_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Statement {
+fn parser_for_statement(tokens: &mut PeekableNth<Iter<Token>>) -> Statement {
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::OpenParen)) => match tokens.peek() { // (
             // 声明的for循环
-            Some(Token::Keyword(Keyword::Int)) => {
-                let init = parser_declaration(tokens, ast_map);
-                let (condition, modifier, body) = parser_for_components(tokens, ast_map);
+            Some(token) if is_type(token) => {
+                let init = parser_declaration(tokens);
+                let (condition, modifier, body) = parser_for_components(tokens);
                 Statement::ForDeclaration(init, condition, modifier, Box::new(body))
             }
             // 表达式的for循环
             _ => {
-                let init = parser_optional_expression(tokens, Punctuator::Semicolon, ast_map);
+                let init = parser_optional_expression(tokens, Punctuator::Semicolon);
                 if let Some(Token::Punctuator(Punctuator::Semicolon)) = tokens.peek() {
                     tokens.next();
                 } else {
@@ -325,7 +505,7 @@ fn parser_for_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap)
                     panic!("Expected semicolon after initializer");
                 }
 
-                let (condition, modifier, body) = parser_for_components(tokens, ast_map);
+                let (condition, modifier, body) = parser_for_components(tokens);
                 Statement::For(init, condition, modifier, Box::new(body))
             }
         },
@@ -337,9 +517,9 @@ fn parser_for_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap)
 /*
  * 这个函数是处理for循环中的第二个参数，第三个参数和中间的表达式，
 */
-fn parser_for_components(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> (Expression, Option<Expression>, Statement) {
+fn parser_for_components(tokens: &mut PeekableNth<Iter<Token>>) -> (Expression, Option<Expression>, Statement) {
     // 分析第二个参数， 有可能参数是以 ， 分隔的表达式
-    let condition = match parser_optional_expression(tokens, Punctuator::Semicolon, ast_map) {
+    let condition = match parser_optional_expression(tokens, Punctuator::Semicolon) {
         Some(expr) => expr,
         None => Expression::Constant(1),
     };
@@ -350,11 +530,11 @@ fn parser_for_components(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::Semicolon)) => {
             // 分析第三个参数
-            modifier = parser_optional_expression(tokens, Punctuator::Semicolon, ast_map);
+            modifier = parser_optional_expression(tokens, Punctuator::Semicolon);
             match tokens.next() {
                 // 分析表达式
                 Some(Token::Punctuator(Punctuator::CloseParen)) => {
-                    body = parser_statement(tokens, ast_map);
+                    body = parser_statement(tokens);
                 },
                 // 语法错误  没有以 ） 结尾
                 _ => panic!("Expected close parenthesis"),
@@ -372,13 +552,13 @@ fn parser_for_components(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap
  *      body
  * }
 */
-fn parser_while_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Statement {
+fn parser_while_statement(tokens: &mut PeekableNth<Iter<Token>>) -> Statement {
     match tokens.next() {
         Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
-            let expression = parser_expression(tokens, ast_map);
+            let expression = parser_expression(tokens);
             match tokens.next() {
                 Some(Token::Punctuator(Punctuator::CloseParen)) => { // )
-                    let body = parser_statement(tokens, ast_map);
+                    let body = parser_statement(tokens);
                     Statement::While(expression, Box::new(body))
                 }
                 _ => panic!("Expected close parenthseis"),
@@ -393,12 +573,12 @@ fn parser_while_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMa
  *  body
  * } while (exxpression)
 */
-fn parser_do_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Statement {
-    let body = parser_statement(tokens, ast_map);
+fn parser_do_statement(tokens: &mut PeekableNth<Iter<Token>>) -> Statement {
+    let body = parser_statement(tokens);
     match tokens.next() {
         Some(Token::Keyword(Keyword::While)) => match tokens.next() { // while
             Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
-                let expression = parser_expression(tokens, ast_map);
+                let expression = parser_expression(tokens);
                 match tokens.next() {
                     Some(Token::Punctuator(Punctuator::CloseParen)) => { // )
                         Statement::DoWhile(expression, Box::new(body))
@@ -418,10 +598,10 @@ fn parser_do_statement(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap)
 /*
  * 以expected为分割符返回表达式
 */
-fn parser_optional_expression(tokens: &mut PeekableNth<Iter<Token>>, expected: Punctuator, ast_map: &AstMap) -> Option<Expression> {
+fn parser_optional_expression(tokens: &mut PeekableNth<Iter<Token>>, expected: Punctuator) -> Option<Expression> {
     match tokens.peek() {
         Some(Token::Punctuator(t)) if t == &expected => None,
-        _ => Some(parser_expression(tokens, ast_map)),
+        _ => Some(parser_expression(tokens)),
     }
 }
 
@@ -433,22 +613,17 @@ fn parser_optional_expression(tokens: &mut PeekableNth<Iter<Token>>, expected: P
  * 如果中间的符号是 =操作符
  * 优先级14
 */
-fn parser_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap, ) -> Expression {
+fn parser_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let expression = parser_conditional_expression(tokens);
+
     match tokens.peek() {
-        Some(Token::Identifier(id)) => {
-            match tokens.peek_nth(1) {
-                // 有id 确定是二元运算符 => id  op  expression
-               Some(Token::Operator(op)) if op.is_assignment_operators() => { // op 为 赋值操作符
-                   tokens.next();
-                   tokens.next();
-                   Expression::AssignmentOperators(*op, id.clone(), Box::new(parser_expression(tokens, ast_map)))
-               }
-               // 其他情况
-               _ => parser_conditional_expression(tokens, ast_map),
-            }
+        // expression op expression，左边是否是左值由 sema 检查
+        Some(Token::Operator(op)) if op.is_assignment_operators() => { // op 为 赋值操作符
+            tokens.next();
+            Expression::AssignmentOperators(*op, Box::new(expression), Box::new(parser_expression(tokens)))
         }
         // 其他情况
-        _ => parser_conditional_expression(tokens, ast_map),
+        _ => expression,
     }
 }
 
@@ -458,15 +633,15 @@ fn parser_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap, )
  * 如果中间的符号是 ?:
  * 优先级13
 */
-fn parser_conditional_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut expression = parser_logical_or_expression(tokens, ast_map);
+fn parser_conditional_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut expression = parser_logical_or_expression(tokens);
 
     while let Some(Token::Punctuator(Punctuator::QuestionMark)) = tokens.peek() {
         tokens.next();
-        let true_expression = parser_expression(tokens, ast_map);
+        let true_expression = parser_expression(tokens);
         match tokens.next() {
             Some(Token::Punctuator(Punctuator::Colon)) => {
-                let false_expression = parser_expression(tokens, ast_map);
+                let false_expression = parser_expression(tokens);
                 expression = Expression::TernaryOperators(Box::new(expression), Box::new(true_expression), Box::new(false_expression));
             }
             // 不符合三目运算符
@@ -483,14 +658,14 @@ fn parser_conditional_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map:
  * 如果中间的符号是 ||
  * 优先级12
 */
-fn parser_logical_or_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut expression = parser_logical_and_expression(tokens, ast_map);
+fn parser_logical_or_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut expression = parser_logical_and_expression(tokens);
 
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::LogicalOr => { // ||
                 tokens.next();
-                let next_expression = parser_expression(tokens, ast_map);
+                let next_expression = parser_logical_and_expression(tokens);
                 expression = Expression::BinaryOperators(*op, Box::new(expression), Box::new(next_expression))
             }
             _ => break,
@@ -506,14 +681,14 @@ fn parser_logical_or_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map:
  * 如果中间的符号是 &&
  * 优先级11
 */
-fn parser_logical_and_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut expression = parser_equality_expression(tokens, ast_map);
+fn parser_logical_and_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut expression = parser_bitwise_or_expression(tokens);
     
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::LogicalAnd => { // &&
                 tokens.next();
-                let next_expression = parser_expression(tokens, ast_map);
+                let next_expression = parser_bitwise_or_expression(tokens);
                 expression = Expression::BinaryOperators(*op, Box::new(expression), Box::new(next_expression))
             }
             _ => break,
@@ -523,20 +698,74 @@ fn parser_logical_and_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map:
     expression
 }
 
+/*
+ * 处理
+ * expression _ expression
+ * 如果中间的符号是 |
+ * 优先级10
+*/
+fn parser_bitwise_or_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_bitwise_xor_expression(tokens);
+
+    while let Some(Token::Operator(Operator::BitwiseOr)) = tokens.peek() { // |
+        tokens.next();
+        let next_term = parser_bitwise_xor_expression(tokens);
+        term = Expression::BinaryOperators(Operator::BitwiseOr, Box::new(term), Box::new(next_term));
+    }
+
+    term
+}
+
+/*
+ * 处理
+ * expression _ expression
+ * 如果中间的符号是 ^
+ * 优先级9
+*/
+fn parser_bitwise_xor_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_bitwise_and_expression(tokens);
+
+    while let Some(Token::Operator(Operator::BitwiseXor)) = tokens.peek() { // ^
+        tokens.next();
+        let next_term = parser_bitwise_and_expression(tokens);
+        term = Expression::BinaryOperators(Operator::BitwiseXor, Box::new(term), Box::new(next_term));
+    }
+
+    term
+}
+
+/*
+ * 处理
+ * expression _ expression
+ * 如果中间的符号是 &
+ * 优先级8
+*/
+fn parser_bitwise_and_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_equality_expression(tokens);
+
+    while let Some(Token::Operator(Operator::BitwiseAnd)) = tokens.peek() { // &
+        tokens.next();
+        let next_term = parser_equality_expression(tokens);
+        term = Expression::BinaryOperators(Operator::BitwiseAnd, Box::new(term), Box::new(next_term));
+    }
+
+    term
+}
+
 /*
  * 处理
  * expression _ expression
  * 如果中间的符号是 == != 
  * 优先级7
 */
-fn parser_equality_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut term = parser_relational_expression(tokens, ast_map);
+fn parser_equality_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_relational_expression(tokens);
 
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::Equal || op == &Operator::NotEqual => { // == !=
                 tokens.next();
-                let next_trem = parser_expression(tokens, ast_map);
+                let next_trem = parser_relational_expression(tokens);
                 term = Expression::BinaryOperators(*op, Box::new(term), Box::new(next_trem))
             }
             _ => break,
@@ -552,14 +781,14 @@ fn parser_equality_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &A
  * 如果中间的符号是 > >= < <=
  * 优先级6
 */
-fn parser_relational_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut term = parser_bitwise_expression(tokens, ast_map);
+fn parser_relational_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_shift_expression(tokens);
 
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::LessThan || op == &Operator::LessThanOrEqual || op == &Operator::GreaterThan || op == &Operator::GreaterThanOrEqual => { // 比较运算符
                 tokens.next();
-                let next_term = parser_expression(tokens, ast_map);
+                let next_term = parser_shift_expression(tokens);
                 term = Expression::BinaryOperators(*op, Box::new(term), Box::new(next_term));
             }
             _ => break,
@@ -572,17 +801,17 @@ fn parser_relational_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map:
 /*
  * 处理
  * expression _ expression
- * 如果中间的符号是 & | << >> ^
+ * 如果中间的符号是 << >>
  * 优先级5
 */
-fn parser_bitwise_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut term = parser_additive_expression(tokens, ast_map);
+fn parser_shift_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_additive_expression(tokens);
 
     loop {
         match tokens.peek() {
-            Some(Token::Operator(op)) if op.is_bitwise_operators() => { // 是位运算
+            Some(Token::Operator(op)) if op == &Operator::BitwiseShiftLeft || op == &Operator::BitwiseShiftRight => { // << >>
                 tokens.next();
-                let next_term = parser_expression(tokens, ast_map);
+                let next_term = parser_additive_expression(tokens);
                 term = Expression::BinaryOperators(*op, Box::new(term), Box::new(next_term));
             }
             _ => break,
@@ -598,14 +827,14 @@ fn parser_bitwise_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &As
  * 如果中间的符号是 + -
  * 优先级4
 */
-fn parser_additive_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut term = parser_term(tokens, ast_map);
+fn parser_additive_expression(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut term = parser_term(tokens);
 
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::Plus || op == &Operator::Minus => {
                 tokens.next();
-                let next_term = parser_term(tokens, ast_map);
+                let next_term = parser_term(tokens);
                 term = Expression::BinaryOperators(*op, Box::new(term), Box::new(next_term));
             }
             _ => break,
@@ -621,14 +850,14 @@ fn parser_additive_expression(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &A
  * 如果中间的符号是* / %
  * 优先级3
 */
-fn parser_term(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression {
-    let mut factor = parser_factor(tokens, ast_map);
+fn parser_term(tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    let mut factor = parser_factor(tokens);
 
     loop {
         match tokens.peek() {
             Some(Token::Operator(op)) if op == &Operator::Multiplication || op == &Operator::Division || op == &Operator::Modulo => { // * / % 同一优先级
                 tokens.next();
-                let next_factor = parser_factor(tokens, ast_map);
+                let next_factor = parser_factor(tokens);
                 factor = Expression::BinaryOperators(*op, Box::new(factor), Box::new(next_factor));
             }
             _ => break,
@@ -642,12 +871,23 @@ fn parser_term(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expre
  * 处理了函数调用，一元运算符和常量
  * 优先级2
 */
-fn parser_factor(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Expression{
+fn parser_factor(tokens: &mut PeekableNth<Iter<Token>>) -> Expression{
     match tokens.next() {
+        // 类型转换 (double)a
+        Some(Token::Punctuator(Punctuator::OpenParen)) if tokens.peek().is_some_and(|t| is_type(t)) => { // (
+            let cast_type = parser_type(tokens);
+            if let Some(Token::Punctuator(Punctuator::CloseParen)) = tokens.next() { // )
+                Expression::Cast(cast_type, Box::new(parser_factor(tokens)))
+            } else {
+                // 语法错误，没有反括号
+                panic!("Expected closing parnthseis after type");
+            }
+        }
+
         Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
-            let expression = parser_expression(tokens, ast_map);
+            let expression = parser_expression(tokens);
             if let Some(Token::Punctuator(Punctuator::CloseParen)) = tokens.next() { // )
-                return expression;
+                expression
             } else {
                 // 语法错误，没有反括号
                 panic!("Expected closing parnthseis");
@@ -656,31 +896,38 @@ fn parser_factor(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Exp
 
         // -(expression) ... 例如 -（a - b）
         Some(Token::Operator(op)) if op.is_unary() => { // - ~ ! 
-            let factor = parser_factor(tokens, ast_map);
+            let factor = parser_factor(tokens);
             Expression::UnaryOperators(*op, Box::new(factor))
         }
 
         // 32
         Some(Token::Constant(c)) => Expression::Constant(*c),
 
+        // 1.5 1.5f 1.5L
+        Some(Token::FloatConstant(f, t)) => Expression::FloatConstant(f.clone(), t.clone()),
+
+        // "hello" 相邻的字符串拼接起来
+        Some(Token::StringLiteral(s)) => {
+            let mut s = s.clone();
+            while let Some(Token::StringLiteral(next)) = tokens.peek() {
+                s.extend_from_slice(next);
+                tokens.next();
+            }
+            Expression::StringLiteral(s)
+        }
+
+        // <stdarg.h> 中的宏
+        Some(Token::Identifier(id)) if is_va_builtin(id) => {
+            parser_va_builtin(id.trim_start_matches("__builtin_"), tokens)
+        }
+
         // 函数调用 + a；
         Some(Token::Identifier(id)) => match tokens.peek() {
             // 函数调用
             Some(Token::Punctuator(Punctuator::OpenParen)) => { // (
                 tokens.next();
-                let args = parser_function_call(tokens, ast_map);
-                if let Some(&(expected_nargs, _)) = ast_map.get(id) {
-                    if args.len() == expected_nargs {
-                        // 函数参数数量和输入数量一样
-                        Expression::FunctionCalls(id.clone(), args)
-                    } else {
-                        // 不一样
-                        panic!("Wrong number of arguments");
-                    }
-                } else {
-                    // 函数未定义
-                    panic!("Undeclared function: {}", id);
-                }
+                let args = parser_function_call(tokens);
+                Expression::FunctionCalls(id.clone(), args)
             }
             // a;
             _ => Expression::Variable(id.clone()),
@@ -690,11 +937,55 @@ fn parser_factor(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Exp
     }
 }
 
+fn is_va_builtin(id: &str) -> bool {
+    matches!(id.trim_start_matches("__builtin_"), "va_start" | "va_arg" | "va_end" | "va_copy")
+}
+
+/*
+ * va_start(ap, last)
+ * va_arg(ap, type)
+ * va_end(ap)
+ * va_copy(dest, src)
+*/
+fn parser_va_builtin(name: &str, tokens: &mut PeekableNth<Iter<Token>>) -> Expression {
+    match tokens.next() {
+        Some(Token::Punctuator(Punctuator::OpenParen)) => {}
+        _ => panic!("Expected opening parenthesis after {}", name),
+    }
+
+    let ap = Box::new(parser_expression(tokens));
+    let expression = match name {
+        "va_start" => {
+            // 最后一个命名参数只用来检查语法
+            match (tokens.next(), tokens.next()) {
+                (Some(Token::Punctuator(Punctuator::Comma)), Some(Token::Identifier(_))) => {}
+                _ => panic!("Expected last named parameter in va_start"),
+            }
+            Expression::VaStart(ap)
+        }
+        "va_arg" => match tokens.next() {
+            Some(Token::Punctuator(Punctuator::Comma)) => Expression::VaArg(ap, parser_type(tokens)),
+            _ => panic!("Expected type in va_arg"),
+        },
+        "va_end" => Expression::VaEnd(ap),
+        "va_copy" => match tokens.next() {
+            Some(Token::Punctuator(Punctuator::Comma)) => Expression::VaCopy(ap, Box::new(parser_expression(tokens))),
+            _ => panic!("Expected source in va_copy"),
+        },
+        _ => panic!("Unknown builtin {}", name),
+    };
+
+    match tokens.next() {
+        Some(Token::Punctuator(Punctuator::CloseParen)) => expression,
+        _ => panic!("Expected closing parenthesis after {}", name),
+    }
+}
+
 /*
  * 处理函数调用表达式
  * 优先级1 ： （）
 */
-fn parser_function_call(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap) -> Vec<Expression> {
+fn parser_function_call(tokens: &mut PeekableNth<Iter<Token>>) -> Vec<Expression> {
     let mut args = Vec::new();
     match tokens.peek() {
         Some(Token::Punctuator(Punctuator::CloseParen)) => { // ) 表示使用无参数方法
@@ -702,14 +993,14 @@ fn parser_function_call(tokens: &mut PeekableNth<Iter<Token>>, ast_map: &AstMap)
             return args;
         }
         Some(_) => {
-            let arg = parser_expression(tokens, ast_map); // 函数参数可能是新的表达式
+            let arg = parser_expression(tokens); // 函数参数可能是新的表达式
             args.push(arg);
 
             loop {
                 match tokens.next() {
                     Some(Token::Punctuator(Punctuator::CloseParen)) => break,
                     Some(Token::Punctuator(Punctuator::Comma)) => { //  ， 多个参数
-                        let arg = parser_expression(tokens, ast_map);
+                        let arg = parser_expression(tokens);
                         args.push(arg);
                     }
                     _ => panic!("Unexpected token in function argument"),
@@ -0,0 +1,130 @@
+use super::asm::{Instr, Operand};
+
+/*
+ * 窥孔优化
+ * 在一个函数的指令序列上反复应用下面的规则，直到不再变化
+ *
+ * push a; pop b          -> mov b, a
+ * mov a, a               -> 删除
+ * mov a, b; mov b, a     -> 删除第二条
+ * mov a, b; mov a, b     -> 删除第二条
+ * 连续两条相同的 movsx/movzx (扩展寄存器自己的低位) -> 删除第二条
+ * jmp L; L:              -> 删除跳转，jcc 也一样
+ * jcc A; jmp B; A:       -> jncc B; A:
+ * ret jmp 之后到下一个标签之前的指令不会被执行 -> 删除
+*/
+pub fn optimize(mut code: Vec<Instr>) -> Vec<Instr> {
+    loop {
+        let (next, changed) = run(&code);
+        code = next;
+        if !changed {
+            return code;
+        }
+    }
+}
+
+// 从 i 开始的连续标签中是否有 label
+fn falls_into(code: &[Instr], i: usize, label: &str) -> bool {
+    code[i..].iter().map_while(|instr| match instr {
+        Instr::Label(l) => Some(l),
+        _ => None,
+    }).any(|l| l == label)
+}
+
+/*
+ * 第二条 mov 是否多余
+ * 第一条之后 a 和 b 已经相等，除非第一条改变了 b 的地址
+*/
+fn redundant_move(first: &Instr, second: &Instr) -> bool {
+    let Instr::Mov(a, b) = first else { return false };
+    let Instr::Mov(c, d) = second else { return false };
+    let same = (c == b && d == a) || (c == a && d == b);
+    let address_changed = match a {
+        Operand::Reg(r, _) => b.is_memory() && b.uses(*r),
+        _ => false,
+    };
+    same && !address_changed
+}
+
+// 扩展寄存器自己的低位，再做一次结果不变
+fn idempotent_extend(first: &Instr, second: &Instr) -> bool {
+    match second {
+        Instr::Movsx(Operand::Reg(a, _), Operand::Reg(b, _)) | Instr::Movzx(Operand::Reg(a, _), Operand::Reg(b, _)) => a == b && first == second,
+        _ => false,
+    }
+}
+
+fn run(code: &[Instr]) -> (Vec<Instr>, bool) {
+    let mut out: Vec<Instr> = Vec::with_capacity(code.len());
+    let mut changed = false;
+    let mut unreachable = false;
+
+    let mut i = 0;
+    while i < code.len() {
+        let instr = &code[i];
+        i += 1;
+
+        // 标签和伪指令之后又可以到达
+        if matches!(instr, Instr::Label(_) | Instr::Directive(_)) {
+            unreachable = false;
+        } else if unreachable {
+            changed = true;
+            continue;
+        }
+
+        match instr {
+            Instr::Pop(b) => {
+                if let Some(Instr::Push(a)) = out.last() {
+                    if !(a.is_memory() && b.is_memory()) {
+                        let a = a.clone();
+                        out.pop();
+                        if a != *b {
+                            out.push(Instr::Mov(b.clone(), a));
+                        }
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+
+            Instr::Mov(a, b) if a == b => {
+                changed = true;
+                continue;
+            }
+
+            Instr::Mov(_, _) | Instr::Movsx(_, _) | Instr::Movzx(_, _) => {
+                if let Some(prev) = out.last() {
+                    if redundant_move(prev, instr) || idempotent_extend(prev, instr) {
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+
+            Instr::Jmp(label) | Instr::Jcc(_, label) if falls_into(code, i, label) => {
+                changed = true;
+                continue;
+            }
+
+            Instr::Jcc(cond, target) => {
+                if let Some(Instr::Jmp(other)) = code.get(i) {
+                    if falls_into(code, i + 1, target) {
+                        out.push(Instr::Jcc(cond.negate(), other.clone()));
+                        i += 1;
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        if matches!(instr, Instr::Jmp(_) | Instr::Ret) {
+            unreachable = true;
+        }
+        out.push(instr.clone());
+    }
+
+    (out, changed)
+}
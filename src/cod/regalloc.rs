@@ -0,0 +1,244 @@
+use super::asm::Reg;
+use super::ir::cfg::predecessors;
+use super::ir::*;
+
+/*
+ * 寄存器分配 (线性扫描)
+ * Poletto, Sarkar: Linear Scan Register Allocation
+ *
+ * 按生成代码的顺序给每条指令编号，每个整数值的活跃区间是一段 [开始, 结束]
+ * 区间按开始的位置排序，依次分配空闲的寄存器，没有空闲的寄存器时溢出结束得最晚的区间
+ * 溢出的值和浮点数都放在栈帧中
+ *
+ * rax rcx rdx rdi 是生成代码时的临时寄存器，参数寄存器在调用时使用，都不参与分配
+ * 跨过函数调用的值只能用被调用者保存的寄存器
+*/
+
+// 被调用者保存的寄存器，用到的要在序言中保存
+pub const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+// 调用者保存的寄存器，调用之后就没有了
+pub const CALLER_SAVED: [Reg; 2] = [Reg::R10, Reg::R11];
+
+/*
+ * 活跃区间
+ * 值
+ * 开始和结束的位置
+ * 是否跨过了函数调用
+*/
+#[derive(Debug, Clone)]
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+/*
+ * 每个值分到的寄存器，None 表示放在栈帧中
+*/
+pub fn allocate(function: &Function) -> Vec<Option<Reg>> {
+    let mut intervals = live_intervals(function);
+    intervals.sort_by_key(|i| (i.start, i.value));
+
+    let mut registers: Vec<Option<Reg>> = vec![None; function.values.len()];
+    let mut active: Vec<Interval> = Vec::new();
+    let mut free: Vec<Reg> = CALLER_SAVED.iter().chain(CALLEE_SAVED.iter()).rev().copied().collect();
+
+    for interval in intervals {
+        // 已经结束的区间释放寄存器
+        active.retain(|a| {
+            if a.end < interval.start {
+                free.push(registers[a.value.0].expect("Active interval without register"));
+                false
+            } else {
+                true
+            }
+        });
+
+        let allowed = |r: &Reg| !interval.crosses_call || CALLEE_SAVED.contains(r);
+
+        // 优先使用调用者保存的寄存器，不用在序言中保存
+        let choice = CALLER_SAVED.iter().chain(CALLEE_SAVED.iter()).find(|r| allowed(r) && free.contains(r)).copied();
+        if let Some(r) = choice {
+            free.retain(|f| *f != r);
+            registers[interval.value.0] = Some(r);
+            active.push(interval);
+            continue;
+        }
+
+        // 没有空闲的寄存器，溢出结束得最晚的区间
+        let victim = active.iter().enumerate()
+            .filter(|(_, a)| allowed(&registers[a.value.0].expect("Active interval without register")))
+            .max_by_key(|(_, a)| a.end)
+            .map(|(i, a)| (i, a.end));
+        match victim {
+            Some((i, end)) if end > interval.end => {
+                let spilled = active.remove(i);
+                registers[interval.value.0] = registers[spilled.value.0].take();
+                active.push(interval);
+            }
+            _ => {}
+        }
+    }
+
+    registers
+}
+
+// 放在通用寄存器中的值
+fn is_integer(ty: Ty) -> bool {
+    !ty.is_float()
+}
+
+/*
+ * 计算活跃区间
+ * 块按编号顺序排列，每条指令 (包括结尾) 一个位置
+ * phi 的赋值发生在前驱的末尾，所以 phi 的区间要延伸到每个前驱的末尾
+*/
+fn live_intervals(function: &Function) -> Vec<Interval> {
+    let n = function.blocks.len();
+    let mut block_start = vec![0; n];
+    let mut block_end = vec![0; n];
+    let mut position = 0;
+    let mut calls = Vec::new();
+    for b in function.block_ids() {
+        block_start[b.0] = position;
+        for instr in function.blocks[b.0].instrs.iter() {
+            if let Instr::Call(_, _, _, _) = instr {
+                calls.push(position);
+            }
+            position += 1;
+        }
+        block_end[b.0] = position;
+        position += 1;
+    }
+
+    let live_out = liveness(function);
+
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; function.values.len()];
+    let mut extend = |v: Value, p: usize| {
+        if !is_integer(function.ty(v)) {
+            return;
+        }
+        ranges[v.0] = Some(match ranges[v.0] {
+            None => (p, p),
+            Some((s, e)) => (s.min(p), e.max(p)),
+        });
+    };
+
+    for p in function.params.iter() {
+        extend(*p, 0);
+    }
+
+    let preds = predecessors(function);
+    for b in function.block_ids() {
+        let block = &function.blocks[b.0];
+        for v in live_out[b.0].iter() {
+            extend(*v, block_end[b.0]);
+        }
+
+        for (position, instr) in (block_start[b.0]..).zip(block.instrs.iter()) {
+            match instr {
+                // phi 的来源在前驱的末尾使用，已经算在前驱的 live_out 中
+                Instr::Phi(v, _) => {
+                    extend(*v, position);
+                    for p in preds[b.0].iter() {
+                        extend(*v, block_end[p.0]);
+                    }
+                }
+                _ => {
+                    for u in instr.uses() {
+                        extend(u, position);
+                    }
+                    if let Some(v) = instr.def() {
+                        extend(v, position);
+                    }
+                }
+            }
+        }
+        for u in block.terminator.uses() {
+            extend(u, block_end[b.0]);
+        }
+
+        // 块开头活跃的值
+        for v in live_in(function, b, &live_out[b.0]) {
+            extend(v, block_start[b.0]);
+        }
+    }
+
+    ranges.into_iter().enumerate().filter_map(|(v, range)| {
+        let (start, end) = range?;
+        Some(Interval {
+            value: Value(v),
+            start,
+            end,
+            crosses_call: calls.iter().any(|c| start < *c && *c < end),
+        })
+    }).collect()
+}
+
+// 块开头活跃的值: 块中使用但没有在之前定义的，加上出口活跃但块中没有定义的
+fn live_in(function: &Function, b: BlockId, live_out: &[Value]) -> Vec<Value> {
+    let block = &function.blocks[b.0];
+    let mut defined = Vec::new();
+    let mut live = Vec::new();
+
+    for instr in block.instrs.iter() {
+        if let Instr::Phi(v, _) = instr {
+            defined.push(*v);
+            continue;
+        }
+        for u in instr.uses() {
+            if !defined.contains(&u) && !live.contains(&u) {
+                live.push(u);
+            }
+        }
+        if let Some(v) = instr.def() {
+            defined.push(v);
+        }
+    }
+    for u in block.terminator.uses().into_iter().chain(live_out.iter().copied()) {
+        if !defined.contains(&u) && !live.contains(&u) {
+            live.push(u);
+        }
+    }
+    live
+}
+
+/*
+ * 每个块出口活跃的值
+ * 后继入口活跃的值，加上后继的 phi 从这个块得到的值
+ * 反复计算直到不再变化
+*/
+fn liveness(function: &Function) -> Vec<Vec<Value>> {
+    let n = function.blocks.len();
+    let mut live_out: Vec<Vec<Value>> = vec![Vec::new(); n];
+    let mut live_ins: Vec<Vec<Value>> = vec![Vec::new(); n];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in function.block_ids().rev() {
+            let mut out: Vec<Value> = Vec::new();
+            for succ in function.blocks[b.0].terminator.successors() {
+                let phi_sources = function.blocks[succ.0].instrs.iter().filter_map(|instr| match instr {
+                    Instr::Phi(_, incoming) => incoming.iter().find(|(p, _)| *p == b).map(|(_, v)| *v),
+                    _ => None,
+                });
+                for v in live_ins[succ.0].iter().copied().chain(phi_sources) {
+                    if !out.contains(&v) {
+                        out.push(v);
+                    }
+                }
+            }
+
+            let live = live_in(function, b, &out);
+            if out.len() != live_out[b.0].len() || live.len() != live_ins[b.0].len() {
+                changed = true;
+            }
+            live_out[b.0] = out;
+            live_ins[b.0] = live;
+        }
+    }
+
+    live_out
+}
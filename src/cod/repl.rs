@@ -0,0 +1,234 @@
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process::exit;
+
+use super::ast::{Ast, AstNode, Item};
+use super::c::declarator;
+use super::driver::{check, panic_message, parse};
+use super::interp::{on_large_stack, Session, Stop};
+use super::lex::lex;
+use super::options::{Arch, Options};
+use super::target::target;
+use super::token::{Keyword, Operator, Punctuator, Token};
+
+/*
+ * 交互式执行 (my_rcc repl)
+ * 每次输入是一个函数定义 (声明)，或者函数体中的语句和声明，表达式后面的 ; 可以省略
+ * 语句放进 __repl 函数中用解释器执行，之前声明的变量是它的参数，最外层新声明的变量保留下来
+ * 花括号没有配对时继续读下一行
+ * :ast 显示上一次输入的语法树，:asm 显示定义的函数和上一次输入生成的汇编，:quit 退出
+*/
+
+// 包装语句的函数
+const ENTRY: &str = "__repl";
+// 错误信息中用这个名字称呼包装语句的函数
+const INPUT: &str = "<input>";
+
+/*
+ * repl 的状态
+ * 解释器保留的变量和内存
+ * 已经定义的函数的源代码
+ * 上一次输入的语法树，和生成汇编用的源代码 (定义的函数和包装语句的函数)
+*/
+#[derive(Default)]
+struct Repl {
+    session: Session,
+    functions: Vec<String>,
+    last: Option<(Ast, String)>,
+}
+
+pub fn repl() {
+    // 语法和语义错误都是 panic，只显示信息，然后继续读下一个输入
+    panic::set_hook(Box::new(|info| eprintln!("Error: {}", panic_message(info.payload()).replace(ENTRY, INPUT))));
+
+    if let Err(e) = on_large_stack(|| Repl::default().run()) {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+}
+
+/*
+ * 花括号的层数，忽略字符串、字符常量和注释中的花括号
+*/
+fn brace_balance(input: &str) -> i64 {
+    let mut balance = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => balance += 1,
+            '}' => balance -= 1,
+            '"' | '\'' => {
+                while let Some(d) = chars.next() {
+                    match d {
+                        '\\' => {
+                            chars.next();
+                        }
+                        d if d == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for d in chars.by_ref() {
+                    if d == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut star = false;
+                for d in chars.by_ref() {
+                    if star && d == '/' {
+                        break;
+                    }
+                    star = d == '*';
+                }
+            }
+            _ => {}
+        }
+    }
+    balance
+}
+
+/*
+ * 是否是函数定义或者声明: 说明符 类型 名字 (
+*/
+fn is_function(tokens: &[Token]) -> bool {
+    let mut i = 0;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Keyword(Keyword::Attribute) => {
+                // 跳过 __attribute__((...))
+                let mut depth = 0;
+                i += 1;
+                while let Some(token) = tokens.get(i) {
+                    match token {
+                        Token::Punctuator(Punctuator::OpenParen) => depth += 1,
+                        Token::Punctuator(Punctuator::CloseParen) => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                continue;
+            }
+            Token::Keyword(Keyword::Static | Keyword::Inline | Keyword::Int | Keyword::Float | Keyword::Double
+                | Keyword::Long | Keyword::Char | Keyword::Void | Keyword::Const | Keyword::VaList)
+            | Token::Operator(Operator::Multiplication) => i += 1,
+            Token::Identifier(_) => return i > 0 && tokens.get(i + 1) == Some(&Token::Punctuator(Punctuator::OpenParen)),
+            _ => return false,
+        }
+    }
+    false
+}
+
+impl Repl {
+    fn run(&mut self) {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            let mut input = String::new();
+            let mut prompt = ">>> ";
+            loop {
+                print!("{}", prompt);
+                let _ = io::stdout().flush();
+                match lines.next() {
+                    Some(Ok(line)) => {
+                        input.push_str(&line);
+                        input.push('\n');
+                    }
+                    // 输入结束
+                    _ => {
+                        println!();
+                        return;
+                    }
+                }
+                if brace_balance(&input) <= 0 {
+                    break;
+                }
+                prompt = "... ";
+            }
+
+            match input.trim() {
+                "" => {}
+                ":quit" | ":q" => return,
+                ":ast" => match &self.last {
+                    Some((ast, _)) => println!("{:#?}", ast),
+                    None => eprintln!("Error: No previous input"),
+                },
+                ":asm" => match &self.last {
+                    Some((_, source)) => {
+                        let _ = panic::catch_unwind(|| print!("{}", assembly(source)));
+                    }
+                    None => eprintln!("Error: No previous input"),
+                },
+                s if s.starts_with(':') => eprintln!("Error: Unknown command {}", s),
+                s => {
+                    let s = s.to_string();
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| self.evaluate(&s)));
+                }
+            }
+        }
+    }
+
+    /*
+     * 执行一次输入
+     * 函数和之前所有的函数一起检查，通过了才保留
+     * 语句放进 __repl(之前的变量...) 中，新声明的同名变量遮住之前的变量
+    */
+    fn evaluate(&mut self, input: &str) {
+        let options = Options { input: "<repl>".to_string(), ..Options::default() };
+
+        if is_function(&lex(input)) {
+            let ast = parse(input);
+            let mut functions = self.functions.clone();
+            functions.push(input.to_string());
+            check(&parse(&functions.join("\n")), &options);
+
+            self.last = Some((ast, functions.join("\n")));
+            self.functions = functions;
+            return;
+        }
+
+        let mut body = input.to_string();
+        if !body.ends_with([';', '}']) {
+            body.push(';');
+        }
+        let ast = parse(&format!("void {}(void) {{\n{}\n}}\n", ENTRY, body));
+        let Ast::Ast(nodes) = &ast;
+        let AstNode::AstNode(_, _, _, _, items) = &nodes[0];
+        let declared: Vec<&str> = items.iter().flatten().filter_map(|item| match item {
+            Item::Declaration(super::ast::Declaration::Declaration(_, name, _)) => Some(&name[..]),
+            _ => None,
+        }).collect();
+
+        let params: Vec<String> = self.session.variables().into_iter()
+            .filter(|(name, _)| !declared.contains(&&name[..]))
+            .map(|(name, ty)| declarator(&ty, &name))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let entry = format!("void {}({}) {{\n{}\n}}\n", ENTRY, params, body);
+
+        // 和 my_rcc run 一样不做常量折叠
+        let source = format!("{}\n{}", self.functions.join("\n"), entry);
+        let program = super::sema::analyze(&parse(&source), &options);
+        self.last = Some((ast, source.clone()));
+
+        match self.session.execute(&program, ENTRY) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(Stop::Exit(status)) => exit(status as i32),
+            Err(Stop::Error(e)) => eprintln!("Error: {}", e.replace(ENTRY, INPUT)),
+        }
+    }
+}
+
+// 定义的函数和上一次输入生成的 x86-64 汇编
+fn assembly(source: &str) -> String {
+    let options = Options { input: "<repl>".to_string(), ..Options::default() };
+    let program = check(&parse(source), &options);
+    target(Arch::X86_64).assembly(&program, &options).unwrap_or_else(|e| panic!("{}", e))
+}
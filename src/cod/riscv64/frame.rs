@@ -0,0 +1,173 @@
+use super::super::ir::{Function, Ty};
+use super::super::stack::{align_to, allocate, outgoing_size};
+
+/*
+ * RISC-V 的栈帧布局
+ * 和 AArch64 一样所有的值都放在栈帧中，序言之后 sp 不再改变，栈槽和值都用相对 sp 的偏移访问
+ * fp (s0) 指向调用者的 sp，也就是第一个栈参数
+ *
+ * fp+0 ...        栈上传来的参数
+ * fp-64 ~ fp      可变参数的寄存器保存区 (只有可变参数函数有，a0-a7)，和栈参数连在一起
+ * ...             保存的 ra 和调用者的 fp
+ * ...             没有分到栈槽的值
+ * ...             栈槽 (局部变量)
+ * sp+N ~ sp+N+16  phi 赋值打破环时用的临时位置
+ * sp ~ sp+N       调用的栈参数
+*/
+
+// 整数参数寄存器的个数 a0 ~ a7
+pub const GP_ARG_REGS: usize = 8;
+// 浮点参数寄存器的个数 fa0 ~ fa7
+pub const FP_ARG_REGS: usize = 8;
+// 可变参数保存区的大小
+pub const VA_SAVE_SIZE: isize = GP_ARG_REGS as isize * 8;
+
+/*
+ * 参数的位置
+ * 整数寄存器
+ * 浮点寄存器
+ * 一对整数寄存器 (long double)
+ * 低64位在 a7，高64位在栈上 (long double，偏移是高64位的位置)
+ * 栈上 (相对于第一个栈参数的偏移)
+*/
+#[derive(Debug, Clone, Copy)]
+pub enum ArgLocation {
+    Gp(usize),
+    Fp(usize),
+    GpPair(usize),
+    Split(usize, isize),
+    Stack(isize),
+}
+
+/*
+ * LP64D 调用约定
+ * 整数和指针依次使用 a0 ~ a7
+ * float double 依次使用 fa0 ~ fa7，用完之后和整数一样使用 a0 ~ a7
+ * 可变参数中的 float double 只使用整数寄存器
+ * long double 是128位的四精度，使用一对整数寄存器，可变参数使用编号为偶数开始的一对
+ * 只剩一个整数寄存器时 long double 的低64位在寄存器中，高64位在栈上
+ * 用完寄存器的参数放在栈上，每个占8字节，long double 占16字节并且16字节对齐
+ * named 是可变参数函数的命名参数个数
+ * 返回每个参数的位置和栈参数占用的大小 (16字节对齐)
+*/
+pub fn classify_arguments(types: &[Ty], named: Option<usize>) -> (Vec<ArgLocation>, isize) {
+    let mut gp = 0;
+    let mut fp = 0;
+    let mut offset = 0;
+
+    let locations = types.iter().enumerate().map(|(i, t)| {
+        let variadic = named.is_some_and(|n| i >= n);
+        match t {
+            Ty::F80 => {
+                if variadic && gp % 2 == 1 {
+                    gp += 1;
+                }
+                if gp + 2 <= GP_ARG_REGS {
+                    gp += 2;
+                    ArgLocation::GpPair(gp - 2)
+                } else if gp < GP_ARG_REGS {
+                    gp += 1;
+                    offset += 8;
+                    ArgLocation::Split(gp - 1, offset - 8)
+                } else {
+                    offset = align_to(offset, 16) + 16;
+                    ArgLocation::Stack(offset - 16)
+                }
+            }
+            Ty::F32 | Ty::F64 if !variadic && fp < FP_ARG_REGS => {
+                fp += 1;
+                ArgLocation::Fp(fp - 1)
+            }
+            _ if gp < GP_ARG_REGS => {
+                gp += 1;
+                ArgLocation::Gp(gp - 1)
+            }
+            _ => {
+                offset += 8;
+                ArgLocation::Stack(offset - 8)
+            }
+        }
+    }).collect();
+
+    (locations, align_to(offset, 16))
+}
+
+// 尾调用的栈参数写到调用者收到的栈参数的位置，不能比调用者的多
+pub fn tail_call_fits(caller: &[Ty], callee: &[Ty], named: Option<usize>) -> bool {
+    classify_arguments(callee, named).1 <= classify_arguments(caller, None).1
+}
+
+/*
+ * 一个函数的栈帧
+ * 每个栈槽相对 sp 的偏移
+ * 每个值相对 sp 的偏移，已经被删除的值没有位置
+ * 每个参数传进来的位置
+ * 调用者自己的栈参数的大小，尾调用的栈参数不能比它多
+ * 序言中 sp 要减去的大小，不包括保存区和 ra fp (16字节对齐)
+ * 保存区和 ra fp 占的大小，fp 减去它就是保存 ra fp 的位置
+ * 可变参数函数中 va_start 得到的位置 (相对 fp)，之后的参数在保存区和栈上是连续的
+ * phi 赋值用的临时位置
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub slots: Vec<isize>,
+    pub values: Vec<Option<isize>>,
+    pub params: Vec<ArgLocation>,
+    pub incoming_size: isize,
+    pub size: isize,
+    pub top: isize,
+    pub va_start: Option<isize>,
+    pub scratch: isize,
+}
+
+/*
+ * 计算函数的栈帧
+ * 值在寄存器中都是64位的，每个值占8字节，long double 占16字节
+*/
+pub fn layout(function: &Function) -> Frame {
+    let types: Vec<Ty> = function.params.iter().map(|p| function.ty(*p)).collect();
+    let (params, incoming_size) = classify_arguments(&types, None);
+    let mut used = outgoing_size(function, |types, named| classify_arguments(types, named).1);
+
+    let scratch = allocate(&mut used, 16, 16);
+
+    let slots = function.slots.iter().map(|slot| {
+        allocate(&mut used, slot.size as isize, slot.align as isize)
+    }).collect();
+
+    let defined = function.def_blocks();
+    let values = function.values.iter().zip(defined.iter()).map(|(ty, def)| {
+        def.map(|_| {
+            let size = ty.size().max(8) as isize;
+            allocate(&mut used, size, size)
+        })
+    }).collect();
+
+    // 命名参数用掉的整数寄存器和栈参数之后就是第一个未命名参数
+    let va_start = if function.variadic {
+        let gp = params.iter().map(|l| match l {
+            ArgLocation::Gp(_) | ArgLocation::Split(_, _) => 1,
+            ArgLocation::GpPair(_) => 2,
+            _ => 0,
+        }).sum::<isize>();
+        let named_stack = types.iter().zip(params.iter()).map(|(t, l)| match l {
+            ArgLocation::Stack(offset) => offset + t.size().max(8) as isize,
+            ArgLocation::Split(_, offset) => offset + 8,
+            _ => 0,
+        }).max().unwrap_or(0);
+        Some(-VA_SAVE_SIZE + gp * 8 + named_stack)
+    } else {
+        None
+    };
+
+    Frame {
+        slots,
+        values,
+        params,
+        incoming_size,
+        size: align_to(used, 16),
+        top: if function.variadic { VA_SAVE_SIZE + 16 } else { 16 },
+        va_start,
+        scratch,
+    }
+}
@@ -0,0 +1,796 @@
+use super::float::{float_bits, quad_bits};
+use super::generator::{add_suffix, unique_suffix};
+use super::ir::*;
+use super::ir::phi::{has_phi, phi_moves, sequence};
+
+pub mod frame;
+
+use self::frame::{classify_arguments, layout, ArgLocation, Frame, VA_SAVE_SIZE};
+
+/*
+ * RISC-V (RV64GC) 的代码生成
+ * 和其他目标共用前端和中间表示，这里只把 IR 翻译成 GNU as 的 RISC-V 汇编
+ * 每个值在栈帧中有固定的位置，指令把操作数读到临时寄存器，算完再写回去
+ * 整数: 第一个操作数和结果 t0，第二个操作数 t1
+ * float double: 第一个操作数和结果 ft0，第二个操作数 ft1
+ * long double: 第一个操作数和结果 a0 a1，第二个操作数 a2 a3，正好是 libgcc 软件浮点函数的参数和返回值
+ * t6 用来计算放不进指令的地址
+*/
+
+/*
+ * 生成一个函数时的上下文
+ * 正在生成的函数
+ * 栈帧布局
+ * 已经生成的指令 (每行一条)
+ * 函数用到的只读数据 (浮点常量)，放在函数的指令之后
+*/
+struct Context<'a> {
+    function: &'a Function,
+    frame: Frame,
+    code: Vec<String>,
+    data: Vec<String>,
+}
+
+impl<'a> Context<'a> {
+    fn emit(&mut self, instr: String) {
+        self.code.push(format!("  {}", instr));
+    }
+
+    fn emit_label(&mut self, label: String) {
+        self.code.push(format!("{}:", label));
+    }
+
+    fn ty(&self, v: Value) -> Ty {
+        self.function.ty(v)
+    }
+
+    // 值相对 sp 的位置
+    fn home(&self, v: Value) -> isize {
+        self.frame.values[v.0].unwrap_or_else(|| unreachable!("Value {} has no location", v))
+    }
+
+    // 基本块的标签
+    fn label(&self, block: BlockId) -> String {
+        format!(".L{}.bb{}", self.function.name, block.0)
+    }
+
+    /*
+     * offset(base) 形式的地址
+     * 偏移是12位有符号数，放不下时先把地址算到 t6 中
+     * size 是访问的大小，long double 要访问 offset 和 offset+8
+    */
+    fn memory(&mut self, base: &str, offset: isize, size: usize) -> String {
+        if fits_immediate(offset) && fits_immediate(offset + size as isize) {
+            format!("{}({})", offset, base)
+        } else {
+            self.emit(format!("li t6, {}", offset));
+            self.emit(format!("add t6, {}, t6", base));
+            "0(t6)".to_string()
+        }
+    }
+
+    // 把 base+offset 算到 reg 中
+    fn emit_address(&mut self, reg: &str, base: &str, offset: isize) {
+        if fits_immediate(offset) {
+            self.emit(format!("addi {}, {}, {}", reg, base, offset));
+        } else {
+            self.emit(format!("li {}, {}", reg, offset));
+            self.emit(format!("add {}, {}, {}", reg, base, reg));
+        }
+    }
+
+    /*
+     * 从 base+offset 读出 t 类型的数
+     * 整数读到 t0 (或 reg)，float double 读到 ft0 (或 reg)，long double 读到 a0 a1 (或 reg 开始的一对)
+    */
+    fn emit_load(&mut self, t: Ty, reg: Register, base: &str, offset: isize) {
+        let address = self.memory(base, offset, t.size());
+        match (t, reg) {
+            (Ty::F80, Register::Pair(n)) => {
+                let high = self.memory(base, offset + 8, 8);
+                self.emit(format!("ld a{}, {}", n, address));
+                self.emit(format!("ld a{}, {}", n + 1, high));
+            }
+            (_, reg) => self.emit(format!("{} {}, {}", load_instr(t), reg.name(), address)),
+        }
+    }
+
+    // 把寄存器中 t 类型的数写到 base+offset
+    fn emit_store(&mut self, t: Ty, reg: Register, base: &str, offset: isize) {
+        let address = self.memory(base, offset, t.size());
+        match (t, reg) {
+            (Ty::F80, Register::Pair(n)) => {
+                let high = self.memory(base, offset + 8, 8);
+                self.emit(format!("sd a{}, {}", n, address));
+                self.emit(format!("sd a{}, {}", n + 1, high));
+            }
+            (_, reg) => self.emit(format!("{} {}, {}", store_instr(t), reg.name(), address)),
+        }
+    }
+
+    // 把值读到寄存器
+    fn load_value(&mut self, v: Value, reg: Register) {
+        let t = register_type(self.ty(v));
+        let offset = self.home(v);
+        self.emit_load(t, reg, "sp", offset);
+    }
+
+    // 寄存器中的结果写回值的位置
+    fn store_value(&mut self, v: Value, reg: Register) {
+        let t = register_type(self.ty(v));
+        let offset = self.home(v);
+        self.emit_store(t, reg, "sp", offset);
+    }
+
+    // 读取第一个操作数: t0 / ft0 / a0 a1
+    fn load_first(&mut self, v: Value) {
+        self.load_value(v, first(self.ty(v)));
+    }
+
+    // 读取第二个操作数: t1 / ft1 / a2 a3
+    fn load_second(&mut self, v: Value) {
+        self.load_value(v, second(self.ty(v)));
+    }
+
+    // 结果写回值的位置
+    fn store_result(&mut self, v: Value) {
+        self.store_value(v, first(self.ty(v)));
+    }
+}
+
+/*
+ * 寄存器
+ * 整数寄存器 (名字)
+ * 浮点寄存器 (名字)
+ * 从 an 开始的一对整数寄存器，放 long double
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    Int(&'static str),
+    Float(&'static str),
+    Pair(usize),
+}
+
+impl Register {
+    fn name(self) -> String {
+        match self {
+            Register::Int(name) | Register::Float(name) => name.to_string(),
+            Register::Pair(n) => format!("a{}", n),
+        }
+    }
+}
+
+const ARG_REGS: [&str; 8] = ["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+const FLOAT_ARG_REGS: [&str; 8] = ["fa0", "fa1", "fa2", "fa3", "fa4", "fa5", "fa6", "fa7"];
+
+fn fits_immediate(n: isize) -> bool {
+    (-2048..2048).contains(&n)
+}
+
+/*
+ * 值在寄存器中的类型
+ * char 在寄存器中按64位处理
+*/
+fn register_type(ty: Ty) -> Ty {
+    match ty {
+        Ty::I8 => Ty::I64,
+        t => t,
+    }
+}
+
+fn first(ty: Ty) -> Register {
+    match ty {
+        Ty::F80 => Register::Pair(0),
+        Ty::F32 | Ty::F64 => Register::Float("ft0"),
+        _ => Register::Int("t0"),
+    }
+}
+
+fn second(ty: Ty) -> Register {
+    match ty {
+        Ty::F80 => Register::Pair(2),
+        Ty::F32 | Ty::F64 => Register::Float("ft1"),
+        _ => Register::Int("t1"),
+    }
+}
+
+// 返回值所在的寄存器
+fn result(ty: Ty) -> Register {
+    match ty {
+        Ty::F32 | Ty::F64 => Register::Float("fa0"),
+        Ty::F80 => Register::Pair(0),
+        _ => Register::Int("a0"),
+    }
+}
+
+fn load_instr(t: Ty) -> &'static str {
+    match t {
+        Ty::I8 => "lb",
+        Ty::F32 => "flw",
+        Ty::F64 => "fld",
+        _ => "ld",
+    }
+}
+
+fn store_instr(t: Ty) -> &'static str {
+    match t {
+        Ty::I8 => "sb",
+        Ty::F32 => "fsw",
+        Ty::F64 => "fsd",
+        _ => "sd",
+    }
+}
+
+// 浮点指令的后缀
+fn float_suffix(t: Ty) -> &'static str {
+    if t == Ty::F32 { "s" } else { "d" }
+}
+
+/*
+ * 层级遍历
+ * Module->Function
+ * 返回整个汇编文件
+*/
+pub fn generate(module: &Module) -> Result<String, String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    // 字符串常量
+    if !module.strings.is_empty() {
+        lines.push("  .section .rodata".to_string());
+        for (i, s) in module.strings.iter().enumerate() {
+            let bytes: Vec<String> = s.iter().chain(std::iter::once(&0)).map(|b| b.to_string()).collect();
+            lines.push(format!(".LS{}:", i));
+            lines.push(format!("  .byte {}", bytes.join(",")));
+        }
+    }
+    lines.push("  .text".to_string());
+
+    for function in module.functions.iter() {
+        lines.extend(generate_function(function)?);
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+/*
+ * 层级遍历
+ * Function->Block
+ * 序言保存 ra 和调用者的 fp，fp 指向调用者的 sp，再给栈帧留出空间
+*/
+fn generate_function(function: &Function) -> Result<Vec<String>, String> {
+    let mut context = Context {
+        function,
+        frame: layout(function),
+        code: Vec::new(),
+        data: Vec::new(),
+    };
+
+    // static 函数只在本文件中可见
+    if !function.specifiers.is_static {
+        context.code.push(format!(".global {}", function.name));
+    }
+    context.code.push("  .p2align 1".to_string());
+    context.emit_label(function.name.clone());
+
+    let top = context.frame.top;
+    context.emit(format!("addi sp, sp, -{}", top));
+    context.emit("sd ra, 8(sp)".to_string());
+    context.emit("sd s0, 0(sp)".to_string());
+    context.emit(format!("addi s0, sp, {}", top));
+
+    let size = context.frame.size;
+    if size > 2048 {
+        context.emit(format!("li t6, {}", size));
+        context.emit("sub sp, sp, t6".to_string());
+    } else if size > 0 {
+        context.emit(format!("addi sp, sp, -{}", size));
+    }
+
+    generate_parameters(&mut context);
+
+    for b in function.block_ids() {
+        let label = context.label(b);
+        context.emit_label(label);
+        let block = &function.blocks[b.0];
+        for instr in block.instrs.iter() {
+            generate_instr(instr, &mut context);
+        }
+        generate_terminator(b, &block.terminator, &mut context)?;
+    }
+
+    let mut code = context.code;
+    if !context.data.is_empty() {
+        code.push("  .section .rodata".to_string());
+        code.extend(context.data);
+        code.push("  .text".to_string());
+    }
+    Ok(code)
+}
+
+/*
+ * 参数保存到栈帧中
+ * 可变参数函数先把 a0-a7 存到保存区，它们和栈参数连在一起，va_arg 只需要一个指针
+*/
+fn generate_parameters(context: &mut Context) {
+    if context.frame.va_start.is_some() {
+        for (i, reg) in ARG_REGS.iter().enumerate() {
+            context.emit(format!("sd {}, {}(s0)", reg, -VA_SAVE_SIZE + i as isize * 8));
+        }
+    }
+
+    let function = context.function;
+    for (param, location) in function.params.iter().zip(context.frame.params.clone()) {
+        let ty = context.ty(*param);
+        let home = context.home(*param);
+        match location {
+            // 寄存器中的 float double 也按位保存
+            ArgLocation::Gp(r) => context.emit_store(Ty::I64, Register::Int(ARG_REGS[r]), "sp", home),
+            ArgLocation::Fp(r) => context.store_value(*param, Register::Float(FLOAT_ARG_REGS[r])),
+            ArgLocation::GpPair(r) => context.store_value(*param, Register::Pair(r)),
+            ArgLocation::Split(r, offset) => {
+                context.emit_store(Ty::I64, Register::Int(ARG_REGS[r]), "sp", home);
+                context.emit_load(Ty::I64, Register::Int("t0"), "s0", offset);
+                context.emit_store(Ty::I64, Register::Int("t0"), "sp", home + 8);
+            }
+            // fp 指向第一个栈参数
+            ArgLocation::Stack(offset) => {
+                context.emit_load(ty, first(ty), "s0", offset);
+                context.store_result(*param);
+            }
+        }
+    }
+}
+
+fn generate_instr(instr: &Instr, context: &mut Context) {
+    match instr {
+        Instr::Const(v, n) => {
+            context.emit(format!("li t0, {}", n));
+            context.store_result(*v);
+        }
+
+        Instr::FloatConst(v, f) => {
+            // 浮点常量放在 .rodata 中
+            let t = context.ty(*v);
+            let label = add_suffix(".LC", &unique_suffix());
+            let (align, words) = match t {
+                Ty::F32 => (2, vec![format!(".word {:#x}", float_bits(f, t) as u32)]),
+                Ty::F64 => (3, vec![format!(".quad {:#x}", float_bits(f, t) as u64)]),
+                _ => {
+                    let bits = quad_bits(f);
+                    (4, vec![format!(".quad {:#x}", bits as u64), format!(".quad {:#x}", (bits >> 64) as u64)])
+                }
+            };
+            context.data.push(format!("  .p2align {}", align));
+            context.data.push(format!("{}:", label));
+            context.data.extend(words.into_iter().map(|w| format!("  {}", w)));
+
+            context.emit(format!("la t6, {}", label));
+            context.emit_load(t, first(t), "t6", 0);
+            context.store_result(*v);
+        }
+
+        Instr::StringAddr(v, index) => {
+            context.emit(format!("la t0, .LS{}", index));
+            context.store_result(*v);
+        }
+
+        Instr::SlotAddr(v, slot) => {
+            let offset = context.frame.slots[slot.0];
+            context.emit_address("t0", "sp", offset);
+            context.store_result(*v);
+        }
+
+        Instr::Load(v, slot) => {
+            let t = context.ty(*v);
+            let offset = context.frame.slots[slot.0];
+            context.emit_load(t, first(t), "sp", offset);
+            context.store_result(*v);
+        }
+
+        Instr::Store(slot, v) => {
+            let t = context.ty(*v);
+            let offset = context.frame.slots[slot.0];
+            context.load_first(*v);
+            context.emit_store(t, first(t), "sp", offset);
+        }
+
+        Instr::Neg(v, a) => {
+            context.load_first(*a);
+            match context.ty(*a) {
+                t @ (Ty::F32 | Ty::F64) => context.emit(format!("fneg.{} ft0, ft0", float_suffix(t))),
+                // 翻转高64位中的符号位
+                Ty::F80 => {
+                    context.emit("li t2, -1".to_string());
+                    context.emit("slli t2, t2, 63".to_string());
+                    context.emit("xor a1, a1, t2".to_string());
+                }
+                _ => context.emit("neg t0, t0".to_string()),
+            }
+            context.store_result(*v);
+        }
+
+        Instr::Binary(v, op, a, b) => {
+            let t = context.ty(*a);
+            context.load_first(*a);
+            context.load_second(*b);
+            match t {
+                Ty::F80 => context.emit(format!("call {}", quad_operator(*op))),
+                Ty::F32 | Ty::F64 => context.emit(format!("{}.{} ft0, ft0, ft1", float_operator(*op), float_suffix(t))),
+                _ => context.emit(format!("{} t0, t0, t1", integer_operator(*op))),
+            }
+            context.store_result(*v);
+        }
+
+        Instr::Compare(v, op, a, b) => {
+            let t = context.ty(*a);
+            context.load_first(*a);
+            context.load_second(*b);
+            match t {
+                Ty::F80 => {
+                    context.emit(format!("call {}", quad_compare(*op)));
+                    generate_sign_test(*op, context);
+                }
+                Ty::F32 | Ty::F64 => generate_float_compare(*op, float_suffix(t), context),
+                _ => generate_integer_compare(*op, context),
+            }
+            context.store_result(*v);
+        }
+
+        Instr::Convert(v, a) => {
+            context.load_first(*a);
+            generate_convert(context.ty(*a), context.ty(*v), context);
+            context.store_result(*v);
+        }
+
+        Instr::Call(v, name, args, named) => generate_call(*v, name, args, *named, context),
+
+        // va_list 只是指向下一个参数的指针
+        Instr::VaStart(ap) => {
+            let start = context.frame.va_start.expect("va_start outside variadic function");
+            context.load_first(*ap);
+            context.emit_address("t1", "s0", start);
+            context.emit("sd t1, 0(t0)".to_string());
+        }
+
+        /*
+         * 所有的参数在 va_list 指向的地方都占8字节，long double 占16字节并且16字节对齐
+        */
+        Instr::VaArg(v, ap) => {
+            let t = context.ty(*v);
+            context.load_first(*ap);
+            context.emit("ld t1, 0(t0)".to_string());
+            if t == Ty::F80 {
+                context.emit("addi t1, t1, 15".to_string());
+                context.emit("andi t1, t1, -16".to_string());
+            }
+            context.emit(format!("addi t2, t1, {}", t.size().max(8)));
+            context.emit("sd t2, 0(t0)".to_string());
+            context.emit_load(t, first(t), "t1", 0);
+            context.store_result(*v);
+        }
+
+        Instr::MemCopy(dest, src, size) => {
+            context.load_value(*dest, Register::Int("t0"));
+            context.load_value(*src, Register::Int("t1"));
+            for offset in (0..*size).step_by(8) {
+                context.emit(format!("ld t2, {}(t1)", offset));
+                context.emit(format!("sd t2, {}(t0)", offset));
+            }
+        }
+
+        // phi 在前驱跳转过来之前赋值
+        Instr::Phi(_, _) => {}
+    }
+}
+
+// 整数运算，左边在 t0，右边在 t1，结果在 t0
+fn integer_operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "sub",
+        BinOp::Mul => "mul",
+        BinOp::Div => "div",
+        BinOp::Rem => "rem",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::Xor => "xor",
+        BinOp::Shl => "sll",
+        BinOp::Shr => "sra",
+    }
+}
+
+fn float_operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "fadd",
+        BinOp::Sub => "fsub",
+        BinOp::Mul => "fmul",
+        BinOp::Div => "fdiv",
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
+
+// long double 的运算函数，参数在 a0 a1 和 a2 a3，结果在 a0 a1
+fn quad_operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "__addtf3",
+        BinOp::Sub => "__subtf3",
+        BinOp::Mul => "__multf3",
+        BinOp::Div => "__divtf3",
+        _ => unreachable!("Unexpected float operator {}", op),
+    }
+}
+
+/*
+ * long double 的比较函数，返回值和0比较的结果就是比较的结果
+ * 有 NaN 时它们返回使比较不成立 (不等成立) 的值
+*/
+fn quad_compare(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "__eqtf2",
+        CmpOp::Ne => "__netf2",
+        CmpOp::Lt => "__lttf2",
+        CmpOp::Le => "__letf2",
+        CmpOp::Gt => "__gttf2",
+        CmpOp::Ge => "__getf2",
+    }
+}
+
+/*
+ * 整数比较，结果 0 或 1 在 t0 中
+ * 只有 slt，其他的比较交换两边或者取反
+*/
+fn generate_integer_compare(op: CmpOp, context: &mut Context) {
+    match op {
+        CmpOp::Eq | CmpOp::Ne => {
+            context.emit("sub t0, t0, t1".to_string());
+            context.emit(format!("{} t0, t0", if op == CmpOp::Eq { "seqz" } else { "snez" }));
+        }
+        CmpOp::Lt => context.emit("slt t0, t0, t1".to_string()),
+        CmpOp::Gt => context.emit("slt t0, t1, t0".to_string()),
+        CmpOp::Le => {
+            context.emit("slt t0, t1, t0".to_string());
+            context.emit("xori t0, t0, 1".to_string());
+        }
+        CmpOp::Ge => {
+            context.emit("slt t0, t0, t1".to_string());
+            context.emit("xori t0, t0, 1".to_string());
+        }
+    }
+}
+
+/*
+ * 浮点比较，结果 0 或 1 在 t0 中
+ * feq flt fle 在有 NaN 时得到 0，> >= 交换两边，不等是相等取反
+*/
+fn generate_float_compare(op: CmpOp, suffix: &str, context: &mut Context) {
+    match op {
+        CmpOp::Eq => context.emit(format!("feq.{} t0, ft0, ft1", suffix)),
+        CmpOp::Ne => {
+            context.emit(format!("feq.{} t0, ft0, ft1", suffix));
+            context.emit("xori t0, t0, 1".to_string());
+        }
+        CmpOp::Lt => context.emit(format!("flt.{} t0, ft0, ft1", suffix)),
+        CmpOp::Le => context.emit(format!("fle.{} t0, ft0, ft1", suffix)),
+        CmpOp::Gt => context.emit(format!("flt.{} t0, ft1, ft0", suffix)),
+        CmpOp::Ge => context.emit(format!("fle.{} t0, ft1, ft0", suffix)),
+    }
+}
+
+// a0 中比较函数的返回值和0比较，结果在 t0 中
+fn generate_sign_test(op: CmpOp, context: &mut Context) {
+    let (test, negate) = match op {
+        CmpOp::Eq => ("seqz", false),
+        CmpOp::Ne => ("snez", false),
+        CmpOp::Lt => ("sltz", false),
+        CmpOp::Le => ("sgtz", true),
+        CmpOp::Gt => ("sgtz", false),
+        CmpOp::Ge => ("sltz", true),
+    };
+    context.emit(format!("{} t0, a0", test));
+    if negate {
+        context.emit("xori t0, t0, 1".to_string());
+    }
+}
+
+/*
+ * 类型转换
+ * 值在 t0 / ft0 / a0 a1 之间移动
+ * 整数和指针都在 t0 中，转换到 char 时截断
+ * 和 long double 之间的转换调用 libgcc
+*/
+fn generate_convert(from: Ty, to: Ty, context: &mut Context) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (Ty::F32, Ty::F64) => context.emit("fcvt.d.s ft0, ft0".to_string()),
+        (Ty::F64, Ty::F32) => context.emit("fcvt.s.d ft0, ft0".to_string()),
+        (Ty::F32, Ty::F80) => {
+            context.emit("fmv.s fa0, ft0".to_string());
+            context.emit("call __extendsftf2".to_string());
+        }
+        (Ty::F64, Ty::F80) => {
+            context.emit("fmv.d fa0, ft0".to_string());
+            context.emit("call __extenddftf2".to_string());
+        }
+        (Ty::F80, Ty::F32) => {
+            context.emit("call __trunctfsf2".to_string());
+            context.emit("fmv.s ft0, fa0".to_string());
+        }
+        (Ty::F80, Ty::F64) => {
+            context.emit("call __trunctfdf2".to_string());
+            context.emit("fmv.d ft0, fa0".to_string());
+        }
+
+        (_, Ty::F32) => context.emit("fcvt.s.l ft0, t0".to_string()),
+        (_, Ty::F64) => context.emit("fcvt.d.l ft0, t0".to_string()),
+        (_, Ty::F80) => {
+            context.emit("mv a0, t0".to_string());
+            context.emit("call __floatditf".to_string());
+        }
+
+        _ => {
+            match from {
+                // 向0舍入
+                Ty::F32 => context.emit("fcvt.l.s t0, ft0, rtz".to_string()),
+                Ty::F64 => context.emit("fcvt.l.d t0, ft0, rtz".to_string()),
+                Ty::F80 => {
+                    context.emit("call __fixtfdi".to_string());
+                    context.emit("mv t0, a0".to_string());
+                }
+                _ => {}
+            }
+            if to == Ty::I8 {
+                context.emit("slli t0, t0, 56".to_string());
+                context.emit("srai t0, t0, 56".to_string());
+            }
+        }
+    }
+}
+
+/*
+ * 函数调用
+ * 栈参数写在栈帧底部预留的空间，sp 不需要调整
+ * 返回值在 a0 / fa0 / a0 a1
+*/
+fn generate_call(dest: Option<Value>, name: &str, args: &[Value], named: Option<usize>, context: &mut Context) {
+    generate_arguments(args, named, "sp", context);
+    context.emit(format!("call {}", name));
+
+    if let Some(v) = dest {
+        context.store_value(v, result(context.ty(v)));
+    }
+}
+
+/*
+ * 尾调用
+ * 栈参数写到调用者自己收到栈参数的位置 (fp 开始)，然后恢复栈帧跳到被调用的函数
+ * 语义检查和尾调用优化已经按 RISC-V 的调用约定检查过，放不下时报错，不能退回普通的调用
+*/
+fn generate_tail_call(name: &str, args: &[Value], named: Option<usize>, context: &mut Context) -> Result<(), String> {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    if classify_arguments(&types, named).1 > context.frame.incoming_size {
+        return Err(format!("Arguments of tail call to {} do not fit in the stack arguments of {}", name, context.function.name));
+    }
+    generate_arguments(args, named, "s0", context);
+    generate_epilogue(context);
+    context.emit(format!("tail {}", name));
+    Ok(())
+}
+
+/*
+ * 准备调用的参数
+ * 栈上的参数写到 base+偏移，寄存器参数直接从栈帧读到寄存器中
+ * 整数寄存器中的 float double 按位传递
+*/
+fn generate_arguments(args: &[Value], named: Option<usize>, base: &str, context: &mut Context) {
+    let types: Vec<Ty> = args.iter().map(|arg| context.ty(*arg)).collect();
+    let (locations, _) = classify_arguments(&types, named);
+
+    // 栈参数要经过 t0，先于寄存器参数写好
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        let home = context.home(*arg);
+        match location {
+            ArgLocation::Stack(offset) => {
+                for part in (0..context.ty(*arg).size().max(8) as isize).step_by(8) {
+                    context.emit_load(Ty::I64, Register::Int("t0"), "sp", home + part);
+                    context.emit_store(Ty::I64, Register::Int("t0"), base, offset + part);
+                }
+            }
+            ArgLocation::Split(_, offset) => {
+                context.emit_load(Ty::I64, Register::Int("t0"), "sp", home + 8);
+                context.emit_store(Ty::I64, Register::Int("t0"), base, *offset);
+            }
+            _ => {}
+        }
+    }
+
+    for (arg, location) in args.iter().zip(locations.iter()) {
+        let home = context.home(*arg);
+        match location {
+            ArgLocation::Gp(r) | ArgLocation::Split(r, _) => context.emit_load(Ty::I64, Register::Int(ARG_REGS[*r]), "sp", home),
+            ArgLocation::Fp(r) => context.load_value(*arg, Register::Float(FLOAT_ARG_REGS[*r])),
+            ArgLocation::GpPair(r) => context.load_value(*arg, Register::Pair(*r)),
+            ArgLocation::Stack(_) => {}
+        }
+    }
+}
+
+/*
+ * 跳到 to 之前给 to 中的 phi 赋值
+ * 每个值都在栈帧中，赋值就是 (目的, 来源, 大小) 的内存复制，环用栈帧中的临时位置打破
+*/
+fn generate_phi_moves(from: BlockId, to: BlockId, context: &mut Context) {
+    let moves = phi_moves(context.function, from, to).into_iter()
+        .map(|(v, a)| (context.home(v), context.home(a), context.ty(v).size().max(8)))
+        .collect();
+    for (dest, src, size) in sequence(moves, context.frame.scratch) {
+        generate_copy(dest, src, size, context);
+    }
+}
+
+// 栈帧中的复制，每次8字节
+fn generate_copy(dest: isize, src: isize, size: usize, context: &mut Context) {
+    for part in (0..size as isize).step_by(8) {
+        context.emit_load(Ty::I64, Register::Int("t0"), "sp", src + part);
+        context.emit_store(Ty::I64, Register::Int("t0"), "sp", dest + part);
+    }
+}
+
+fn generate_terminator(block: BlockId, terminator: &Terminator, context: &mut Context) -> Result<(), String> {
+    match terminator {
+        Terminator::Jump(target) => {
+            generate_phi_moves(block, *target, context);
+            context.emit(format!("j {}", context.label(*target)));
+        }
+
+        /*
+         * 条件跳转的范围只有 ±4KB，只用它跳过一条 j
+         * 目标有 phi 时需要先在这条边上赋值
+        */
+        Terminator::Branch(c, if_true, if_false) => {
+            let taken = format!("{}.taken", context.label(block));
+            let false_edge = if has_phi(context.function, *if_false) {
+                format!("{}.to{}", context.label(block), if_false.0)
+            } else {
+                context.label(*if_false)
+            };
+            context.load_first(*c);
+            context.emit(format!("bnez t0, {}", taken));
+            context.emit(format!("j {}", false_edge));
+            context.emit_label(taken);
+            generate_phi_moves(block, *if_true, context);
+            context.emit(format!("j {}", context.label(*if_true)));
+
+            if has_phi(context.function, *if_false) {
+                context.emit_label(false_edge);
+                generate_phi_moves(block, *if_false, context);
+                context.emit(format!("j {}", context.label(*if_false)));
+            }
+        }
+
+        Terminator::Return(v) => {
+            // 返回值放在 a0 / fa0 / a0 a1
+            if let Some(v) = v {
+                context.load_value(*v, result(context.ty(*v)));
+            }
+            generate_epilogue(context);
+            context.emit("ret".to_string());
+        }
+
+        Terminator::TailCall(name, args, named) => return generate_tail_call(name, args, *named, context),
+    }
+    Ok(())
+}
+
+// 恢复 ra 和调用者的栈帧
+fn generate_epilogue(context: &mut Context) {
+    let top = context.frame.top;
+    context.emit(format!("addi sp, s0, -{}", top));
+    context.emit("ld ra, 8(sp)".to_string());
+    context.emit("ld s0, 0(sp)".to_string());
+    context.emit(format!("addi sp, sp, {}", top));
+}
@@ -0,0 +1,84 @@
+use super::asm::{Address, AluOp, Directive, Instr, Operand, Reg, Size};
+use super::asm::{RAX, RBP, RCX, RDI, RDX, RSP};
+use super::driver::compile;
+use super::elf::Object;
+use super::encode::assemble;
+use super::generator::generate;
+use super::options::Options;
+
+/*
+ * 和编译器一起提供的运行时
+ * 启动代码: _start 和 libc 需要的汇编函数 (系统调用 读写内存)，总是链接
+ * libc: runtime/libc.c，由 my_rcc 自己编译，按需链接
+*/
+const LIBC: &str = include_str!("../../runtime/libc.c");
+
+fn function(name: &str, body: Vec<Instr>) -> Vec<Instr> {
+    let mut code = vec![Instr::Directive(Directive::Global(name.to_string())), Instr::Label(name.to_string())];
+    code.extend(body);
+    code
+}
+
+fn memory(size: Size, r: Reg) -> Operand {
+    Operand::Mem(size, Address::Base(r, 0))
+}
+
+/*
+ * 启动代码
+ * 进程开始时 [rsp] 是 argc，之后是 argv 和 envp 两个以 0 结尾的指针数组
+ * _start 调用 main(argc, argv, envp)，用它的返回值 exit_group
+*/
+pub fn startup() -> Object {
+    let rsi = Reg::Rsi.qword();
+    let r8 = Reg::R8.qword();
+    let r9 = Reg::R9.qword();
+    let r10 = Reg::R10.qword();
+    let mut program = function("_start", vec![
+        Instr::Alu(AluOp::Xor, RBP, RBP),
+        Instr::Mov(RDI, memory(Size::Qword, Reg::Rsp)),
+        Instr::Lea(rsi.clone(), Address::Base(Reg::Rsp, 8)),
+        // envp = argv + 8 * (argc + 1)
+        Instr::Mov(RDX, RDI),
+        Instr::Alu(AluOp::Imul, RDX, Operand::Imm(8)),
+        Instr::Alu(AluOp::Add, RDX, rsi.clone()),
+        Instr::Alu(AluOp::Add, RDX, Operand::Imm(8)),
+        Instr::Alu(AluOp::And, RSP, Operand::Imm(-16)),
+        Instr::Call("main".to_string()),
+        Instr::Mov(RDI, RAX),
+        Instr::Mov(RAX, Operand::Imm(231)),
+        Instr::Syscall,
+    ]);
+
+    // __syscall(n, a, b, c, d, e, f): 系统调用号在 rax，参数在 rdi rsi rdx r10 r8 r9
+    program.extend(function("__syscall", vec![
+        Instr::Mov(RAX, RDI),
+        Instr::Mov(RDI, rsi.clone()),
+        Instr::Mov(rsi.clone(), RDX),
+        Instr::Mov(RDX, RCX),
+        Instr::Mov(r10, r8.clone()),
+        Instr::Mov(r8, r9.clone()),
+        Instr::Mov(r9, Operand::Mem(Size::Qword, Address::Base(Reg::Rsp, 8))),
+        Instr::Syscall,
+        Instr::Ret,
+    ]));
+    // __write8(fd, c): c 的低字节放在栈上 (红区中)，直接 write 一个字节
+    program.extend(function("__write8", vec![
+        Instr::Mov(Operand::Mem(Size::Byte, Address::Base(Reg::Rsp, -8)), Reg::Rsi.byte()),
+        Instr::Mov(RAX, Operand::Imm(1)),
+        Instr::Lea(rsi.clone(), Address::Base(Reg::Rsp, -8)),
+        Instr::Mov(RDX, Operand::Imm(1)),
+        Instr::Syscall,
+        Instr::Ret,
+    ]));
+    program.extend(function("__load8", vec![Instr::Movzx(RAX, memory(Size::Byte, Reg::Rdi)), Instr::Ret]));
+    program.extend(function("__store8", vec![Instr::Mov(memory(Size::Byte, Reg::Rdi), Reg::Rsi.byte()), Instr::Ret]));
+    program.extend(function("__load64", vec![Instr::Mov(RAX, memory(Size::Qword, Reg::Rdi)), Instr::Ret]));
+    program.extend(function("__store64", vec![Instr::Mov(memory(Size::Qword, Reg::Rdi), rsi), Instr::Ret]));
+    assemble(&program)
+}
+
+// 编译 libc，总是按 -O2 优化
+pub fn libc() -> Object {
+    let options = Options { input: "libc.c".to_string(), opt_level: 2, ..Options::default() };
+    assemble(&generate(&compile(LIBC, &options)))
+}
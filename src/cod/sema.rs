@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+
+use super::ast::*;
+use super::ir::Ty;
+use super::options::{Arch, Options};
+use super::target::target;
+use super::token::Operator;
+use super::typed_ast::*;
+use super::types::{FunctionSpecifiers, Signature, Type};
+
+/*
+ * 语义分析
+ * 在 parser 和 generate 之间
+ * 把每个标识符解析到它的声明，给每个表达式确定类型
+ * 检查左值、重复声明、作用域、break continue 的位置、参数类型
+ * 所有用户的错误都在这里报告，后端只处理合法的程序
+*/
+
+/*
+ * 已经声明过的函数
+ * 函数名 -> (签名, 是否有函数体)
+ * 函数名 -> 所有声明的说明符合在一起
+ * 是否允许隐式声明 (C89)
+ * 目标机器，检查必须的尾调用
+*/
+#[derive(Debug, Default)]
+struct FunctionTable {
+    functions: HashMap<String, (Signature, bool)>,
+    specifiers: HashMap<String, FunctionSpecifiers>,
+    implicit_declarations: bool,
+    arch: Arch,
+}
+
+/*
+ * 分析一个函数时的状态
+ * 函数签名
+ * 所有变量的类型，下标就是变量编号
+ * 所有变量的名字
+ * 作用域栈，每层是 变量名 -> 变量编号，内层可以遮蔽外层
+ * 当前在几层循环里
+*/
+#[derive(Debug)]
+struct Scope {
+    name: String,
+    signature: Signature,
+    locals: Vec<Type>,
+    names: Vec<String>,
+    scopes: Vec<HashMap<String, VarId>>,
+    loop_depth: usize,
+}
+
+impl Scope {
+    /*
+     * 在当前作用域加入变量
+     * 同一作用域中不能重复声明，函数体最外层和参数是同一个作用域
+    */
+    fn declare(&mut self, name: &str, var_type: &Type) -> VarId {
+        let scope = self.scopes.last_mut().expect("Missing scope");
+        if scope.contains_key(name) {
+            panic!("Variable {} declared twice in same scope", name);
+        }
+
+        let id = self.locals.len();
+        self.locals.push(var_type.clone());
+        self.names.push(name.to_string());
+        scope.insert(name.to_string(), id);
+        id
+    }
+
+    // 从内到外查找变量
+    fn lookup(&self, name: &str) -> VarId {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(id) => *id,
+            None => panic!("Variable {} undeclared", name),
+        }
+    }
+}
+
+pub fn analyze(ast: &Ast, options: &Options) -> Program {
+    let mut table = FunctionTable {
+        implicit_declarations: options.implicit_declarations,
+        arch: options.target,
+        ..Default::default()
+    };
+    let mut functions = Vec::new();
+
+    match ast {
+        Ast::Ast(nodes) => {
+            for AstNode::AstNode(name, signature, specifiers, params, body) in nodes {
+                analyze_function_declaration(name, signature, specifiers, body.is_some(), &mut table);
+                if let Some(items) = body {
+                    functions.push(analyze_function(name, signature, params, items, &mut table));
+                }
+            }
+        }
+    }
+
+    // 定义之后的声明也可以加上说明符
+    for function in functions.iter_mut() {
+        function.specifiers = table.specifiers[&function.name];
+    }
+
+    Program { functions }
+}
+
+/*
+ * 函数声明
+ * 和之前的声明比较，两次声明必须兼容，只能定义一次
+ * 之前的声明不是 static 的，后面的声明不能是 static
+ * noinline 和 always_inline 不能同时使用
+*/
+fn analyze_function_declaration(name: &str, signature: &Signature, specifiers: &FunctionSpecifiers, has_body: bool, table: &mut FunctionTable) {
+    let declared = table.functions.contains_key(name);
+    let orig_specifiers = table.specifiers.get(name).copied().unwrap_or_default();
+    if specifiers.is_static && declared && !orig_specifiers.is_static {
+        panic!("Static declaration of function {} follows non-static declaration", name);
+    }
+    let merged = orig_specifiers.merge(specifiers);
+    if merged.noinline && merged.always_inline {
+        panic!("Function {} declared both noinline and always_inline", name);
+    }
+    table.specifiers.insert(name.to_string(), merged);
+
+    match table.functions.get(name) {
+        Some((orig_signature, orig_has_body)) => {
+            if !orig_signature.is_compatible(signature) {
+                // 参数类型或者返回类型不同
+                panic!("Conflicting types for function {}", name);
+            } else if *orig_has_body && has_body {
+                panic!("Redefinition of function {}", name);
+            }
+            // 保留有原型的那个
+            let merged = if signature.prototyped { signature.clone() } else { orig_signature.clone() };
+            let has_body = has_body || *orig_has_body;
+            table.functions.insert(name.to_string(), (merged, has_body));
+        }
+        None => {
+            table.functions.insert(name.to_string(), (signature.clone(), has_body));
+        }
+    }
+}
+
+/*
+ * 函数定义
+ * 参数先放进最外层作用域，函数体和参数共用这个作用域
+*/
+fn analyze_function(name: &str, signature: &Signature, params: &[String], items: &[Item], table: &mut FunctionTable) -> Function {
+    let mut scope = Scope {
+        name: name.to_string(),
+        signature: signature.clone(),
+        locals: Vec::new(),
+        names: Vec::new(),
+        scopes: vec![HashMap::new()],
+        loop_depth: 0,
+    };
+
+    let params = signature.params.iter().zip(params.iter()).map(|(param_type, param)| {
+        scope.declare(param, param_type)
+    }).collect();
+
+    let body = analyze_items(items, &mut scope, table);
+
+    Function {
+        name: name.to_string(),
+        signature: signature.clone(),
+        specifiers: FunctionSpecifiers::default(),
+        params,
+        locals: scope.locals,
+        names: scope.names,
+        body,
+    }
+}
+
+/*
+ * 块内的元素，作用域由调用者负责
+*/
+fn analyze_items(items: &[Item], scope: &mut Scope, table: &mut FunctionTable) -> Vec<Stmt> {
+    items.iter().map(|item| match item {
+        Item::Declaration(declaration) => analyze_declaration(declaration, scope, table),
+        Item::Statement(statement) => analyze_statement(statement, scope, table),
+    }).collect()
+}
+
+/*
+ * 变量声明
+ * 变量的作用域从声明处开始，所以初始值里已经可以看到它
+*/
+fn analyze_declaration(declaration: &Declaration, scope: &mut Scope, table: &mut FunctionTable) -> Stmt {
+    match declaration {
+        Declaration::Declaration(var_type, name, init) => {
+            if var_type == &Type::Void {
+                panic!("Variable {} declared void", name);
+            }
+            if var_type == &Type::VaList && init.is_some() {
+                panic!("va_list {} cannot be initialized", name);
+            }
+
+            let id = scope.declare(name, var_type);
+            let init = init.as_ref().map(|expr| {
+                let expr = analyze_expression(expr, scope, table);
+                convert_implicit(expr, var_type, || format!("initialization of {}", name))
+            });
+
+            Stmt::Declaration(id, init)
+        }
+    }
+}
+
+fn analyze_statement(statement: &Statement, scope: &mut Scope, table: &mut FunctionTable) -> Stmt {
+    match statement {
+        Statement::Expression(Some(expr)) => Stmt::Expression(analyze_expression(expr, scope, table)),
+
+        // 空语句
+        Statement::Expression(None) => Stmt::Block(Vec::new()),
+
+        Statement::Return(expr) => {
+            let return_type = scope.signature.return_type.clone();
+            match expr {
+                Some(_) if return_type == Type::Void => panic!("Void function {} should not return a value", scope.name),
+                Some(expr) => {
+                    let expr = analyze_expression(expr, scope, table);
+                    Stmt::Return(Some(convert_implicit(expr, &return_type, || "return".to_string())))
+                }
+                None if return_type != Type::Void => panic!("Non-void function {} should return a value", scope.name),
+                None => Stmt::Return(None),
+            }
+        }
+
+        // 返回类型相同，参数放得进调用者的栈参数区，才能保证是尾调用
+        Statement::TailReturn(expr) => {
+            let expr = analyze_expression(expr, scope, table);
+            let ExprKind::Call(name, signature, args) = &expr.kind else {
+                panic!("musttail return value in {} is not a function call", scope.name);
+            };
+            if expr.ty != scope.signature.return_type {
+                panic!("musttail call to {} in {} must have the same return type", name, scope.name);
+            }
+            let value_type = |t: &Type| Ty::from_type(t).expect("Argument without value");
+            let caller: Vec<Ty> = scope.signature.params.iter().map(value_type).collect();
+            let callee: Vec<Ty> = args.iter().map(|arg| value_type(&arg.ty)).collect();
+            // 和生成中间表示时一样，没有原型的函数所有参数都按命名参数传
+            let named = if !signature.prototyped {
+                Some(callee.len())
+            } else {
+                signature.variadic.then_some(signature.params.len())
+            };
+            if !target(table.arch).tail_call_fits(&caller, &callee, named) {
+                panic!("Arguments of musttail call to {} do not fit in the stack arguments of {}", name, scope.name);
+            }
+            Stmt::TailCall(expr)
+        }
+
+        Statement::If(condition, if_body, else_body) => {
+            let condition = analyze_condition(condition, scope, table);
+            let if_body = analyze_statement(if_body, scope, table);
+            let else_body = else_body.as_ref().map(|body| Box::new(analyze_statement(body, scope, table)));
+            Stmt::If(condition, Box::new(if_body), else_body)
+        }
+
+        // 新的块就是新的作用域
+        Statement::Compound(items) => {
+            scope.scopes.push(HashMap::new());
+            let block = analyze_items(items, scope, table);
+            scope.scopes.pop();
+            Stmt::Block(block)
+        }
+
+        Statement::For(init, condition, post_expression, body) => {
+            let init = init.as_ref().map(|expr| Box::new(Stmt::Expression(analyze_expression(expr, scope, table))));
+            analyze_for(init, condition, post_expression, body, scope, table)
+        }
+
+        // for 中声明的变量只在循环里可见
+        Statement::ForDeclaration(declaration, condition, post_expression, body) => {
+            scope.scopes.push(HashMap::new());
+            let init = Some(Box::new(analyze_declaration(declaration, scope, table)));
+            let stmt = analyze_for(init, condition, post_expression, body, scope, table);
+            scope.scopes.pop();
+            stmt
+        }
+
+        Statement::While(condition, body) => {
+            let condition = analyze_condition(condition, scope, table);
+            Stmt::While(condition, Box::new(analyze_loop_body(body, scope, table)))
+        }
+
+        Statement::DoWhile(condition, body) => {
+            let body = analyze_loop_body(body, scope, table);
+            Stmt::DoWhile(Box::new(body), analyze_condition(condition, scope, table))
+        }
+
+        Statement::Break => {
+            if scope.loop_depth == 0 {
+                panic!("Break statement not in loop");
+            }
+            Stmt::Break
+        }
+
+        Statement::Continue => {
+            if scope.loop_depth == 0 {
+                panic!("Continue statement not in loop");
+            }
+            Stmt::Continue
+        }
+    }
+}
+
+fn analyze_for(init: Option<Box<Stmt>>, condition: &Expression, post_expression: &Option<Expression>, body: &Statement, scope: &mut Scope, table: &mut FunctionTable) -> Stmt {
+    let condition = analyze_condition(condition, scope, table);
+    let post_expression = post_expression.as_ref().map(|expr| analyze_expression(expr, scope, table));
+    let body = analyze_loop_body(body, scope, table);
+    Stmt::For(init, condition, post_expression, Box::new(body))
+}
+
+// 循环体内可以使用 break continue
+fn analyze_loop_body(body: &Statement, scope: &mut Scope, table: &mut FunctionTable) -> Stmt {
+    scope.loop_depth += 1;
+    let body = analyze_statement(body, scope, table);
+    scope.loop_depth -= 1;
+    body
+}
+
+/*
+ * 条件表达式
+ * 结果统一成整数，浮点数变成和0比较
+*/
+fn analyze_condition(expression: &Expression, scope: &mut Scope, table: &mut FunctionTable) -> Expr {
+    let expr = analyze_expression(expression, scope, table);
+    to_condition(expr)
+}
+
+fn to_condition(expr: Expr) -> Expr {
+    let expr = value(expr);
+    if expr.ty.is_floating() {
+        let zero = Expr::new(ExprKind::FloatConstant("0".to_string()), expr.ty.clone());
+        Expr::new(ExprKind::Binary(Operator::NotEqual, Box::new(expr), Box::new(zero)), Type::Int)
+    } else {
+        expr
+    }
+}
+
+// 表达式的值要被使用，不能是 void
+fn value(expr: Expr) -> Expr {
+    if expr.ty == Type::Void {
+        panic!("Void value not ignored as it ought to be");
+    }
+    expr
+}
+
+// 转换到指定类型，类型相同时不变
+fn convert(expr: Expr, target: &Type) -> Expr {
+    if &expr.ty == target {
+        expr
+    } else {
+        Expr::new(ExprKind::Cast(Box::new(expr)), target.clone())
+    }
+}
+
+// 整数常量0可以作为空指针
+fn is_null_pointer(expr: &Expr) -> bool {
+    matches!(expr.kind, ExprKind::Constant(0))
+}
+
+/*
+ * 赋值、初始化、传参、返回时的隐式转换
+ * context 描述发生转换的位置，只在出错时使用
+*/
+fn convert_implicit(expr: Expr, target: &Type, context: impl Fn() -> String) -> Expr {
+    let expr = value(expr);
+    let null_pointer = matches!(target, Type::Pointer(_)) && is_null_pointer(&expr);
+
+    if !null_pointer && !target.is_convertible_from(&expr.ty) {
+        panic!("Incompatible types in {}: expected {:?} but got {:?}", context(), target, expr.ty);
+    }
+    convert(expr, target)
+}
+
+fn is_integer(t: &Type) -> bool {
+    matches!(t, Type::Int | Type::Char)
+}
+
+// a op= b 对应的二元运算
+fn compound_operator(op: Operator) -> Operator {
+    match op {
+        Operator::AssignPlus => Operator::Plus,
+        Operator::AssignMinus => Operator::Minus,
+        Operator::AssignMult => Operator::Multiplication,
+        Operator::AssignDiv => Operator::Division,
+        Operator::AssignMod => Operator::Modulo,
+        _ => panic!("Unexpected assignment operator {:?}", op),
+    }
+}
+
+/*
+ * 算术运算的操作数类型
+ * + - * / 需要算术类型，% 和位运算需要整数
+ * 返回运算时使用的类型
+*/
+fn arithmetic_type(op: Operator, lhs: &Type, rhs: &Type) -> Type {
+    let integer_only = op.is_bitwise_operators() || op == Operator::Modulo;
+    let valid = if integer_only {
+        is_integer(lhs) && is_integer(rhs)
+    } else {
+        lhs.is_arithmetic() && rhs.is_arithmetic()
+    };
+
+    if !valid {
+        panic!("Invalid operands to binary operator {:?}: {:?} and {:?}", op, lhs, rhs);
+    }
+    Type::common(lhs, rhs)
+}
+
+/*
+ * 比较的操作数类型
+ * 算术类型之间比较，或者兼容的指针之间比较，指针也可以和0比较
+*/
+fn comparison_type(op: Operator, lhs: &Expr, rhs: &Expr) -> Type {
+    match (&lhs.ty, &rhs.ty) {
+        (a, b) if a.is_arithmetic() && b.is_arithmetic() => Type::common(a, b),
+        (Type::Pointer(_), Type::Pointer(_)) if lhs.ty.is_convertible_from(&rhs.ty) => lhs.ty.clone(),
+        (Type::Pointer(_), _) if is_null_pointer(rhs) => lhs.ty.clone(),
+        (_, Type::Pointer(_)) if is_null_pointer(lhs) => rhs.ty.clone(),
+        (a, b) => panic!("Invalid operands to binary operator {:?}: {:?} and {:?}", op, a, b),
+    }
+}
+
+/*
+ * ?: 两个分支的类型
+*/
+fn conditional_type(e2: &Expr, e3: &Expr) -> Type {
+    match (&e2.ty, &e3.ty) {
+        (a, b) if a.is_arithmetic() && b.is_arithmetic() => Type::common(a, b),
+        (a, b) if a == b => a.clone(),
+        (Type::Pointer(_), _) if is_null_pointer(e3) => e2.ty.clone(),
+        (_, Type::Pointer(_)) if is_null_pointer(e2) => e3.ty.clone(),
+        (a, b) => panic!("Type mismatch in conditional expression: {:?} and {:?}", a, b),
+    }
+}
+
+fn analyze_expression(expression: &Expression, scope: &mut Scope, table: &mut FunctionTable) -> Expr {
+    match expression {
+        Expression::Constant(n) => Expr::new(ExprKind::Constant(*n as i64), Type::Int),
+
+        Expression::FloatConstant(f, t) => Expr::new(ExprKind::FloatConstant(f.clone()), t.clone()),
+
+        Expression::StringLiteral(s) => Expr::new(ExprKind::StringLiteral(s.clone()), Type::Char.pointer_to()),
+
+        Expression::Variable(name) => {
+            let id = scope.lookup(name);
+            match &scope.locals[id] {
+                // 数组退化成指针
+                Type::VaList => Expr::new(ExprKind::Address(id), Type::VaList.pointer_to()),
+                t => Expr::new(ExprKind::Variable(id), t.clone()),
+            }
+        }
+
+        Expression::UnaryOperators(Operator::LogicalNegation, expr) => {
+            let expr = analyze_condition(expr, scope, table);
+            Expr::new(ExprKind::Unary(Operator::LogicalNegation, Box::new(expr)), Type::Int)
+        }
+
+        Expression::UnaryOperators(op, expr) => {
+            let expr = value(analyze_expression(expr, scope, table));
+            // ~ 只能用于整数
+            let valid = if *op == Operator::BitwiseComplement { is_integer(&expr.ty) } else { expr.ty.is_arithmetic() };
+            if !valid {
+                panic!("Invalid operand to unary {:?}: {:?}", op, expr.ty);
+            }
+            // char 先提升为 int
+            let operand_type = match &expr.ty {
+                Type::Char => Type::Int,
+                t => t.clone(),
+            };
+            let expr = convert(expr, &operand_type);
+            Expr::new(ExprKind::Unary(*op, Box::new(expr)), operand_type)
+        }
+
+        Expression::AssignmentOperators(op, lhs, rhs) => {
+            let lhs = analyze_expression(lhs, scope, table);
+            if !lhs.is_lvalue() {
+                panic!("lvalue required as left operand of assignment");
+            }
+            let rhs = analyze_expression(rhs, scope, table);
+            let var_type = lhs.ty.clone();
+
+            if op == &Operator::Assignment {
+                let rhs = convert_implicit(rhs, &var_type, || "assignment".to_string());
+                return Expr::new(ExprKind::Assign(Box::new(lhs), Box::new(rhs)), var_type);
+            }
+
+            // a op= b 相当于 a = a op b，运算在两边的公共类型上进行
+            let binary_op = compound_operator(*op);
+            let rhs = value(rhs);
+            let common_type = arithmetic_type(binary_op, &var_type, &rhs.ty);
+            let rhs = convert(rhs, &common_type);
+            Expr::new(ExprKind::CompoundAssign(binary_op, Box::new(lhs), Box::new(rhs)), var_type)
+        }
+
+        Expression::BinaryOperators(op, lhs, rhs) if op == &Operator::LogicalAnd || op == &Operator::LogicalOr => {
+            let lhs = analyze_condition(lhs, scope, table);
+            let rhs = analyze_condition(rhs, scope, table);
+            Expr::new(ExprKind::Binary(*op, Box::new(lhs), Box::new(rhs)), Type::Int)
+        }
+
+        Expression::BinaryOperators(op, lhs, rhs) => {
+            let lhs = value(analyze_expression(lhs, scope, table));
+            let rhs = value(analyze_expression(rhs, scope, table));
+
+            // 两边先转换成同一类型
+            let (operand_type, result_type) = if op.is_comparison_operators() {
+                (comparison_type(*op, &lhs, &rhs), Type::Int)
+            } else {
+                let t = arithmetic_type(*op, &lhs.ty, &rhs.ty);
+                (t.clone(), t)
+            };
+
+            let lhs = convert(lhs, &operand_type);
+            let rhs = convert(rhs, &operand_type);
+            Expr::new(ExprKind::Binary(*op, Box::new(lhs), Box::new(rhs)), result_type)
+        }
+
+        Expression::TernaryOperators(e1, e2, e3) => {
+            let e1 = analyze_condition(e1, scope, table);
+            let e2 = analyze_expression(e2, scope, table);
+            let e3 = analyze_expression(e3, scope, table);
+            let result_type = conditional_type(&e2, &e3);
+
+            let e2 = convert(e2, &result_type);
+            let e3 = convert(e3, &result_type);
+            Expr::new(ExprKind::Ternary(Box::new(e1), Box::new(e2), Box::new(e3)), result_type)
+        }
+
+        Expression::FunctionCalls(id, args) => analyze_call(id, args, scope, table),
+
+        Expression::Cast(t, expr) => {
+            let expr = analyze_expression(expr, scope, table);
+            let valid = match (t, &expr.ty) {
+                (Type::Void, _) => true,
+                (Type::VaList, _) => false,
+                (_, Type::Void) => panic!("Void value not ignored as it ought to be"),
+                // 指针和浮点数之间不能转换
+                (Type::Pointer(_), from) => !from.is_floating(),
+                (to, Type::Pointer(_)) => !to.is_floating(),
+                (to, from) => to.is_arithmetic() && from.is_arithmetic(),
+            };
+            if !valid {
+                panic!("Invalid cast from {:?} to {:?}", expr.ty, t);
+            }
+            convert(expr, t)
+        }
+
+        Expression::VaStart(ap) => {
+            if !scope.signature.variadic {
+                panic!("va_start used in function with fixed arguments");
+            }
+            let ap = analyze_va_list(ap, scope, table);
+            Expr::new(ExprKind::VaStart(Box::new(ap)), Type::Void)
+        }
+
+        Expression::VaArg(ap, t) => {
+            match t {
+                Type::Float => panic!("float is promoted to double when passed through ..."),
+                Type::Void | Type::VaList => panic!("Invalid type {:?} in va_arg", t),
+                _ => {}
+            }
+            let ap = analyze_va_list(ap, scope, table);
+            Expr::new(ExprKind::VaArg(Box::new(ap)), t.clone())
+        }
+
+        Expression::VaEnd(ap) => {
+            let ap = analyze_va_list(ap, scope, table);
+            Expr::new(ExprKind::VaEnd(Box::new(ap)), Type::Void)
+        }
+
+        Expression::VaCopy(dest, src) => {
+            let dest = analyze_va_list(dest, scope, table);
+            let src = analyze_va_list(src, scope, table);
+            Expr::new(ExprKind::VaCopy(Box::new(dest), Box::new(src)), Type::Void)
+        }
+    }
+}
+
+// va_list 参数，值是 va_list 的地址
+fn analyze_va_list(ap: &Expression, scope: &mut Scope, table: &mut FunctionTable) -> Expr {
+    let ap = analyze_expression(ap, scope, table);
+    if ap.ty != Type::VaList.pointer_to() {
+        panic!("Expected va_list");
+    }
+    ap
+}
+
+/*
+ * 函数调用
+ * 没有声明的函数在 C89 中隐式声明为 int f();
+ * 有原型时检查参数个数和类型，多出来的参数 (可变参数、没有原型) 按默认参数提升
+*/
+fn analyze_call(id: &str, args: &[Expression], scope: &mut Scope, table: &mut FunctionTable) -> Expr {
+    let signature = match table.functions.get(id) {
+        Some((signature, _)) => signature.clone(),
+        None if table.implicit_declarations => {
+            eprintln!("warning: implicit declaration of function '{}'", id);
+            table.functions.insert(id.to_string(), (Signature::implicit(), false));
+            Signature::implicit()
+        }
+        None => panic!("Undeclared function: {}", id),
+    };
+
+    let expected_nargs = signature.params.len();
+    if signature.prototyped && args.len() != expected_nargs && !(signature.variadic && args.len() > expected_nargs) {
+        panic!("Wrong number of arguments to {}: expected {} but got {}", id, expected_nargs, args.len());
+    }
+
+    let args = args.iter().enumerate().map(|(i, arg)| {
+        let arg = analyze_expression(arg, scope, table);
+        match signature.params.get(i) {
+            Some(param_type) if signature.prototyped => {
+                convert_implicit(arg, param_type, || format!("argument {} of {}", i + 1, id))
+            }
+            _ => {
+                let arg = value(arg);
+                let promoted = arg.ty.promoted();
+                convert(arg, &promoted)
+            }
+        }
+    }).collect();
+
+    let return_type = signature.return_type.clone();
+    Expr::new(ExprKind::Call(id.to_string(), signature, args), return_type)
+}
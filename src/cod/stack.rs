@@ -0,0 +1,42 @@
+use super::ir::{Function, Instr, Terminator, Ty};
+
+/*
+ * 各个目标的栈帧布局共用的计算
+ * x86-64 的栈帧从 rbp 往下分配，AArch64 和 RISC-V 的栈帧从 sp 往上分配
+*/
+
+pub fn align_to(n: isize, align: isize) -> isize {
+    (n + align - 1) / align * align
+}
+
+// 从 sp 往上分配 size 字节，返回相对 sp 的偏移
+pub fn allocate(used: &mut isize, size: isize, align: isize) -> isize {
+    let offset = align_to(*used, align);
+    *used = offset + size;
+    offset
+}
+
+// 从帧指针往下分配 size 字节，返回相对帧指针的偏移 (负数)
+pub fn allocate_below(used: &mut isize, size: isize, align: isize) -> isize {
+    *used = align_to(*used + size, align);
+    -*used
+}
+
+/*
+ * 函数中的调用需要的最大的栈参数空间
+ * classify 按目标的调用约定返回栈参数的大小，named 是可变参数函数的命名参数个数
+*/
+pub fn outgoing_size(function: &Function, classify: impl Fn(&[Ty], Option<usize>) -> isize) -> isize {
+    let types = |args: &[_]| -> Vec<Ty> { args.iter().map(|a| function.ty(*a)).collect() };
+    function.blocks.iter().flat_map(|block| {
+        let calls = block.instrs.iter().filter_map(|instr| match instr {
+            Instr::Call(_, _, args, named) => Some(classify(&types(args), *named)),
+            _ => None,
+        });
+        let tail = match &block.terminator {
+            Terminator::TailCall(_, args, named) => Some(classify(&types(args), *named)),
+            _ => None,
+        };
+        calls.chain(tail)
+    }).max().unwrap_or(0)
+}
@@ -0,0 +1,123 @@
+use super::dialect::{assembly, dialect};
+use super::driver::lower;
+use super::elf::write_relocatable;
+use super::encode::assemble;
+use super::generator::generate;
+use super::ir::Ty;
+use super::options::{Arch, Options, Syntax};
+use super::typed_ast::Program;
+
+/*
+ * 目标机器 (--target=)
+ * 前端是共用的，原生的目标共用中间表示和优化，只负责把中间表示翻译成自己的代码
+ * wasm32 需要结构化的控制流，直接从带类型的语法树生成
+ * 汇编文本，-masm= 选择语法
+ * 目标文件 (-c)
+ * 目标文件默认的扩展名
+ * 尾调用的参数能不能放进调用者收到的栈参数区，named 是可变参数函数的命名参数个数
+ * 目标不支持的输出返回错误
+*/
+pub trait Target {
+    fn assembly(&self, program: &Program, options: &Options) -> Result<String, String>;
+
+    fn object(&self, program: &Program, options: &Options) -> Result<Vec<u8>, String>;
+
+    fn object_extension(&self) -> &'static str {
+        "o"
+    }
+
+    fn tail_call_fits(&self, caller: &[Ty], callee: &[Ty], named: Option<usize>) -> bool;
+}
+
+pub fn target(arch: Arch) -> Box<dyn Target> {
+    match arch {
+        Arch::X86_64 => Box::new(X86_64),
+        Arch::Aarch64 => Box::new(Aarch64),
+        Arch::Riscv64 => Box::new(Riscv64),
+        Arch::Wasm32 => Box::new(Wasm32),
+    }
+}
+
+// 只有 x86-64 有多种汇编语法
+fn default_syntax(options: &Options) -> Result<(), String> {
+    match options.syntax {
+        Syntax::Intel => Ok(()),
+        _ => Err("-masm= is only supported for x86_64-linux".to_string()),
+    }
+}
+
+// x86-64 Linux，有三种汇编语法和内置的汇编器
+pub struct X86_64;
+
+impl Target for X86_64 {
+    fn assembly(&self, program: &Program, options: &Options) -> Result<String, String> {
+        Ok(assembly(&generate(&lower(program, options)), dialect(options.syntax).as_ref()))
+    }
+
+    fn object(&self, program: &Program, options: &Options) -> Result<Vec<u8>, String> {
+        Ok(write_relocatable(&assemble(&generate(&lower(program, options)))))
+    }
+
+    fn tail_call_fits(&self, caller: &[Ty], callee: &[Ty], _named: Option<usize>) -> bool {
+        super::frame::tail_call_fits(caller, callee)
+    }
+}
+
+// AArch64 Linux，只输出 GNU as 语法的汇编
+pub struct Aarch64;
+
+impl Target for Aarch64 {
+    fn assembly(&self, program: &Program, options: &Options) -> Result<String, String> {
+        default_syntax(options)?;
+        super::aarch64::generate(&lower(program, options))
+    }
+
+    fn object(&self, _program: &Program, _options: &Options) -> Result<Vec<u8>, String> {
+        Err("-c is only supported for x86_64-linux and wasm32".to_string())
+    }
+
+    fn tail_call_fits(&self, caller: &[Ty], callee: &[Ty], _named: Option<usize>) -> bool {
+        super::aarch64::frame::tail_call_fits(caller, callee)
+    }
+}
+
+// RISC-V RV64GC Linux，只输出 GNU as 语法的汇编
+pub struct Riscv64;
+
+impl Target for Riscv64 {
+    fn assembly(&self, program: &Program, options: &Options) -> Result<String, String> {
+        default_syntax(options)?;
+        super::riscv64::generate(&lower(program, options))
+    }
+
+    fn object(&self, _program: &Program, _options: &Options) -> Result<Vec<u8>, String> {
+        Err("-c is only supported for x86_64-linux and wasm32".to_string())
+    }
+
+    fn tail_call_fits(&self, caller: &[Ty], callee: &[Ty], named: Option<usize>) -> bool {
+        super::riscv64::frame::tail_call_fits(caller, callee, named)
+    }
+}
+
+// WebAssembly，汇编是 .wat 文本，-c 输出二进制的 .wasm 模块
+pub struct Wasm32;
+
+impl Target for Wasm32 {
+    fn assembly(&self, program: &Program, options: &Options) -> Result<String, String> {
+        default_syntax(options)?;
+        Ok(super::wasm::generate(program)?.to_string())
+    }
+
+    fn object(&self, program: &Program, _options: &Options) -> Result<Vec<u8>, String> {
+        Ok(super::wasm::binary::encode(&super::wasm::generate(program)?))
+    }
+
+    fn object_extension(&self) -> &'static str {
+        "wasm"
+    }
+
+    // 参数都在 wasm 的栈上，只有可变参数要放在影子栈上，不能用 return_call
+    fn tail_call_fits(&self, _caller: &[Ty], callee: &[Ty], named: Option<usize>) -> bool {
+        named.is_none_or(|n| callee.len() <= n)
+    }
+}
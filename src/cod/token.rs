@@ -1,3 +1,5 @@
+use super::types::Type;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // C语言中有六类
@@ -10,6 +12,8 @@ pub enum Token {
     A~Z
     */
     Constant(i32), // 常量
+    FloatConstant(String, Type), // 浮点常量 保留原文，生成代码时再按类型转换
+    StringLiteral(Vec<u8>), // 字符串 已经处理过转义
     Operator(Operator), // 操作符号
     Punctuator(Punctuator), // 标点符号
 }
@@ -25,6 +29,16 @@ pub enum Keyword {
     Do,
     If,
     While,
+    Float,
+    Double,
+    Long,
+    Char,
+    Void,
+    Const,
+    VaList,
+    Static,
+    Inline,
+    Attribute, // __attribute__
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -38,6 +52,7 @@ pub enum Punctuator {
     Comma, // ,
     Colon, // :
     Semicolon, // ;
+    Ellipsis, // ...
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -53,6 +68,7 @@ pub enum Operator {
     BitwiseAnd,         // &
     BitwiseOr,          // |
     BitwiseXor,         // ^
+    BitwiseComplement,  // ~
 
     LogicalNegation,    // !
     LogicalAnd,         // &&
@@ -76,34 +92,36 @@ pub enum Operator {
 
 impl Operator {
     pub fn is_unary(self) -> bool { // - ! ~
-        match self {
-            Operator::Minus |
-            Operator::LogicalNegation => true,
-            _ => false,
-        }
+        matches!(self, Operator::Minus | Operator::LogicalNegation | Operator::BitwiseComplement)
     }
  
     pub fn is_bitwise_operators(self) -> bool { // << >> & | ^
-        match self {
+        matches!(self,
             Operator::BitwiseShiftLeft |
             Operator::BitwiseShiftRight |
             Operator::BitwiseAnd |
-            Operator::BitwiseOr | 
-            Operator::BitwiseXor => true,
-            _ => false,
-        }
+            Operator::BitwiseOr |
+            Operator::BitwiseXor)
+    }
+
+    pub fn is_comparison_operators(self) -> bool { // == != < <= > >=
+        matches!(self,
+            Operator::Equal
+            | Operator::NotEqual
+            | Operator::LessThan
+            | Operator::LessThanOrEqual
+            | Operator::GreaterThan
+            | Operator::GreaterThanOrEqual)
     }
 
     pub fn is_assignment_operators(self) -> bool { // = += -= *= /= %=
-        match self {
+        matches!(self,
             Operator::Assignment
             | Operator::AssignPlus
             | Operator::AssignMinus
             | Operator::AssignMult
             | Operator::AssignDiv
-            | Operator::AssignMod => true,
-            _ => false,
-        }
+            | Operator::AssignMod)
     }
     
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+use super::token::Operator;
+use super::types::{FunctionSpecifiers, Signature, Type};
+// 语义分析之后的语法树
+
+/*
+ * 和 ast 的区别
+ * 每个变量都已经解析成函数内唯一的编号
+ * 每个表达式都带有类型，隐式类型转换都变成了 Cast
+ * 条件表达式 (if while for ?: ! && ||) 都已经是整数
+ * 后端不需要再报告用户的错误
+*/
+
+// 变量编号，也是 Function::locals 的下标
+pub type VarId = usize;
+
+/*
+ * 定义了的函数
+ * 只有声明没有定义的函数留给链接器
+*/
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+/*
+ * 函数名
+ * 函数签名
+ * 说明符 (所有声明合在一起)
+ * 参数对应的变量
+ * 函数内所有变量的类型 (包括参数)
+ * 函数内所有变量的名字，用于报告错误和 REPL 按名字保存变量
+ * 函数体
+*/
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub signature: Signature,
+    pub specifiers: FunctionSpecifiers,
+    pub params: Vec<VarId>,
+    pub locals: Vec<Type>,
+    pub names: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+/*
+ * 语句
+ * 声明也是一种语句，作用域就是所在的 Block
+*/
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expression(Expr),
+    Declaration(VarId, Option<Expr>), // int a = 1; 初始值已经转换成变量的类型
+    Return(Option<Expr>), // 返回值已经转换成返回类型
+    TailCall(Expr), // 必须是尾调用的 return f(x)，返回类型和函数相同
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    Block(Vec<Stmt>),
+    For(Option<Box<Stmt>>, Expr, Option<Expr>, Box<Stmt>), // 初始化是声明或者表达式语句
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    Break,
+    Continue,
+}
+
+/*
+ * 表达式和它的类型
+*/
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Constant(i64),
+    FloatConstant(String), // 字面量的原文，精度由类型决定
+    StringLiteral(Vec<u8>),
+    Variable(VarId),
+    Address(VarId), // 数组 (va_list) 退化成指针
+    Unary(Operator, Box<Expr>), // - !
+    Binary(Operator, Box<Expr>, Box<Expr>), // 两边已经转换成同一类型
+    Assign(Box<Expr>, Box<Expr>), // 左值 = 右值
+    CompoundAssign(Operator, Box<Expr>, Box<Expr>), // a += b，右边已经转换成运算的类型
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(String, Signature, Vec<Expr>), // 参数已经转换成形参或提升后的类型
+    Cast(Box<Expr>), // 从里面表达式的类型转换到这个表达式的类型
+    VaStart(Box<Expr>),
+    VaArg(Box<Expr>),
+    VaEnd(Box<Expr>),
+    VaCopy(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, ty: Type) -> Self {
+        Expr { kind, ty }
+    }
+
+    // 是否可以放在赋值的左边
+    pub fn is_lvalue(&self) -> bool {
+        matches!(self.kind, ExprKind::Variable(_))
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * 类型
+ * 整数和指针统一按64位寄存器处理
+ * float double 使用SSE
+ * long double 使用x87
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Type {
+    Void,
+    #[default]
+    Int,
+    Char,
+    Float,
+    Double,
+    LongDouble,
+    Pointer(Box<Type>),
+    // System V 的 va_list 是一个24字节的结构体数组，使用时退化成指针
+    VaList,
+}
+
+impl Type {
+    pub fn is_floating(&self) -> bool {
+        matches!(self, Type::Float | Type::Double | Type::LongDouble)
+    }
+
+    pub fn pointer_to(&self) -> Type {
+        Type::Pointer(Box::new(self.clone()))
+    }
+
+    /*
+     * 传给可变参数时的默认参数提升
+     * char -> int float -> double
+    */
+    pub fn promoted(&self) -> Type {
+        match self {
+            Type::Char => Type::Int,
+            Type::Float => Type::Double,
+            Type::VaList => Type::VaList.pointer_to(),
+            t => t.clone(),
+        }
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, Type::Int | Type::Char) || self.is_floating()
+    }
+
+    /*
+     * 能否隐式转换 (赋值、传参)
+     * 算术类型之间可以互相转换
+     * 指针只能转换成相同类型的指针，void * 可以和任何指针互相转换
+    */
+    pub fn is_convertible_from(&self, from: &Type) -> bool {
+        match (self, from) {
+            (a, b) if a.is_arithmetic() && b.is_arithmetic() => true,
+            (Type::Pointer(a), Type::Pointer(b)) => a == b || **a == Type::Void || **b == Type::Void,
+            _ => false,
+        }
+    }
+
+    // 寻常算术转换的等级
+    fn rank(&self) -> u8 {
+        match self {
+            Type::Float => 1,
+            Type::Double => 2,
+            Type::LongDouble => 3,
+            _ => 0,
+        }
+    }
+
+    /*
+     * 寻常算术转换
+     * 两个操作数转换到等级更高的类型
+     * 都是整数时 char 提升为 int，有指针时结果为指针
+    */
+    pub fn common(a: &Type, b: &Type) -> Type {
+        if a.rank() == 0 && b.rank() == 0 {
+            return match (a, b) {
+                (Type::Pointer(_), _) => a.clone(),
+                (_, Type::Pointer(_)) => b.clone(),
+                _ => Type::Int,
+            };
+        }
+
+        if a.rank() >= b.rank() {
+            a.clone()
+        } else {
+            b.clone()
+        }
+    }
+}
+
+/*
+ * 函数签名
+ * 返回类型
+ * 参数类型
+ * 是否有可变参数 ...
+ * 是否有原型，int f(); 和隐式声明的函数没有原型，调用时不检查参数
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Signature {
+    pub return_type: Type,
+    pub params: Vec<Type>,
+    pub variadic: bool,
+    pub prototyped: bool,
+}
+
+/*
+ * 函数说明符和属性
+ * static 只在本文件中可见
+ * inline 建议内联
+ * __attribute__((noinline)) 不内联
+ * __attribute__((always_inline)) 总是内联
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FunctionSpecifiers {
+    pub is_static: bool,
+    pub inline: bool,
+    pub noinline: bool,
+    pub always_inline: bool,
+}
+
+impl FunctionSpecifiers {
+    // 同一个函数的多次声明，说明符合在一起
+    pub fn merge(&self, other: &FunctionSpecifiers) -> FunctionSpecifiers {
+        FunctionSpecifiers {
+            is_static: self.is_static || other.is_static,
+            inline: self.inline || other.inline,
+            noinline: self.noinline || other.noinline,
+            always_inline: self.always_inline || other.always_inline,
+        }
+    }
+}
+
+impl Signature {
+    // C89 中隐式声明的函数: int f();
+    pub fn implicit() -> Self {
+        Signature {
+            return_type: Type::Int,
+            ..Default::default()
+        }
+    }
+
+    /*
+     * 两次声明是否兼容
+     * 没有原型的声明只需要返回类型相同
+    */
+    pub fn is_compatible(&self, other: &Signature) -> bool {
+        if !self.prototyped || !other.prototyped {
+            return self.return_type == other.return_type;
+        }
+        self == other
+    }
+}
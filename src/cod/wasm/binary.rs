@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use super::{FuncType, Instr, Module, ValType, DATA_BASE};
+
+/*
+ * WebAssembly 二进制格式 (.wasm)
+ * 依次输出 类型 导入 函数 内存 全局变量 导出 代码 数据 段
+ * 整数都用 LEB128 编码
+*/
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: &[u8] = &[1, 0, 0, 0];
+
+// 段的编号
+const TYPE_SECTION: u8 = 1;
+const IMPORT_SECTION: u8 = 2;
+const FUNCTION_SECTION: u8 = 3;
+const MEMORY_SECTION: u8 = 5;
+const GLOBAL_SECTION: u8 = 6;
+const EXPORT_SECTION: u8 = 7;
+const CODE_SECTION: u8 = 10;
+const DATA_SECTION: u8 = 11;
+
+// 导入导出的种类
+const FUNCTION_KIND: u8 = 0;
+const MEMORY_KIND: u8 = 2;
+
+fn unsigned(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        // 剩下的位都是符号位并且和这个字节的最高位相同时结束
+        if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, s: &str) {
+    unsigned(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+// 段: 编号，长度，内容
+fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+    out.push(id);
+    unsigned(out, content.len() as u64);
+    out.extend_from_slice(content);
+}
+
+// 向量: 元素个数，元素
+fn vector<T>(items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) -> Vec<u8> {
+    let mut out = Vec::new();
+    unsigned(&mut out, items.len() as u64);
+    for i in items.iter() {
+        item(&mut out, i);
+    }
+    out
+}
+
+fn value_type(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+    }
+}
+
+fn block_type(ty: &Option<ValType>) -> u8 {
+    ty.map_or(0x40, value_type)
+}
+
+// 没有立即数的指令的操作码
+fn opcode(name: &str) -> &'static [u8] {
+    match name {
+        "i32.eqz" => &[0x45],
+        "i32.eq" => &[0x46],
+        "i32.ne" => &[0x47],
+        "i32.lt_u" => &[0x49],
+        "i32.gt_u" => &[0x4b],
+        "i32.le_u" => &[0x4d],
+        "i32.ge_u" => &[0x4f],
+        "i64.eqz" => &[0x50],
+        "i64.eq" => &[0x51],
+        "i64.ne" => &[0x52],
+        "i64.lt_s" => &[0x53],
+        "i64.gt_s" => &[0x55],
+        "i64.le_s" => &[0x57],
+        "i64.ge_s" => &[0x59],
+        "f32.eq" => &[0x5b],
+        "f32.ne" => &[0x5c],
+        "f32.lt" => &[0x5d],
+        "f32.gt" => &[0x5e],
+        "f32.le" => &[0x5f],
+        "f32.ge" => &[0x60],
+        "f64.eq" => &[0x61],
+        "f64.ne" => &[0x62],
+        "f64.lt" => &[0x63],
+        "f64.gt" => &[0x64],
+        "f64.le" => &[0x65],
+        "f64.ge" => &[0x66],
+        "i32.add" => &[0x6a],
+        "i32.sub" => &[0x6b],
+        "i32.and" => &[0x71],
+        "i64.add" => &[0x7c],
+        "i64.sub" => &[0x7d],
+        "i64.mul" => &[0x7e],
+        "i64.div_s" => &[0x7f],
+        "i64.rem_s" => &[0x81],
+        "i64.and" => &[0x83],
+        "i64.or" => &[0x84],
+        "i64.xor" => &[0x85],
+        "i64.shl" => &[0x86],
+        "i64.shr_s" => &[0x87],
+        "f32.neg" => &[0x8c],
+        "f32.add" => &[0x92],
+        "f32.sub" => &[0x93],
+        "f32.mul" => &[0x94],
+        "f32.div" => &[0x95],
+        "f64.neg" => &[0x9a],
+        "f64.add" => &[0xa0],
+        "f64.sub" => &[0xa1],
+        "f64.mul" => &[0xa2],
+        "f64.div" => &[0xa3],
+        "i32.wrap_i64" => &[0xa7],
+        "i64.extend_i32_u" => &[0xad],
+        "f32.convert_i64_s" => &[0xb4],
+        "f32.demote_f64" => &[0xb6],
+        "f64.convert_i64_s" => &[0xb9],
+        "f64.promote_f32" => &[0xbb],
+        "i64.extend8_s" => &[0xc2],
+        "i64.trunc_sat_f32_s" => &[0xfc, 0x04],
+        "i64.trunc_sat_f64_s" => &[0xfc, 0x06],
+        _ => unreachable!("Unknown instruction {}", name),
+    }
+}
+
+fn memory_opcode(name: &str) -> u8 {
+    match name {
+        "i32.load" => 0x28,
+        "i64.load" => 0x29,
+        "f32.load" => 0x2a,
+        "f64.load" => 0x2b,
+        "i64.load8_s" => 0x30,
+        "i32.store" => 0x36,
+        "i64.store" => 0x37,
+        "f32.store" => 0x38,
+        "f64.store" => 0x39,
+        _ => unreachable!("Unknown memory instruction {}", name),
+    }
+}
+
+fn instruction(out: &mut Vec<u8>, instr: &Instr, functions: &HashMap<&str, u32>) {
+    let function = |name: &str| *functions.get(name).unwrap_or_else(|| unreachable!("Unknown function {}", name)) as u64;
+    match instr {
+        Instr::Block(ty) => out.extend_from_slice(&[0x02, block_type(ty)]),
+        Instr::Loop(ty) => out.extend_from_slice(&[0x03, block_type(ty)]),
+        Instr::If(ty) => out.extend_from_slice(&[0x04, block_type(ty)]),
+        Instr::Else => out.push(0x05),
+        Instr::End => out.push(0x0b),
+        Instr::Br(depth) => {
+            out.push(0x0c);
+            unsigned(out, *depth as u64);
+        }
+        Instr::BrIf(depth) => {
+            out.push(0x0d);
+            unsigned(out, *depth as u64);
+        }
+        Instr::Return => out.push(0x0f),
+        Instr::Call(name) => {
+            out.push(0x10);
+            unsigned(out, function(name));
+        }
+        Instr::ReturnCall(name) => {
+            out.push(0x12);
+            unsigned(out, function(name));
+        }
+        Instr::Drop => out.push(0x1a),
+        Instr::LocalGet(i) | Instr::LocalSet(i) | Instr::LocalTee(i) | Instr::GlobalGet(i) | Instr::GlobalSet(i) => {
+            out.push(match instr {
+                Instr::LocalGet(_) => 0x20,
+                Instr::LocalSet(_) => 0x21,
+                Instr::LocalTee(_) => 0x22,
+                Instr::GlobalGet(_) => 0x23,
+                _ => 0x24,
+            });
+            unsigned(out, *i as u64);
+        }
+        Instr::I32Const(n) => {
+            out.push(0x41);
+            signed(out, *n as i64);
+        }
+        Instr::I64Const(n) => {
+            out.push(0x42);
+            signed(out, *n);
+        }
+        Instr::F32Const(x) => {
+            out.push(0x43);
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        Instr::F64Const(x) => {
+            out.push(0x44);
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        Instr::Memory(name, align, offset) => {
+            out.push(memory_opcode(name));
+            unsigned(out, *align as u64);
+            unsigned(out, *offset as u64);
+        }
+        Instr::Op(name) => out.extend_from_slice(opcode(name)),
+    }
+}
+
+// 函数体: 局部变量 (相同类型连续的合成一组)，指令，end
+fn function_body(locals: &[ValType], body: &[Instr], functions: &HashMap<&str, u32>) -> Vec<u8> {
+    let mut groups: Vec<(u32, ValType)> = Vec::new();
+    for local in locals.iter() {
+        match groups.last_mut() {
+            Some((count, ty)) if ty == local => *count += 1,
+            _ => groups.push((1, *local)),
+        }
+    }
+
+    let mut out = vector(&groups, |out, (count, ty)| {
+        unsigned(out, *count as u64);
+        out.push(value_type(*ty));
+    });
+    for instr in body.iter() {
+        instruction(&mut out, instr, functions);
+    }
+    out.push(0x0b);
+    out
+}
+
+// 相同的函数类型只出现一次
+fn type_index(types: &mut Vec<FuncType>, ty: &FuncType) -> u64 {
+    match types.iter().position(|t| t == ty) {
+        Some(i) => i as u64,
+        None => {
+            types.push(ty.clone());
+            (types.len() - 1) as u64
+        }
+    }
+}
+
+pub fn encode(module: &Module) -> Vec<u8> {
+    let functions = module.function_indices();
+
+    let mut types = Vec::new();
+    let import_types: Vec<u64> = module.imports.iter().map(|i| type_index(&mut types, &i.ty)).collect();
+    let function_types: Vec<u64> = module.functions.iter().map(|f| type_index(&mut types, &f.ty)).collect();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(VERSION);
+
+    section(&mut out, TYPE_SECTION, &vector(&types, |out, ty| {
+        out.push(0x60);
+        let params = vector(&ty.params, |out, p| out.push(value_type(*p)));
+        out.extend_from_slice(&params);
+        let results = vector(&ty.result.iter().collect::<Vec<_>>(), |out, r| out.push(value_type(**r)));
+        out.extend_from_slice(&results);
+    }));
+
+    let imports: Vec<_> = module.imports.iter().zip(import_types).collect();
+    section(&mut out, IMPORT_SECTION, &vector(&imports, |out, (import, ty)| {
+        name(out, "env");
+        name(out, &import.name);
+        out.push(FUNCTION_KIND);
+        unsigned(out, *ty);
+    }));
+
+    section(&mut out, FUNCTION_SECTION, &vector(&function_types, |out, ty| unsigned(out, *ty)));
+
+    // 一块内存，只有最小的页数
+    section(&mut out, MEMORY_SECTION, &vector(&[module.pages], |out, pages| {
+        out.push(0x00);
+        unsigned(out, *pages as u64);
+    }));
+
+    // 可以修改的 i32 __stack_pointer
+    section(&mut out, GLOBAL_SECTION, &vector(&[module.stack_pointer], |out, sp| {
+        out.extend_from_slice(&[value_type(ValType::I32), 0x01, 0x41]);
+        signed(out, *sp as i64);
+        out.push(0x0b);
+    }));
+
+    let mut exports = vec![("memory", MEMORY_KIND, 0)];
+    for function in module.functions.iter().filter(|f| f.export) {
+        exports.push((&function.name, FUNCTION_KIND, functions[&function.name[..]]));
+    }
+    section(&mut out, EXPORT_SECTION, &vector(&exports, |out, (export, kind, index)| {
+        name(out, export);
+        out.push(*kind);
+        unsigned(out, *index as u64);
+    }));
+
+    section(&mut out, CODE_SECTION, &vector(&module.functions, |out, function| {
+        let body = function_body(&function.locals, &function.body, &functions);
+        unsigned(out, body.len() as u64);
+        out.extend_from_slice(&body);
+    }));
+
+    // 一个放在 DATA_BASE 的数据段
+    if !module.data.is_empty() {
+        section(&mut out, DATA_SECTION, &vector(&[&module.data], |out, data| {
+            out.push(0x00);
+            out.push(0x41);
+            signed(out, DATA_BASE as i64);
+            out.push(0x0b);
+            unsigned(out, data.len() as u64);
+            out.extend_from_slice(data);
+        }));
+    }
+
+    out
+}
@@ -0,0 +1,1016 @@
+use std::collections::HashMap;
+
+use super::float::float_bits;
+use super::ir::Ty;
+use super::token::Operator;
+use super::typed_ast::{Expr, ExprKind, Function, Program, Stmt, VarId};
+use super::types::{Signature, Type};
+
+pub mod binary;
+pub mod text;
+
+/*
+ * WebAssembly (wasm32) 的代码生成
+ * Wasm 只有结构化的控制流，所以不经过中间表示，直接从带类型的语法树生成
+ * if while do-while for 变成 block loop if，break continue 变成跳出外层的 br
+ * 局部变量都是 Wasm 的局部变量，要取地址的变量 (va_list) 放在线性内存里的影子栈上
+ *
+ * 类型: int char 是 i64 (char 总是符号扩展过的)，指针是 i32
+ * float 是 f32，double 是 f64，long double 也按 f64 处理
+ *
+ * 线性内存: DATA_BASE 开始是字符串常量，之后是 STACK_SIZE 大小的影子栈，向下增长
+ * 全局变量 0 是影子栈的栈顶 __stack_pointer
+ *
+ * 可变参数: 命名参数照常传递，多出来的参数由调用者写在影子栈上的一块内存中，地址作为最后一个 i32 参数
+ * 每个参数按自己的大小对齐 (int double 8字节，指针4字节)，va_list 就是指向下一个参数的指针
+ *
+ * 没有定义的函数从 "env" 模块导入，不是 static 的函数和内存 "memory" 都导出
+*/
+
+// 字符串常量的起始地址，0 附近留空，空指针不会指向字符串
+pub const DATA_BASE: u32 = 1024;
+// 影子栈的大小
+const STACK_SIZE: u32 = 64 * 1024;
+const PAGE_SIZE: u32 = 64 * 1024;
+// 影子栈栈顶的全局变量
+const STACK_POINTER: u32 = 0;
+// 影子栈上 va_list 的大小
+const VA_LIST_SIZE: u32 = 4;
+
+// 值类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+// 函数类型，Wasm 的函数最多有一个返回值
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub result: Option<ValType>,
+}
+
+/*
+ * 指令
+ * block loop if 的结果类型，用 End 结束
+ * br 的目标是从内向外数第几层块
+ * 调用用函数名，生成二进制时换成函数的编号
+ * 读写内存: 名字，对齐 (2的幂次)，偏移
+ * 没有立即数的指令用文本格式中的名字表示
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Block(Option<ValType>),
+    Loop(Option<ValType>),
+    If(Option<ValType>),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(String),
+    ReturnCall(String),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    Memory(&'static str, u32, u32),
+    Op(&'static str),
+}
+
+// 从 "env" 导入的函数
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub name: String,
+    pub ty: FuncType,
+}
+
+/*
+ * 定义的函数
+ * 参数之后的局部变量的类型
+*/
+#[derive(Debug, Clone)]
+pub struct WasmFunction {
+    pub name: String,
+    pub ty: FuncType,
+    pub export: bool,
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+}
+
+/*
+ * 模块
+ * 放在 DATA_BASE 的数据
+ * 影子栈栈顶的初值
+ * 线性内存的页数
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub functions: Vec<WasmFunction>,
+    pub data: Vec<u8>,
+    pub stack_pointer: u32,
+    pub pages: u32,
+}
+
+impl Module {
+    // 函数名对应的编号，导入的函数在前
+    pub fn function_indices(&self) -> HashMap<&str, u32> {
+        let imports = self.imports.iter().map(|i| &i.name[..]);
+        let functions = self.functions.iter().map(|f| &f.name[..]);
+        imports.chain(functions).enumerate().map(|(i, name)| (name, i as u32)).collect()
+    }
+}
+
+/*
+ * 变量的位置
+ * Wasm 的局部变量
+ * 影子栈栈帧中的偏移
+*/
+#[derive(Debug, Clone, Copy)]
+enum Home {
+    Local(u32),
+    Frame(u32),
+}
+
+/*
+ * 生成一个函数时的上下文
+ * 正在生成的函数
+ * 定义了的函数的签名，调用时按被调用函数的形参转换
+ * 导入的函数和字符串常量，整个模块共用
+ * 参数之后的局部变量
+ * 每个变量的位置
+ * 可变参数函数中保存可变参数地址的参数
+ * 影子栈栈帧: 保存帧地址的局部变量和栈帧的大小
+ * va_arg 用的两个临时变量
+ * 现在打开的块的层数
+ * 循环栈 (break 的目标, continue 的目标)，都是块的层数
+ * 已经生成的指令
+ * 出错的原因
+*/
+struct Context<'a> {
+    function: &'a Function,
+    signatures: &'a HashMap<&'a str, &'a Signature>,
+    imports: &'a mut Vec<Import>,
+    data: &'a mut Vec<u8>,
+    locals: Vec<ValType>,
+    homes: Vec<Option<Home>>,
+    va_param: Option<u32>,
+    frame: Option<(u32, u32)>,
+    va_temps: Option<(u32, u32)>,
+    depth: u32,
+    loops: Vec<(u32, u32)>,
+    code: Vec<Instr>,
+    error: Option<String>,
+}
+
+impl<'a> Context<'a> {
+    fn emit(&mut self, instr: Instr) {
+        self.code.push(instr);
+    }
+
+    fn op(&mut self, name: &'static str) {
+        self.code.push(Instr::Op(name));
+    }
+
+    // 新的局部变量，编号在参数之后
+    fn new_local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        (self.param_count() + self.locals.len() - 1) as u32
+    }
+
+    fn param_count(&self) -> usize {
+        self.function.params.len() + self.va_param.map_or(0, |_| 1)
+    }
+
+    fn home(&self, id: VarId) -> Home {
+        self.homes[id].expect("Variable without location")
+    }
+
+    // 打开一层 block loop if，返回它的层数
+    fn open(&mut self, instr: Instr) -> u32 {
+        self.emit(instr);
+        self.depth += 1;
+        self.depth
+    }
+
+    fn close(&mut self) {
+        self.emit(Instr::End);
+        self.depth -= 1;
+    }
+
+    // 跳到第 depth 层块
+    fn branch(&mut self, depth: u32) {
+        let relative = self.depth - depth;
+        self.emit(Instr::Br(relative));
+    }
+
+    fn branch_if(&mut self, depth: u32) {
+        let relative = self.depth - depth;
+        self.emit(Instr::BrIf(relative));
+    }
+
+    fn zero(&mut self, ty: ValType) {
+        self.emit(match ty {
+            ValType::I32 => Instr::I32Const(0),
+            ValType::I64 => Instr::I64Const(0),
+            ValType::F32 => Instr::F32Const(0.0),
+            ValType::F64 => Instr::F64Const(0.0),
+        });
+    }
+
+    // 离开函数之前释放影子栈栈帧
+    fn epilogue(&mut self) {
+        if let Some((fp, size)) = self.frame {
+            self.emit(Instr::LocalGet(fp));
+            self.emit(Instr::I32Const(size as i32));
+            self.op("i32.add");
+            self.emit(Instr::GlobalSet(STACK_POINTER));
+        }
+    }
+
+    /*
+     * 调用一个没有定义的函数，第一次调用时加入导入
+     * 同一个函数只能有一种类型
+    */
+    fn import(&mut self, name: &str, ty: FuncType) {
+        match self.imports.iter().find(|i| i.name == name) {
+            Some(import) if import.ty != ty => {
+                self.error.get_or_insert(format!("Conflicting types for imported function {}", name));
+            }
+            Some(_) => {}
+            None => self.imports.push(Import { name: name.to_string(), ty }),
+        }
+    }
+}
+
+// C 的类型对应的值类型，void 和 va_list 没有
+fn value_type(t: &Type) -> Option<ValType> {
+    match t {
+        Type::Void | Type::VaList => None,
+        Type::Int | Type::Char => Some(ValType::I64),
+        Type::Pointer(_) => Some(ValType::I32),
+        Type::Float => Some(ValType::F32),
+        Type::Double | Type::LongDouble => Some(ValType::F64),
+    }
+}
+
+fn expect_value(t: &Type) -> ValType {
+    value_type(t).expect("Expression without value")
+}
+
+fn align_to(n: u32, align: u32) -> u32 {
+    n.div_ceil(align) * align
+}
+
+/*
+ * 值在内存中的大小和读写指令
+ * 对齐就是大小
+*/
+fn memory_size(ty: ValType) -> u32 {
+    match ty {
+        ValType::I32 | ValType::F32 => 4,
+        ValType::I64 | ValType::F64 => 8,
+    }
+}
+
+fn load_instr(ty: ValType) -> Instr {
+    match ty {
+        ValType::I32 => Instr::Memory("i32.load", 2, 0),
+        ValType::I64 => Instr::Memory("i64.load", 3, 0),
+        ValType::F32 => Instr::Memory("f32.load", 2, 0),
+        ValType::F64 => Instr::Memory("f64.load", 3, 0),
+    }
+}
+
+fn store_instr(ty: ValType, offset: u32) -> Instr {
+    match ty {
+        ValType::I32 => Instr::Memory("i32.store", 2, offset),
+        ValType::I64 => Instr::Memory("i64.store", 3, offset),
+        ValType::F32 => Instr::Memory("f32.store", 2, offset),
+        ValType::F64 => Instr::Memory("f64.store", 3, offset),
+    }
+}
+
+pub fn generate(program: &Program) -> Result<Module, String> {
+    let signatures: HashMap<&str, &Signature> = program.functions.iter().map(|f| (&f.name[..], &f.signature)).collect();
+    let mut module = Module::default();
+
+    for function in program.functions.iter() {
+        let function = generate_function(function, &signatures, &mut module.imports, &mut module.data)?;
+        module.functions.push(function);
+    }
+
+    module.stack_pointer = align_to(DATA_BASE + module.data.len() as u32, 16) + STACK_SIZE;
+    module.pages = module.stack_pointer.div_ceil(PAGE_SIZE);
+    Ok(module)
+}
+
+// 定义了的函数的类型，可变参数函数最后多一个可变参数的地址
+fn function_type(signature: &Signature) -> FuncType {
+    let mut params: Vec<ValType> = signature.params.iter().map(expect_value).collect();
+    if signature.variadic {
+        params.push(ValType::I32);
+    }
+    FuncType { params, result: value_type(&signature.return_type) }
+}
+
+fn generate_function(
+    function: &Function,
+    signatures: &HashMap<&str, &Signature>,
+    imports: &mut Vec<Import>,
+    data: &mut Vec<u8>,
+) -> Result<WasmFunction, String> {
+    let ty = function_type(&function.signature);
+    let mut context = Context {
+        function,
+        signatures,
+        imports,
+        data,
+        locals: Vec::new(),
+        homes: vec![None; function.locals.len()],
+        va_param: None,
+        frame: None,
+        va_temps: None,
+        depth: 0,
+        loops: Vec::new(),
+        code: Vec::new(),
+        error: None,
+    };
+
+    // 参数就是最前面的局部变量
+    for (i, id) in function.params.iter().enumerate() {
+        context.homes[*id] = Some(Home::Local(i as u32));
+    }
+    if function.signature.variadic {
+        context.va_param = Some(function.params.len() as u32);
+    }
+
+    // va_list 放在影子栈上，其余的变量各用一个局部变量
+    let mut frame_size = 0;
+    for (id, t) in function.locals.iter().enumerate() {
+        if context.homes[id].is_some() {
+            continue;
+        }
+        context.homes[id] = Some(match value_type(t) {
+            Some(ty) => Home::Local(context.new_local(ty)),
+            None => {
+                frame_size += VA_LIST_SIZE;
+                Home::Frame(frame_size - VA_LIST_SIZE)
+            }
+        });
+    }
+
+    // 序言: 在影子栈上分配栈帧
+    if frame_size > 0 {
+        let size = align_to(frame_size, 16);
+        let fp = context.new_local(ValType::I32);
+        context.frame = Some((fp, size));
+        context.emit(Instr::GlobalGet(STACK_POINTER));
+        context.emit(Instr::I32Const(size as i32));
+        context.op("i32.sub");
+        context.emit(Instr::LocalTee(fp));
+        context.emit(Instr::GlobalSet(STACK_POINTER));
+    }
+
+    for statement in function.body.iter() {
+        generate_statement(statement, &mut context);
+    }
+
+    // 没有 return 就结束的函数返回0
+    context.epilogue();
+    if let Some(result) = ty.result {
+        context.zero(result);
+    }
+
+    if let Some(error) = context.error {
+        return Err(error);
+    }
+    Ok(WasmFunction {
+        name: function.name.clone(),
+        ty,
+        export: !function.specifiers.is_static,
+        locals: context.locals,
+        body: context.code,
+    })
+}
+
+fn generate_statement(statement: &Stmt, context: &mut Context) {
+    match statement {
+        Stmt::Expression(expr) => {
+            if generate_expression(expr, context).is_some() {
+                context.emit(Instr::Drop);
+            }
+        }
+
+        Stmt::Declaration(id, init) => {
+            if let Some(expr) = init {
+                generate_expression(expr, context);
+                generate_store(*id, context);
+            }
+        }
+
+        Stmt::Return(expr) => {
+            if let Some(expr) = expr {
+                generate_expression(expr, context);
+            }
+            context.epilogue();
+            context.emit(Instr::Return);
+        }
+
+        Stmt::TailCall(expr) => {
+            let ExprKind::Call(name, signature, args) = &expr.kind else {
+                unreachable!("Tail call of a non-call expression");
+            };
+            generate_call(name, signature, args, true, context);
+        }
+
+        Stmt::If(condition, if_body, else_body) => {
+            generate_condition(condition, context);
+            context.open(Instr::If(None));
+            generate_statement(if_body, context);
+            if let Some(else_body) = else_body {
+                context.emit(Instr::Else);
+                generate_statement(else_body, context);
+            }
+            context.close();
+        }
+
+        Stmt::Block(block) => {
+            for statement in block.iter() {
+                generate_statement(statement, context);
+            }
+        }
+
+        /*
+         * block $break
+         *   loop $top
+         *     条件不成立时 br $break
+         *     block $continue 循环体 end
+         *     post
+         *     br $top
+         *   end
+         * end
+        */
+        Stmt::For(init, condition, post_expression, body) => {
+            if let Some(init) = init {
+                generate_statement(init, context);
+            }
+            let break_depth = context.open(Instr::Block(None));
+            let top = context.open(Instr::Loop(None));
+            generate_condition(condition, context);
+            context.op("i32.eqz");
+            context.branch_if(break_depth);
+
+            let continue_depth = context.open(Instr::Block(None));
+            generate_loop_body(body, break_depth, continue_depth, context);
+            context.close();
+
+            if let Some(expr) = post_expression {
+                if generate_expression(expr, context).is_some() {
+                    context.emit(Instr::Drop);
+                }
+            }
+            context.branch(top);
+            context.close();
+            context.close();
+        }
+
+        // continue 直接回到 loop 的开头重新判断条件
+        Stmt::While(condition, body) => {
+            let break_depth = context.open(Instr::Block(None));
+            let top = context.open(Instr::Loop(None));
+            generate_condition(condition, context);
+            context.op("i32.eqz");
+            context.branch_if(break_depth);
+
+            generate_loop_body(body, break_depth, top, context);
+            context.branch(top);
+            context.close();
+            context.close();
+        }
+
+        // continue 跳出循环体所在的块，之后判断条件
+        Stmt::DoWhile(body, condition) => {
+            let break_depth = context.open(Instr::Block(None));
+            let top = context.open(Instr::Loop(None));
+            let continue_depth = context.open(Instr::Block(None));
+            generate_loop_body(body, break_depth, continue_depth, context);
+            context.close();
+
+            generate_condition(condition, context);
+            context.branch_if(top);
+            context.close();
+            context.close();
+        }
+
+        Stmt::Break => {
+            let (break_depth, _) = *context.loops.last().expect("Break outside loop");
+            context.branch(break_depth);
+        }
+
+        Stmt::Continue => {
+            let (_, continue_depth) = *context.loops.last().expect("Continue outside loop");
+            context.branch(continue_depth);
+        }
+    }
+}
+
+fn generate_loop_body(body: &Stmt, break_depth: u32, continue_depth: u32, context: &mut Context) {
+    context.loops.push((break_depth, continue_depth));
+    generate_statement(body, context);
+    context.loops.pop();
+}
+
+// 把栈顶的值写到变量中
+fn generate_store(id: VarId, context: &mut Context) {
+    match context.home(id) {
+        Home::Local(local) => context.emit(Instr::LocalSet(local)),
+        Home::Frame(_) => unreachable!("Assignment to va_list"),
+    }
+}
+
+/*
+ * 条件，结果是 i32 的 0 或 1
+ * 比较直接用比较指令的结果
+ * && || 短路求值，右边放在 if 中
+*/
+fn generate_condition(condition: &Expr, context: &mut Context) {
+    match &condition.kind {
+        ExprKind::Constant(n) => context.emit(Instr::I32Const((*n != 0) as i32)),
+
+        ExprKind::Binary(Operator::LogicalAnd, lhs, rhs) => {
+            generate_condition(lhs, context);
+            context.open(Instr::If(Some(ValType::I32)));
+            generate_condition(rhs, context);
+            context.emit(Instr::Else);
+            context.emit(Instr::I32Const(0));
+            context.close();
+        }
+
+        ExprKind::Binary(Operator::LogicalOr, lhs, rhs) => {
+            generate_condition(lhs, context);
+            context.open(Instr::If(Some(ValType::I32)));
+            context.emit(Instr::I32Const(1));
+            context.emit(Instr::Else);
+            generate_condition(rhs, context);
+            context.close();
+        }
+
+        ExprKind::Unary(Operator::LogicalNegation, expr) => {
+            generate_condition(expr, context);
+            context.op("i32.eqz");
+        }
+
+        ExprKind::Binary(op, lhs, rhs) if op.is_comparison_operators() => {
+            generate_expression(lhs, context);
+            generate_expression(rhs, context);
+            context.op(compare_operator(*op, &lhs.ty));
+        }
+
+        _ => {
+            let ty = generate_expression(condition, context).expect("Void value used as condition");
+            match ty {
+                ValType::I32 => {
+                    context.op("i32.eqz");
+                    context.op("i32.eqz");
+                }
+                ValType::I64 => {
+                    context.op("i64.eqz");
+                    context.op("i32.eqz");
+                }
+                ValType::F32 => {
+                    context.emit(Instr::F32Const(0.0));
+                    context.op("f32.ne");
+                }
+                ValType::F64 => {
+                    context.emit(Instr::F64Const(0.0));
+                    context.op("f64.ne");
+                }
+            }
+        }
+    }
+}
+
+fn binary_operator(op: Operator, ty: ValType) -> &'static str {
+    match (op, ty) {
+        (Operator::Plus, ValType::I64) => "i64.add",
+        (Operator::Minus, ValType::I64) => "i64.sub",
+        (Operator::Multiplication, ValType::I64) => "i64.mul",
+        (Operator::Division, ValType::I64) => "i64.div_s",
+        (Operator::Modulo, ValType::I64) => "i64.rem_s",
+        (Operator::BitwiseAnd, ValType::I64) => "i64.and",
+        (Operator::BitwiseOr, ValType::I64) => "i64.or",
+        (Operator::BitwiseXor, ValType::I64) => "i64.xor",
+        (Operator::BitwiseShiftLeft, ValType::I64) => "i64.shl",
+        (Operator::BitwiseShiftRight, ValType::I64) => "i64.shr_s",
+        (Operator::Plus, ValType::F32) => "f32.add",
+        (Operator::Minus, ValType::F32) => "f32.sub",
+        (Operator::Multiplication, ValType::F32) => "f32.mul",
+        (Operator::Division, ValType::F32) => "f32.div",
+        (Operator::Plus, ValType::F64) => "f64.add",
+        (Operator::Minus, ValType::F64) => "f64.sub",
+        (Operator::Multiplication, ValType::F64) => "f64.mul",
+        (Operator::Division, ValType::F64) => "f64.div",
+        _ => unreachable!("Unexpected binary operator {:?} on {:?}", op, ty),
+    }
+}
+
+// 指针按无符号数比较
+fn compare_operator(op: Operator, ty: &Type) -> &'static str {
+    let names = match expect_value(ty) {
+        ValType::I32 => ["i32.eq", "i32.ne", "i32.lt_u", "i32.le_u", "i32.gt_u", "i32.ge_u"],
+        ValType::I64 => ["i64.eq", "i64.ne", "i64.lt_s", "i64.le_s", "i64.gt_s", "i64.ge_s"],
+        ValType::F32 => ["f32.eq", "f32.ne", "f32.lt", "f32.le", "f32.gt", "f32.ge"],
+        ValType::F64 => ["f64.eq", "f64.ne", "f64.lt", "f64.le", "f64.gt", "f64.ge"],
+    };
+    match op {
+        Operator::Equal => names[0],
+        Operator::NotEqual => names[1],
+        Operator::LessThan => names[2],
+        Operator::LessThanOrEqual => names[3],
+        Operator::GreaterThan => names[4],
+        Operator::GreaterThanOrEqual => names[5],
+        _ => unreachable!("Unexpected comparison operator {:?}", op),
+    }
+}
+
+/*
+ * 表达式
+ * 值留在操作数栈上，返回值的类型，void 表达式返回 None
+*/
+fn generate_expression(expression: &Expr, context: &mut Context) -> Option<ValType> {
+    let ty = value_type(&expression.ty);
+
+    match &expression.kind {
+        ExprKind::Constant(n) => match expect_value(&expression.ty) {
+            ValType::I32 => context.emit(Instr::I32Const(*n as i32)),
+            ValType::I64 => context.emit(Instr::I64Const(*n)),
+            ValType::F32 => context.emit(Instr::F32Const(*n as f32)),
+            ValType::F64 => context.emit(Instr::F64Const(*n as f64)),
+        },
+
+        ExprKind::FloatConstant(f) => match expect_value(&expression.ty) {
+            ValType::F32 => context.emit(Instr::F32Const(f32::from_bits(float_bits(f, Ty::F32) as u32))),
+            _ => context.emit(Instr::F64Const(f64::from_bits(float_bits(f, Ty::F64) as u64))),
+        },
+
+        ExprKind::StringLiteral(s) => {
+            let address = DATA_BASE + context.data.len() as u32;
+            context.data.extend_from_slice(s);
+            context.data.push(0);
+            context.emit(Instr::I32Const(address as i32));
+        }
+
+        ExprKind::Variable(id) => match context.home(*id) {
+            Home::Local(local) => context.emit(Instr::LocalGet(local)),
+            Home::Frame(_) => unreachable!("va_list used as a value"),
+        },
+
+        ExprKind::Address(id) => match context.home(*id) {
+            Home::Frame(offset) => {
+                let (fp, _) = context.frame.expect("Frame variable without frame");
+                context.emit(Instr::LocalGet(fp));
+                if offset > 0 {
+                    context.emit(Instr::I32Const(offset as i32));
+                    context.op("i32.add");
+                }
+            }
+            Home::Local(_) => unreachable!("Address of a local"),
+        },
+
+        ExprKind::Unary(Operator::LogicalNegation, _) | ExprKind::Binary(Operator::LogicalAnd | Operator::LogicalOr, _, _) => {
+            generate_condition(expression, context);
+            context.op("i64.extend_i32_u");
+        }
+
+        // ~a 是 a ^ -1
+        ExprKind::Unary(Operator::BitwiseComplement, expr) => {
+            generate_expression(expr, context);
+            context.emit(Instr::I64Const(-1));
+            context.op("i64.xor");
+        }
+
+        // 整数的 -a 是 0 - a
+        ExprKind::Unary(_, expr) => match expect_value(&expression.ty) {
+            ValType::F32 => {
+                generate_expression(expr, context);
+                context.op("f32.neg");
+            }
+            ValType::F64 => {
+                generate_expression(expr, context);
+                context.op("f64.neg");
+            }
+            _ => {
+                context.emit(Instr::I64Const(0));
+                generate_expression(expr, context);
+                context.op("i64.sub");
+            }
+        },
+
+        ExprKind::Assign(lhs, rhs) => {
+            generate_expression(rhs, context);
+            generate_tee(lhs, context);
+        }
+
+        // a op= b 相当于 a = a op b，运算类型就是右边的类型
+        ExprKind::CompoundAssign(op, lhs, rhs) => {
+            generate_expression(lhs, context);
+            generate_convert(&lhs.ty, &rhs.ty, context);
+            generate_expression(rhs, context);
+            context.op(binary_operator(*op, expect_value(&rhs.ty)));
+            generate_convert(&rhs.ty, &lhs.ty, context);
+            generate_tee(lhs, context);
+        }
+
+        ExprKind::Binary(op, lhs, rhs) => {
+            generate_expression(lhs, context);
+            generate_expression(rhs, context);
+            if op.is_comparison_operators() {
+                context.op(compare_operator(*op, &lhs.ty));
+                context.op("i64.extend_i32_u");
+            } else {
+                context.op(binary_operator(*op, expect_value(&expression.ty)));
+            }
+        }
+
+        ExprKind::Ternary(e1, e2, e3) => {
+            generate_condition(e1, context);
+            context.open(Instr::If(ty));
+            generate_arm(e2, ty, context);
+            context.emit(Instr::Else);
+            generate_arm(e3, ty, context);
+            context.close();
+        }
+
+        ExprKind::Call(name, signature, args) => return generate_call(name, signature, args, false, context),
+
+        ExprKind::Cast(expr) => {
+            let inner = generate_expression(expr, context);
+            return match (ty, inner) {
+                (Some(_), Some(_)) => {
+                    generate_convert(&expr.ty, &expression.ty, context);
+                    ty
+                }
+                // 转换成 void 只求值
+                (None, Some(_)) => {
+                    context.emit(Instr::Drop);
+                    None
+                }
+                _ => None,
+            };
+        }
+
+        // 把可变参数的地址写到 va_list 中
+        ExprKind::VaStart(ap) => {
+            generate_expression(ap, context);
+            let va_param = context.va_param.expect("va_start in function with fixed arguments");
+            context.emit(Instr::LocalGet(va_param));
+            context.emit(store_instr(ValType::I32, 0));
+        }
+
+        ExprKind::VaArg(ap) => generate_va_arg(ap, &expression.ty, context),
+
+        ExprKind::VaEnd(ap) => {
+            generate_expression(ap, context);
+            context.emit(Instr::Drop);
+        }
+
+        ExprKind::VaCopy(dest, src) => {
+            generate_expression(dest, context);
+            generate_expression(src, context);
+            context.emit(load_instr(ValType::I32));
+            context.emit(store_instr(ValType::I32, 0));
+        }
+    }
+
+    ty
+}
+
+// ?: 的一个分支，结果是 void 时丢掉分支的值
+fn generate_arm(arm: &Expr, ty: Option<ValType>, context: &mut Context) {
+    if generate_expression(arm, context).is_some() && ty.is_none() {
+        context.emit(Instr::Drop);
+    }
+}
+
+// 赋值: 写到变量中，值留在栈上作为表达式的结果
+fn generate_tee(lvalue: &Expr, context: &mut Context) {
+    match lvalue.kind {
+        ExprKind::Variable(id) => match context.home(id) {
+            Home::Local(local) => context.emit(Instr::LocalTee(local)),
+            Home::Frame(_) => unreachable!("Assignment to va_list"),
+        },
+        _ => unreachable!("Not an lvalue: {:?}", lvalue),
+    }
+}
+
+/*
+ * 类型转换，栈顶的值从 from 转换到 to
+ * 指针转换成整数时按无符号数扩展
+ * 浮点数转换成整数时超出范围的值取最大或最小值
+ * 转换成 char 时截断到8位再符号扩展
+*/
+fn generate_convert(from: &Type, to: &Type, context: &mut Context) {
+    let name = match (expect_value(from), expect_value(to)) {
+        (ValType::I64, ValType::I32) => Some("i32.wrap_i64"),
+        (ValType::I32, ValType::I64) => Some("i64.extend_i32_u"),
+        (ValType::I64, ValType::F32) => Some("f32.convert_i64_s"),
+        (ValType::I64, ValType::F64) => Some("f64.convert_i64_s"),
+        (ValType::F32, ValType::I64) => Some("i64.trunc_sat_f32_s"),
+        (ValType::F64, ValType::I64) => Some("i64.trunc_sat_f64_s"),
+        (ValType::F32, ValType::F64) => Some("f64.promote_f32"),
+        (ValType::F64, ValType::F32) => Some("f32.demote_f64"),
+        (a, b) if a == b => None,
+        (a, b) => unreachable!("Invalid conversion from {:?} to {:?}", a, b),
+    };
+    if let Some(name) = name {
+        context.op(name);
+    }
+    if *to == Type::Char && *from != Type::Char {
+        context.op("i64.extend8_s");
+    }
+}
+
+/*
+ * va_arg: 取出 va_list 中的地址，按类型对齐之后读取，再把下一个参数的地址写回去
+ * char 按提升之后的 int 传递，读最低的字节
+*/
+fn generate_va_arg(ap: &Expr, ty: &Type, context: &mut Context) {
+    let value = expect_value(ty);
+    let size = memory_size(value) as i32;
+    let (list, arg) = match context.va_temps {
+        Some(temps) => temps,
+        None => {
+            let temps = (context.new_local(ValType::I32), context.new_local(ValType::I32));
+            context.va_temps = Some(temps);
+            temps
+        }
+    };
+
+    generate_expression(ap, context);
+    context.emit(Instr::LocalTee(list));
+    context.emit(load_instr(ValType::I32));
+    context.emit(Instr::I32Const(size - 1));
+    context.op("i32.add");
+    context.emit(Instr::I32Const(-size));
+    context.op("i32.and");
+    context.emit(Instr::LocalSet(arg));
+
+    context.emit(Instr::LocalGet(list));
+    context.emit(Instr::LocalGet(arg));
+    context.emit(Instr::I32Const(size));
+    context.op("i32.add");
+    context.emit(store_instr(ValType::I32, 0));
+
+    context.emit(Instr::LocalGet(arg));
+    match ty {
+        Type::Char => context.emit(Instr::Memory("i64.load8_s", 0, 0)),
+        _ => context.emit(load_instr(value)),
+    }
+}
+
+/*
+ * 调用
+ * 调用定义了的函数时参数转换成它的形参类型，少的参数补0，多的参数求值之后丢掉
+ * 没有定义的函数按调用处的签名导入，没有原型时按实际参数的类型
+ * 可变参数写在影子栈上新分配的一块内存中，调用之后释放
+ * tail 是必须的尾调用，没有可变参数时用 return_call
+*/
+fn generate_call(name: &str, signature: &Signature, args: &[Expr], tail: bool, context: &mut Context) -> Option<ValType> {
+    let callee = match context.signatures.get(name) {
+        Some(signature) => (*signature).clone(),
+        None if signature.prototyped => signature.clone(),
+        None => Signature {
+            params: args.iter().map(|arg| arg.ty.clone()).collect(),
+            ..signature.clone()
+        },
+    };
+    if !context.signatures.contains_key(name) {
+        context.import(name, function_type(&callee));
+    }
+
+    let named = callee.params.len().min(args.len());
+    for (arg, param) in args[..named].iter().zip(callee.params.iter()) {
+        generate_expression(arg, context);
+        generate_convert(&arg.ty, param, context);
+    }
+    for param in callee.params[named..].iter() {
+        context.zero(expect_value(param));
+    }
+
+    let rest = &args[named..];
+    let buffer = if !callee.variadic {
+        for arg in rest.iter() {
+            if generate_expression(arg, context).is_some() {
+                context.emit(Instr::Drop);
+            }
+        }
+        None
+    } else if rest.is_empty() {
+        context.emit(Instr::I32Const(0));
+        None
+    } else {
+        Some(generate_variadic_arguments(rest, context))
+    };
+
+    let result = value_type(&callee.return_type);
+    match buffer {
+        Some((buffer, size)) => {
+            context.emit(Instr::Call(name.to_string()));
+            context.emit(Instr::LocalGet(buffer));
+            context.emit(Instr::I32Const(size as i32));
+            context.op("i32.add");
+            context.emit(Instr::GlobalSet(STACK_POINTER));
+            if tail {
+                context.epilogue();
+                context.emit(Instr::Return);
+            }
+        }
+        None if tail => {
+            context.epilogue();
+            context.emit(Instr::ReturnCall(name.to_string()));
+        }
+        None => context.emit(Instr::Call(name.to_string())),
+    }
+    result
+}
+
+/*
+ * 在影子栈上分配可变参数的内存，依次写入，把地址留在栈上
+ * 返回保存地址的局部变量和分配的大小
+*/
+fn generate_variadic_arguments(args: &[Expr], context: &mut Context) -> (u32, u32) {
+    let mut offsets = Vec::new();
+    let mut size = 0;
+    for arg in args.iter() {
+        let arg_size = memory_size(expect_value(&arg.ty));
+        size = align_to(size, arg_size);
+        offsets.push(size);
+        size += arg_size;
+    }
+    let size = align_to(size, 16);
+
+    let buffer = context.new_local(ValType::I32);
+    context.emit(Instr::GlobalGet(STACK_POINTER));
+    context.emit(Instr::I32Const(size as i32));
+    context.op("i32.sub");
+    context.emit(Instr::LocalTee(buffer));
+    context.emit(Instr::GlobalSet(STACK_POINTER));
+
+    for (arg, offset) in args.iter().zip(offsets) {
+        context.emit(Instr::LocalGet(buffer));
+        let ty = generate_expression(arg, context).expect("Void value used as argument");
+        context.emit(store_instr(ty, offset));
+    }
+    context.emit(Instr::LocalGet(buffer));
+    (buffer, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+
+    use super::super::driver::analyze;
+    use super::super::options::{Arch, Options};
+    use super::super::target::target;
+
+    // 导入的 putchar 写到 stdout，有 node 时运行 main
+    const HOST: &str = "const fs = require('fs');
+        const env = { putchar: c => { process.stdout.write(String.fromCharCode(Number(c))); return c; } };
+        const module = new WebAssembly.Module(fs.readFileSync(process.argv[1]));
+        process.stdout.write(' ' + new WebAssembly.Instance(module, { env }).exports.main());";
+
+    #[test]
+    fn imports_host_functions_and_exports_definitions() {
+        let source = "int putchar(int c);
+            int twice(int x) { return x * 2; }
+            int main() { putchar('w'); return twice(21); }";
+        let options = Options { input: "test.c".to_string(), target: Arch::Wasm32, ..Options::default() };
+        let program = analyze(source, &options);
+        let text = target(Arch::Wasm32).assembly(&program, &options).unwrap();
+        assert!(text.contains("(import \"env\" \"putchar\""));
+        assert!(text.contains("(export \"main\""));
+        let binary = target(Arch::Wasm32).object(&program, &options).unwrap();
+        assert_eq!(binary[..8], [0, b'a', b's', b'm', 1, 0, 0, 0]);
+
+        let path = std::env::temp_dir().join(format!("my_rcc_wasm_test_{}.wasm", std::process::id()));
+        fs::write(&path, binary).unwrap();
+        let output = Command::new("node").arg("-e").arg(HOST).arg(&path).output();
+        let _ = fs::remove_file(&path);
+        match output {
+            Ok(output) => assert_eq!(String::from_utf8_lossy(&output.stdout), "w 42", "{}", String::from_utf8_lossy(&output.stderr)),
+            Err(_) => eprintln!("node not found, the module is not run"),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use super::{FuncType, Instr, Module, ValType, DATA_BASE};
+
+/*
+ * WebAssembly 文本格式 (.wat)
+ * 函数用 $名字 引用，局部变量、全局变量和跳转目标用编号
+ * 指令按块的嵌套缩进
+*/
+
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// (param ...) (result ...)，前面带空格
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.params.is_empty() {
+            write!(f, " (param")?;
+            for param in self.params.iter() {
+                write!(f, " {}", param)?;
+            }
+            write!(f, ")")?;
+        }
+        if let Some(result) = self.result {
+            write!(f, " (result {})", result)?;
+        }
+        Ok(())
+    }
+}
+
+// 块的结果类型，前面带空格
+fn block_type(ty: &Option<ValType>) -> String {
+    match ty {
+        Some(ty) => format!(" (result {})", ty),
+        None => String::new(),
+    }
+}
+
+// 浮点常量写成能精确还原的十进制
+fn float_literal(text: String) -> String {
+    match &text[..] {
+        "NaN" => "nan".to_string(),
+        "-NaN" => "-nan".to_string(),
+        _ => text,
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Block(ty) => write!(f, "block{}", block_type(ty)),
+            Instr::Loop(ty) => write!(f, "loop{}", block_type(ty)),
+            Instr::If(ty) => write!(f, "if{}", block_type(ty)),
+            Instr::Else => write!(f, "else"),
+            Instr::End => write!(f, "end"),
+            Instr::Br(depth) => write!(f, "br {}", depth),
+            Instr::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instr::Return => write!(f, "return"),
+            Instr::Call(name) => write!(f, "call ${}", name),
+            Instr::ReturnCall(name) => write!(f, "return_call ${}", name),
+            Instr::Drop => write!(f, "drop"),
+            Instr::LocalGet(i) => write!(f, "local.get {}", i),
+            Instr::LocalSet(i) => write!(f, "local.set {}", i),
+            Instr::LocalTee(i) => write!(f, "local.tee {}", i),
+            Instr::GlobalGet(i) => write!(f, "global.get {}", i),
+            Instr::GlobalSet(i) => write!(f, "global.set {}", i),
+            Instr::I32Const(n) => write!(f, "i32.const {}", n),
+            Instr::I64Const(n) => write!(f, "i64.const {}", n),
+            Instr::F32Const(x) => write!(f, "f32.const {}", float_literal(format!("{:?}", x))),
+            Instr::F64Const(x) => write!(f, "f64.const {}", float_literal(format!("{:?}", x))),
+            Instr::Memory(name, _, 0) => write!(f, "{}", name),
+            Instr::Memory(name, _, offset) => write!(f, "{} offset={}", name, offset),
+            Instr::Op(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// 字符串中的字节，可打印的 ASCII 原样输出，其余写成 \十六进制
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| match b {
+        b'"' | b'\\' => format!("\\{}", *b as char),
+        0x20..=0x7e => (*b as char).to_string(),
+        _ => format!("\\{:02x}", b),
+    }).collect()
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "(module")?;
+        for import in self.imports.iter() {
+            writeln!(f, "  (import \"env\" \"{}\" (func ${}{}))", import.name, import.name, import.ty)?;
+        }
+        writeln!(f, "  (memory (export \"memory\") {})", self.pages)?;
+        writeln!(f, "  (global $__stack_pointer (mut i32) (i32.const {}))", self.stack_pointer)?;
+        if !self.data.is_empty() {
+            writeln!(f, "  (data (i32.const {}) \"{}\")", DATA_BASE, escape(&self.data))?;
+        }
+
+        for function in self.functions.iter() {
+            write!(f, "  (func ${}", function.name)?;
+            if function.export {
+                write!(f, " (export \"{}\")", function.name)?;
+            }
+            writeln!(f, "{}", function.ty)?;
+            if !function.locals.is_empty() {
+                write!(f, "    (local")?;
+                for local in function.locals.iter() {
+                    write!(f, " {}", local)?;
+                }
+                writeln!(f, ")")?;
+            }
+
+            let mut depth = 2;
+            for instr in function.body.iter() {
+                if matches!(instr, Instr::Else | Instr::End) {
+                    depth -= 1;
+                }
+                writeln!(f, "{:width$}{}", "", instr, width = depth * 2)?;
+                if matches!(instr, Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        writeln!(f, ")")
+    }
+}
@@ -1,10 +1,21 @@
 use std::env;
 use std::fs::File;
-use std::io::Error;
-use std::io::Read;
+use std::fs;
+use std::io::{self, Error, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use std::process::exit;
 
-use crate::cod::generator::generate;
+use crate::cod::c;
+use crate::cod::elf::read_relocatable;
+use crate::cod::driver::{check, interpret, lower, parse};
+use crate::cod::interp::Session;
+use crate::cod::link::link;
+use crate::cod::llvm;
+use crate::cod::runtime::{libc, startup};
+use crate::cod::options::{Emit, LinkOptions, Options};
+use crate::cod::repl::repl;
+use crate::cod::target::target;
 
 mod cod;
 
@@ -13,43 +24,145 @@ fn main() {
     // 首先获取命令行的参数
     // 参考代码：https://www.perfcode.com/p/rust-gets-command-line-parameters.html
     
-    // 假设输入的是： rustc/cargo run (需要运行的c文件目录)
+    // 假设输入的是： rustc/cargo run [选项] (需要运行的c文件目录)
     let args: Vec<String> = env::args().collect();
 
     // println!("{:?}", args);
 
-    // 如果输入的参数有问题 报错并退出
-    if args.len() != 2 {
-        eprintln!("Error: Some question occur in input");
-        exit(1);
+    // my_rcc link: 把目标文件链接成可执行文件
+    if args.get(1).map(|s| &s[..]) == Some("link") {
+        link_command(&args[2..]);
+        return;
+    }
+
+    // my_rcc run: 直接解释执行，退出码是 main 的返回值
+    if args.get(1).map(|s| &s[..]) == Some("run") {
+        run_command(&args[2..]);
+        return;
+    }
+
+    // my_rcc jit: 即时编译，在当前进程中调用 main
+    if args.get(1).map(|s| &s[..]) == Some("jit") {
+        jit_command(&args[2..]);
+        return;
     }
+
+    // my_rcc repl: 交互式执行
+    if args.get(1).map(|s| &s[..]) == Some("repl") {
+        repl();
+        return;
+    }
+
+    // 如果输入的参数有问题 报错并退出
+    let options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
     // println!("{}", &args[1]);
-    let tokens = match read_file(&args[1]) {
-        
-        // 在这里将所有的字符串进行lex
-        Ok(s) => cod::lex::lex(&s),
+    let source = match read_file(&options.input) {
+        Ok(s) => s,
         Err(e) => {
             eprintln!("Error: {}", e);
             exit(1);
         }
     };
 
-    // println!("{:?}", tokens);
-
-    // TODO: Debug tokens
+    let program = check(&parse(&source), &options);
 
-    let ast = cod::parser::parser(&tokens);
-    
-    // TODO: Debug ast
-
-    // println!("{:?}", ast);
+    // 汇编和目标文件由选择的目标机器生成
+    let target = target(options.target);
+    let output = match options.emit {
+        Emit::Ir => Ok(lower(&program, &options).to_string().into_bytes()),
+        Emit::C => Ok(c::generate(&program).into_bytes()),
+        Emit::Llvm => Ok(llvm::generate(&program, options.target).into_bytes()),
+        Emit::Asm => target.assembly(&program, &options).map(String::into_bytes),
+        Emit::Object => target.object(&program, &options),
+    };
+    let result = output.and_then(|output| write_output(&options, target.object_extension(), &output).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+}
 
-    generate(&ast);
+// 有 -o 时写到文件，目标文件默认写到 输入文件名.扩展名，其余输出到标准输出
+fn write_output(options: &Options, extension: &str, output: &[u8]) -> Result<(), Error> {
+    let path = match (&options.output, options.emit) {
+        (Some(path), _) => path.clone(),
+        (None, Emit::Object) => {
+            let stem = Path::new(&options.input).file_stem().unwrap_or_default().to_string_lossy();
+            format!("{}.{}", stem, extension)
+        }
+        (None, _) => return io::stdout().write_all(output),
+    };
+    fs::write(path, output)
 }
 
 fn read_file(input: &str) -> Result<String, Error> {
-    let mut file = File::open(input).unwrap();
+    let mut file = File::open(input)?;
     let mut contents = String::new();
-    file.read_to_string(&mut contents);
+    file.read_to_string(&mut contents)?;
     Ok(contents)
-}
\ No newline at end of file
+}
+/*
+ * 链接目标文件和运行时，输出可以直接运行的文件
+*/
+fn link_command(args: &[String]) {
+    let result = LinkOptions::parse(args).and_then(|options| {
+        let mut objects = vec![startup()];
+        for input in options.inputs.iter() {
+            let bytes = fs::read(input).map_err(|e| format!("{}: {}", input, e))?;
+            objects.push(read_relocatable(&bytes).map_err(|e| format!("{}: {}", input, e))?);
+        }
+        let executable = link(&objects, &[libc()])?;
+        fs::write(&options.output, executable).map_err(|e| e.to_string())?;
+        fs::set_permissions(&options.output, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+}
+
+/*
+ * 解释执行源文件，不需要汇编器和链接器
+*/
+fn run_command(args: &[String]) {
+    let result = Options::parse(args).and_then(|options| {
+        let source = read_file(&options.input).map_err(|e| e.to_string())?;
+        interpret(&source, &options)
+    });
+    match result {
+        Ok(status) => exit(status as i32),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/*
+ * 即时编译源文件，在当前进程中调用 main(argc, argv)，退出码是 main 的返回值
+ * 不需要汇编器、链接器和临时文件
+*/
+fn jit_command(args: &[String]) {
+    type Main = extern "C" fn(i32, *const *const u8) -> i32;
+    let result = Options::parse(args).and_then(|options| {
+        let source = read_file(&options.input).map_err(|e| e.to_string())?;
+        let jit = Session::jit(&source)?;
+        let main: Main = unsafe { jit.function("main") }.ok_or("Undefined reference to main")?;
+        let program = format!("{}\0", options.input);
+        let argv = [program.as_ptr(), std::ptr::null()];
+        Ok(main(1, argv.as_ptr()))
+    });
+    match result {
+        Ok(status) => exit(status),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    }
+}